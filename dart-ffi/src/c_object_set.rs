@@ -1,4 +1,6 @@
 use isar_core::object::isar_object::IsarObject;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
 use std::{ptr, slice};
 
 #[repr(C)]
@@ -47,10 +49,17 @@ impl CObject {
     }
 }
 
+/// Objects point into the memory of the transaction and are only valid until it is finished
+/// unless they were copied or the set pins the transaction.
 #[repr(C)]
 pub struct CObjectSet {
     objects: *mut CObject,
     length: u32,
+    /// Owns the object bytes if they were copied.
+    buffer: *mut u8,
+    buffer_length: u32,
+    /// Pin count of the transaction that has to outlive the set.
+    txn_pins: *const AtomicU32,
 }
 
 unsafe impl Send for CObjectSet {}
@@ -60,9 +69,18 @@ impl CObjectSet {
         let mut objects = objects.into_boxed_slice();
         self.objects = objects.as_mut_ptr();
         self.length = objects.len() as u32;
+        self.buffer = ptr::null_mut();
+        self.buffer_length = 0;
+        self.txn_pins = ptr::null();
         std::mem::forget(objects);
     }
 
+    /// Keeps the transaction from being finished until the set is freed.
+    pub fn pin_txn(&mut self, txn_pins: Arc<AtomicU32>) {
+        txn_pins.fetch_add(1, Ordering::SeqCst);
+        self.txn_pins = Arc::into_raw(txn_pins);
+    }
+
     #[allow(clippy::mut_from_ref)]
     pub unsafe fn get_objects(&self) -> &mut [CObject] {
         std::slice::from_raw_parts_mut(self.objects, self.length as usize)
@@ -71,6 +89,28 @@ impl CObjectSet {
     pub fn get_length(&self) -> usize {
         self.length as usize
     }
+
+    /// Copies all objects into a single buffer that is freed with the set.
+    pub unsafe fn copy_objects(&mut self) {
+        let objects = self.get_objects();
+        let mut buffer = Vec::with_capacity(objects.iter().map(|o| o.buffer_length as usize).sum());
+        for object in objects.iter() {
+            if !object.buffer.is_null() {
+                buffer.extend_from_slice(object.get_object().as_bytes());
+            }
+        }
+        let mut buffer = buffer.into_boxed_slice();
+        let mut offset = 0;
+        for object in objects.iter_mut() {
+            if !object.buffer.is_null() {
+                object.buffer = buffer.as_mut_ptr().add(offset);
+                offset += object.buffer_length as usize;
+            }
+        }
+        self.buffer = buffer.as_mut_ptr();
+        self.buffer_length = buffer.len() as u32;
+        std::mem::forget(buffer);
+    }
 }
 
 #[no_mangle]
@@ -78,4 +118,15 @@ pub unsafe extern "C" fn isar_free_c_object_set(ros: &mut CObjectSet) {
     Vec::from_raw_parts(ros.objects, ros.length as usize, ros.length as usize);
     ros.objects = ptr::null_mut();
     ros.length = 0;
+    if !ros.buffer.is_null() {
+        let buffer = slice::from_raw_parts_mut(ros.buffer, ros.buffer_length as usize);
+        drop(Box::from_raw(buffer));
+        ros.buffer = ptr::null_mut();
+        ros.buffer_length = 0;
+    }
+    if !ros.txn_pins.is_null() {
+        let txn_pins = Arc::from_raw(ros.txn_pins);
+        txn_pins.fetch_sub(1, Ordering::SeqCst);
+        ros.txn_pins = ptr::null();
+    }
 }
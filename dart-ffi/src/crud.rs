@@ -1,10 +1,16 @@
 use crate::c_object_set::{CObject, CObjectSet};
 use crate::txn::CIsarTxn;
-use crate::{from_c_str, BoolSend, UintSend};
+use crate::{from_c_str, BoolSend, UintSend, UlongSend};
 use intmap::IntMap;
-use isar_core::collection::IsarCollection;
+use isar_core::collection::{ConflictStrategy, IsarCollection};
+use isar_core::error::{illegal_arg, IsarError};
 use isar_core::index::index_key::IndexKey;
-use serde_json::Value;
+use isar_core::instance::{BatchOp, IsarInstance};
+use isar_core::object::isar_object::IsarObject;
+use isar_core::object::json_encode_decode::JsonDecodeOptions;
+use isar_core::object::ndjson_importer::{ImportErrorPolicy, ImportProgress, NdjsonImporter};
+use serde_json::{Map, Value};
+use std::ffi::CString;
 use std::os::raw::c_char;
 
 #[no_mangle]
@@ -49,9 +55,10 @@ pub unsafe extern "C" fn isar_get_all(
     objects: &'static mut CObjectSet,
 ) -> i64 {
     isar_try_txn!(txn, move |txn| {
-        for object in objects.get_objects() {
-            let id = object.get_id();
-            let result = collection.get(txn, id)?;
+        let objects = objects.get_objects();
+        let ids = objects.iter_mut().map(|o| o.get_id()).collect::<Vec<_>>();
+        let results = collection.get_all(txn, &ids)?;
+        for (object, result) in objects.iter_mut().zip(results) {
             object.set_object(result);
         }
         Ok(())
@@ -69,8 +76,8 @@ pub unsafe extern "C" fn isar_get_all_by_index(
     let slice = std::slice::from_raw_parts(keys, objects.get_length());
     let keys: Vec<IndexKey> = slice.iter().map(|k| *Box::from_raw(*k)).collect();
     isar_try_txn!(txn, move |txn| {
-        for (object, key) in objects.get_objects().iter_mut().zip(keys) {
-            let result = collection.get_by_index(txn, index_id, &key)?;
+        let results = collection.get_all_by_index(txn, index_id, &keys)?;
+        for (object, result) in objects.get_objects().iter_mut().zip(results) {
             if let Some((id, obj)) = result {
                 object.set_id(id);
                 object.set_object(Some(obj));
@@ -82,6 +89,31 @@ pub unsafe extern "C" fn isar_get_all_by_index(
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn isar_get_all_by_index_key(
+    collection: &'static IsarCollection,
+    txn: &mut CIsarTxn,
+    index_id: u64,
+    key: *mut IndexKey,
+    result: &'static mut CObjectSet,
+) -> i64 {
+    let key = *Box::from_raw(key);
+    isar_try_txn!(txn, move |txn| {
+        let objects = collection
+            .get_all_by_index_key(txn, index_id, &key)?
+            .into_iter()
+            .map(|(id, object)| {
+                let mut raw_obj = CObject::new();
+                raw_obj.set_id(id);
+                raw_obj.set_object(Some(object));
+                raw_obj
+            })
+            .collect();
+        result.fill_from_vec(objects);
+        Ok(())
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn isar_put(
     collection: &'static mut IsarCollection,
@@ -100,6 +132,31 @@ pub unsafe extern "C" fn isar_put(
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn isar_put_with_conflict(
+    collection: &'static mut IsarCollection,
+    txn: &mut CIsarTxn,
+    object: &'static mut CObject,
+    strategy: u8,
+) -> i64 {
+    isar_try_txn!(txn, move |txn| {
+        let id = if object.get_id() != i64::MIN {
+            Some(object.get_id())
+        } else {
+            None
+        };
+        let strategy = match strategy {
+            1 => ConflictStrategy::Error,
+            2 => ConflictStrategy::Replace,
+            3 => ConflictStrategy::Ignore,
+            _ => ConflictStrategy::Default,
+        };
+        let id = collection.put_with_conflict(txn, id, object.get_object(), &strategy)?;
+        object.set_id(id.unwrap_or(i64::MIN));
+        Ok(())
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn isar_put_by_index(
     collection: &'static mut IsarCollection,
@@ -114,6 +171,37 @@ pub unsafe extern "C" fn isar_put_by_index(
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn isar_put_if_newer(
+    collection: &'static IsarCollection,
+    txn: &mut CIsarTxn,
+    object: &'static mut CObject,
+    version: u64,
+    written: &'static mut bool,
+) -> i64 {
+    let written = BoolSend(written);
+    isar_try_txn!(txn, move |txn| {
+        let written = written;
+        *written.0 = collection.put_if_newer(txn, object.get_id(), object.get_object(), version)?;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_get_version(
+    collection: &'static IsarCollection,
+    txn: &mut CIsarTxn,
+    id: i64,
+    version: &'static mut u64,
+) -> i64 {
+    let version = UlongSend(version);
+    isar_try_txn!(txn, move |txn| {
+        let version = version;
+        *version.0 = collection.get_version(txn, id)?.unwrap_or(0);
+        Ok(())
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn isar_put_all(
     collection: &'static IsarCollection,
@@ -134,6 +222,89 @@ pub unsafe extern "C" fn isar_put_all(
     })
 }
 
+/// Objects are packed as `id: i64, length: u32, bytes` in little endian. `i64::MIN` generates an
+/// id.
+fn unpack_objects(
+    mut bytes: &[u8],
+) -> isar_core::error::Result<Vec<(Option<i64>, IsarObject<'_>)>> {
+    let mut objects = vec![];
+    while !bytes.is_empty() {
+        if bytes.len() < 12 {
+            return illegal_arg("Invalid packed objects.");
+        }
+        let id = i64::from_le_bytes(bytes[..8].try_into().unwrap());
+        let length = u32::from_le_bytes(bytes[8..12].try_into().unwrap()) as usize;
+        if bytes.len() < 12 + length {
+            return illegal_arg("Invalid packed objects.");
+        }
+        let object = IsarObject::from_bytes(&bytes[12..12 + length]);
+        objects.push((if id != i64::MIN { Some(id) } else { None }, object));
+        bytes = &bytes[12 + length..];
+    }
+    Ok(objects)
+}
+
+/// Puts all objects of a packed buffer and writes their ids to `ids` which needs space for
+/// `ids_length` ids.
+#[no_mangle]
+pub unsafe extern "C" fn isar_put_all_packed(
+    collection: &'static IsarCollection,
+    txn: &mut CIsarTxn,
+    packed: *const u8,
+    packed_length: u32,
+    ids: *mut i64,
+    ids_length: u32,
+) -> i64 {
+    let packed = std::slice::from_raw_parts(packed, packed_length as usize);
+    let ids = std::slice::from_raw_parts_mut(ids, ids_length as usize);
+    isar_try_txn!(txn, move |txn| {
+        let objects = unpack_objects(packed)?;
+        if objects.len() != ids.len() {
+            return illegal_arg("Wrong number of ids.");
+        }
+        let put_ids = collection.put_all(txn, &objects)?;
+        ids.copy_from_slice(&put_ids);
+        Ok(())
+    })
+}
+
+/// Returns the objects packed as `length: u32, bytes` in little endian. Missing objects have a
+/// length of zero. The buffer has to be freed with `isar_free_packed`.
+#[no_mangle]
+pub unsafe extern "C" fn isar_get_all_packed(
+    collection: &'static IsarCollection,
+    txn: &mut CIsarTxn,
+    ids: *const i64,
+    ids_length: u32,
+    packed: *mut *mut u8,
+    packed_length: &'static mut u32,
+) -> i64 {
+    let ids = std::slice::from_raw_parts(ids, ids_length as usize);
+    let packed = RawBytesSend(packed);
+    let packed_length = UintSend(packed_length);
+    isar_try_txn!(txn, move |txn| {
+        let packed = packed;
+        let packed_length = packed_length;
+        let mut bytes = vec![];
+        for object in collection.get_all(txn, ids)? {
+            let object = object.map_or(&[][..], |o| o.as_bytes());
+            bytes.extend_from_slice(&(object.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(object);
+        }
+        let mut bytes = bytes.into_boxed_slice();
+        *packed_length.0 = bytes.len() as u32;
+        packed.0.write(bytes.as_mut_ptr());
+        std::mem::forget(bytes);
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_free_packed(packed: *mut u8, packed_length: u32) {
+    let packed = std::slice::from_raw_parts_mut(packed, packed_length as usize);
+    drop(Box::from_raw(packed));
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn isar_put_all_by_index(
     collection: &'static IsarCollection,
@@ -191,13 +362,7 @@ pub unsafe extern "C" fn isar_delete_all(
     let ids = std::slice::from_raw_parts(ids, ids_length as usize);
     let count = UintSend(count);
     isar_try_txn!(txn, move |txn| {
-        let mut n = 0u32;
-        for id in ids {
-            if collection.delete(txn, *id)? {
-                n += 1;
-            }
-        }
-        *count.0 = n;
+        *count.0 = collection.delete_all(txn, ids)?;
         Ok(())
     })
 }
@@ -215,13 +380,7 @@ pub unsafe extern "C" fn isar_delete_all_by_index(
     let keys: Vec<IndexKey> = slice.iter().map(|k| *Box::from_raw(*k)).collect();
     let count = UintSend(count);
     isar_try_txn!(txn, move |txn| {
-        let mut n = 0u32;
-        for key in keys {
-            if collection.delete_by_index(txn, index_id, &key)? {
-                n += 1;
-            }
-        }
-        *count.0 = n;
+        *count.0 = collection.delete_all_by_index(txn, index_id, &keys)?;
         Ok(())
     })
 }
@@ -234,6 +393,91 @@ pub unsafe extern "C" fn isar_clear(
     isar_try_txn!(txn, move |txn| collection.clear(txn))
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn isar_truncate(
+    collection: &'static IsarCollection,
+    txn: &mut CIsarTxn,
+) -> i64 {
+    isar_try_txn!(txn, move |txn| collection.truncate(txn))
+}
+
+struct TombstonesJsonSend(*mut *mut c_char);
+
+unsafe impl Send for TombstonesJsonSend {}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_get_tombstones(
+    collection: &'static IsarCollection,
+    txn: &mut CIsarTxn,
+    tombstones_json: *mut *mut c_char,
+) -> i64 {
+    let tombstones_json = TombstonesJsonSend(tombstones_json);
+    isar_try_txn!(txn, move |txn| {
+        let tombstones_json = tombstones_json;
+        let tombstones = collection.tombstones(txn)?;
+        let json = serde_json::to_string(&tombstones).unwrap();
+        tombstones_json
+            .0
+            .write(CString::new(json).unwrap().into_raw());
+        Ok(())
+    })
+}
+
+/// Purges all tombstones if `deleted_before` is negative.
+#[no_mangle]
+pub unsafe extern "C" fn isar_purge_tombstones(
+    collection: &'static IsarCollection,
+    txn: &mut CIsarTxn,
+    deleted_before: i64,
+    count: &'static mut u32,
+) -> i64 {
+    let count = UintSend(count);
+    isar_try_txn!(txn, move |txn| {
+        let count = count;
+        let deleted_before = if deleted_before >= 0 {
+            Some(deleted_before as u64)
+        } else {
+            None
+        };
+        *count.0 = collection.purge_tombstones(txn, deleted_before)?;
+        Ok(())
+    })
+}
+
+/// `deleted_at` is 0 if there is no deleted object with the id.
+#[no_mangle]
+pub unsafe extern "C" fn isar_get_deleted(
+    collection: &'static IsarCollection,
+    txn: &mut CIsarTxn,
+    object: &'static mut CObject,
+    deleted_at: &'static mut u64,
+) -> i64 {
+    let deleted_at = UlongSend(deleted_at);
+    isar_try_txn!(txn, move |txn| {
+        let deleted_at = deleted_at;
+        let id = object.get_id();
+        let result = collection.get_deleted(txn, id)?;
+        *deleted_at.0 = result.as_ref().map_or(0, |(_, deleted_at)| *deleted_at);
+        object.set_object(result.map(|(object, _)| object));
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_restore(
+    collection: &'static IsarCollection,
+    txn: &mut CIsarTxn,
+    id: i64,
+    restored: &'static mut bool,
+) -> i64 {
+    let restored = BoolSend(restored);
+    isar_try_txn!(txn, move |txn| {
+        let restored = restored;
+        *restored.0 = collection.restore(txn, id)?;
+        Ok(())
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn isar_json_import(
     collection: &'static IsarCollection,
@@ -250,6 +494,104 @@ pub unsafe extern "C" fn isar_json_import(
     })
 }
 
+struct RawBytesSend(*mut *mut u8);
+
+unsafe impl Send for RawBytesSend {}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_raw_export(
+    collection: &'static IsarCollection,
+    txn: &mut CIsarTxn,
+    raw_bytes: *mut *mut u8,
+    raw_length: &'static mut u32,
+) -> i64 {
+    let raw_bytes = RawBytesSend(raw_bytes);
+    let raw_length = UintSend(raw_length);
+    isar_try_txn!(txn, move |txn| {
+        let raw_bytes = raw_bytes;
+        let raw_length = raw_length;
+        let mut bytes = collection.export_raw(txn)?.into_boxed_slice();
+        *raw_length.0 = bytes.len() as u32;
+        raw_bytes.0.write(bytes.as_mut_ptr());
+        std::mem::forget(bytes);
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_raw_import(
+    collection: &'static IsarCollection,
+    txn: &mut CIsarTxn,
+    raw_bytes: *const u8,
+    raw_length: u32,
+    count: &'static mut u32,
+) -> i64 {
+    let bytes = std::slice::from_raw_parts(raw_bytes, raw_length as usize).to_vec();
+    let count = UintSend(count);
+    isar_try_txn!(txn, move |txn| {
+        let count = count;
+        *count.0 = collection.import_raw(txn, &bytes)?;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_json_import_lenient(
+    collection: &'static IsarCollection,
+    txn: &mut CIsarTxn,
+    id_name: *const c_char,
+    json_bytes: *const u8,
+    json_length: u32,
+    defaults_bytes: *const u8,
+    defaults_length: u32,
+) -> i64 {
+    let id_name = from_c_str(id_name).unwrap();
+    let bytes = std::slice::from_raw_parts(json_bytes, json_length as usize);
+    let json: Value = serde_json::from_slice(bytes).unwrap();
+    let defaults = if defaults_length > 0 {
+        let bytes = std::slice::from_raw_parts(defaults_bytes, defaults_length as usize);
+        serde_json::from_slice(bytes).unwrap()
+    } else {
+        Map::new()
+    };
+    let options = JsonDecodeOptions {
+        lenient: true,
+        defaults,
+    };
+    isar_try_txn!(txn, move |txn| {
+        collection.import_json_with_options(txn, id_name, json, &options)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_patch(
+    collection: &'static IsarCollection,
+    txn: &mut CIsarTxn,
+    id: i64,
+    json_bytes: *const u8,
+    json_length: u32,
+    patched: &'static mut bool,
+) -> i64 {
+    let bytes = std::slice::from_raw_parts(json_bytes, json_length as usize);
+    let json: Value = serde_json::from_slice(bytes).unwrap();
+    let patched = BoolSend(patched);
+    isar_try_txn!(txn, move |txn| {
+        let patched = patched;
+        let map = json.as_object().ok_or(IsarError::InvalidJson {})?;
+        let mut changes = vec![];
+        for (name, value) in map {
+            let property = collection.properties.iter().find(|p| &p.name == name);
+            if let Some(property) = property {
+                changes.push((property, value.clone()));
+            } else {
+                illegal_arg("Property does not exist.")?;
+            }
+        }
+        *patched.0 = collection.patch(txn, id, &changes)?;
+        Ok(())
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn isar_count(
     collection: &'static IsarCollection,
@@ -288,3 +630,282 @@ pub unsafe extern "C" fn isar_verify(
     }
     isar_try_txn!(txn, move |txn| { collection.verify(txn, &objects_map) })
 }
+
+struct NdjsonImporterSend(*mut NdjsonImporter<'static>);
+
+unsafe impl Send for NdjsonImporterSend {}
+
+struct ImportErrorsSend(*mut *mut c_char);
+
+unsafe impl Send for ImportErrorsSend {}
+
+#[repr(C)]
+pub struct CImportProgress {
+    lines: u64,
+    bytes: u64,
+    imported: u64,
+}
+
+impl CImportProgress {
+    fn set(&mut self, progress: ImportProgress) {
+        self.lines = progress.lines;
+        self.bytes = progress.bytes;
+        self.imported = progress.imported;
+    }
+}
+
+struct ImportProgressSend(&'static mut CImportProgress);
+
+unsafe impl Send for ImportProgressSend {}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_ndjson_importer_create(
+    collection: &'static IsarCollection,
+    id_name: *const c_char,
+    error_policy: u8,
+) -> *mut NdjsonImporter<'static> {
+    let id_name = from_c_str(id_name).unwrap();
+    let policy = match error_policy {
+        0 => ImportErrorPolicy::Fail,
+        1 => ImportErrorPolicy::Skip,
+        _ => ImportErrorPolicy::Collect,
+    };
+    let importer = NdjsonImporter::new(collection, id_name, policy);
+    Box::into_raw(Box::new(importer))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_ndjson_importer_add_chunk(
+    importer: *mut NdjsonImporter<'static>,
+    txn: &mut CIsarTxn,
+    chunk_bytes: *const u8,
+    chunk_length: u32,
+    progress: &'static mut CImportProgress,
+) -> i64 {
+    let chunk = std::slice::from_raw_parts(chunk_bytes, chunk_length as usize).to_vec();
+    let importer = NdjsonImporterSend(importer);
+    let progress = ImportProgressSend(progress);
+    isar_try_txn!(txn, move |txn| {
+        let importer = importer;
+        let progress = progress;
+        let importer = importer.0.as_mut().unwrap();
+        let result = importer.add_chunk(txn, &chunk);
+        progress.0.set(importer.progress());
+        result
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_ndjson_importer_finish(
+    importer: *mut NdjsonImporter<'static>,
+    txn: &mut CIsarTxn,
+    progress: &'static mut CImportProgress,
+    errors_json: *mut *mut c_char,
+) -> i64 {
+    let importer = NdjsonImporterSend(importer);
+    let progress = ImportProgressSend(progress);
+    let errors_json = ImportErrorsSend(errors_json);
+    isar_try_txn!(txn, move |txn| {
+        let importer = importer;
+        let progress = progress;
+        let errors_json = errors_json;
+        let importer = importer.0.as_mut().unwrap();
+        let result = importer.finish(txn);
+        progress.0.set(importer.progress());
+        result?;
+        let json = serde_json::to_string(importer.errors()).unwrap();
+        errors_json.0.write(CString::new(json).unwrap().into_raw());
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_ndjson_importer_free(importer: *mut NdjsonImporter<'static>) {
+    let _ = Box::from_raw(importer);
+}
+
+#[repr(C)]
+pub struct CBatchOp {
+    op: u8,
+    collection: &'static IsarCollection,
+    link_id: u64,
+    target_id: i64,
+    object: CObject,
+}
+
+struct CBatchOpsSend(*mut CBatchOp);
+
+unsafe impl Send for CBatchOpsSend {}
+
+/// Ops are 0 = put, 1 = delete, 2 = link, 3 = unlink. The ids of put objects are updated.
+#[no_mangle]
+pub unsafe extern "C" fn isar_write_batch(
+    instance: &'static IsarInstance,
+    txn: &mut CIsarTxn,
+    ops: *mut CBatchOp,
+    length: u32,
+) -> i64 {
+    let ops = CBatchOpsSend(ops);
+    isar_try_txn!(txn, move |txn| {
+        let ops = ops;
+        let c_ops = std::slice::from_raw_parts_mut(ops.0, length as usize);
+        let batch = c_ops
+            .iter_mut()
+            .map(|c_op| {
+                let collection = c_op.collection;
+                let id = c_op.object.get_id();
+                match c_op.op {
+                    0 => Ok(BatchOp::Put {
+                        collection,
+                        id: if id != i64::MIN { Some(id) } else { None },
+                        object: c_op.object.get_object(),
+                    }),
+                    1 => Ok(BatchOp::Delete { collection, id }),
+                    2 => Ok(BatchOp::Link {
+                        collection,
+                        link_id: c_op.link_id,
+                        id,
+                        target_id: c_op.target_id,
+                    }),
+                    3 => Ok(BatchOp::Unlink {
+                        collection,
+                        link_id: c_op.link_id,
+                        id,
+                        target_id: c_op.target_id,
+                    }),
+                    _ => illegal_arg("Unknown batch operation."),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut ids = instance.write_batch(txn, &batch)?.into_iter();
+        drop(batch);
+        for c_op in c_ops.iter_mut().filter(|c_op| c_op.op == 0) {
+            c_op.object.set_id(ids.next().unwrap());
+        }
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_append_blob(
+    collection: &'static IsarCollection,
+    txn: &mut CIsarTxn,
+    id: i64,
+    name: *const c_char,
+    bytes: *const u8,
+    length: u32,
+) -> i64 {
+    let name = from_c_str(name).unwrap().unwrap_or_default();
+    let bytes = std::slice::from_raw_parts(bytes, length as usize);
+    isar_try_txn!(txn, move |txn| {
+        collection.append_blob(txn, id, name, bytes)
+    })
+}
+
+/// Points `bytes` to the chunk of the blob. The chunk is only valid until the transaction ends.
+/// Missing chunks have a length of zero.
+#[no_mangle]
+pub unsafe extern "C" fn isar_read_blob_chunk(
+    collection: &'static IsarCollection,
+    txn: &mut CIsarTxn,
+    id: i64,
+    name: *const c_char,
+    chunk: u64,
+    bytes: *mut *mut u8,
+    length: &'static mut u32,
+) -> i64 {
+    let name = from_c_str(name).unwrap().unwrap_or_default();
+    let bytes = RawBytesSend(bytes);
+    let length = UintSend(length);
+    isar_try_txn!(txn, move |txn| {
+        let bytes = bytes;
+        let length = length;
+        let chunk = collection.read_blob_chunk(txn, id, name, chunk)?;
+        let chunk = chunk.unwrap_or_default();
+        *length.0 = chunk.len() as u32;
+        bytes.0.write(chunk.as_ptr() as *mut u8);
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_delete_blob(
+    collection: &'static IsarCollection,
+    txn: &mut CIsarTxn,
+    id: i64,
+    name: *const c_char,
+    deleted: &'static mut bool,
+) -> i64 {
+    let name = from_c_str(name).unwrap().unwrap_or_default();
+    let deleted = BoolSend(deleted);
+    isar_try_txn!(txn, move |txn| {
+        *deleted.0 = collection.delete_blob(txn, id, name)?;
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::open_instance;
+    use crate::txn::{isar_txn_begin, isar_txn_finish};
+    use std::sync::Arc;
+
+    fn pack(objects: &[(i64, &[u8])]) -> Vec<u8> {
+        let mut packed = vec![];
+        for (id, bytes) in objects {
+            packed.extend_from_slice(&id.to_le_bytes());
+            packed.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+            packed.extend_from_slice(bytes);
+        }
+        packed
+    }
+
+    #[test]
+    fn test_put_and_get_all_packed() {
+        let instance = open_instance(
+            br#"[{"name": "col", "embedded": false, "properties": [{"name": "value", "type": "Long"}]}]"#,
+        );
+        let isar: &'static IsarInstance = unsafe { &*Arc::as_ptr(&instance) };
+        let col = &isar.collections()[0];
+        let mut ob = col.new_object_builder(None);
+        ob.write_long(col.properties[0].offset, 42);
+        let object = ob.finish().as_bytes().to_vec();
+        let packed = pack(&[(5, &object), (i64::MIN, &object)]);
+        assert!(unpack_objects(&packed[..packed.len() - 1]).is_err());
+
+        unsafe {
+            let mut txn = std::ptr::null();
+            assert_eq!(isar_txn_begin(isar, &mut txn, true, true, false, 0), 0);
+            let txn = &mut *(txn as *mut CIsarTxn);
+            let mut ids = [0; 2];
+            let (packed_ptr, packed_length) = (packed.as_ptr(), packed.len() as u32);
+            let result =
+                isar_put_all_packed(col, txn, packed_ptr, packed_length, ids.as_mut_ptr(), 1);
+            assert_ne!(result, 0);
+            let result =
+                isar_put_all_packed(col, txn, packed_ptr, packed_length, ids.as_mut_ptr(), 2);
+            assert_eq!(result, 0);
+            assert_eq!(ids[0], 5);
+            assert_ne!(ids[1], 5);
+
+            let get_ids = [ids[1], 100, 5];
+            let mut bytes = std::ptr::null_mut();
+            let length = Box::into_raw(Box::new(0u32));
+            let result =
+                isar_get_all_packed(col, txn, get_ids.as_ptr(), 3, &mut bytes, &mut *length);
+            assert_eq!(result, 0);
+            let packed = std::slice::from_raw_parts(bytes, *length as usize);
+            let mut expected = vec![];
+            for object in [&object[..], &[], &object] {
+                expected.extend_from_slice(&(object.len() as u32).to_le_bytes());
+                expected.extend_from_slice(object);
+            }
+            assert_eq!(packed, expected);
+            isar_free_packed(bytes, *length);
+            drop(Box::from_raw(length));
+            assert_eq!(isar_txn_finish(txn, true), 0);
+        }
+        assert!(instance.close_and_delete());
+    }
+}
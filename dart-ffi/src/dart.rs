@@ -1,15 +1,19 @@
-use once_cell::sync::OnceCell;
+use crate::transport::{set_transport, IsarPort, Transport};
 
-static DART_POST_C_OBJECT: OnceCell<DartPostCObjectFnType> = OnceCell::new();
+pub type DartPostCObjectFnType = extern "C" fn(port_id: IsarPort, message: *mut DartCObject) -> i8;
 
-pub fn dart_post_int(port: DartPort, value: i64) {
-    let dart_post = DART_POST_C_OBJECT.get().unwrap();
-    dart_post(port, &mut DartCObject::new(value));
-}
+struct DartTransport(DartPostCObjectFnType);
 
-pub type DartPort = i64;
+impl Transport for DartTransport {
+    fn post_int(&self, port: IsarPort, value: i64) {
+        (self.0)(port, &mut DartCObject::new(value));
+    }
 
-pub type DartPostCObjectFnType = extern "C" fn(port_id: DartPort, message: *mut DartCObject) -> i8;
+    fn post_ids(&self, port: IsarPort, ids: &[i64]) {
+        // typed data is copied when the message is posted
+        (self.0)(port, &mut DartCObject::new_int64_list(ids));
+    }
+}
 
 #[repr(C)]
 pub struct DartCObject {
@@ -24,15 +28,37 @@ impl DartCObject {
             value: DartCObjectValue { value },
         }
     }
+
+    fn new_int64_list(values: &[i64]) -> Self {
+        DartCObject {
+            ty: 7,
+            value: DartCObjectValue {
+                typed_data: DartTypedData {
+                    ty: 8,
+                    length: values.len() as isize,
+                    values: values.as_ptr() as *const u8,
+                },
+            },
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct DartTypedData {
+    ty: i32,
+    length: isize,
+    values: *const u8,
 }
 
 #[repr(C)]
 union DartCObjectValue {
     pub value: i64,
+    typed_data: DartTypedData,
     _union_align: [u64; 5usize],
 }
 
 #[no_mangle]
 pub unsafe extern "C" fn isar_connect_dart_api(ptr: DartPostCObjectFnType) {
-    let _ = DART_POST_C_OBJECT.set(ptr);
+    set_transport(Box::new(DartTransport(ptr)));
 }
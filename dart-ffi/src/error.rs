@@ -1,10 +1,10 @@
-use isar_core::error::Result;
+use isar_core::error::{IsarError, Result};
 use once_cell::sync::Lazy;
 use std::ffi::CString;
 use std::os::raw::c_char;
 use std::sync::Mutex;
 
-type ErrCounter = (Vec<(i64, String)>, i64);
+type ErrCounter = (Vec<(i64, IsarError)>, i64);
 static ERRORS: Lazy<Mutex<ErrCounter>> = Lazy::new(|| Mutex::new((vec![], 1)));
 
 pub trait DartErrCode {
@@ -20,7 +20,7 @@ impl DartErrCode for Result<()> {
                 errors.remove(0);
             }
             let err_code = *counter;
-            errors.push((err_code, err.to_string()));
+            errors.push((err_code, err));
             *counter = counter.wrapping_add(1);
             if *counter == 0 {
                 *counter = 1
@@ -59,9 +59,88 @@ macro_rules! isar_try_txn {
 pub unsafe extern "C" fn isar_get_error(err_code: i64) -> *mut c_char {
     let lock = ERRORS.lock().unwrap();
     let error = lock.0.iter().find(|(code, _)| *code == err_code);
-    if let Some((_, err_msg)) = error {
-        CString::new(err_msg.as_str()).unwrap().into_raw()
+    if let Some((_, err)) = error {
+        CString::new(err.to_string()).unwrap().into_raw()
     } else {
         std::ptr::null_mut()
     }
 }
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_get_error_unique_violation(
+    err_code: i64,
+    index_name: *mut *mut c_char,
+    id: *mut i64,
+) -> bool {
+    let lock = ERRORS.lock().unwrap();
+    let error = lock.0.iter().find(|(code, _)| *code == err_code);
+    if let Some((
+        _,
+        IsarError::UniqueViolated {
+            index,
+            id: existing_id,
+        },
+    )) = error
+    {
+        index_name.write(CString::new(index.as_str()).unwrap().into_raw());
+        id.write(*existing_id);
+        true
+    } else {
+        false
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_get_error_unique_constraint_violation(
+    err_code: i64,
+    constraint_name: *mut *mut c_char,
+    id: *mut i64,
+) -> bool {
+    let lock = ERRORS.lock().unwrap();
+    let error = lock.0.iter().find(|(code, _)| *code == err_code);
+    if let Some((
+        _,
+        IsarError::UniqueConstraintViolated {
+            constraint,
+            id: existing_id,
+        },
+    )) = error
+    {
+        constraint_name.write(CString::new(constraint.as_str()).unwrap().into_raw());
+        id.write(*existing_id);
+        true
+    } else {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr;
+
+    #[test]
+    fn test_unique_violation_details() {
+        let violated: Result<()> = Err(IsarError::UniqueViolated {
+            index: "email".to_string(),
+            id: 3,
+        });
+        let err_code = violated.into_dart_result_code();
+        let mut index_name = ptr::null_mut();
+        let mut id = 0;
+        unsafe {
+            assert!(isar_get_error_unique_violation(
+                err_code,
+                &mut index_name,
+                &mut id
+            ));
+            assert_eq!(CString::from_raw(index_name).to_str().unwrap(), "email");
+        }
+        assert_eq!(id, 3);
+
+        let other: Result<()> = Err(IsarError::DbFull {});
+        let err_code = other.into_dart_result_code();
+        let found = unsafe { isar_get_error_unique_violation(err_code, &mut index_name, &mut id) };
+        assert!(!found);
+    }
+}
@@ -2,10 +2,12 @@ use crate::from_c_str;
 use isar_core::collection::IsarCollection;
 use isar_core::error::illegal_arg;
 use isar_core::error::Result;
+use isar_core::index::geo::GeoBox;
 use isar_core::object::data_type::DataType;
 use isar_core::object::isar_object::IsarObject;
 use isar_core::object::property::Property;
 use isar_core::query::filter::*;
+use isar_core::query::NanPolicy;
 use std::os::raw::c_char;
 use std::slice;
 
@@ -85,6 +87,30 @@ pub unsafe extern "C" fn isar_filter_object(
     }
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn isar_filter_object_path(
+    collection: &IsarCollection,
+    filter: *mut *const Filter,
+    condition: *mut Filter,
+    property_ids: *const u64,
+    length: u32,
+) -> i64 {
+    isar_try! {
+        let property_ids = slice::from_raw_parts(property_ids, length as usize);
+        let mut path = vec![];
+        let mut embedded_col_id = 0;
+        for property_id in property_ids {
+            let property = get_property(collection, embedded_col_id, *property_id)?;
+            embedded_col_id = property.target_id.unwrap_or(0);
+            path.push(property);
+        }
+        let condition = *Box::from_raw(condition);
+        let query_filter = Filter::object_path(&path, condition)?;
+        let ptr = Box::into_raw(Box::new(query_filter));
+        filter.write(ptr);
+    }
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn isar_filter_link(
     collection: &IsarCollection,
@@ -257,6 +283,75 @@ pub unsafe extern "C" fn isar_filter_double(
     }
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn isar_filter_double_nan(
+    collection: &IsarCollection,
+    filter: *mut *const Filter,
+    lower: f64,
+    upper: f64,
+    include_nan: bool,
+    embedded_col_id: u64,
+    property_id: u64,
+) -> i64 {
+    let nan = if include_nan {
+        NanPolicy::Include
+    } else {
+        NanPolicy::Exclude
+    };
+    isar_try! {
+        let property = get_property(collection, embedded_col_id, property_id)?;
+        let query_filter = if property.data_type == DataType::Float || property.data_type == DataType::FloatList {
+            Filter::float_nan(property, lower as f32, upper as f32, nan)?
+        } else {
+            Filter::double_nan(property, lower, upper, nan)?
+        };
+        let ptr = Box::into_raw(Box::new(query_filter));
+        filter.write(ptr);
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_filter_geo_box(
+    collection: &IsarCollection,
+    filter: *mut *const Filter,
+    embedded_col_id: u64,
+    lat_property_id: u64,
+    lon_property_id: u64,
+    min_lat: f64,
+    min_lon: f64,
+    max_lat: f64,
+    max_lon: f64,
+) -> i64 {
+    isar_try! {
+        let lat = get_property(collection, embedded_col_id, lat_property_id)?;
+        let lon = get_property(collection, embedded_col_id, lon_property_id)?;
+        let geo_box = GeoBox::new(min_lat, min_lon, max_lat, max_lon);
+        let query_filter = Filter::geo_box(lat, lon, geo_box)?;
+        let ptr = Box::into_raw(Box::new(query_filter));
+        filter.write(ptr);
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_filter_geo_radius(
+    collection: &IsarCollection,
+    filter: *mut *const Filter,
+    embedded_col_id: u64,
+    lat_property_id: u64,
+    lon_property_id: u64,
+    center_lat: f64,
+    center_lon: f64,
+    radius: f64,
+) -> i64 {
+    isar_try! {
+        let lat = get_property(collection, embedded_col_id, lat_property_id)?;
+        let lon = get_property(collection, embedded_col_id, lon_property_id)?;
+        let query_filter = Filter::geo_radius(lat, lon, center_lat, center_lon, radius)?;
+        let ptr = Box::into_raw(Box::new(query_filter));
+        filter.write(ptr);
+    }
+}
+
 unsafe fn get_lower_str(lower: Option<Vec<u8>>, include_lower: bool) -> Option<Vec<u8>> {
     if include_lower {
         lower
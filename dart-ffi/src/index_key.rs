@@ -1,6 +1,7 @@
 use crate::from_c_str;
 use isar_core::index::index_key::IndexKey;
 use isar_core::object::isar_object::IsarObject;
+use isar_core::schema::index_schema::Collation;
 use paste::paste;
 use std::os::raw::c_char;
 
@@ -56,6 +57,41 @@ pub unsafe extern "C" fn isar_key_add_string(
     key.add_string(value, case_sensitive)
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn isar_key_add_string_prefix(
+    key: &mut IndexKey,
+    prefix: *const c_char,
+    case_sensitive: bool,
+    upper: bool,
+) {
+    let prefix = from_c_str(prefix).unwrap().unwrap();
+    if upper {
+        key.add_string_prefix_upper_bound(prefix, case_sensitive)
+    } else {
+        key.add_string_prefix(prefix, case_sensitive)
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_key_add_string_collated(
+    key: &mut IndexKey,
+    value: *const c_char,
+    case_sensitive: bool,
+    collation: u8,
+    upper: bool,
+) {
+    let value = from_c_str(value).unwrap();
+    let collation = match collation {
+        1 => Collation::CaseInsensitive,
+        _ => Collation::Binary,
+    };
+    if upper {
+        key.add_string_upper_bound(value, case_sensitive, &collation)
+    } else {
+        key.add_string_value(value, case_sensitive, &collation)
+    }
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn isar_key_add_string_hash(
     key: &mut IndexKey,
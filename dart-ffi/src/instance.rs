@@ -1,17 +1,23 @@
 use crate::app_dir::get_app_dir;
-use crate::dart::{dart_post_int, DartPort};
 use crate::error::DartErrCode;
 use crate::from_c_str;
+use crate::transport::{post_int, IsarPort};
 use crate::txn::run_async;
 use crate::txn::CIsarTxn;
-use crate::CharsSend;
+use crate::{CharsSend, UintSend};
 use isar_core::collection::IsarCollection;
 use isar_core::error::{illegal_arg, Result};
-use isar_core::instance::{CompactCondition, IsarInstance};
+use isar_core::instance::{
+    CompactCondition, Durability, EnvGeometry, IndexFillCallback, IndexFillOptions, IsarInstance,
+    OpenOptions,
+};
+use isar_core::logging::{set_log_callback, LogCallback};
 use isar_core::schema::Schema;
 use std::ffi::CString;
 use std::os::raw::c_char;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
 include!(concat!(env!("OUT_DIR"), "/version.rs"));
 
@@ -19,40 +25,135 @@ struct IsarInstanceSend(*mut *const IsarInstance);
 
 unsafe impl Send for IsarInstanceSend {}
 
+struct OpenOptionsSend(*mut OpenOptions);
+
+unsafe impl Send for OpenOptionsSend {}
+
+const MIB: usize = 1 << 20;
+
 #[no_mangle]
 pub unsafe extern "C" fn isar_version() -> i64 {
     ISAR_VERSION as i64
 }
 
+/// Incremented whenever a function of the C API changes incompatibly.
+#[no_mangle]
+pub extern "C" fn isar_c_api_version() -> u32 {
+    2
+}
+
+#[no_mangle]
+pub extern "C" fn isar_open_options_create() -> *mut OpenOptions {
+    Box::into_raw(Box::default())
+}
+
+#[no_mangle]
+pub extern "C" fn isar_open_options_set_durability(options: &mut OpenOptions, durability: u8) {
+    options.durability = match durability {
+        0 => Durability::Safe,
+        1 => Durability::NoMetaSync,
+        2 => Durability::AsyncFlush,
+        _ => Durability::NoSync,
+    };
+}
+
+#[no_mangle]
+pub extern "C" fn isar_open_options_set_compact_condition(
+    options: &mut OpenOptions,
+    min_file_size: u32,
+    min_bytes: u32,
+    min_ratio: f64,
+) {
+    options.compact_condition = Some(CompactCondition {
+        min_file_size: min_file_size as u64,
+        min_bytes: min_bytes as u64,
+        min_ratio,
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn isar_open_options_set_geometry(
+    options: &mut OpenOptions,
+    max_size_mib: u32,
+    growth_step_mib: u32,
+    shrink_threshold_mib: u32,
+) {
+    options.geometry = Some(EnvGeometry {
+        max_size: max_size_mib as usize * MIB,
+        growth_step: growth_step_mib as usize * MIB,
+        shrink_threshold: shrink_threshold_mib as usize * MIB,
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn isar_open_options_set_read_only(options: &mut OpenOptions, read_only: bool) {
+    options.read_only = read_only;
+}
+
+#[no_mangle]
+pub extern "C" fn isar_open_options_set_in_memory(options: &mut OpenOptions, in_memory: bool) {
+    options.in_memory = in_memory;
+}
+
+#[no_mangle]
+pub extern "C" fn isar_open_options_set_change_feed(options: &mut OpenOptions, change_feed: bool) {
+    options.change_feed = change_feed;
+}
+
+#[no_mangle]
+pub extern "C" fn isar_open_options_set_multi_process(
+    options: &mut OpenOptions,
+    multi_process: bool,
+) {
+    options.multi_process = multi_process;
+}
+
+/// Index fill progress is posted to `index_fill_port` in per mille.
+#[no_mangle]
+pub unsafe extern "C" fn isar_open_options_set_index_fill(
+    options: &mut OpenOptions,
+    index_fill_port: IsarPort,
+    cancel_token: *const AtomicBool,
+) {
+    let progress = if index_fill_port != 0 {
+        let callback: IndexFillCallback = Box::new(move |_, processed, total| {
+            let per_mille = (processed * 1000).checked_div(total).unwrap_or(1000);
+            post_int(index_fill_port, per_mille as i64);
+        });
+        Some(callback)
+    } else {
+        None
+    };
+    let cancel = if !cancel_token.is_null() {
+        Arc::increment_strong_count(cancel_token);
+        Some(Arc::from_raw(cancel_token))
+    } else {
+        None
+    };
+    options.index_fill = if progress.is_some() || cancel.is_some() {
+        Some(IndexFillOptions { progress, cancel })
+    } else {
+        None
+    };
+}
+
+/// Takes ownership of `options`.
 #[no_mangle]
 pub unsafe extern "C" fn isar_instance_create(
     isar: *mut *const IsarInstance,
     name: *const c_char,
     path: *const c_char,
     schema_json: *const c_char,
-    relaxed_durability: bool,
-    compact_min_file_size: u32,
-    compact_min_bytes: u32,
-    compact_min_ratio: f64,
+    options: *mut OpenOptions,
 ) -> i64 {
+    let options = *Box::from_raw(options);
     let open = || -> Result<()> {
         let name = from_c_str(name).unwrap().unwrap();
         let path = from_c_str(path).unwrap().or_else(get_app_dir);
         let schema_json = from_c_str(schema_json).unwrap().unwrap();
         let schema = Schema::from_json(schema_json.as_bytes())?;
 
-        let compact_condition = if compact_min_ratio.is_nan() {
-            None
-        } else {
-            Some(CompactCondition {
-                min_file_size: compact_min_file_size as u64,
-                min_bytes: compact_min_bytes as u64,
-                min_ratio: compact_min_ratio,
-            })
-        };
-
-        let instance =
-            IsarInstance::open(name, path, schema, relaxed_durability, compact_condition)?;
+        let instance = IsarInstance::open(name, path, schema, options)?;
         isar.write(Arc::into_raw(instance));
         Ok(())
     };
@@ -66,33 +167,49 @@ pub unsafe extern "C" fn isar_instance_create_async(
     name: *const c_char,
     path: *const c_char,
     schema_json: *const c_char,
-    relaxed_durability: bool,
-    compact_min_file_size: u32,
-    compact_min_bytes: u32,
-    compact_min_ratio: f64,
-    port: DartPort,
+    options: *mut OpenOptions,
+    port: IsarPort,
 ) {
     let isar = IsarInstanceSend(isar);
     let name = CharsSend(name);
     let path = CharsSend(path);
     let schema_json = CharsSend(schema_json);
+    let options = OpenOptionsSend(options);
     run_async(move || {
         let isar = isar;
         let name = name;
         let path = path;
         let schema_json = schema_json;
-        let result = isar_instance_create(
-            isar.0,
-            name.0,
-            path.0,
-            schema_json.0,
-            relaxed_durability,
-            compact_min_file_size,
-            compact_min_bytes,
-            compact_min_ratio,
-        );
-        dart_post_int(port, result);
+        let options = options;
+        let result = isar_instance_create(isar.0, name.0, path.0, schema_json.0, options.0);
+        post_int(port, result);
+    });
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_cancel_token_create() -> *const AtomicBool {
+    Arc::into_raw(Arc::new(AtomicBool::new(false)))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_cancel_token_cancel(token: &AtomicBool) {
+    token.store(true, Ordering::Relaxed);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_cancel_token_free(token: *const AtomicBool) {
+    drop(Arc::from_raw(token));
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_set_log_callback(callback: Option<extern "C" fn(*const c_char)>) {
+    let callback = callback.map(|callback| -> LogCallback {
+        Box::new(move |event| {
+            let message = CString::new(event.to_string()).unwrap();
+            callback(message.as_ptr());
+        })
     });
+    set_log_callback(callback);
 }
 
 #[no_mangle]
@@ -112,6 +229,21 @@ pub unsafe extern "C" fn isar_instance_get_path(isar: &'static IsarInstance) ->
     CString::new(isar.dir.as_str()).unwrap().into_raw()
 }
 
+#[no_mangle]
+pub extern "C" fn isar_instance_get_schema_hash(isar: &'static IsarInstance) -> u64 {
+    isar.schema_hash()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_instance_get_commit_sequence(
+    isar: &'static IsarInstance,
+    sequence: *mut u64,
+) -> i64 {
+    isar_try! {
+        sequence.write(isar.commit_sequence()?);
+    }
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn isar_instance_get_collection<'a>(
     isar: &'a IsarInstance,
@@ -119,7 +251,7 @@ pub unsafe extern "C" fn isar_instance_get_collection<'a>(
     collection_id: u64,
 ) -> i64 {
     isar_try! {
-        let new_collection = isar.collections.iter().find(|c| c.id == collection_id);
+        let new_collection = isar.collections().iter().find(|c| c.id == collection_id);
         if let Some(new_collection) = new_collection {
             collection.write(new_collection);
         } else {
@@ -142,18 +274,278 @@ pub unsafe extern "C" fn isar_instance_get_size(
     })
 }
 
+#[repr(C)]
+pub struct CDbStats {
+    name: *mut c_char,
+    entries: u64,
+    depth: u32,
+    branch_pages: u64,
+    leaf_pages: u64,
+    overflow_pages: u64,
+    page_size: u32,
+}
+
+struct CDbStatsSend(*mut *mut CDbStats);
+
+unsafe impl Send for CDbStatsSend {}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_collection_get_stats(
+    collection: &'static IsarCollection,
+    txn: &mut CIsarTxn,
+    stats: *mut *mut CDbStats,
+    length: &'static mut u32,
+) -> i64 {
+    let stats = CDbStatsSend(stats);
+    let length = UintSend(length);
+    isar_try_txn!(txn, move |txn| {
+        let stats = stats;
+        let length = length;
+        let mut c_stats = collection
+            .stats(txn)?
+            .into_iter()
+            .map(|s| CDbStats {
+                name: CString::new(s.name.as_str()).unwrap().into_raw(),
+                entries: s.entries,
+                depth: s.depth,
+                branch_pages: s.branch_pages,
+                leaf_pages: s.leaf_pages,
+                overflow_pages: s.overflow_pages,
+                page_size: s.page_size,
+            })
+            .collect::<Vec<_>>()
+            .into_boxed_slice();
+        *length.0 = c_stats.len() as u32;
+        stats.0.write(c_stats.as_mut_ptr());
+        std::mem::forget(c_stats);
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_free_stats(stats: *mut CDbStats, length: u32) {
+    let stats = Vec::from_raw_parts(stats, length as usize, length as usize);
+    for stat in stats {
+        let _ = CString::from_raw(stat.name);
+    }
+}
+
+struct HistogramJsonSend(*mut *mut c_char);
+
+unsafe impl Send for HistogramJsonSend {}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_collection_get_size_histogram(
+    collection: &'static IsarCollection,
+    txn: &mut CIsarTxn,
+    histogram_json: *mut *mut c_char,
+) -> i64 {
+    let histogram_json = HistogramJsonSend(histogram_json);
+    isar_try_txn!(txn, move |txn| {
+        let histogram_json = histogram_json;
+        let histogram = collection.size_histogram(txn)?;
+        let json = serde_json::to_string(&histogram).unwrap();
+        histogram_json
+            .0
+            .write(CString::new(json).unwrap().into_raw());
+        Ok(())
+    })
+}
+
+struct IndexStatsJsonSend(*mut *mut c_char);
+
+unsafe impl Send for IndexStatsJsonSend {}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_collection_get_index_stats(
+    collection: &'static IsarCollection,
+    txn: &mut CIsarTxn,
+    index_name: *const c_char,
+    stats_json: *mut *mut c_char,
+) -> i64 {
+    let index_name = from_c_str(index_name).unwrap().unwrap().to_string();
+    let stats_json = IndexStatsJsonSend(stats_json);
+    isar_try_txn!(txn, move |txn| {
+        let stats_json = stats_json;
+        let stats = collection.index_stats(txn, &index_name)?;
+        let json = serde_json::to_string(&stats).unwrap();
+        stats_json.0.write(CString::new(json).unwrap().into_raw());
+        Ok(())
+    })
+}
+
+#[repr(C)]
+pub struct CChangeFeedEntry {
+    sequence: i64,
+    collection_id: u64,
+    id: i64,
+    op: u8,
+}
+
+struct CChangeFeedSend(*mut *mut CChangeFeedEntry);
+
+unsafe impl Send for CChangeFeedSend {}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_instance_read_change_feed(
+    instance: &'static IsarInstance,
+    txn: &mut CIsarTxn,
+    from_sequence: i64,
+    limit: u32,
+    entries: *mut *mut CChangeFeedEntry,
+    length: &'static mut u32,
+) -> i64 {
+    let entries = CChangeFeedSend(entries);
+    let length = UintSend(length);
+    isar_try_txn!(txn, move |txn| {
+        let entries = entries;
+        let length = length;
+        let mut c_entries = instance
+            .read_change_feed(txn, from_sequence, limit as usize)?
+            .into_iter()
+            .map(|(sequence, e)| CChangeFeedEntry {
+                sequence,
+                collection_id: e.collection_id,
+                id: e.id.unwrap_or(0),
+                op: e.op.to_byte(),
+            })
+            .collect::<Vec<_>>()
+            .into_boxed_slice();
+        *length.0 = c_entries.len() as u32;
+        entries.0.write(c_entries.as_mut_ptr());
+        std::mem::forget(c_entries);
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_free_change_feed(entries: *mut CChangeFeedEntry, length: u32) {
+    let _ = Vec::from_raw_parts(entries, length as usize, length as usize);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_instance_trim_change_feed(
+    instance: &'static IsarInstance,
+    txn: &mut CIsarTxn,
+    to_sequence: i64,
+) -> i64 {
+    isar_try_txn!(txn, move |txn| instance.trim_change_feed(txn, to_sequence))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_instance_plan_migration(
+    name: *const c_char,
+    path: *const c_char,
+    schema_json: *const c_char,
+    plan_json: *mut *mut c_char,
+) -> i64 {
+    let plan = || -> Result<()> {
+        let name = from_c_str(name).unwrap().unwrap();
+        let path = from_c_str(path).unwrap().or_else(get_app_dir);
+        let schema_json = from_c_str(schema_json).unwrap().unwrap();
+        let schema = Schema::from_json(schema_json.as_bytes())?;
+        let path = if let Some(path) = path {
+            path
+        } else {
+            return illegal_arg("Please provide a valid directory.");
+        };
+
+        let plan = IsarInstance::plan_migration(name, path, &schema)?;
+        let json = serde_json::to_string(&plan).unwrap();
+        plan_json.write(CString::new(json).unwrap().into_raw());
+        Ok(())
+    };
+
+    plan().into_dart_result_code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_instance_list(
+    path: *const c_char,
+    names_json: *mut *mut c_char,
+) -> i64 {
+    let list = || -> Result<()> {
+        let path = from_c_str(path).unwrap().or_else(get_app_dir);
+        let path = if let Some(path) = path {
+            path
+        } else {
+            return illegal_arg("Please provide a valid directory.");
+        };
+
+        let names = IsarInstance::list_instances(path)?;
+        let json = serde_json::to_string(&names).unwrap();
+        names_json.write(CString::new(json).unwrap().into_raw());
+        Ok(())
+    };
+
+    list().into_dart_result_code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_instance_get_db_info(
+    name: *const c_char,
+    path: *const c_char,
+    info_json: *mut *mut c_char,
+) -> i64 {
+    let info = || -> Result<()> {
+        let name = from_c_str(name).unwrap().unwrap();
+        let path = from_c_str(path).unwrap().or_else(get_app_dir);
+        let path = if let Some(path) = path {
+            path
+        } else {
+            return illegal_arg("Please provide a valid directory.");
+        };
+
+        let info = IsarInstance::read_db_info(name, path)?;
+        if let Some(info) = info {
+            let json = serde_json::to_string(&info).unwrap();
+            info_json.write(CString::new(json).unwrap().into_raw());
+        } else {
+            info_json.write(std::ptr::null_mut());
+        }
+        Ok(())
+    };
+
+    info().into_dart_result_code()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_instance_rename_collection(
+    instance: &'static IsarInstance,
+    old_name: *const c_char,
+    new_name: *const c_char,
+) -> i64 {
+    isar_try! {
+        let old_name = from_c_str(old_name)?.unwrap();
+        let new_name = from_c_str(new_name)?.unwrap();
+        instance.rename_collection(old_name, new_name)?;
+    }
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn isar_instance_copy_to_file(
     instance: &'static IsarInstance,
     path: *const c_char,
-    port: DartPort,
+    port: IsarPort,
 ) {
     let path = CharsSend(path);
     run_async(move || {
         let path = path;
         let path = from_c_str(path.0).unwrap().unwrap();
         let result = instance.copy_to_file(path);
-        dart_post_int(port, result.into_dart_result_code());
+        post_int(port, result.into_dart_result_code());
+    });
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_instance_build_pending_indexes(
+    instance: &'static IsarInstance,
+    batch_size: u32,
+    port: IsarPort,
+) {
+    run_async(move || {
+        let result = instance.build_pending_indexes(batch_size as usize);
+        post_int(port, result.into_dart_result_code());
     });
 }
 
@@ -165,6 +557,152 @@ pub unsafe extern "C" fn isar_instance_verify(
     isar_try_txn!(txn, move |txn| { instance.verify(txn) })
 }
 
+struct ProblemsJsonSend(*mut *mut c_char);
+
+unsafe impl Send for ProblemsJsonSend {}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_instance_check_integrity(
+    instance: &'static IsarInstance,
+    txn: &mut CIsarTxn,
+    problems_json: *mut *mut c_char,
+) -> i64 {
+    let problems_json = ProblemsJsonSend(problems_json);
+    isar_try_txn!(txn, move |txn| {
+        let problems_json = problems_json;
+        let problems = instance.check_integrity(txn)?;
+        let json = serde_json::to_string(&problems).unwrap();
+        problems_json
+            .0
+            .write(CString::new(json).unwrap().into_raw());
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_instance_repair(
+    instance: &'static IsarInstance,
+    txn: &mut CIsarTxn,
+    problems_json: *mut *mut c_char,
+) -> i64 {
+    let problems_json = ProblemsJsonSend(problems_json);
+    isar_try_txn!(txn, move |txn| {
+        let problems_json = problems_json;
+        let problems = instance.repair(txn)?;
+        let json = serde_json::to_string(&problems).unwrap();
+        problems_json
+            .0
+            .write(CString::new(json).unwrap().into_raw());
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn isar_instance_set_validate_objects(
+    instance: &'static IsarInstance,
+    validate: bool,
+) {
+    instance.set_validate_objects(validate);
+}
+
+#[no_mangle]
+pub extern "C" fn isar_instance_set_query_memory_limit(
+    instance: &'static IsarInstance,
+    max_bytes: u64,
+) {
+    let max_bytes = if max_bytes > 0 {
+        Some(max_bytes as usize)
+    } else {
+        None
+    };
+    instance.set_query_memory_limit(max_bytes);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_instance_set_query_spill(
+    instance: &'static IsarInstance,
+    enabled: bool,
+    dir: *const c_char,
+) -> i64 {
+    isar_try! {
+        let dir = from_c_str(dir)?;
+        instance.set_query_spill(enabled, dir);
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_instance_set_max_read_txn_age(
+    instance: &'static IsarInstance,
+    max_age_ms: u64,
+) {
+    let max_age = if max_age_ms > 0 {
+        Some(Duration::from_millis(max_age_ms))
+    } else {
+        None
+    };
+    instance.set_max_read_txn_age(max_age);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_instance_get_reader_info(
+    instance: &'static IsarInstance,
+    readers_json: *mut *mut c_char,
+) -> i64 {
+    isar_try! {
+        let readers = instance.reader_info()?;
+        let json = serde_json::to_string(&readers).unwrap();
+        readers_json.write(CString::new(json).unwrap().into_raw());
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_instance_get_perf_counters(
+    instance: &'static IsarInstance,
+    counters_json: *mut *mut c_char,
+) {
+    let counters = instance.perf_counters();
+    let json = serde_json::to_string(&counters).unwrap();
+    counters_json.write(CString::new(json).unwrap().into_raw());
+}
+
+#[no_mangle]
+pub extern "C" fn isar_instance_set_cursor_tracking(
+    instance: &'static IsarInstance,
+    enabled: bool,
+) {
+    instance.set_cursor_tracking(enabled);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_instance_get_cursor_stats(
+    instance: &'static IsarInstance,
+    stats_json: *mut *mut c_char,
+) {
+    let stats = instance.cursor_stats();
+    let json = serde_json::to_string(&stats).unwrap();
+    stats_json.write(CString::new(json).unwrap().into_raw());
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_instance_reset_stale_readers(
+    instance: &'static IsarInstance,
+    count: &mut u32,
+) -> i64 {
+    isar_try! {
+        *count = instance.reset_stale_readers()?;
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_instance_poll_external_changes(
+    instance: &'static IsarInstance,
+    changed: &mut bool,
+) -> i64 {
+    isar_try! {
+        *changed = instance.poll_external_changes()?;
+    }
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn isar_get_offsets(
     collection: &IsarCollection,
@@ -183,3 +721,73 @@ pub unsafe extern "C" fn isar_get_offsets(
     let property = properties.iter().max_by_key(|p| p.offset);
     property.map_or(2, |p| p.offset + p.data_type.get_static_size()) as u32
 }
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_instance_truncate_collection(
+    instance: &'static IsarInstance,
+    collection: &'static IsarCollection,
+    shrink: bool,
+) -> i64 {
+    isar_try! {
+        instance.truncate_collection(collection, shrink)?;
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_instance_warm_up(instance: &'static IsarInstance) -> i64 {
+    isar_try! {
+        instance.warm_up()?;
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_instance_set_object_cache_size(
+    instance: &'static IsarInstance,
+    collection: &'static IsarCollection,
+    max_bytes: u64,
+) -> i64 {
+    isar_try! {
+        instance.set_object_cache_size(collection, max_bytes as usize)?;
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_instance_flush(instance: &'static IsarInstance) -> i64 {
+    isar_try! {
+        instance.flush()?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr;
+
+    #[test]
+    fn test_open_without_dart() {
+        assert!(isar_c_api_version() > 0);
+        let dir = std::env::temp_dir().join(format!("c_api_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let name = CString::new("c_api").unwrap();
+        let path = CString::new(dir.to_str().unwrap()).unwrap();
+        let schema = CString::new(r#"[{"name": "col", "embedded": false, "properties": []}]"#);
+        let schema = schema.unwrap();
+
+        unsafe {
+            let options = isar_open_options_create();
+            isar_open_options_set_durability(&mut *options, 1);
+            let mut isar = ptr::null();
+            let result = isar_instance_create(
+                &mut isar,
+                name.as_ptr(),
+                path.as_ptr(),
+                schema.as_ptr(),
+                options,
+            );
+            assert_eq!(result, 0);
+            let instance_path = CString::from_raw(isar_instance_get_path(&*isar));
+            assert_eq!(instance_path, path);
+            assert!(isar_instance_close_and_delete(isar));
+        }
+    }
+}
@@ -20,6 +20,7 @@ pub mod instance;
 pub mod link;
 pub mod query;
 pub mod query_aggregation;
+pub mod transport;
 pub mod txn;
 pub mod watchers;
 
@@ -38,6 +39,10 @@ pub struct UintSend(&'static mut u32);
 
 unsafe impl Send for UintSend {}
 
+pub struct UlongSend(&'static mut u64);
+
+unsafe impl Send for UlongSend {}
+
 pub struct BoolSend(&'static mut bool);
 
 unsafe impl Send for BoolSend {}
@@ -76,3 +81,21 @@ pub unsafe extern "C" fn isar_free_word_boundaries(boundaries: *mut u32, word_co
 pub unsafe extern "C" fn isar_free_string(string: *mut c_char) {
     let _ = CString::from_raw(string);
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use isar_core::instance::{IsarInstance, OpenOptions};
+    use isar_core::schema::Schema;
+    use std::sync::Arc;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    /// Opens a new instance with the JSON schema in a new temporary directory.
+    pub fn open_instance(schema: &[u8]) -> Arc<IsarInstance> {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        let name = format!("ffi_test_{}", nanos.as_nanos());
+        let dir = std::env::temp_dir().join(&name);
+        std::fs::create_dir_all(&dir).unwrap();
+        let schema = Schema::from_json(schema).unwrap();
+        IsarInstance::open(&name, dir.to_str(), schema, OpenOptions::default()).unwrap()
+    }
+}
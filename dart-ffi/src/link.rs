@@ -1,7 +1,10 @@
+use crate::from_c_str;
 use crate::txn::CIsarTxn;
+use crate::UintSend;
 use isar_core::collection::IsarCollection;
 use isar_core::error::Result;
 use itertools::Itertools;
+use std::os::raw::c_char;
 
 #[no_mangle]
 pub unsafe extern "C" fn isar_link(
@@ -44,6 +47,52 @@ pub unsafe extern "C" fn isar_link_unlink_all(
     })
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn isar_link_insert_at(
+    collection: &'static IsarCollection,
+    txn: &mut CIsarTxn,
+    link_id: u64,
+    id: i64,
+    target_id: i64,
+    index: u32,
+) -> i64 {
+    isar_try_txn!(txn, move |txn| -> Result<()> {
+        collection.link_at(txn, link_id, id, target_id, index as usize)?;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_link_move(
+    collection: &'static IsarCollection,
+    txn: &mut CIsarTxn,
+    link_id: u64,
+    id: i64,
+    target_id: i64,
+    index: u32,
+) -> i64 {
+    isar_try_txn!(txn, move |txn| -> Result<()> {
+        collection.move_link(txn, link_id, id, target_id, index as usize)?;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_link_count(
+    collection: &'static IsarCollection,
+    txn: &mut CIsarTxn,
+    link_id: u64,
+    id: i64,
+    count: &'static mut u32,
+) -> i64 {
+    let count = UintSend(count);
+    isar_try_txn!(txn, move |txn| -> Result<()> {
+        let count = count;
+        *count.0 = collection.link_count(txn, link_id, id)? as u32;
+        Ok(())
+    })
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn isar_link_update_all(
     collection: &'static IsarCollection,
@@ -60,16 +109,9 @@ pub unsafe extern "C" fn isar_link_update_all(
         if replace {
             collection.unlink_all(txn, link_id, id)?;
         }
-        for target_id in ids.iter().take(link_count as usize) {
-            collection.link(txn, link_id, id, *target_id)?;
-        }
-        for target_id in ids
-            .iter()
-            .skip(link_count as usize)
-            .take(unlink_count as usize)
-        {
-            collection.unlink(txn, link_id, id, *target_id)?;
-        }
+        let (link_ids, unlink_ids) = ids.split_at(link_count as usize);
+        collection.link_all(txn, link_id, id, link_ids)?;
+        collection.unlink_all_targets(txn, link_id, id, unlink_ids)?;
         Ok(())
     })
 }
@@ -89,3 +131,15 @@ pub unsafe extern "C" fn isar_link_verify(
         Ok(())
     })
 }
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_get_link_id(
+    collection: &IsarCollection,
+    name: *const c_char,
+    link_id: &mut u64,
+) -> i64 {
+    isar_try! {
+        let name = from_c_str(name)?.unwrap();
+        *link_id = collection.link_id(name)?;
+    }
+}
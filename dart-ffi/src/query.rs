@@ -3,11 +3,16 @@ use crate::filter::get_property;
 use crate::txn::CIsarTxn;
 use crate::{from_c_str, UintSend};
 use isar_core::collection::IsarCollection;
+use isar_core::error::Result;
+use isar_core::index::geo::GeoBox;
 use isar_core::index::index_key::IndexKey;
 use isar_core::query::filter::Filter;
 use isar_core::query::query_builder::QueryBuilder;
-use isar_core::query::{Query, Sort};
+use isar_core::query::{Case, NanPolicy, Query, Sort};
+use isar_core::txn::IsarTxn;
 use std::os::raw::c_char;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
 
 #[no_mangle]
 pub extern "C" fn isar_qb_create(collection: &IsarCollection) -> *mut QueryBuilder {
@@ -53,6 +58,89 @@ pub unsafe extern "C" fn isar_qb_add_index_where_clause(
     }
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn isar_qb_add_string_prefix_where_clause(
+    builder: &mut QueryBuilder,
+    index_id: u64,
+    prefix: *const c_char,
+    sort_asc: bool,
+    skip_duplicates: bool,
+) -> i64 {
+    let sort = if sort_asc {
+        Sort::Ascending
+    } else {
+        Sort::Descending
+    };
+    isar_try! {
+        let prefix = from_c_str(prefix)?.unwrap();
+        builder.add_string_prefix_where_clause(index_id, &[], prefix, sort, skip_duplicates)?;
+    }
+}
+
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn isar_qb_add_float_where_clause(
+    builder: &mut QueryBuilder,
+    index_id: u64,
+    lower: f64,
+    include_lower: bool,
+    upper: f64,
+    include_upper: bool,
+    include_nan: bool,
+    sort_asc: bool,
+    skip_duplicates: bool,
+) -> i64 {
+    let sort = if sort_asc {
+        Sort::Ascending
+    } else {
+        Sort::Descending
+    };
+    let nan = if include_nan {
+        NanPolicy::Include
+    } else {
+        NanPolicy::Exclude
+    };
+    isar_try! {
+        builder.add_float_where_clause(
+            index_id,
+            &[],
+            Some((lower, include_lower)),
+            Some((upper, include_upper)),
+            nan,
+            sort,
+            skip_duplicates,
+        )?;
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_qb_add_geo_box_where_clause(
+    builder: &mut QueryBuilder,
+    index_id: u64,
+    min_lat: f64,
+    min_lon: f64,
+    max_lat: f64,
+    max_lon: f64,
+) -> i64 {
+    isar_try! {
+        let geo_box = GeoBox::new(min_lat, min_lon, max_lat, max_lon);
+        builder.add_geo_box_where_clause(index_id, geo_box)?;
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_qb_add_geo_radius_where_clause(
+    builder: &mut QueryBuilder,
+    index_id: u64,
+    lat: f64,
+    lon: f64,
+    radius: f64,
+) -> i64 {
+    isar_try! {
+        builder.add_geo_radius_where_clause(index_id, lat, lon, radius)?;
+    }
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn isar_qb_add_link_where_clause(
     builder: &mut QueryBuilder,
@@ -65,6 +153,24 @@ pub unsafe extern "C" fn isar_qb_add_link_where_clause(
     }
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn isar_qb_use_index(
+    builder: &mut QueryBuilder,
+    index_name: *const c_char,
+) -> i64 {
+    isar_try! {
+        let index_name = from_c_str(index_name)?.unwrap();
+        builder.use_index(index_name)?;
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_qb_no_index(builder: &mut QueryBuilder) -> i64 {
+    isar_try! {
+        builder.no_index()?;
+    }
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn isar_qb_set_filter(builder: &mut QueryBuilder, filter: *mut Filter) {
     let filter = *Box::from_raw(filter);
@@ -88,6 +194,25 @@ pub unsafe extern "C" fn isar_qb_add_sort_by(
     }
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn isar_qb_add_link_sort_by(
+    builder: &mut QueryBuilder,
+    target_collection: &IsarCollection,
+    link_id: u64,
+    property_id: u64,
+    asc: bool,
+) -> i64 {
+    let sort = if asc {
+        Sort::Ascending
+    } else {
+        Sort::Descending
+    };
+    isar_try! {
+        let property = get_property(target_collection, 0, property_id)?;
+        builder.add_link_sort(link_id, property, sort)?;
+    }
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn isar_qb_add_distinct_by(
     builder: &mut QueryBuilder,
@@ -96,7 +221,12 @@ pub unsafe extern "C" fn isar_qb_add_distinct_by(
 ) -> i64 {
     isar_try! {
         let property = get_property(builder.collection, 0, property_id)?;
-            builder.add_distinct(property, case_sensitive);
+        let case = if case_sensitive {
+            Case::Sensitive
+        } else {
+            Case::Insensitive
+        };
+        builder.add_distinct(property, case);
     }
 }
 
@@ -116,17 +246,46 @@ pub unsafe extern "C" fn isar_qb_set_offset_limit(
     builder.set_limit(limit);
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn isar_qb_set_cancel_token(
+    builder: &mut QueryBuilder,
+    cancel_token: *const AtomicBool,
+) {
+    Arc::increment_strong_count(cancel_token);
+    builder.set_cancel_token(Arc::from_raw(cancel_token));
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn isar_qb_build(builder: *mut QueryBuilder) -> *mut Query {
     let query = Box::from_raw(builder).build();
     Box::into_raw(Box::new(query))
 }
 
+/// Can be called from any thread while the query is executed.
+#[no_mangle]
+pub extern "C" fn isar_query_cancel(query: &Query) {
+    query.cancel();
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn isar_q_free(query: *mut Query) {
     let _ = Box::from_raw(query);
 }
 
+fn find_objects(query: &Query, txn: &mut IsarTxn, limit: u32) -> Result<Vec<CObject>> {
+    let mut objects = vec![];
+    let mut count = 0;
+    query.find_while(txn, |id, object| {
+        let mut raw_obj = CObject::new();
+        raw_obj.set_id(id);
+        raw_obj.set_object(Some(object));
+        objects.push(raw_obj);
+        count += 1;
+        count < limit
+    })?;
+    Ok(objects)
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn isar_q_find(
     query: &'static Query,
@@ -135,18 +294,63 @@ pub unsafe extern "C" fn isar_q_find(
     limit: u32,
 ) -> i64 {
     isar_try_txn!(txn, move |txn| {
-        let mut objects = vec![];
-        let mut count = 0;
-        query.find_while(txn, |id, object| {
+        result.fill_from_vec(find_objects(query, txn, limit)?);
+        Ok(())
+    })
+}
+
+/// Like `isar_q_find` but `copy` returns objects that stay valid after the transaction is
+/// finished until the set is freed. Otherwise the objects point into the memory of the
+/// transaction and it cannot be finished until the set is freed. Objects of write transactions
+/// are always copied because later writes may reuse their pages.
+#[no_mangle]
+pub unsafe extern "C" fn isar_q_find_with_mode(
+    query: &'static Query,
+    txn: &mut CIsarTxn,
+    result: &'static mut CObjectSet,
+    limit: u32,
+    copy: bool,
+) -> i64 {
+    let txn_pins = txn.pins().clone();
+    isar_try_txn!(txn, move |txn| {
+        result.fill_from_vec(find_objects(query, txn, limit)?);
+        if copy || txn.is_write() {
+            result.copy_objects();
+        } else {
+            result.pin_txn(txn_pins);
+        }
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_q_find_joined(
+    query: &'static Query,
+    collection: &'static IsarCollection,
+    txn: &mut CIsarTxn,
+    link_id: u64,
+    sources: &'static mut CObjectSet,
+    targets: &'static mut CObjectSet,
+) -> i64 {
+    isar_try_txn!(txn, move |txn| {
+        let mut source_objects = vec![];
+        let mut target_objects = vec![];
+        for ((id, object), (target_id, target)) in
+            query.find_all_joined(txn, collection, link_id)?
+        {
             let mut raw_obj = CObject::new();
             raw_obj.set_id(id);
             raw_obj.set_object(Some(object));
-            objects.push(raw_obj);
-            count += 1;
-            count < limit
-        })?;
+            source_objects.push(raw_obj);
 
-        result.fill_from_vec(objects);
+            let mut raw_obj = CObject::new();
+            raw_obj.set_id(target_id);
+            raw_obj.set_object(Some(target));
+            target_objects.push(raw_obj);
+        }
+
+        sources.fill_from_vec(source_objects);
+        targets.fill_from_vec(target_objects);
         Ok(())
     })
 }
@@ -210,3 +414,44 @@ pub unsafe extern "C" fn isar_q_export_json(
 pub unsafe extern "C" fn isar_free_json(json_bytes: *mut u8, json_length: u32) {
     Vec::from_raw_parts(json_bytes, json_length as usize, json_length as usize);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::c_object_set::isar_free_c_object_set;
+    use crate::tests::open_instance;
+    use crate::txn::{isar_txn_begin, isar_txn_finish};
+    use isar_core::instance::IsarInstance;
+
+    #[test]
+    fn test_zero_copy_set_pins_txn() {
+        let instance = open_instance(br#"[{"name": "col", "embedded": false, "properties": []}]"#);
+        let isar: &'static IsarInstance = unsafe { &*Arc::as_ptr(&instance) };
+        let col = &isar.collections()[0];
+        let mut txn = isar.begin_txn(true, false).unwrap();
+        for _ in 0..2 {
+            let ob = col.new_object_builder(None);
+            col.put(&mut txn, None, ob.finish()).unwrap();
+        }
+        txn.commit().unwrap();
+
+        unsafe {
+            let query = &*isar_qb_build(isar_qb_create(col));
+            let mut txn = std::ptr::null();
+            assert_eq!(isar_txn_begin(isar, &mut txn, true, false, false, 0), 0);
+            let txn = txn as *mut CIsarTxn;
+            let set: *mut CObjectSet = Box::into_raw(Box::new(std::mem::zeroed()));
+            assert_eq!(
+                isar_q_find_with_mode(query, &mut *txn, &mut *set, 10, false),
+                0
+            );
+            assert_eq!((*set).get_length(), 2);
+            assert_ne!(isar_txn_finish(txn, false), 0);
+
+            isar_free_c_object_set(&mut *set);
+            assert_eq!(isar_txn_finish(txn, false), 0);
+            drop(Box::from_raw(set));
+        }
+        assert!(instance.close_and_delete());
+    }
+}
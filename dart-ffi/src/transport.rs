@@ -0,0 +1,83 @@
+use once_cell::sync::OnceCell;
+
+/// Identifies the receiver of notifications. For Dart this is the id of a `SendPort`, other
+/// embedders can use any value that is passed back to their callback.
+pub type IsarPort = i64;
+
+/// Delivers watcher events and results of async operations to the embedder.
+pub trait Transport: Send + Sync {
+    fn post_int(&self, port: IsarPort, value: i64);
+
+    /// Posts a list of ids. The embedder has to copy the ids during the call.
+    fn post_ids(&self, port: IsarPort, ids: &[i64]);
+}
+
+static TRANSPORT: OnceCell<Box<dyn Transport>> = OnceCell::new();
+
+pub(crate) fn set_transport(transport: Box<dyn Transport>) {
+    let _ = TRANSPORT.set(transport);
+}
+
+pub fn post_int(port: IsarPort, value: i64) {
+    if let Some(transport) = TRANSPORT.get() {
+        transport.post_int(port, value);
+    }
+}
+
+pub fn post_ids(port: IsarPort, ids: &[i64]) {
+    if let Some(transport) = TRANSPORT.get() {
+        transport.post_ids(port, ids);
+    }
+}
+
+pub type IsarPostCallback = extern "C" fn(port: IsarPort, value: i64);
+
+pub type IsarPostIdsCallback = extern "C" fn(port: IsarPort, ids: *const i64, length: u32);
+
+struct CallbackTransport(IsarPostCallback, IsarPostIdsCallback);
+
+impl Transport for CallbackTransport {
+    fn post_int(&self, port: IsarPort, value: i64) {
+        (self.0)(port, value)
+    }
+
+    fn post_ids(&self, port: IsarPort, ids: &[i64]) {
+        (self.1)(port, ids.as_ptr(), ids.len() as u32)
+    }
+}
+
+/// Connects an embedder that is not using Dart. The callbacks may be called from any thread.
+#[no_mangle]
+pub unsafe extern "C" fn isar_connect_callback(
+    callback: IsarPostCallback,
+    ids_callback: IsarPostIdsCallback,
+) {
+    set_transport(Box::new(CallbackTransport(callback, ids_callback)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    static POSTED: Mutex<Vec<(IsarPort, Vec<i64>)>> = Mutex::new(vec![]);
+
+    extern "C" fn record_int(port: IsarPort, value: i64) {
+        POSTED.lock().unwrap().push((port, vec![value]));
+    }
+
+    extern "C" fn record_ids(port: IsarPort, ids: *const i64, length: u32) {
+        let ids = unsafe { std::slice::from_raw_parts(ids, length as usize) };
+        POSTED.lock().unwrap().push((port, ids.to_vec()));
+    }
+
+    #[test]
+    fn test_callback_transport() {
+        let transport: Box<dyn Transport> = Box::new(CallbackTransport(record_int, record_ids));
+        transport.post_int(1, 42);
+        transport.post_ids(2, &[3, 4, 5]);
+        transport.post_ids(3, &[]);
+        let posted = POSTED.lock().unwrap();
+        assert_eq!(*posted, [(1, vec![42]), (2, vec![3, 4, 5]), (3, vec![])]);
+    }
+}
@@ -1,14 +1,16 @@
-use crate::dart::{dart_post_int, DartPort};
 use crate::error::DartErrCode;
-use isar_core::error::{IsarError, Result};
+use crate::transport::{post_int, IsarPort};
+use isar_core::error::{illegal_arg, IsarError, Result};
 use isar_core::instance::IsarInstance;
 use isar_core::txn::IsarTxn;
 use once_cell::sync::Lazy;
 use std::borrow::BorrowMut;
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::mpsc;
 use std::sync::mpsc::{Receiver, Sender};
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::time::Duration;
 use threadpool::{Builder, ThreadPool};
 
 static THREAD_POOL: Lazy<Mutex<ThreadPool>> =
@@ -27,7 +29,7 @@ pub unsafe extern "C" fn isar_txn_begin(
     sync: bool,
     write: bool,
     silent: bool,
-    port: DartPort,
+    port: IsarPort,
 ) -> i64 {
     isar_try! {
         let new_txn = if sync {
@@ -40,26 +42,64 @@ pub unsafe extern "C" fn isar_txn_begin(
     }
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn isar_txn_begin_snapshot(
+    isar: &'static IsarInstance,
+    txn: *mut *const CIsarTxn,
+    warn_after_ms: u64,
+    port: IsarPort,
+) -> i64 {
+    isar_try! {
+        let warn_after = Duration::from_millis(warn_after_ms);
+        let new_txn = CIsarTxn::begin_async_with(move || isar.begin_snapshot(warn_after), port);
+        let txn_ptr = Box::into_raw(Box::new(new_txn));
+        txn.write(txn_ptr);
+    }
+}
+
+/// Fails without finishing the transaction if object sets still point into its memory.
 #[no_mangle]
 pub unsafe extern "C" fn isar_txn_finish(txn: *mut CIsarTxn, commit: bool) -> i64 {
-    let txn = Box::from_raw(txn);
     isar_try! {
+        if (*txn).pins().load(Ordering::SeqCst) > 0 {
+            illegal_arg("The transaction is still used by object sets.")?;
+        }
+        let txn = Box::from_raw(txn);
         txn.finish(commit)?;
     }
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn isar_txn_begin_nested(txn: &mut CIsarTxn) -> i64 {
+    isar_try_txn!(txn, move |txn| txn.begin_nested())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_txn_finish_nested(txn: &mut CIsarTxn, commit: bool) -> i64 {
+    isar_try_txn!(txn, move |txn| {
+        if commit {
+            txn.commit_nested()
+        } else {
+            txn.rollback_nested()
+        }
+    })
+}
+
 pub struct IsarTxnSend(IsarTxn<'static>);
 
 unsafe impl Send for IsarTxnSend {}
 
+/// `pins` counts the object sets that point into the memory of the transaction.
 pub enum CIsarTxn {
     Sync {
         txn: Option<IsarTxn<'static>>,
+        pins: Arc<AtomicU32>,
     },
     Async {
         tx: Sender<AsyncJob>,
-        port: DartPort,
+        port: IsarPort,
         txn: Arc<Mutex<Option<IsarTxnSend>>>,
+        pins: Arc<AtomicU32>,
     },
 }
 
@@ -67,6 +107,7 @@ impl CIsarTxn {
     fn begin_sync(isar: &'static IsarInstance, write: bool, silent: bool) -> Result<CIsarTxn> {
         let sync_txn = CIsarTxn::Sync {
             txn: Some(isar.begin_txn(write, silent)?),
+            pins: Arc::new(AtomicU32::new(0)),
         };
         Ok(sync_txn)
     }
@@ -75,17 +116,25 @@ impl CIsarTxn {
         isar: &'static IsarInstance,
         write: bool,
         silent: bool,
-        port: DartPort,
+        port: IsarPort,
     ) -> CIsarTxn {
+        Self::begin_async_with(move || isar.begin_txn(write, silent), port)
+    }
+
+    /// Begins the transaction on a worker thread that runs all jobs of the transaction.
+    fn begin_async_with<F>(begin: F, port: IsarPort) -> CIsarTxn
+    where
+        F: FnOnce() -> Result<IsarTxn<'static>> + Send + 'static,
+    {
         let (tx, rx): (Sender<AsyncJob>, Receiver<AsyncJob>) = mpsc::channel();
         let txn = Arc::new(Mutex::new(None));
         let txn_clone = txn.clone();
         run_async(move || {
-            let new_txn = isar.begin_txn(write, silent);
+            let new_txn = begin();
             match new_txn {
                 Ok(new_txn) => {
                     txn_clone.lock().unwrap().replace(IsarTxnSend(new_txn));
-                    dart_post_int(port, 0);
+                    post_int(port, 0);
                     loop {
                         let (job, stop) = rx.recv().unwrap();
                         job();
@@ -95,23 +144,34 @@ impl CIsarTxn {
                     }
                 }
                 Err(e) => {
-                    dart_post_int(port, Err(e).into_dart_result_code());
+                    post_int(port, Err(e).into_dart_result_code());
                 }
             }
         });
 
-        CIsarTxn::Async { tx, port, txn }
+        CIsarTxn::Async {
+            tx,
+            port,
+            txn,
+            pins: Arc::new(AtomicU32::new(0)),
+        }
+    }
+
+    pub fn pins(&self) -> &Arc<AtomicU32> {
+        match self {
+            CIsarTxn::Sync { pins, .. } | CIsarTxn::Async { pins, .. } => pins,
+        }
     }
 
     pub fn exec_async_internal<F: FnOnce() -> Result<()> + Send + 'static>(
         job: F,
-        port: DartPort,
+        port: IsarPort,
         tx: Sender<AsyncJob>,
         stop: bool,
     ) {
         let handle_response_job = move || {
             let result = job().into_dart_result_code();
-            dart_post_int(port, result as i64);
+            post_int(port, result as i64);
         };
         tx.send((Box::new(handle_response_job), stop)).unwrap();
     }
@@ -121,14 +181,14 @@ impl CIsarTxn {
         job: Box<dyn FnOnce(&mut IsarTxn) -> Result<()> + Send + 'static>,
     ) -> Result<()> {
         match self.borrow_mut() {
-            CIsarTxn::Sync { ref mut txn } => {
+            CIsarTxn::Sync { ref mut txn, .. } => {
                 if let Some(ref mut txn) = txn {
                     job(txn)
                 } else {
                     Err(IsarError::TransactionClosed {})
                 }
             }
-            CIsarTxn::Async { txn, tx, port } => {
+            CIsarTxn::Async { txn, tx, port, .. } => {
                 let txn = txn.clone();
                 let job = move || -> Result<()> {
                     let mut lock = txn.lock().unwrap();
@@ -146,7 +206,7 @@ impl CIsarTxn {
 
     pub fn finish(self, commit: bool) -> Result<()> {
         match self {
-            CIsarTxn::Sync { mut txn } => {
+            CIsarTxn::Sync { mut txn, .. } => {
                 if let Some(txn) = txn.take() {
                     if commit {
                         txn.commit()
@@ -158,7 +218,7 @@ impl CIsarTxn {
                     Err(IsarError::TransactionClosed {})
                 }
             }
-            CIsarTxn::Async { txn, tx, port } => {
+            CIsarTxn::Async { txn, tx, port, .. } => {
                 let txn = txn.clone();
                 let job = move || -> Result<()> {
                     let mut lock = txn.lock().unwrap();
@@ -1,20 +1,55 @@
+use isar_core::change_journal::ChangeJournalEntry;
 use isar_core::collection::IsarCollection;
 use isar_core::instance::IsarInstance;
 use isar_core::query::Query;
+use isar_core::watch::lazy_watcher::LazyQueryWatcher;
+use isar_core::watch::query_diff::QueryDiff;
 use isar_core::watch::WatchHandle;
-use crate::dart::{dart_post_int, DartPort};
+use std::ffi::CString;
+use std::os::raw::c_char;
+use std::sync::Arc;
+use std::time::Duration;
+use crate::transport::{post_ids, post_int, IsarPort};
+use crate::filter::get_property;
+
+fn get_debounce(debounce_ms: u32) -> Option<Duration> {
+    if debounce_ms > 0 {
+        Some(Duration::from_millis(debounce_ms as u64))
+    } else {
+        None
+    }
+}
 
 #[no_mangle]
 pub extern "C" fn isar_watch_collection(
     isar: &IsarInstance,
     collection: &IsarCollection,
-    port: DartPort,
+    port: IsarPort,
+    debounce_ms: u32,
 ) -> *mut WatchHandle {
     let handle = isar.watch_collection(
         collection,
-        Box::new(move ||  {
-            dart_post_int(port, 1);
+        Box::new(move |sequence| {
+            post_int(port, sequence as i64);
+        }),
+        get_debounce(debounce_ms),
+    );
+    Box::into_raw(Box::new(handle))
+}
+
+#[no_mangle]
+pub extern "C" fn isar_watch_collection_clear(
+    isar: &IsarInstance,
+    collection: &IsarCollection,
+    port: IsarPort,
+    debounce_ms: u32,
+) -> *mut WatchHandle {
+    let handle = isar.watch_collection_clear(
+        collection,
+        Box::new(move |sequence| {
+            post_int(port, sequence as i64);
         }),
+        get_debounce(debounce_ms),
     );
     Box::into_raw(Box::new(handle))
 }
@@ -24,36 +59,242 @@ pub unsafe extern "C" fn isar_watch_object(
     isar: &IsarInstance,
     collection: &IsarCollection,
     id: i64,
-    port: DartPort,
+    port: IsarPort,
+    debounce_ms: u32,
 ) -> *mut WatchHandle {
     let handle = isar.watch_object(
         collection,
         id,
-        Box::new(move || {
-            dart_post_int(port, 1);
+        Box::new(move |sequence| {
+            post_int(port, sequence as i64);
         }),
+        get_debounce(debounce_ms),
     );
     Box::into_raw(Box::new(handle))
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn isar_watch_property(
+    isar: &IsarInstance,
+    collection: &IsarCollection,
+    id: i64,
+    property_id: u64,
+    port: IsarPort,
+    debounce_ms: u32,
+    handle: *mut *mut WatchHandle,
+) -> i64 {
+    isar_try! {
+        let property = get_property(collection, 0, property_id)?;
+        let watch_handle = isar.watch_property(
+            collection,
+            id,
+            property,
+            Box::new(move |sequence| {
+                post_int(port, sequence as i64);
+            }),
+            get_debounce(debounce_ms),
+        );
+        handle.write(Box::into_raw(Box::new(watch_handle)));
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn isar_watch_query(
     isar: &IsarInstance,
     collection: &IsarCollection,
     query: &Query,
-    port: DartPort,
+    port: IsarPort,
+    debounce_ms: u32,
 ) -> *mut WatchHandle {
     let handle = isar.watch_query(
         collection,
         query.clone(),
-        Box::new(move ||  {
-            dart_post_int(port, 1);
+        Box::new(move |sequence| {
+            post_int(port, sequence as i64);
         }),
+        get_debounce(debounce_ms),
     );
     Box::into_raw(Box::new(handle))
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn isar_watch_query_lazy(
+    isar: &IsarInstance,
+    collection: &IsarCollection,
+    query: &Query,
+    watcher: *mut *const LazyQueryWatcher,
+) -> *mut WatchHandle {
+    let (handle, lazy_watcher) = isar.watch_query_lazy(collection, query.clone());
+    watcher.write(Arc::into_raw(lazy_watcher));
+    Box::into_raw(Box::new(handle))
+}
+
+#[no_mangle]
+pub extern "C" fn isar_lazy_watcher_poll(watcher: &LazyQueryWatcher) -> bool {
+    watcher.poll()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_free_lazy_watcher(watcher: *const LazyQueryWatcher) {
+    let _ = Arc::from_raw(watcher);
+}
+
+/// Encodes the diff as `[added count, removed count, ...added, ...removed, ...changed]`.
+fn diff_message(diff: QueryDiff) -> Vec<i64> {
+    let mut message = vec![diff.added.len() as i64, diff.removed.len() as i64];
+    message.extend(diff.added);
+    message.extend(diff.removed);
+    message.extend(diff.changed);
+    message
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_watch_query_diff(
+    isar: &IsarInstance,
+    collection: &IsarCollection,
+    query: &Query,
+    port: IsarPort,
+    debounce_ms: u32,
+    handle: *mut *mut WatchHandle,
+) -> i64 {
+    isar_try! {
+        let watch_handle = isar.watch_query_diff(
+            collection,
+            query.clone(),
+            Box::new(move |diff: QueryDiff| {
+                post_ids(port, &diff_message(diff));
+            }),
+            get_debounce(debounce_ms),
+        )?;
+        handle.write(Box::into_raw(Box::new(watch_handle)));
+    }
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn isar_stop_watching(handle: *mut WatchHandle) {
     Box::from_raw(handle).stop();
 }
+
+#[no_mangle]
+pub extern "C" fn isar_pause_watching(handle: &WatchHandle) {
+    handle.pause();
+}
+
+#[no_mangle]
+pub extern "C" fn isar_resume_watching(handle: &WatchHandle) {
+    handle.resume();
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_get_active_watchers(
+    isar: &IsarInstance,
+    watchers_json: *mut *mut c_char,
+) {
+    let watchers = isar.active_watchers();
+    let json = serde_json::to_string(&watchers).unwrap();
+    watchers_json.write(CString::new(json).unwrap().into_raw());
+}
+
+#[repr(C)]
+pub struct CChangeJournalEntry {
+    collection_id: u64,
+    id: i64,
+    op: u8,
+}
+
+#[repr(C)]
+pub struct CChangeJournal {
+    entries: *mut CChangeJournalEntry,
+    length: u32,
+}
+
+#[no_mangle]
+pub extern "C" fn isar_set_change_journal_port(isar: &IsarInstance, port: IsarPort) {
+    if port == 0 {
+        isar.set_change_journal_callback(None);
+        return;
+    }
+    isar.set_change_journal_callback(Some(Box::new(move |entries: &[ChangeJournalEntry]| {
+        let mut entries = entries
+            .iter()
+            .map(|e| CChangeJournalEntry {
+                collection_id: e.collection_id,
+                id: e.id.unwrap_or(0),
+                op: e.op.to_byte(),
+            })
+            .collect::<Vec<_>>()
+            .into_boxed_slice();
+        let journal = CChangeJournal {
+            entries: entries.as_mut_ptr(),
+            length: entries.len() as u32,
+        };
+        std::mem::forget(entries);
+        post_int(port, Box::into_raw(Box::new(journal)) as i64);
+    })));
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn isar_free_change_journal(journal: *mut CChangeJournal) {
+    let journal = Box::from_raw(journal);
+    let _ = Vec::from_raw_parts(
+        journal.entries,
+        journal.length as usize,
+        journal.length as usize,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::{isar_qb_build, isar_qb_create};
+    use crate::tests::open_instance;
+    use crate::transport::{set_transport, Transport};
+    use std::sync::Mutex;
+    use std::time::Instant;
+
+    static MESSAGES: Mutex<Vec<(IsarPort, Vec<i64>)>> = Mutex::new(vec![]);
+
+    struct RecordingTransport;
+
+    impl Transport for RecordingTransport {
+        fn post_int(&self, port: IsarPort, value: i64) {
+            MESSAGES.lock().unwrap().push((port, vec![value]));
+        }
+
+        fn post_ids(&self, port: IsarPort, ids: &[i64]) {
+            MESSAGES.lock().unwrap().push((port, ids.to_vec()));
+        }
+    }
+
+    #[test]
+    fn test_query_diff_is_posted_as_ids() {
+        set_transport(Box::new(RecordingTransport));
+        let instance = open_instance(br#"[{"name": "col", "embedded": false, "properties": []}]"#);
+        let col = &instance.collections()[0];
+        let mut handle = std::ptr::null_mut();
+        unsafe {
+            let query = &*isar_qb_build(isar_qb_create(col));
+            let result = isar_watch_query_diff(&instance, col, query, 7, 0, &mut handle);
+            assert_eq!(result, 0);
+        }
+
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        let id = col.put(&mut txn, None, col.new_object_builder(None).finish());
+        let id = id.unwrap();
+        txn.commit().unwrap();
+
+        let start = Instant::now();
+        let message = loop {
+            let messages = MESSAGES.lock().unwrap();
+            if let Some((_, message)) = messages.iter().find(|(port, _)| *port == 7) {
+                break message.clone();
+            }
+            drop(messages);
+            assert!(start.elapsed() < Duration::from_secs(5));
+            std::thread::sleep(Duration::from_millis(10));
+        };
+        assert_eq!(message, vec![1, 0, id]);
+        unsafe { isar_stop_watching(handle) };
+        assert!(instance.close_and_delete());
+    }
+}
@@ -0,0 +1 @@
+cbindgen --config cbindgen.toml --crate isar --output isar.h
@@ -0,0 +1,166 @@
+use crate::cursor::IsarCursors;
+use crate::error::{illegal_arg, Result};
+use crate::index::index_key::IndexKey;
+use crate::storage::{Db, StorageCursor};
+use xxhash_rust::xxh3::xxh3_64;
+
+/// The blobs of a collection. Blobs are stored outside of the objects in chunks with the key
+/// collection id + object id + blob id + chunk index.
+pub(crate) struct IsarBlobs {
+    db: Db,
+    col_id: u64,
+    blob_ids: Vec<(String, u64)>,
+}
+
+impl IsarBlobs {
+    pub const CHUNK_SIZE: usize = 64 * 1024;
+
+    pub fn new(db: Db, col_id: u64, names: &[String]) -> Self {
+        let blob_ids = names
+            .iter()
+            .map(|name| (name.clone(), xxh3_64(name.as_bytes())))
+            .collect();
+        IsarBlobs {
+            db,
+            col_id,
+            blob_ids,
+        }
+    }
+
+    pub fn blob_id(&self, name: &str) -> Result<u64> {
+        if let Some((_, blob_id)) = self.blob_ids.iter().find(|(n, _)| n == name) {
+            Ok(*blob_id)
+        } else {
+            illegal_arg("Blob does not exist.")
+        }
+    }
+
+    fn get_key(&self, id: i64, blob_id: u64, chunk: u64) -> IndexKey {
+        let mut key = IndexKey::new();
+        key.add_hash(self.col_id);
+        key.add_long(id);
+        key.add_hash(blob_id);
+        key.add_hash(chunk);
+        key
+    }
+
+    /// Index of the last chunk of the blob.
+    fn last_chunk(&self, cursors: &IsarCursors, id: i64, blob_id: u64) -> Result<Option<u64>> {
+        let mut cursor = cursors.get_cursor(self.db)?;
+        let lower = self.get_key(id, blob_id, 0);
+        let upper = self.get_key(id, blob_id, u64::MAX);
+        let mut last = None;
+        cursor.iter_between(&lower, &upper, false, false, false, |_, key, _| {
+            last = Some(u64::from_be_bytes(key[24..].try_into().unwrap()));
+            Ok(false)
+        })?;
+        Ok(last)
+    }
+
+    pub fn append(&self, cursors: &IsarCursors, id: i64, blob_id: u64, bytes: &[u8]) -> Result<()> {
+        let first_chunk = self
+            .last_chunk(cursors, id, blob_id)?
+            .map_or(0, |last| last + 1);
+        let mut cursor = cursors.get_cursor(self.db)?;
+        for (chunk, bytes) in (first_chunk..).zip(bytes.chunks(Self::CHUNK_SIZE)) {
+            cursor.put(&self.get_key(id, blob_id, chunk), bytes)?;
+        }
+        Ok(())
+    }
+
+    /// Returns `false` if the blob does not exist.
+    pub fn read<'txn, F>(
+        &self,
+        cursors: &IsarCursors<'txn, '_>,
+        id: i64,
+        blob_id: u64,
+        mut callback: F,
+    ) -> Result<bool>
+    where
+        F: FnMut(&'txn [u8]) -> Result<bool>,
+    {
+        let mut cursor = cursors.get_cursor(self.db)?;
+        let lower = self.get_key(id, blob_id, 0);
+        let upper = self.get_key(id, blob_id, u64::MAX);
+        let mut exists = false;
+        cursor.iter_between(&lower, &upper, false, false, true, |_, _, bytes| {
+            exists = true;
+            callback(bytes)
+        })?;
+        Ok(exists)
+    }
+
+    pub fn read_chunk<'txn>(
+        &self,
+        cursors: &IsarCursors<'txn, '_>,
+        id: i64,
+        blob_id: u64,
+        chunk: u64,
+    ) -> Result<Option<&'txn [u8]>> {
+        let mut cursor = cursors.get_cursor(self.db)?;
+        let key = self.get_key(id, blob_id, chunk);
+        Ok(cursor.move_to(&key)?.map(|(_, bytes)| bytes))
+    }
+
+    fn delete_between(
+        &self,
+        cursors: &IsarCursors,
+        lower: IndexKey,
+        upper: IndexKey,
+    ) -> Result<bool> {
+        let mut cursor = cursors.get_cursor(self.db)?;
+        let mut deleted = false;
+        cursor.iter_between(&lower, &upper, false, false, true, |cursor, _, _| {
+            cursor.delete_current()?;
+            deleted = true;
+            Ok(true)
+        })?;
+        Ok(deleted)
+    }
+
+    pub fn delete(&self, cursors: &IsarCursors, id: i64, blob_id: u64) -> Result<bool> {
+        let lower = self.get_key(id, blob_id, 0);
+        let upper = self.get_key(id, blob_id, u64::MAX);
+        self.delete_between(cursors, lower, upper)
+    }
+
+    /// Deletes all blobs of the object.
+    pub fn delete_for_object(&self, cursors: &IsarCursors, id: i64) -> Result<()> {
+        let lower = self.get_key(id, 0, 0);
+        let upper = self.get_key(id, u64::MAX, u64::MAX);
+        self.delete_between(cursors, lower, upper)?;
+        Ok(())
+    }
+
+    /// Moves all blobs to the collection `col_id`. Chunks are copied one at a time so large
+    /// blobs are not loaded into memory.
+    pub fn move_to_collection(&self, cursors: &IsarCursors, col_id: u64) -> Result<()> {
+        let lower = self.get_key(i64::MIN, 0, 0);
+        let upper = self.get_key(i64::MAX, u64::MAX, u64::MAX);
+        let mut cursor = cursors.get_cursor(self.db)?;
+        let mut keys = vec![];
+        cursor.iter_between(&lower, &upper, false, false, true, |_, key, _| {
+            keys.push(key.to_vec());
+            Ok(true)
+        })?;
+        for key in keys {
+            let mut new_key = col_id.to_be_bytes().to_vec();
+            new_key.extend_from_slice(&key[8..]);
+            let bytes = if let Some((_, bytes)) = cursor.move_to(&IndexKey::from_bytes(key))? {
+                bytes.to_vec()
+            } else {
+                continue;
+            };
+            cursor.delete_current()?;
+            cursor.put(&IndexKey::from_bytes(new_key), &bytes)?;
+        }
+        Ok(())
+    }
+
+    pub fn clear(&self, cursors: &IsarCursors) -> Result<()> {
+        let lower = self.get_key(i64::MIN, 0, 0);
+        let upper = self.get_key(i64::MAX, u64::MAX, u64::MAX);
+        self.delete_between(cursors, lower, upper)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,188 @@
+use crate::error::{IsarError, Result};
+use crate::object::id::BytesToId;
+use crate::storage::{Db, StorageCursor, Txn, UnboundCursor};
+use std::sync::Arc;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ChangeOp {
+    Created,
+    Updated,
+    Deleted,
+    Cleared,
+}
+
+impl ChangeOp {
+    pub fn to_byte(self) -> u8 {
+        match self {
+            ChangeOp::Created => 0,
+            ChangeOp::Updated => 1,
+            ChangeOp::Deleted => 2,
+            ChangeOp::Cleared => 3,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(ChangeOp::Created),
+            1 => Some(ChangeOp::Updated),
+            2 => Some(ChangeOp::Deleted),
+            3 => Some(ChangeOp::Cleared),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct ChangeJournalEntry {
+    pub collection_id: u64,
+    /// `None` if the whole collection was cleared.
+    pub id: Option<i64>,
+    pub op: ChangeOp,
+}
+
+pub type ChangeJournalCallback = Box<dyn Fn(&[ChangeJournalEntry]) + Send + Sync + 'static>;
+
+pub(crate) struct ChangeJournal {
+    entries: Vec<ChangeJournalEntry>,
+    callback: Option<Arc<ChangeJournalCallback>>,
+    feed_db: Option<Db>,
+}
+
+impl ChangeJournal {
+    const ENTRY_SIZE: usize = 17;
+
+    pub fn new(callback: Option<Arc<ChangeJournalCallback>>, feed_db: Option<Db>) -> Self {
+        ChangeJournal {
+            entries: vec![],
+            callback,
+            feed_db,
+        }
+    }
+
+    pub fn record(&mut self, collection_id: u64, id: Option<i64>, op: ChangeOp) {
+        self.entries.push(ChangeJournalEntry {
+            collection_id,
+            id,
+            op,
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        self.entries.truncate(len)
+    }
+
+    pub fn persist(&self, txn: &Txn) -> Result<()> {
+        let feed_db = if let Some(feed_db) = self.feed_db {
+            feed_db
+        } else {
+            return Ok(());
+        };
+        if self.entries.is_empty() {
+            return Ok(());
+        }
+
+        let mut cursor = UnboundCursor::new().bind(txn, feed_db)?;
+        let mut sequence = if let Some((key, _)) = cursor.move_to_last()? {
+            key.to_id()
+        } else {
+            0
+        };
+        for entry in &self.entries {
+            sequence += 1;
+            cursor.put(&sequence, &Self::encode_entry(entry))?;
+        }
+        Ok(())
+    }
+
+    fn encode_entry(entry: &ChangeJournalEntry) -> [u8; Self::ENTRY_SIZE] {
+        let mut bytes = [0; Self::ENTRY_SIZE];
+        bytes[0..8].copy_from_slice(&entry.collection_id.to_le_bytes());
+        bytes[8..16].copy_from_slice(&entry.id.unwrap_or(0).to_le_bytes());
+        bytes[16] = entry.op.to_byte();
+        bytes
+    }
+
+    pub fn decode_entry(bytes: &[u8]) -> Result<ChangeJournalEntry> {
+        if bytes.len() != Self::ENTRY_SIZE {
+            return Err(IsarError::DbCorrupted {
+                message: "Invalid change feed entry.".to_string(),
+            });
+        }
+        let op = ChangeOp::from_byte(bytes[16]).ok_or_else(|| IsarError::DbCorrupted {
+            message: "Invalid change feed operation.".to_string(),
+        })?;
+        let collection_id = u64::from_le_bytes(bytes[0..8].try_into().unwrap());
+        let id = if op == ChangeOp::Cleared {
+            None
+        } else {
+            Some(i64::from_le_bytes(bytes[8..16].try_into().unwrap()))
+        };
+        Ok(ChangeJournalEntry {
+            collection_id,
+            id,
+            op,
+        })
+    }
+
+    pub fn publish(self) {
+        if let Some(callback) = &self.callback {
+            if !self.entries.is_empty() {
+                (*callback)(&self.entries);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instance::tests::{collections, open_instance, put_object, put_values};
+    use serde_json::json;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_journal_is_published_on_commit() {
+        let instance = open_instance(collections());
+        let col = &instance.collections()[0];
+        let entries = Arc::new(Mutex::new(vec![]));
+        let recorded = entries.clone();
+        instance.set_change_journal_callback(Some(Box::new(move |journal| {
+            recorded.lock().unwrap().push(journal.to_vec());
+        })));
+
+        let ids = put_values(&instance, &[1, 2]);
+        put_object(&instance, col, Some(ids[1]), json!({"value": 3}));
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        col.delete(&mut txn, ids[0]).unwrap();
+        col.clear(&mut txn).unwrap();
+        txn.abort();
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        col.delete(&mut txn, ids[0]).unwrap();
+        col.clear(&mut txn).unwrap();
+        txn.commit().unwrap();
+
+        let entry = |id, op| ChangeJournalEntry {
+            collection_id: col.id,
+            id,
+            op,
+        };
+        assert_eq!(
+            *entries.lock().unwrap(),
+            vec![
+                vec![
+                    entry(Some(ids[0]), ChangeOp::Created),
+                    entry(Some(ids[1]), ChangeOp::Created),
+                ],
+                vec![entry(Some(ids[1]), ChangeOp::Updated)],
+                vec![
+                    entry(Some(ids[0]), ChangeOp::Deleted),
+                    entry(None, ChangeOp::Cleared),
+                ],
+            ]
+        );
+    }
+}
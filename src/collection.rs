@@ -1,24 +1,104 @@
+use crate::blob::IsarBlobs;
+use crate::change_journal::ChangeOp;
 use crate::cursor::IsarCursors;
 use crate::error::{illegal_arg, IsarError, Result};
 use crate::index::index_key::IndexKey;
 use crate::index::index_key_builder::IndexKeyBuilder;
+use crate::index::index_stats::IndexStats;
+use crate::index::unique_constraint::UniqueConstraint;
 use crate::index::IsarIndex;
+use crate::instance::IndexFillOptions;
+use crate::integrity::{IntegrityProblem, ProblemKind};
+use crate::isar_log;
 use crate::link::IsarLink;
-use crate::mdbx::db::Db;
+use crate::logging::LogEvent;
+use crate::object::buffer_pool::{get_buffer_pool, BufferPool};
+use crate::object::data_type::DataType;
 use crate::object::id::BytesToId;
 use crate::object::isar_object::IsarObject;
-use crate::object::json_encode_decode::JsonEncodeDecode;
+use crate::object::json_encode_decode::{JsonDecodeOptions, JsonEncodeDecode};
 use crate::object::object_builder::ObjectBuilder;
+use crate::object::object_cache::ObjectCache;
 use crate::object::property::Property;
+use crate::object::property_transform::{PropertyTransform, PropertyTransforms};
 use crate::query::query_builder::QueryBuilder;
+use crate::schema::collection_schema::IdStrategy;
+use crate::schema::derived_schema::Transform;
+use crate::schema::index_schema::IndexType;
+use crate::schema::schema_manager::SchemaManager;
+use crate::storage::{Db, DbStat, StorageCursor};
 use crate::txn::IsarTxn;
 use crate::watch::change_set::ChangeSet;
 use intmap::IntMap;
 use itertools::Itertools;
+use serde::Serialize;
 use serde_json::Value;
 use std::cell::Cell;
 use std::ops::Deref;
-use xxhash_rust::xxh3::xxh3_64;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use xxhash_rust::xxh3::{xxh3_64, xxh3_64_with_seed};
+
+/// Merges the existing and the new object. The builder already contains the new object.
+pub type MergeCallback<'a> = dyn Fn(IsarObject, IsarObject, &mut ObjectBuilder) -> Result<()> + 'a;
+
+/// How `put_with_conflict` handles objects that violate a unique index.
+pub enum ConflictStrategy<'a> {
+    /// Uses the `replace` flag of the index.
+    Default,
+    Error,
+    Replace,
+    Ignore,
+    /// Replaces the conflicting object with the merged object and keeps its id.
+    Merge(&'a MergeCallback<'a>),
+}
+
+pub struct DbStats {
+    pub name: String,
+    pub entries: u64,
+    pub depth: u32,
+    pub branch_pages: u64,
+    pub leaf_pages: u64,
+    pub overflow_pages: u64,
+    pub page_size: u32,
+}
+
+impl DbStats {
+    pub(crate) fn new(name: String, stat: DbStat) -> Self {
+        DbStats {
+            name,
+            entries: stat.entries,
+            depth: stat.depth,
+            branch_pages: stat.branch_pages,
+            leaf_pages: stat.leaf_pages,
+            overflow_pages: stat.overflow_pages,
+            page_size: stat.page_size,
+        }
+    }
+
+    pub fn total_pages(&self) -> u64 {
+        self.branch_pages + self.leaf_pages + self.overflow_pages
+    }
+
+    pub fn size(&self) -> u64 {
+        self.total_pages() * self.page_size as u64
+    }
+}
+
+/// Distribution of object sizes. Bucket `i` counts objects with a size below `2^(i + 1)` bytes.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SizeHistogram {
+    pub count: u64,
+    pub total_bytes: u64,
+    pub max_bytes: u64,
+    pub buckets: Vec<u64>,
+    /// Aggregate bytes per top-level property.
+    pub property_bytes: Vec<(String, u64)>,
+}
+
+static RANDOM_ID_SEED: AtomicU64 = AtomicU64::new(0);
 
 pub struct IsarCollection {
     pub name: String,
@@ -26,24 +106,54 @@ pub struct IsarCollection {
 
     pub properties: Vec<Property>,
     pub embedded_properties: IntMap<Vec<Property>>,
+    /// Index of every property by the hash of its name.
+    property_lookup: IntMap<usize>,
 
     pub(crate) instance_id: u64,
     pub(crate) db: Db,
+    versions_db: Option<Db>,
+    /// Only set if deletes leave tombstones.
+    tombstones_db: Option<Db>,
 
     pub(crate) indexes: Vec<IsarIndex>,
     pub(crate) links: Vec<IsarLink>, // links from this collection
     backlinks: Vec<IsarLink>,        // links to this collection
+    /// Source property, derived property and transform.
+    derived: Vec<(Property, Property, Transform)>,
+    /// Properties that only allow the listed values.
+    enum_values: Vec<(Property, Vec<i64>)>,
+    pub(crate) unique_constraints: Vec<UniqueConstraint>,
+    /// Only missing if the instance is read-only and no blobs were written.
+    blobs: Option<IsarBlobs>,
 
     auto_increment: Cell<i64>,
+    /// The info db if the highest auto-increment id is persisted.
+    auto_increment_db: Option<Db>,
+    id_strategy: IdStrategy,
+    pub(crate) buffer_pool: Arc<BufferPool>,
+    /// Whether objects are validated before they are returned.
+    validate_objects: AtomicBool,
+    /// Default memory limit of sorted and distinct queries.
+    query_memory_limit: AtomicUsize,
+    /// Directory for the temporary databases of sorted queries that exceed the memory limit.
+    query_spill_dir: RwLock<Option<String>>,
+    object_cache: RwLock<Option<Arc<ObjectCache>>>,
+    transforms: RwLock<PropertyTransforms>,
 }
 
 unsafe impl Send for IsarCollection {}
 unsafe impl Sync for IsarCollection {}
 
 impl IsarCollection {
+    const INDEX_FILL_PROGRESS_STEP: usize = 1000;
+    const RAW_MAGIC: [u8; 4] = *b"ISRW";
+    const MAX_SAFE_ID: u64 = (1 << 53) - 1;
+
     #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         db: Db,
+        versions_db: Option<Db>,
+        tombstones_db: Option<Db>,
         instance_id: u64,
         name: &str,
         properties: Vec<Property>,
@@ -51,24 +161,69 @@ impl IsarCollection {
         indexes: Vec<IsarIndex>,
         links: Vec<IsarLink>,
         backlinks: Vec<IsarLink>,
+        derived: Vec<(Property, Property, Transform)>,
+        enum_values: Vec<(Property, Vec<i64>)>,
+        unique_constraints: Vec<UniqueConstraint>,
+        auto_increment_db: Option<Db>,
+        id_strategy: IdStrategy,
+        blobs: Option<IsarBlobs>,
     ) -> Self {
         let id = xxh3_64(name.as_bytes());
+        let mut property_lookup = IntMap::new();
+        for (i, property) in properties.iter().enumerate() {
+            property_lookup.insert(xxh3_64(property.name.as_bytes()), i);
+        }
         IsarCollection {
             name: name.to_string(),
             id,
             properties,
+            property_lookup,
             embedded_properties,
             instance_id,
             db,
+            versions_db,
+            tombstones_db,
             indexes,
             links,
             backlinks,
+            derived,
+            enum_values,
+            unique_constraints,
+            blobs,
             auto_increment: Cell::new(0),
+            auto_increment_db,
+            id_strategy,
+            buffer_pool: get_buffer_pool(instance_id),
+            validate_objects: AtomicBool::new(cfg!(debug_assertions)),
+            query_memory_limit: AtomicUsize::new(usize::MAX),
+            query_spill_dir: RwLock::new(None),
+            object_cache: RwLock::new(None),
+            transforms: RwLock::new(PropertyTransforms::default()),
+        }
+    }
+
+    pub fn property(&self, name: &str) -> Result<&Property> {
+        let property = self
+            .property_lookup
+            .get(xxh3_64(name.as_bytes()))
+            .map(|i| &self.properties[*i])
+            .filter(|p| p.name == name);
+        if let Some(property) = property {
+            Ok(property)
+        } else {
+            illegal_arg("Property does not exist.")
         }
     }
 
+    /// Uses a buffer of the instance buffer pool if no buffer is provided.
     pub fn new_object_builder(&self, buffer: Option<Vec<u8>>) -> ObjectBuilder {
-        ObjectBuilder::new(&self.properties, buffer)
+        let buffer = buffer.unwrap_or_else(|| self.buffer_pool.get(0));
+        ObjectBuilder::new(&self.properties, Some(buffer))
+    }
+
+    /// Returns the buffer of the builder to the instance buffer pool.
+    pub fn recycle_object_builder(&self, ob: ObjectBuilder) {
+        self.buffer_pool.put(ob.recycle());
     }
 
     pub fn new_query_builder(&self) -> QueryBuilder {
@@ -81,17 +236,88 @@ impl IsarCollection {
             let id = key.deref().to_id();
             self.update_auto_increment(id);
         }
+        if let Some(db) = self.auto_increment_db {
+            let mut cursor = cursors.get_cursor(db)?;
+            let key = SchemaManager::auto_increment_key(&self.name);
+            if let Some((_, bytes)) = cursor.move_to(&key)? {
+                let id =
+                    i64::from_le_bytes(bytes.try_into().map_err(|_| IsarError::DbCorrupted {
+                        message: "Invalid auto increment".to_string(),
+                    })?);
+                self.update_auto_increment(id);
+            }
+        }
         Ok(())
     }
 
-    pub(crate) fn update_auto_increment(&self, id: i64) {
+    /// Returns whether `id` is the new highest id.
+    pub(crate) fn update_auto_increment(&self, id: i64) -> bool {
         if id > self.auto_increment.get() {
             self.auto_increment.set(id);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn persist_auto_increment(&self, cursors: &IsarCursors) -> Result<()> {
+        if let Some(db) = self.auto_increment_db {
+            let mut cursor = cursors.get_cursor(db)?;
+            let key = SchemaManager::auto_increment_key(&self.name);
+            cursor.put(&key, &self.auto_increment.get().to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Returns a new id using the id strategy of the collection.
+    pub fn auto_increment(&self, txn: &mut IsarTxn) -> Result<i64> {
+        if self.auto_increment_db.is_some() {
+            txn.write(self.instance_id, |cursors, _| self.next_id(cursors))
+        } else {
+            txn.read(self.instance_id, |cursors| self.next_id(cursors))
+        }
+    }
+
+    /// Random ids do not touch the auto increment counter.
+    pub(crate) fn next_id(&self, cursors: &IsarCursors) -> Result<i64> {
+        let previous = self.auto_increment.get();
+        let id = match self.id_strategy {
+            IdStrategy::AutoIncrement => self.auto_increment_internal()?,
+            IdStrategy::Random => return self.random_id(cursors),
+            IdStrategy::TimeOrdered => self.time_ordered_id()?,
+        };
+        self.update_auto_increment(id);
+        if self.auto_increment.get() != previous {
+            self.persist_auto_increment(cursors)?;
+        }
+        Ok(id)
+    }
+
+    fn random_id(&self, cursors: &IsarCursors) -> Result<i64> {
+        let mut cursor = cursors.get_cursor(self.db)?;
+        loop {
+            let nanos = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_nanos() as u64);
+            let seed = RANDOM_ID_SEED.fetch_add(1, Ordering::Relaxed);
+            let id = (xxh3_64_with_seed(&nanos.to_le_bytes(), seed) & Self::MAX_SAFE_ID) as i64;
+            if id != 0 && cursor.move_to(&id)?.is_none() {
+                return Ok(id);
+            }
         }
     }
 
-    pub fn auto_increment(&self, _: &mut IsarTxn) -> Result<i64> {
-        self.auto_increment_internal()
+    /// Uses the next id if more than 1024 ids were generated in the current millisecond.
+    fn time_ordered_id(&self) -> Result<i64> {
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as i64);
+        let id = millis << 10;
+        if id > self.auto_increment.get() {
+            Ok(id)
+        } else {
+            self.auto_increment_internal()
+        }
     }
 
     pub(crate) fn auto_increment_internal(&self) -> Result<i64> {
@@ -104,13 +330,156 @@ impl IsarCollection {
         }
     }
 
+    pub(crate) fn set_validate_objects(&self, validate: bool) {
+        self.validate_objects.store(validate, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_query_memory_limit(&self, max_bytes: usize) {
+        self.query_memory_limit.store(max_bytes, Ordering::Relaxed);
+    }
+
+    pub(crate) fn query_memory_limit(&self) -> usize {
+        self.query_memory_limit.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_query_spill_dir(&self, dir: Option<&str>) {
+        *self.query_spill_dir.write().unwrap() = dir.map(|d| d.to_string());
+    }
+
+    pub(crate) fn query_spill_dir(&self) -> Option<String> {
+        self.query_spill_dir.read().unwrap().clone()
+    }
+
+    /// Takes over the settings and the id counter of the collection this one replaces.
+    pub(crate) fn copy_settings(&self, previous: &IsarCollection) {
+        self.update_auto_increment(previous.auto_increment.get());
+        self.set_validate_objects(previous.validate_objects.load(Ordering::Relaxed));
+        self.set_query_memory_limit(previous.query_memory_limit());
+        *self.query_spill_dir.write().unwrap() = previous.query_spill_dir();
+        *self.object_cache.write().unwrap() = previous.object_cache.read().unwrap().clone();
+        *self.transforms.write().unwrap() = previous.transforms.read().unwrap().clone();
+    }
+
+    /// Checks that the dynamic data is in bounds and all strings are valid UTF-8 if validation
+    /// is enabled.
+    fn read_object<'a>(&self, bytes: &'a [u8]) -> Result<IsarObject<'a>> {
+        let object = IsarObject::from_bytes(bytes);
+        if self.validate_objects.load(Ordering::Relaxed)
+            && !object.is_valid(&self.properties, &self.embedded_properties)
+        {
+            return Err(IsarError::DbCorrupted {
+                message: "Invalid object.".to_string(),
+            });
+        }
+        Ok(object)
+    }
+
+    pub(crate) fn set_object_cache(&self, cache: Option<ObjectCache>) {
+        *self.object_cache.write().unwrap() = cache.map(Arc::new);
+    }
+
+    /// Number of cached objects and their total size.
+    pub fn object_cache_stats(&self) -> Option<(usize, usize)> {
+        let cache = self.object_cache.read().unwrap();
+        cache.as_ref().map(|cache| cache.stats())
+    }
+
+    /// The cache is only used by read transactions because write transactions may see
+    /// uncommitted changes.
+    fn get_object_cache(&self, txn: &IsarTxn) -> Option<Arc<ObjectCache>> {
+        if txn.is_write() {
+            None
+        } else {
+            self.object_cache.read().unwrap().clone()
+        }
+    }
+
+    fn invalidate_cached_object(&self, cursors: &IsarCursors, id: Option<i64>) {
+        if let Some(cache) = self.object_cache.read().unwrap().as_ref() {
+            if let Some(id) = id {
+                cache.invalidate(id, cursors.txn_id());
+            } else {
+                cache.invalidate_all(cursors.txn_id());
+            }
+        }
+    }
+
+    pub(crate) fn set_property_transform(
+        &self,
+        property_name: &str,
+        transform: Arc<dyn PropertyTransform>,
+    ) -> Result<()> {
+        let property = self.property(property_name)?;
+        let mut transforms = self.transforms.write().unwrap();
+        *transforms = transforms.with(&self.properties, property, transform)?;
+        Ok(())
+    }
+
+    pub(crate) fn property_transforms(&self) -> PropertyTransforms {
+        self.transforms.read().unwrap().clone()
+    }
+
+    fn get_cached<'txn>(
+        &self,
+        txn: &'txn IsarTxn,
+        cursors: &IsarCursors<'txn, '_>,
+        cache: Option<&ObjectCache>,
+        id: i64,
+    ) -> Result<Option<IsarObject<'txn>>> {
+        let transforms = self.property_transforms();
+        if let Some(bytes) = cache.and_then(|cache| cache.get(id, cursors.txn_id())) {
+            let object = IsarObject::from_bytes(txn.pin(bytes));
+            return Ok(Some(transforms.decode(txn, object)?));
+        }
+        let mut cursor = cursors.get_cursor(self.db)?;
+        if let Some((_, bytes)) = cursor.move_to(&id)? {
+            let object = self.read_object(bytes)?;
+            if let Some(cache) = cache {
+                cache.insert(id, cursors.txn_id(), bytes);
+            }
+            Ok(Some(transforms.decode(txn, object)?))
+        } else {
+            Ok(None)
+        }
+    }
+
     pub fn get<'txn>(&self, txn: &'txn mut IsarTxn, id: i64) -> Result<Option<IsarObject<'txn>>> {
-        txn.read(self.instance_id, |cursors| {
-            let mut cursor = cursors.get_cursor(self.db)?;
-            let object = cursor
-                .move_to(&id)?
-                .map(|(_, v)| IsarObject::from_bytes(&v));
-            Ok(object)
+        let txn: &'txn IsarTxn = txn;
+        let cache = self.get_object_cache(txn);
+        txn.read_shared(self.instance_id, |cursors| {
+            self.get_cached(txn, cursors, cache.as_deref(), id)
+        })
+    }
+
+    fn get_all_internal<'txn>(
+        &self,
+        txn: &'txn IsarTxn,
+        cursors: &IsarCursors<'txn, '_>,
+        ids: &[i64],
+    ) -> Result<Vec<Option<IsarObject<'txn>>>> {
+        let mut sorted_ids = ids.iter().copied().enumerate().collect_vec();
+        sorted_ids.sort_unstable_by_key(|(_, id)| *id);
+
+        let transforms = self.property_transforms();
+        let mut objects = vec![None; ids.len()];
+        let mut cursor = cursors.get_cursor(self.db)?;
+        for (i, id) in sorted_ids {
+            if let Some((_, bytes)) = cursor.move_to(&id)? {
+                let object = self.read_object(bytes)?;
+                objects[i] = Some(transforms.decode(txn, object)?);
+            }
+        }
+        Ok(objects)
+    }
+
+    pub fn get_all<'txn>(
+        &self,
+        txn: &'txn mut IsarTxn,
+        ids: &[i64],
+    ) -> Result<Vec<Option<IsarObject<'txn>>>> {
+        let txn: &'txn IsarTxn = txn;
+        txn.read_shared(self.instance_id, |cursors| {
+            self.get_all_internal(txn, cursors, ids)
         })
     }
 
@@ -121,40 +490,156 @@ impl IsarCollection {
             .ok_or(IsarError::UnknownIndex {})
     }
 
+    fn get_built_index_by_id(&self, index_id: u64) -> Result<&IsarIndex> {
+        let index = self.get_index_by_id(index_id)?;
+        if index.is_pending() {
+            illegal_arg("The index has not been built yet.")
+        } else {
+            Ok(index)
+        }
+    }
+
+    /// Index that contains every distinct value of the property exactly once as a key.
+    pub(crate) fn get_distinct_index(&self, property: &Property) -> Option<&IsarIndex> {
+        // string keys may be truncated so they are not suitable
+        if property.data_type == DataType::String {
+            return None;
+        }
+        self.indexes.iter().find(|i| {
+            let first = i.properties.first().unwrap();
+            i.properties.len() == 1
+                && !i.multi_entry
+                && !i.is_pending()
+                && first.index_type == IndexType::Value
+                && &first.property == property
+        })
+    }
+
+    pub(crate) fn get_pending_index_ids(&self) -> Vec<u64> {
+        self.indexes
+            .iter()
+            .filter(|i| i.is_pending())
+            .map(|i| i.id)
+            .collect()
+    }
+
     pub fn get_by_index<'txn>(
         &self,
         txn: &'txn mut IsarTxn,
         index_id: u64,
         key: &IndexKey,
     ) -> Result<Option<(i64, IsarObject<'txn>)>> {
-        let index = self.get_index_by_id(index_id)?;
-        txn.read(self.instance_id, |cursors| {
+        let index = self.get_built_index_by_id(index_id)?;
+        index.validate_key(key)?;
+        let txn: &'txn IsarTxn = txn;
+        let cache = self.get_object_cache(txn);
+        txn.read_shared(self.instance_id, |cursors| {
             if let Some(id) = index.get_id(cursors, key)? {
-                let mut cursor = cursors.get_cursor(self.db)?;
-                let (_, bytes) = cursor.move_to(&id)?.ok_or(IsarError::DbCorrupted {
-                    message: "Invalid index entry".to_string(),
-                })?;
-                let result = (id, IsarObject::from_bytes(&bytes));
-                Ok(Some(result))
+                let object = self.get_cached(txn, cursors, cache.as_deref(), id)?.ok_or(
+                    IsarError::DbCorrupted {
+                        message: "Invalid index entry".to_string(),
+                    },
+                )?;
+                Ok(Some((id, object)))
             } else {
                 Ok(None)
             }
         })
     }
 
+    pub fn get_all_by_index<'txn>(
+        &self,
+        txn: &'txn mut IsarTxn,
+        index_id: u64,
+        keys: &[IndexKey],
+    ) -> Result<Vec<Option<(i64, IsarObject<'txn>)>>> {
+        let index = self.get_built_index_by_id(index_id)?;
+        for key in keys {
+            index.validate_key(key)?;
+        }
+        let txn: &'txn IsarTxn = txn;
+        txn.read_shared(self.instance_id, |cursors| {
+            let mut sorted_keys = keys.iter().enumerate().collect_vec();
+            sorted_keys.sort_unstable_by_key(|(_, key)| *key);
+
+            let mut ids = vec![None; keys.len()];
+            for (i, key) in sorted_keys {
+                ids[i] = index.get_id(cursors, key)?;
+            }
+
+            let existing_ids = ids.iter().flatten().copied().collect_vec();
+            let mut objects = self
+                .get_all_internal(txn, cursors, &existing_ids)?
+                .into_iter();
+            let mut results = Vec::with_capacity(keys.len());
+            for id in ids {
+                if let Some(id) = id {
+                    let object = objects.next().unwrap().ok_or(IsarError::DbCorrupted {
+                        message: "Invalid index entry".to_string(),
+                    })?;
+                    results.push(Some((id, object)));
+                } else {
+                    results.push(None);
+                }
+            }
+            Ok(results)
+        })
+    }
+
+    /// Returns every object with the given key. Unlike `get_by_index` this also works for
+    /// duplicate keys of non-unique indexes.
+    pub fn get_all_by_index_key<'txn>(
+        &self,
+        txn: &'txn mut IsarTxn,
+        index_id: u64,
+        key: &IndexKey,
+    ) -> Result<Vec<(i64, IsarObject<'txn>)>> {
+        let index = self.get_built_index_by_id(index_id)?;
+        let txn: &'txn IsarTxn = txn;
+        txn.read_shared(self.instance_id, |cursors| {
+            let ids = index.get_ids(cursors, key)?;
+            let objects = self.get_all_internal(txn, cursors, &ids)?;
+            ids.into_iter()
+                .zip(objects)
+                .map(|(id, object)| {
+                    let object = object.ok_or(IsarError::DbCorrupted {
+                        message: "Invalid index entry".to_string(),
+                    })?;
+                    Ok((id, object))
+                })
+                .collect()
+        })
+    }
+
     pub fn put(&self, txn: &mut IsarTxn, id: Option<i64>, object: IsarObject) -> Result<i64> {
         txn.write(self.instance_id, |cursors, change_set| {
             self.put_internal(cursors, change_set, id, object)
         })
     }
 
+    /// Puts all objects in a single write. Returns the ids of the objects.
+    pub fn put_all(
+        &self,
+        txn: &mut IsarTxn,
+        objects: &[(Option<i64>, IsarObject)],
+    ) -> Result<Vec<i64>> {
+        txn.write(self.instance_id, |cursors, mut change_set| {
+            objects
+                .iter()
+                .map(|(id, object)| {
+                    self.put_internal(cursors, change_set.as_deref_mut(), *id, *object)
+                })
+                .collect()
+        })
+    }
+
     pub fn put_by_index(
         &self,
         txn: &mut IsarTxn,
         index_id: u64,
         object: IsarObject,
     ) -> Result<i64> {
-        let index = self.get_index_by_id(index_id)?;
+        let index = self.get_built_index_by_id(index_id)?;
         if index.multi_entry {
             illegal_arg("Cannot put by a multi-entry index")?;
         }
@@ -167,64 +652,740 @@ impl IsarCollection {
         })
     }
 
-    fn put_internal(
+    /// Returns `None` if the object was ignored because of a conflict.
+    pub fn put_with_conflict(
+        &self,
+        txn: &mut IsarTxn,
+        id: Option<i64>,
+        object: IsarObject,
+        strategy: &ConflictStrategy,
+    ) -> Result<Option<i64>> {
+        txn.write(self.instance_id, |cursors, change_set| {
+            let replace = match strategy {
+                ConflictStrategy::Default => None,
+                ConflictStrategy::Error => Some(false),
+                ConflictStrategy::Replace => Some(true),
+                ConflictStrategy::Ignore => {
+                    if self.find_conflict(cursors, id, object)?.is_some() {
+                        return Ok(None);
+                    }
+                    Some(false)
+                }
+                ConflictStrategy::Merge(callback) => {
+                    if let Some(existing_id) = self.find_conflict(cursors, id, object)? {
+                        let merged = self.merge_object(cursors, existing_id, object, callback)?;
+                        let merged = IsarObject::from_bytes(&merged);
+                        let id = self.put_internal_with(
+                            cursors,
+                            change_set,
+                            Some(existing_id),
+                            merged,
+                            Some(false),
+                        )?;
+                        return Ok(Some(id));
+                    }
+                    Some(false)
+                }
+            };
+            let id = self.put_internal_with(cursors, change_set, id, object, replace)?;
+            Ok(Some(id))
+        })
+    }
+
+    fn find_conflict(
         &self,
         cursors: &IsarCursors,
-        mut change_set: Option<&mut ChangeSet>,
+        id: Option<i64>,
+        object: IsarObject,
+    ) -> Result<Option<i64>> {
+        for index in &self.indexes {
+            if let Some(existing_id) = index.find_conflict(cursors, id, object)? {
+                return Ok(Some(existing_id));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Fails with the error `put_internal` would return for `object` without writing anything.
+    pub(crate) fn check_put(
+        &self,
+        cursors: &IsarCursors,
+        id: Option<i64>,
+        object: IsarObject,
+    ) -> Result<()> {
+        let prepared_bytes = self.prepare_object(object)?;
+        let object = prepared_bytes
+            .as_deref()
+            .map_or(object, IsarObject::from_bytes);
+        for index in self.indexes.iter().filter(|index| !index.replace) {
+            if let Some(existing_id) = index.find_conflict(cursors, id, object)? {
+                return Err(IsarError::UniqueViolated {
+                    index: index.name.clone(),
+                    id: existing_id,
+                });
+            }
+        }
+        for constraint in &self.unique_constraints {
+            if let Some(existing_id) = constraint.find_conflict(cursors, self.db, id, object)? {
+                return Err(IsarError::UniqueConstraintViolated {
+                    constraint: constraint.name.clone(),
+                    id: existing_id,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn merge_object(
+        &self,
+        cursors: &IsarCursors,
+        existing_id: i64,
+        object: IsarObject,
+        callback: &MergeCallback,
+    ) -> Result<Vec<u8>> {
+        let mut cursor = cursors.get_cursor(self.db)?;
+        let existing_bytes = if let Some((_, bytes)) = cursor.move_to(&existing_id)? {
+            bytes.to_vec()
+        } else {
+            return Err(IsarError::DbCorrupted {
+                message: "Index entry without object.".to_string(),
+            });
+        };
+        let existing = IsarObject::from_bytes(&existing_bytes);
+
+        let mut ob = ObjectBuilder::new(&self.properties, None);
+        for property in &self.properties {
+            ob.write_from(property, object);
+        }
+        callback(existing, object, &mut ob)?;
+
+        // overwritten dynamic properties leave unused bytes behind
+        let merged = ob.finish();
+        let mut compacted = ObjectBuilder::new(&self.properties, None);
+        for property in &self.properties {
+            compacted.write_from(property, merged);
+        }
+        Ok(compacted.finish().as_bytes().to_vec())
+    }
+
+    pub(crate) fn put_internal(
+        &self,
+        cursors: &IsarCursors,
+        change_set: Option<&mut ChangeSet>,
         id: Option<i64>,
         object: IsarObject,
     ) -> Result<i64> {
+        self.put_internal_with(cursors, change_set, id, object, None)
+    }
+
+    fn derive_properties(&self, object: IsarObject) -> Vec<u8> {
+        let mut ob = ObjectBuilder::new(&self.properties, None);
+        for property in &self.properties {
+            let derived = self.derived.iter().find(|(_, p, _)| p == property);
+            if let Some((source, _, transform)) = derived {
+                let value = object
+                    .read_string(source.offset)
+                    .map(|value| transform.apply(value));
+                ob.write_string(property.offset, value.as_deref());
+            } else {
+                ob.write_from(property, object);
+            }
+        }
+        ob.finish().as_bytes().to_vec()
+    }
+
+    fn verify_enum_values(&self, object: IsarObject) -> Result<()> {
+        for (property, values) in &self.enum_values {
+            let offset = property.offset;
+            let valid = match property.data_type {
+                DataType::Byte => values.contains(&(object.read_byte(offset) as i64)),
+                DataType::Int => {
+                    let value = object.read_int(offset);
+                    value == IsarObject::NULL_INT || values.contains(&(value as i64))
+                }
+                DataType::Long => {
+                    let value = object.read_long(offset);
+                    value == IsarObject::NULL_LONG || values.contains(&value)
+                }
+                DataType::ByteList => object
+                    .read_byte_list(offset)
+                    .unwrap_or_default()
+                    .iter()
+                    .all(|value| values.contains(&(*value as i64))),
+                DataType::IntList => object
+                    .read_int_list(offset)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .all(|value| value == IsarObject::NULL_INT || values.contains(&(value as i64))),
+                DataType::LongList => object
+                    .read_long_list(offset)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .all(|value| value == IsarObject::NULL_LONG || values.contains(&value)),
+                _ => true,
+            };
+            if !valid {
+                return illegal_arg(&format!(
+                    "Invalid enum value for property {}.",
+                    property.name
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Verifies the object, derives properties and applies the transforms. Returns `None` if
+    /// the object is stored as it is.
+    fn prepare_object(&self, object: IsarObject) -> Result<Option<Vec<u8>>> {
         if object.len() > IsarObject::MAX_SIZE as usize {
             illegal_arg("Object is bigger than 16MB")?;
         }
 
-        let id = if let Some(id) = id {
-            self.delete_internal(cursors, false, change_set.as_deref_mut(), id)?;
-            self.update_auto_increment(id);
-            id
+        self.verify_enum_values(object)?;
+
+        let derived_bytes = (!self.derived.is_empty()).then(|| self.derive_properties(object));
+        let object = derived_bytes
+            .as_deref()
+            .map_or(object, IsarObject::from_bytes);
+        // indexes use the transformed values
+        let transformed_bytes = self.property_transforms().encode(object)?;
+        Ok(transformed_bytes.or(derived_bytes))
+    }
+
+    fn put_internal_with(
+        &self,
+        cursors: &IsarCursors,
+        mut change_set: Option<&mut ChangeSet>,
+        id: Option<i64>,
+        object: IsarObject,
+        replace: Option<bool>,
+    ) -> Result<i64> {
+        let prepared_bytes = self.prepare_object(object)?;
+        let object = prepared_bytes
+            .as_deref()
+            .map_or(object, IsarObject::from_bytes);
+
+        let (id, existed) = if let Some(id) = id {
+            let existed = self.delete_internal(cursors, false, change_set.as_deref_mut(), id)?;
+            if self.update_auto_increment(id) {
+                self.persist_auto_increment(cursors)?;
+            }
+            (id, existed)
         } else {
-            self.auto_increment_internal()?
+            (self.next_id(cursors)?, false)
         };
 
         for index in &self.indexes {
-            index.create_for_object(cursors, id, object, |id| {
+            let replace = replace.unwrap_or(index.replace);
+            index.create_for_object_with(cursors, id, object, replace, |id| {
                 self.delete_internal(cursors, true, change_set.as_deref_mut(), id)?;
                 Ok(())
             })?;
         }
 
+        for constraint in &self.unique_constraints {
+            constraint.create_for_object(cursors, self.db, id, object)?;
+        }
+
         let mut cursor = cursors.get_cursor(self.db)?;
         cursor.put(&id, object.as_bytes())?;
+        self.invalidate_cached_object(cursors, Some(id));
+        self.delete_tombstone(cursors, id)?;
         if let Some(change_set) = change_set {
             change_set.register_change(self.id, id, object);
+            let op = if existed {
+                ChangeOp::Updated
+            } else {
+                ChangeOp::Created
+            };
+            change_set.record(self.id, Some(id), op);
         }
         Ok(id)
     }
 
-    pub fn delete(&self, txn: &mut IsarTxn, id: i64) -> Result<bool> {
-        txn.write(self.instance_id, |cursors, change_set| {
-            self.delete_internal(cursors, true, change_set, id)
+    pub(crate) fn get_id_key(col_id: u64, id: i64) -> IndexKey {
+        let mut key = IndexKey::new();
+        key.add_hash(col_id);
+        key.add_long(id);
+        key
+    }
+
+    fn get_versions_db(&self) -> Result<Db> {
+        if let Some(versions_db) = self.versions_db {
+            Ok(versions_db)
+        } else {
+            Err(IsarError::ReadOnly {})
+        }
+    }
+
+    fn get_version_internal(&self, cursors: &IsarCursors, id: i64) -> Result<Option<u64>> {
+        if let Some(versions_db) = self.versions_db {
+            let mut cursor = cursors.get_cursor(versions_db)?;
+            let key = Self::get_id_key(self.id, id);
+            if let Some((_, version)) = cursor.move_to(&key)? {
+                let version = u64::from_le_bytes(version.try_into().unwrap());
+                return Ok(Some(version));
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn get_version(&self, txn: &mut IsarTxn, id: i64) -> Result<Option<u64>> {
+        txn.read(self.instance_id, |cursors| {
+            self.get_version_internal(cursors, id)
         })
     }
 
-    pub fn delete_by_index(
+    /// Only writes the object if `version` is greater than the stored version of the object.
+    pub fn put_if_newer(
         &self,
         txn: &mut IsarTxn,
-        index_id: u64,
-        key: &IndexKey,
+        id: i64,
+        object: IsarObject,
+        version: u64,
     ) -> Result<bool> {
-        let index = self.get_index_by_id(index_id)?;
+        let versions_db = self.get_versions_db()?;
         txn.write(self.instance_id, |cursors, change_set| {
-            if let Some(id) = index.get_id(cursors, key)? {
-                self.delete_internal(cursors, true, change_set, id)?;
-                Ok(true)
-            } else {
-                Ok(false)
+            let current_version = self.get_version_internal(cursors, id)?;
+            if current_version.is_some_and(|current| current >= version) {
+                return Ok(false);
             }
-        })
+
+            self.put_internal(cursors, change_set, Some(id), object)?;
+            let mut cursor = cursors.get_cursor(versions_db)?;
+            let key = Self::get_id_key(self.id, id);
+            cursor.put(&key, &version.to_le_bytes())?;
+            Ok(true)
+        })
+    }
+
+    fn delete_version(&self, cursors: &IsarCursors, id: i64) -> Result<()> {
+        if let Some(versions_db) = self.versions_db {
+            let mut cursor = cursors.get_cursor(versions_db)?;
+            let key = Self::get_id_key(self.id, id);
+            if cursor.move_to(&key)?.is_some() {
+                cursor.delete_current()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Tombstones contain the time of deletion followed by the hidden object.
+    fn put_tombstone(&self, cursors: &IsarCursors, id: i64, object: IsarObject) -> Result<()> {
+        if let Some(tombstones_db) = self.tombstones_db {
+            let deleted_at = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_millis() as u64);
+            let mut tombstone = deleted_at.to_le_bytes().to_vec();
+            tombstone.extend_from_slice(object.as_bytes());
+            let mut cursor = cursors.get_cursor(tombstones_db)?;
+            let key = Self::get_id_key(self.id, id);
+            cursor.put(&key, &tombstone)?;
+        }
+        Ok(())
+    }
+
+    fn deleted_at(tombstone: &[u8]) -> u64 {
+        u64::from_le_bytes(tombstone[..8].try_into().unwrap())
+    }
+
+    fn tombstone_id(key: &[u8]) -> i64 {
+        (u64::from_be_bytes(key[8..].try_into().unwrap()) ^ 1 << 63) as i64
+    }
+
+    fn delete_tombstone(&self, cursors: &IsarCursors, id: i64) -> Result<()> {
+        if let Some(tombstones_db) = self.tombstones_db {
+            let mut cursor = cursors.get_cursor(tombstones_db)?;
+            let key = Self::get_id_key(self.id, id);
+            if cursor.move_to(&key)?.is_some() {
+                cursor.delete_current()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Ids of deleted objects and the time of deletion in milliseconds since the epoch. Only
+    /// collections with soft delete keep tombstones.
+    pub fn tombstones(&self, txn: &mut IsarTxn) -> Result<Vec<(i64, u64)>> {
+        let tombstones_db = if let Some(tombstones_db) = self.tombstones_db {
+            tombstones_db
+        } else {
+            return Ok(vec![]);
+        };
+        txn.read(self.instance_id, |cursors| {
+            let mut cursor = cursors.get_cursor(tombstones_db)?;
+            let lower = Self::get_id_key(self.id, i64::MIN);
+            let upper = Self::get_id_key(self.id, i64::MAX);
+            let mut tombstones = vec![];
+            cursor.iter_between(&lower, &upper, false, false, true, |_, key, tombstone| {
+                tombstones.push((Self::tombstone_id(key), Self::deleted_at(tombstone)));
+                Ok(true)
+            })?;
+            Ok(tombstones)
+        })
+    }
+
+    /// Returns a hidden deleted object and the time of deletion.
+    pub fn get_deleted<'txn>(
+        &self,
+        txn: &'txn mut IsarTxn,
+        id: i64,
+    ) -> Result<Option<(IsarObject<'txn>, u64)>> {
+        let tombstones_db = if let Some(tombstones_db) = self.tombstones_db {
+            tombstones_db
+        } else {
+            return Ok(None);
+        };
+        let txn: &'txn IsarTxn = txn;
+        txn.read_shared(self.instance_id, |cursors| {
+            let mut cursor = cursors.get_cursor(tombstones_db)?;
+            let key = Self::get_id_key(self.id, id);
+            if let Some((_, tombstone)) = cursor.move_to(&key)? {
+                let object = self.read_object(&tombstone[8..])?;
+                let object = self.property_transforms().decode(txn, object)?;
+                Ok(Some((object, Self::deleted_at(tombstone))))
+            } else {
+                Ok(None)
+            }
+        })
+    }
+
+    /// Puts a hidden deleted object back into the collection. Returns `false` if there is no
+    /// deleted object with the id.
+    pub fn restore(&self, txn: &mut IsarTxn, id: i64) -> Result<bool> {
+        let tombstones_db = if let Some(tombstones_db) = self.tombstones_db {
+            tombstones_db
+        } else {
+            return Ok(false);
+        };
+        txn.write(self.instance_id, |cursors, change_set| {
+            let mut cursor = cursors.get_cursor(tombstones_db)?;
+            let key = Self::get_id_key(self.id, id);
+            let bytes = if let Some((_, tombstone)) = cursor.move_to(&key)? {
+                tombstone[8..].to_vec()
+            } else {
+                return Ok(false);
+            };
+            drop(cursor);
+            // the hidden object is encoded, put encodes it again
+            let decoded_bytes = self
+                .property_transforms()
+                .decode_bytes(IsarObject::from_bytes(&bytes))?;
+            let object = IsarObject::from_bytes(decoded_bytes.as_deref().unwrap_or(&bytes));
+            self.put_internal(cursors, change_set, Some(id), object)?;
+            Ok(true)
+        })
+    }
+
+    /// Permanently removes the hidden objects deleted before `deleted_before` or all hidden
+    /// objects including their links and blobs. Returns the number of removed tombstones.
+    pub fn purge_tombstones(&self, txn: &mut IsarTxn, deleted_before: Option<u64>) -> Result<u32> {
+        let tombstones_db = if let Some(tombstones_db) = self.tombstones_db {
+            tombstones_db
+        } else {
+            return Ok(0);
+        };
+        txn.write(self.instance_id, |cursors, _| {
+            let mut cursor = cursors.get_cursor(tombstones_db)?;
+            let lower = Self::get_id_key(self.id, i64::MIN);
+            let upper = Self::get_id_key(self.id, i64::MAX);
+            let mut purged = vec![];
+            cursor.iter_between(
+                &lower,
+                &upper,
+                false,
+                false,
+                true,
+                |cursor, key, tombstone| {
+                    let deleted_at = Self::deleted_at(tombstone);
+                    if deleted_before.is_none_or(|before| deleted_at < before) {
+                        purged.push(Self::tombstone_id(key));
+                        cursor.delete_current()?;
+                    }
+                    Ok(true)
+                },
+            )?;
+            drop(cursor);
+            for id in &purged {
+                self.delete_links_and_blobs(cursors, *id)?;
+            }
+            Ok(purged.len() as u32)
+        })
+    }
+
+    fn get_blobs(&self) -> Result<&IsarBlobs> {
+        if let Some(blobs) = &self.blobs {
+            Ok(blobs)
+        } else {
+            Err(IsarError::ReadOnly {})
+        }
+    }
+
+    /// Appends `bytes` to the blob of an existing object. Large blobs can be written in
+    /// multiple steps without keeping them in memory.
+    pub fn append_blob(&self, txn: &mut IsarTxn, id: i64, name: &str, bytes: &[u8]) -> Result<()> {
+        let blobs = self.get_blobs()?;
+        let blob_id = blobs.blob_id(name)?;
+        txn.write(self.instance_id, |cursors, change_set| {
+            let mut cursor = cursors.get_cursor(self.db)?;
+            let object = if let Some((_, object)) = cursor.move_to(&id)? {
+                IsarObject::from_bytes(object)
+            } else {
+                return illegal_arg("Object does not exist.");
+            };
+            blobs.append(cursors, id, blob_id, bytes)?;
+            if let Some(change_set) = change_set {
+                change_set.register_change(self.id, id, object);
+            }
+            Ok(())
+        })
+    }
+
+    /// Calls `callback` with the chunks of the blob in order until it returns `false`. Returns
+    /// `false` if the blob is empty or does not exist.
+    pub fn read_blob<'txn, F>(
+        &self,
+        txn: &'txn mut IsarTxn,
+        id: i64,
+        name: &str,
+        callback: F,
+    ) -> Result<bool>
+    where
+        F: FnMut(&'txn [u8]) -> Result<bool>,
+    {
+        let blobs = if let Some(blobs) = &self.blobs {
+            blobs
+        } else {
+            return Ok(false);
+        };
+        let blob_id = blobs.blob_id(name)?;
+        txn.read(self.instance_id, |cursors| {
+            blobs.read(cursors, id, blob_id, callback)
+        })
+    }
+
+    /// Reads a single chunk of the blob. Chunks written by a single append have 64 KiB except for
+    /// the last one.
+    pub fn read_blob_chunk<'txn>(
+        &self,
+        txn: &'txn mut IsarTxn,
+        id: i64,
+        name: &str,
+        chunk: u64,
+    ) -> Result<Option<&'txn [u8]>> {
+        let blobs = if let Some(blobs) = &self.blobs {
+            blobs
+        } else {
+            return Ok(None);
+        };
+        let blob_id = blobs.blob_id(name)?;
+        txn.read(self.instance_id, |cursors| {
+            blobs.read_chunk(cursors, id, blob_id, chunk)
+        })
+    }
+
+    /// Returns `false` if the blob did not exist.
+    pub fn delete_blob(&self, txn: &mut IsarTxn, id: i64, name: &str) -> Result<bool> {
+        let blobs = self.get_blobs()?;
+        let blob_id = blobs.blob_id(name)?;
+        txn.write(self.instance_id, |cursors, change_set| {
+            let deleted = blobs.delete(cursors, id, blob_id)?;
+            if deleted {
+                if let Some(change_set) = change_set {
+                    let mut cursor = cursors.get_cursor(self.db)?;
+                    if let Some((_, object)) = cursor.move_to(&id)? {
+                        change_set.register_change(self.id, id, IsarObject::from_bytes(object));
+                    }
+                }
+            }
+            Ok(deleted)
+        })
+    }
+
+    pub(crate) fn delete_id_keys(
+        cursors: &IsarCursors,
+        versions_db: Db,
+        col_id: u64,
+    ) -> Result<()> {
+        let mut cursor = cursors.get_cursor(versions_db)?;
+        let lower = Self::get_id_key(col_id, i64::MIN);
+        let upper = Self::get_id_key(col_id, i64::MAX);
+        cursor.iter_between(&lower, &upper, false, false, true, |cursor, _, _| {
+            cursor.delete_current()?;
+            Ok(true)
+        })?;
+        Ok(())
+    }
+    /// Moves the entries of the collection `col_id` to the collection `new_col_id`.
+    pub(crate) fn move_id_keys(
+        cursors: &IsarCursors,
+        db: Db,
+        col_id: u64,
+        new_col_id: u64,
+    ) -> Result<()> {
+        let mut cursor = cursors.get_cursor(db)?;
+        let lower = Self::get_id_key(col_id, i64::MIN);
+        let upper = Self::get_id_key(col_id, i64::MAX);
+        let mut entries = vec![];
+        cursor.iter_between(&lower, &upper, false, false, true, |cursor, key, value| {
+            let mut new_key = new_col_id.to_be_bytes().to_vec();
+            new_key.extend_from_slice(&key[8..]);
+            entries.push((new_key, value.to_vec()));
+            cursor.delete_current()?;
+            Ok(true)
+        })?;
+        for (key, value) in entries {
+            cursor.put(&IndexKey::from_bytes(key), &value)?;
+        }
+        Ok(())
+    }
+
+    /// Updates only the given properties of an existing object. Returns `false` if the object
+    /// does not exist.
+    pub fn patch(
+        &self,
+        txn: &mut IsarTxn,
+        id: i64,
+        changes: &[(&Property, Value)],
+    ) -> Result<bool> {
+        for (property, _) in changes {
+            if !self.properties.contains(*property) {
+                illegal_arg("Property does not belong to this collection.")?;
+            }
+        }
+
+        // derived properties change with their source
+        let changed = |property: &Property| {
+            let is_changed = |property: &Property| changes.iter().any(|(p, _)| *p == property);
+            is_changed(property)
+                || self
+                    .derived
+                    .iter()
+                    .any(|(source, derived, _)| derived == property && is_changed(source))
+        };
+
+        txn.write(self.instance_id, |cursors, mut change_set| {
+            let mut cursor = cursors.get_cursor(self.db)?;
+            let old_bytes = if let Some((_, bytes)) = cursor.move_to(&id)? {
+                bytes.to_vec()
+            } else {
+                return Ok(false);
+            };
+            drop(cursor);
+            let stored_object = IsarObject::from_bytes(&old_bytes);
+            // the stored object is encoded
+            let decoded_bytes = self.property_transforms().decode_bytes(stored_object)?;
+            let old_object = decoded_bytes
+                .as_deref()
+                .map_or(stored_object, IsarObject::from_bytes);
+
+            let mut ob = ObjectBuilder::new(&self.properties, None);
+            for property in &self.properties {
+                if let Some((_, value)) = changes.iter().find(|(p, _)| *p == property) {
+                    JsonEncodeDecode::decode_property(
+                        property,
+                        &self.embedded_properties,
+                        &mut ob,
+                        value,
+                        false,
+                    )?;
+                } else {
+                    ob.write_from(property, old_object);
+                }
+            }
+            let new_object = ob.finish();
+            let prepared_bytes = self.prepare_object(new_object)?;
+            let new_object = prepared_bytes
+                .as_deref()
+                .map_or(new_object, IsarObject::from_bytes);
+
+            for index in &self.indexes {
+                if index.properties.iter().any(|p| changed(&p.property)) {
+                    index.delete_for_object(cursors, id, stored_object)?;
+                    index.create_for_object(cursors, id, new_object, |id| {
+                        self.delete_internal(cursors, true, change_set.as_deref_mut(), id)?;
+                        Ok(())
+                    })?;
+                }
+            }
+            for constraint in &self.unique_constraints {
+                if constraint.properties.iter().any(changed) {
+                    constraint.delete_for_object(cursors, id, stored_object)?;
+                    constraint.create_for_object(cursors, self.db, id, new_object)?;
+                }
+            }
+
+            let mut cursor = cursors.get_cursor(self.db)?;
+            cursor.put(&id, new_object.as_bytes())?;
+            self.invalidate_cached_object(cursors, Some(id));
+            if let Some(change_set) = change_set {
+                change_set.register_delete(self.id, id, stored_object);
+                change_set.register_change(self.id, id, new_object);
+                change_set.record(self.id, Some(id), ChangeOp::Updated);
+            }
+            Ok(true)
+        })
+    }
+
+    pub fn delete(&self, txn: &mut IsarTxn, id: i64) -> Result<bool> {
+        txn.write(self.instance_id, |cursors, change_set| {
+            self.delete_internal(cursors, true, change_set, id)
+        })
+    }
+
+    pub fn delete_by_index(
+        &self,
+        txn: &mut IsarTxn,
+        index_id: u64,
+        key: &IndexKey,
+    ) -> Result<bool> {
+        let index = self.get_built_index_by_id(index_id)?;
+        index.validate_key(key)?;
+        txn.write(self.instance_id, |cursors, change_set| {
+            if let Some(id) = index.get_id(cursors, key)? {
+                self.delete_internal(cursors, true, change_set, id)?;
+                Ok(true)
+            } else {
+                Ok(false)
+            }
+        })
+    }
+
+    pub fn delete_all(&self, txn: &mut IsarTxn, ids: &[i64]) -> Result<u32> {
+        txn.write(self.instance_id, |cursors, mut change_set| {
+            let mut count = 0;
+            for id in ids {
+                if self.delete_internal(cursors, true, change_set.as_deref_mut(), *id)? {
+                    count += 1;
+                }
+            }
+            Ok(count)
+        })
     }
 
-    fn delete_internal(
+    pub fn delete_all_by_index(
+        &self,
+        txn: &mut IsarTxn,
+        index_id: u64,
+        keys: &[IndexKey],
+    ) -> Result<u32> {
+        let index = self.get_built_index_by_id(index_id)?;
+        for key in keys {
+            index.validate_key(key)?;
+        }
+        txn.write(self.instance_id, |cursors, mut change_set| {
+            let mut count = 0;
+            for key in keys {
+                if let Some(id) = index.get_id(cursors, key)? {
+                    self.delete_internal(cursors, true, change_set.as_deref_mut(), id)?;
+                    count += 1;
+                }
+            }
+            Ok(count)
+        })
+    }
+
+    pub(crate) fn delete_internal(
         &self,
         cursors: &IsarCursors,
         delete_links: bool,
@@ -237,16 +1398,24 @@ impl IsarCollection {
             for index in &self.indexes {
                 index.delete_for_object(cursors, id, object)?;
             }
+            for constraint in &self.unique_constraints {
+                constraint.delete_for_object(cursors, id, object)?;
+            }
             if delete_links {
-                for link in &self.links {
-                    link.delete_all_for_object(cursors, id)?;
-                }
-                for link in &self.backlinks {
-                    link.delete_all_for_object(cursors, id)?;
+                self.delete_version(cursors, id)?;
+                if self.tombstones_db.is_some() {
+                    // hidden objects keep their links and blobs until they are purged
+                    self.put_tombstone(cursors, id, object)?;
+                } else {
+                    self.delete_links_and_blobs(cursors, id)?;
                 }
             }
+            self.invalidate_cached_object(cursors, Some(id));
             if let Some(change_set) = change_set {
-                change_set.register_change(self.id, id, object);
+                change_set.register_delete(self.id, id, object);
+                if delete_links {
+                    change_set.record(self.id, Some(id), ChangeOp::Deleted);
+                }
             }
             cursor.delete_current()?;
             Ok(true)
@@ -255,6 +1424,19 @@ impl IsarCollection {
         }
     }
 
+    fn delete_links_and_blobs(&self, cursors: &IsarCursors, id: i64) -> Result<()> {
+        if let Some(blobs) = &self.blobs {
+            blobs.delete_for_object(cursors, id)?;
+        }
+        for link in &self.links {
+            link.delete_all_for_object(cursors, id)?;
+        }
+        for link in &self.backlinks {
+            link.delete_all_for_object(cursors, id)?;
+        }
+        Ok(())
+    }
+
     pub(crate) fn get_link_backlink(&self, link_id: u64) -> Result<&IsarLink> {
         if let Some(link) = self.links.iter().find(|l| l.id == link_id) {
             Ok(link)
@@ -265,6 +1447,21 @@ impl IsarCollection {
         }
     }
 
+    /// Looks up links before backlinks. Backlinks that are not declared in the schema have the
+    /// name of the link in the other collection.
+    pub fn link_id(&self, name: &str) -> Result<u64> {
+        let link = self
+            .links
+            .iter()
+            .chain(&self.backlinks)
+            .find(|l| l.name == name);
+        if let Some(link) = link {
+            Ok(link.id)
+        } else {
+            illegal_arg("IsarLink does not exist")
+        }
+    }
+
     pub fn link(&self, txn: &mut IsarTxn, link_id: u64, id: i64, target_id: i64) -> Result<bool> {
         let link = self.get_link_backlink(link_id)?;
         txn.write(self.instance_id, |cursors, _| {
@@ -279,6 +1476,109 @@ impl IsarCollection {
         })
     }
 
+    pub fn link_count(&self, txn: &mut IsarTxn, link_id: u64, id: i64) -> Result<usize> {
+        let link = self.get_link_backlink(link_id)?;
+        txn.read(self.instance_id, |cursors| link.count(cursors, id))
+    }
+
+    /// Links the target at `index` of an ordered link or moves it there if it is already linked.
+    pub fn link_at(
+        &self,
+        txn: &mut IsarTxn,
+        link_id: u64,
+        id: i64,
+        target_id: i64,
+        index: usize,
+    ) -> Result<bool> {
+        let link = self.get_link_backlink(link_id)?;
+        txn.write(self.instance_id, |cursors, change_set| {
+            let linked = link.insert_at(cursors, id, target_id, index)?;
+            if let Some(change_set) = change_set.filter(|_| linked) {
+                change_set.record(self.id, Some(id), ChangeOp::Updated);
+            }
+            Ok(linked)
+        })
+    }
+
+    /// Moves a target of an ordered link to `index`.
+    pub fn move_link(
+        &self,
+        txn: &mut IsarTxn,
+        link_id: u64,
+        id: i64,
+        target_id: i64,
+        index: usize,
+    ) -> Result<bool> {
+        let link = self.get_link_backlink(link_id)?;
+        txn.write(self.instance_id, |cursors, change_set| {
+            let moved = link.move_to(cursors, id, target_id, index)?;
+            if let Some(change_set) = change_set.filter(|_| moved) {
+                change_set.record(self.id, Some(id), ChangeOp::Updated);
+            }
+            Ok(moved)
+        })
+    }
+
+    /// Ids of the linked objects. Ordered links return them in order.
+    pub fn get_link_ids(&self, txn: &mut IsarTxn, link_id: u64, id: i64) -> Result<Vec<i64>> {
+        let link = self.get_link_backlink(link_id)?;
+        txn.read(self.instance_id, |cursors| {
+            let mut ids = vec![];
+            link.iter(cursors, id, |id, _| {
+                ids.push(id);
+                Ok(true)
+            })?;
+            Ok(ids)
+        })
+    }
+
+    /// Links all targets in a single write. Returns the number of created links.
+    pub fn link_all(
+        &self,
+        txn: &mut IsarTxn,
+        link_id: u64,
+        id: i64,
+        target_ids: &[i64],
+    ) -> Result<u32> {
+        let link = self.get_link_backlink(link_id)?;
+        txn.write(self.instance_id, |cursors, mut change_set| {
+            let mut count = 0;
+            for target_id in target_ids {
+                // targets that are already linked are skipped
+                if !link.exists(cursors, id, *target_id)? && link.create(cursors, id, *target_id)? {
+                    count += 1;
+                    if let Some(change_set) = change_set.as_deref_mut() {
+                        change_set.record(self.id, Some(id), ChangeOp::Updated);
+                    }
+                }
+            }
+            Ok(count)
+        })
+    }
+
+    /// Unlinks all targets in a single write. Returns the number of removed links.
+    pub fn unlink_all_targets(
+        &self,
+        txn: &mut IsarTxn,
+        link_id: u64,
+        id: i64,
+        target_ids: &[i64],
+    ) -> Result<u32> {
+        let link = self.get_link_backlink(link_id)?;
+        txn.write(self.instance_id, |cursors, mut change_set| {
+            let mut count = 0;
+            for target_id in target_ids {
+                if link.delete(cursors, id, *target_id)? {
+                    count += 1;
+                    if let Some(change_set) = change_set.as_deref_mut() {
+                        change_set.record(self.id, Some(id), ChangeOp::Updated);
+                    }
+                }
+            }
+            Ok(count)
+        })
+    }
+
     pub fn unlink_all(&self, txn: &mut IsarTxn, link_id: u64, id: i64) -> Result<()> {
         let link = self.get_link_backlink(link_id)?;
         txn.write(self.instance_id, |cursors, _| {
@@ -287,23 +1587,79 @@ impl IsarCollection {
     }
 
     pub fn clear(&self, txn: &mut IsarTxn) -> Result<()> {
+        self.clear_internal(txn, false)
+    }
+
+    /// Clears the collection including its links and resets the auto increment even if ids are
+    /// never reused. Watchers of the collection are notified with a distinct clear event.
+    pub fn truncate(&self, txn: &mut IsarTxn) -> Result<()> {
+        self.clear_internal(txn, true)
+    }
+
+    fn clear_internal(&self, txn: &mut IsarTxn, truncate: bool) -> Result<()> {
+        // hidden objects keep their links and blobs until they are purged
+        let keep_links = !truncate && self.tombstones_db.is_some();
         txn.write(self.instance_id, |cursors, change_set| {
             for index in &self.indexes {
-                index.clear(cursors)?;
-            }
-            for link in &self.links {
-                link.clear(cursors)?;
+                index.clear(cursors)?;
+            }
+            for constraint in &self.unique_constraints {
+                constraint.clear(cursors)?;
+            }
+            if !keep_links {
+                for link in &self.links {
+                    link.clear(cursors)?;
+                }
+                for link in &self.backlinks {
+                    link.clear(cursors)?;
+                }
+            }
+            if truncate {
+                if let Some(tombstones_db) = self.tombstones_db {
+                    Self::delete_id_keys(cursors, tombstones_db, self.id)?;
+                }
+            } else if self.tombstones_db.is_some() {
+                let mut cursor = cursors.get_cursor(self.db)?;
+                cursor.iter_all(false, true, |_, id, object| {
+                    self.put_tombstone(cursors, id.to_id(), IsarObject::from_bytes(object))?;
+                    Ok(true)
+                })?;
+            }
+            cursors.clear_db(self.db)?;
+            if let Some(blobs) = self.blobs.as_ref().filter(|_| !keep_links) {
+                blobs.clear(cursors)?;
+            }
+            self.invalidate_cached_object(cursors, None);
+            if let Some(versions_db) = self.versions_db {
+                Self::delete_id_keys(cursors, versions_db, self.id)?;
+            }
+            if truncate || self.auto_increment_db.is_none() {
+                self.auto_increment.set(0);
+                self.persist_auto_increment(cursors)?;
+            }
+
+            if let Some(change_set) = change_set {
+                if truncate {
+                    change_set.register_clear(self.id);
+                } else {
+                    change_set.register_all(self.id);
+                }
+                change_set.record(self.id, None, ChangeOp::Cleared);
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Opens all index and link dbs that have not been used yet.
+    pub fn warm_up(&self, txn: &mut IsarTxn) -> Result<()> {
+        txn.read(self.instance_id, |cursors| {
+            for index in &self.indexes {
+                index.warm_up(cursors)?;
             }
-            for link in &self.backlinks {
-                link.clear(cursors)?;
-            }
-            cursors.clear_db(self.db)?;
-            self.auto_increment.set(0);
-
-            if let Some(change_set) = change_set {
-                change_set.register_all(self.id);
+            for link in self.links.iter().chain(&self.backlinks) {
+                link.warm_up(cursors)?;
             }
-
             Ok(())
         })
     }
@@ -337,46 +1693,228 @@ impl IsarCollection {
         })
     }
 
+    pub fn stats(&self, txn: &mut IsarTxn) -> Result<Vec<DbStats>> {
+        txn.read(self.instance_id, |cursors| {
+            let mut stats = vec![DbStats::new(
+                self.name.clone(),
+                cursors.db_raw_stat(self.db)?,
+            )];
+            for index in &self.indexes {
+                stats.push(index.get_stats(cursors, &self.name)?);
+            }
+            for link in &self.links {
+                stats.extend(link.get_stats(cursors, &self.name)?);
+            }
+            Ok(stats)
+        })
+    }
+
+    pub fn index_stats(&self, txn: &mut IsarTxn, index_name: &str) -> Result<IndexStats> {
+        let index = self
+            .indexes
+            .iter()
+            .find(|i| i.name == index_name)
+            .ok_or(IsarError::UnknownIndex {})?;
+        txn.read(self.instance_id, |cursors| index.get_index_stats(cursors))
+    }
+
+    pub fn size_histogram(&self, txn: &mut IsarTxn) -> Result<SizeHistogram> {
+        txn.read(self.instance_id, |cursors| {
+            let mut histogram = SizeHistogram {
+                count: 0,
+                total_bytes: 0,
+                max_bytes: 0,
+                buckets: vec![],
+                property_bytes: self
+                    .properties
+                    .iter()
+                    .map(|p| (p.name.clone(), 0))
+                    .collect(),
+            };
+            let mut cursor = cursors.get_cursor(self.db)?;
+            cursor.iter_all(false, true, |_, _, bytes| {
+                let size = bytes.len() as u64;
+                let bucket = (u64::BITS - size.leading_zeros()).saturating_sub(1) as usize;
+                if histogram.buckets.len() <= bucket {
+                    histogram.buckets.resize(bucket + 1, 0);
+                }
+                histogram.buckets[bucket] += 1;
+                histogram.count += 1;
+                histogram.total_bytes += size;
+                histogram.max_bytes = histogram.max_bytes.max(size);
+
+                let object = IsarObject::from_bytes(bytes);
+                for (property, (_, total)) in
+                    self.properties.iter().zip(&mut histogram.property_bytes)
+                {
+                    *total += object.property_size(property) as u64;
+                }
+                Ok(true)
+            })?;
+            Ok(histogram)
+        })
+    }
+
     pub fn import_json(&self, txn: &mut IsarTxn, id_name: Option<&str>, json: Value) -> Result<()> {
+        self.import_json_with_options(txn, id_name, json, &JsonDecodeOptions::default())
+    }
+
+    pub fn import_json_with_options(
+        &self,
+        txn: &mut IsarTxn,
+        id_name: Option<&str>,
+        json: Value,
+        options: &JsonDecodeOptions,
+    ) -> Result<()> {
         txn.write(self.instance_id, |cursors, mut change_set| {
             let array = json.as_array().ok_or(IsarError::InvalidJson {})?;
-            let mut ob_result_cache = None;
+            let mut ob_result_cache = Some(self.buffer_pool.get(0));
             for value in array {
-                let id = if let Some(id_name) = id_name {
-                    if let Some(id) = value.get(id_name) {
-                        let id = id.as_i64().ok_or(IsarError::InvalidJson {})?;
-                        Some(id)
-                    } else {
-                        None
-                    }
-                } else {
-                    None
-                };
-
-                let mut ob = ObjectBuilder::new(&self.properties, ob_result_cache);
-                JsonEncodeDecode::decode(
-                    &self.properties,
-                    &self.embedded_properties,
-                    &mut ob,
-                    value,
-                )?;
+                let (id, ob) = self.decode_json(id_name, value, options, ob_result_cache)?;
                 let object = ob.finish();
                 self.put_internal(cursors, change_set.as_deref_mut(), id, object)?;
                 ob_result_cache = Some(ob.recycle());
             }
+            if let Some(buffer) = ob_result_cache {
+                self.buffer_pool.put(buffer);
+            }
             Ok(())
         })
     }
 
-    pub(crate) fn fill_indexes(&self, index_ids: &[u64], cursors: &IsarCursors) -> Result<()> {
+    pub(crate) fn decode_json(
+        &self,
+        id_name: Option<&str>,
+        value: &Value,
+        options: &JsonDecodeOptions,
+        buffer: Option<Vec<u8>>,
+    ) -> Result<(Option<i64>, ObjectBuilder)> {
+        let id = if let Some(id_name) = id_name {
+            if let Some(id) = value.get(id_name) {
+                let id = id.as_i64().ok_or(IsarError::InvalidJson {})?;
+                Some(id)
+            } else {
+                None
+            }
+        } else {
+            None
+        };
+
+        let mut ob = ObjectBuilder::new(&self.properties, buffer);
+        JsonEncodeDecode::decode_with_options(
+            &self.properties,
+            &self.embedded_properties,
+            &mut ob,
+            value,
+            options,
+        )?;
+        Ok((id, ob))
+    }
+
+    fn layout_hash(&self) -> u64 {
+        let mut layout = vec![];
+        let mut add_properties = |properties: &[Property]| {
+            for property in properties {
+                layout.extend_from_slice(property.name.as_bytes());
+                layout.push(property.data_type as u8);
+                layout.extend_from_slice(&(property.offset as u32).to_le_bytes());
+                layout.extend_from_slice(&property.target_id.unwrap_or(0).to_le_bytes());
+            }
+        };
+        add_properties(&self.properties);
+        let mut embedded_ids = self.embedded_properties.keys().copied().collect_vec();
+        embedded_ids.sort_unstable();
+        for embedded_id in embedded_ids {
+            add_properties(self.embedded_properties.get(embedded_id).unwrap());
+        }
+        xxh3_64(&layout)
+    }
+
+    /// Exports all objects as a header followed by id, length and bytes of each object. The
+    /// header contains a hash of the object layout so `import_raw` can reject incompatible data.
+    pub fn export_raw(&self, txn: &mut IsarTxn) -> Result<Vec<u8>> {
+        txn.read(self.instance_id, |cursors| {
+            let mut bytes = Self::RAW_MAGIC.to_vec();
+            bytes.extend_from_slice(&self.layout_hash().to_le_bytes());
+            let mut cursor = cursors.get_cursor(self.db)?;
+            cursor.iter_all(false, true, |_, id_bytes, object| {
+                bytes.extend_from_slice(&id_bytes.to_id().to_le_bytes());
+                bytes.extend_from_slice(&(object.len() as u32).to_le_bytes());
+                bytes.extend_from_slice(object);
+                Ok(true)
+            })?;
+            Ok(bytes)
+        })
+    }
+
+    /// Imports objects created by `export_raw`. Returns the number of imported objects.
+    pub fn import_raw(&self, txn: &mut IsarTxn, bytes: &[u8]) -> Result<u32> {
+        let header_len = Self::RAW_MAGIC.len() + 8;
+        if bytes.len() < header_len || bytes[..Self::RAW_MAGIC.len()] != Self::RAW_MAGIC {
+            return illegal_arg("Invalid raw export.");
+        }
+        let hash = u64::from_le_bytes(bytes[Self::RAW_MAGIC.len()..header_len].try_into().unwrap());
+        if hash != self.layout_hash() {
+            return Err(IsarError::SchemaMismatch {});
+        }
+
+        txn.write(self.instance_id, |cursors, mut change_set| {
+            let mut count = 0;
+            let mut offset = header_len;
+            while offset < bytes.len() {
+                if bytes.len() - offset < 12 {
+                    return Err(IsarError::InvalidObject {});
+                }
+                let id = i64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap());
+                let len =
+                    u32::from_le_bytes(bytes[offset + 8..offset + 12].try_into().unwrap()) as usize;
+                offset += 12;
+                if bytes.len() - offset < len {
+                    return Err(IsarError::InvalidObject {});
+                }
+                let object = IsarObject::from_bytes(&bytes[offset..offset + len]);
+                if !object.is_valid(&self.properties, &self.embedded_properties) {
+                    return Err(IsarError::InvalidObject {});
+                }
+                self.put_internal(cursors, change_set.as_deref_mut(), Some(id), object)?;
+                offset += len;
+                count += 1;
+            }
+            Ok(count)
+        })
+    }
+
+    pub(crate) fn fill_indexes(
+        &self,
+        index_ids: &[u64],
+        cursors: &IsarCursors,
+        options: Option<&IndexFillOptions>,
+    ) -> Result<()> {
         let indexes = index_ids
             .iter()
             .map(|id| self.get_index_by_id(*id).unwrap())
             .collect_vec();
 
+        let progress = options.and_then(|o| o.progress.as_ref());
+        let cancel = options.and_then(|o| o.cancel.as_ref());
+        let (total, _) = cursors.db_stat(self.db)?;
+
+        let mut count = 0;
         let mut cursor = cursors.get_cursor(self.db)?;
         cursor.iter_all(false, true, |cursor, id_bytes, object| {
             let id = id_bytes.to_id();
+            count += 1;
+
+            if let Some(cancel) = cancel {
+                if cancel.load(Ordering::Relaxed) {
+                    return Err(IsarError::Cancelled {});
+                }
+            }
+            if let Some(progress) = progress {
+                if count % Self::INDEX_FILL_PROGRESS_STEP == 0 {
+                    progress(&self.name, count as u64, total);
+                }
+            }
 
             // The object might become invalid if another one is deleted by an index. TODO: Find a better solution
             let bytes = object.to_vec();
@@ -393,6 +1931,160 @@ impl IsarCollection {
             }
             Ok(true)
         })?;
+        if let Some(progress) = progress {
+            progress(&self.name, count as u64, total);
+        }
+        isar_log!(LogEvent::IndexFill {
+            collection: &self.name,
+            objects: count,
+        });
+        Ok(())
+    }
+
+    /// Rebuilds the unique constraints and fails if existing objects violate them.
+    pub(crate) fn fill_unique_constraints(
+        &self,
+        names: &[String],
+        cursors: &IsarCursors,
+    ) -> Result<()> {
+        let constraints = self
+            .unique_constraints
+            .iter()
+            .filter(|c| names.contains(&c.name))
+            .collect_vec();
+        for constraint in &constraints {
+            constraint.clear(cursors)?;
+        }
+        let mut cursor = cursors.get_cursor(self.db)?;
+        cursor.iter_all(false, true, |_, id, object| {
+            let object = IsarObject::from_bytes(object);
+            for constraint in &constraints {
+                constraint.create_for_object(cursors, self.db, id.to_id(), object)?;
+            }
+            Ok(true)
+        })?;
+        Ok(())
+    }
+
+    /// Fills pending indexes for up to `batch_size` objects starting at id `start`. Returns the id
+    /// to continue with or `None` if all objects have been indexed.
+    pub(crate) fn fill_pending_indexes(
+        &self,
+        index_ids: &[u64],
+        cursors: &IsarCursors,
+        start: i64,
+        batch_size: usize,
+    ) -> Result<Option<i64>> {
+        let indexes = index_ids
+            .iter()
+            .map(|id| self.get_index_by_id(*id).unwrap())
+            .collect_vec();
+
+        let mut count = 0;
+        let mut next = None;
+        let mut cursor = cursors.get_cursor(self.db)?;
+        cursor.iter_between(
+            &start,
+            &i64::MAX,
+            false,
+            false,
+            true,
+            |cursor, id_bytes, object| {
+                let id = id_bytes.to_id();
+                if count >= batch_size {
+                    next = Some(id);
+                    return Ok(false);
+                }
+                count += 1;
+
+                let bytes = object.to_vec();
+                let object = IsarObject::from_bytes(&bytes);
+
+                for index in &indexes {
+                    // objects written since the index was added already have entries
+                    index.delete_for_object(cursors, id, object)?;
+                    index.create_for_object(cursors, id, object, |id| {
+                        let deleted = self.delete_internal(cursors, true, None, id)?;
+                        if deleted {
+                            cursor.move_to_next()?;
+                        }
+                        Ok(())
+                    })?;
+                }
+                Ok(true)
+            },
+        )?;
+        isar_log!(LogEvent::IndexFill {
+            collection: &self.name,
+            objects: count,
+        });
+        Ok(next)
+    }
+
+    pub(crate) fn check_integrity(
+        &self,
+        cursors: &IsarCursors,
+        problems: &mut Vec<IntegrityProblem>,
+    ) -> Result<()> {
+        let mut cursor = cursors.get_cursor(self.db)?;
+        cursor.iter_all(false, true, |_, id_bytes, bytes| {
+            if !IsarObject::from_bytes(bytes).is_valid(&self.properties, &self.embedded_properties)
+            {
+                problems.push(IntegrityProblem::new(
+                    ProblemKind::InvalidObject,
+                    &self.name,
+                    None,
+                    id_bytes.to_id(),
+                ));
+            }
+            Ok(true)
+        })?;
+
+        // pending indexes are incomplete by design
+        for index in self.indexes.iter().filter(|i| !i.is_pending()) {
+            index.check_integrity(cursors, self.db, &self.name, problems)?;
+        }
+        for link in &self.links {
+            link.check_integrity(cursors, &self.name, problems)?;
+        }
+        Ok(())
+    }
+
+    /// Rebuilds indexes with problems and repairs links. Invalid objects are only reported.
+    pub(crate) fn repair(
+        &self,
+        cursors: &IsarCursors,
+        problems: &mut Vec<IntegrityProblem>,
+    ) -> Result<()> {
+        let mut found = vec![];
+        self.check_integrity(cursors, &mut found)?;
+
+        let broken_index_ids = self
+            .indexes
+            .iter()
+            .filter(|index| {
+                found.iter().any(|p| {
+                    matches!(
+                        p.kind,
+                        ProblemKind::MissingIndexEntry | ProblemKind::ObsoleteIndexEntry
+                    ) && p.name.as_deref() == Some(index.name.as_str())
+                })
+            })
+            .map(|index| index.id)
+            .collect_vec();
+        for index_id in &broken_index_ids {
+            self.get_index_by_id(*index_id)?.clear(cursors)?;
+        }
+        if !broken_index_ids.is_empty() {
+            self.fill_indexes(&broken_index_ids, cursors, None)?;
+        }
+
+        // link problems are reported by the link repair
+        found.retain(|p| p.target_id.is_none());
+        for link in &self.links {
+            link.repair(cursors, &self.name, &mut found)?;
+        }
+        problems.extend(found);
         Ok(())
     }
 
@@ -445,3 +2137,772 @@ impl IsarCollection {
         txn.read(self.instance_id, |cursors| link.verify(cursors, links))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::index_key::IndexValue;
+    use crate::instance::tests::{collection, find_ids, index, open_instance, put_object, reopen};
+    use crate::instance::IsarInstance;
+    use crate::query::Sort;
+    use serde_json::json;
+
+    struct Reverse;
+
+    impl PropertyTransform for Reverse {
+        fn encode(&self, value: &str) -> Result<String> {
+            Ok(value.chars().rev().collect())
+        }
+
+        fn decode(&self, value: &str) -> Result<String> {
+            Ok(value.chars().rev().collect())
+        }
+    }
+
+    fn put_json(instance: &IsarInstance, values: Value) -> i64 {
+        put_object(instance, &instance.collections()[0], None, values)
+    }
+
+    fn patch_object(instance: &IsarInstance, id: i64, name: &str, value: Value) -> Result<bool> {
+        let col = &instance.collections()[0];
+        let property = col.property(name)?;
+        let mut txn = instance.begin_txn(true, false)?;
+        let patched = col.patch(&mut txn, id, &[(property, value)])?;
+        txn.commit()?;
+        Ok(patched)
+    }
+
+    fn read_string(instance: &IsarInstance, id: i64, name: &str) -> Option<String> {
+        let col = &instance.collections()[0];
+        let offset = col.property(name).unwrap().offset;
+        let mut txn = instance.begin_txn(false, false).unwrap();
+        let object = col.get(&mut txn, id).unwrap()?;
+        object.read_string(offset).map(|s| s.to_string())
+    }
+
+    #[test]
+    fn test_patch_encodes_transformed_properties() {
+        let mut col = collection(
+            "col",
+            json!([{"name": "a", "type": "String"}, {"name": "b", "type": "String"}]),
+        );
+        col["indexes"] = json!([index("a", &[("a", true)], false)]);
+        let instance = open_instance(json!([col]));
+        let col = &instance.collections()[0];
+        col.set_property_transform("a", Arc::new(Reverse)).unwrap();
+        col.set_property_transform("b", Arc::new(Reverse)).unwrap();
+
+        let id = put_json(&instance, json!({"a": "abc", "b": "xyz"}));
+        assert!(patch_object(&instance, id, "a", json!("def")).unwrap());
+        assert_eq!(read_string(&instance, id, "a").as_deref(), Some("def"));
+        // unchanged properties must not be encoded twice
+        assert_eq!(read_string(&instance, id, "b").as_deref(), Some("xyz"));
+
+        let index = &col.indexes[0];
+        let mut key = IndexKey::new();
+        key.add_string(Some("fed"), true);
+        let mut txn = instance.begin_txn(false, false).unwrap();
+        let found = txn.read(instance.instance_id, |cursors| index.get_id(cursors, &key));
+        assert_eq!(found.unwrap(), Some(id));
+        drop(txn);
+
+        assert!(!patch_object(&instance, id + 1, "a", json!("x")).unwrap());
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_patch_verifies_enum_values() {
+        let properties = json!([{"name": "state", "type": "Long", "enumValues": [1, 2]}]);
+        let instance = open_instance(json!([collection("col", properties)]));
+        let id = put_json(&instance, json!({"state": 1}));
+        assert!(patch_object(&instance, id, "state", json!(2)).unwrap());
+        let result = patch_object(&instance, id, "state", json!(3));
+        assert!(matches!(result, Err(IsarError::IllegalArg { .. })));
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_patch_updates_derived_properties() {
+        let mut col = collection(
+            "col",
+            json!([{"name": "name", "type": "String"}, {"name": "nameLower", "type": "String"}]),
+        );
+        col["derived"] = json!([{"name": "nameLower", "source": "name", "transform": "Lowercase"}]);
+        let instance = open_instance(json!([col]));
+        let id = put_json(&instance, json!({"name": "Abc", "nameLower": null}));
+        assert_eq!(
+            read_string(&instance, id, "nameLower").as_deref(),
+            Some("abc")
+        );
+        assert!(patch_object(&instance, id, "name", json!("DEF")).unwrap());
+        assert_eq!(
+            read_string(&instance, id, "nameLower").as_deref(),
+            Some("def")
+        );
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_patch_keeps_other_properties() {
+        let mut col = collection(
+            "col",
+            json!([{"name": "a", "type": "String"}, {"name": "b", "type": "String"}]),
+        );
+        col["indexes"] = json!([index("a", &[("a", true)], false)]);
+        let instance = open_instance(json!([col]));
+        let id = put_json(&instance, json!({"a": "old", "b": "keep"}));
+        assert!(patch_object(&instance, id, "a", json!("new")).unwrap());
+        assert!(!patch_object(&instance, id + 1, "a", json!("new")).unwrap());
+        assert_eq!(read_string(&instance, id, "a").as_deref(), Some("new"));
+        assert_eq!(read_string(&instance, id, "b").as_deref(), Some("keep"));
+
+        let col = &instance.collections()[0];
+        let mut txn = instance.begin_txn(false, false).unwrap();
+        let index_id = xxh3_64(b"a");
+        for (value, count) in [("old", 0), ("new", 1)] {
+            let mut qb = col.new_query_builder();
+            let prefix = [IndexValue::String(Some(value))];
+            qb.add_index_prefix_where_clause(index_id, &prefix, None, None, Sort::Ascending, false)
+                .unwrap();
+            assert_eq!(qb.build().count(&txn).unwrap(), count);
+        }
+        instance.verify(&mut txn).unwrap();
+        drop(txn);
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_patch_keeps_version_and_unchanged_indexes() {
+        let mut col = collection(
+            "col",
+            json!([{"name": "a", "type": "String"}, {"name": "b", "type": "Long"}]),
+        );
+        col["indexes"] = json!([index("a", &[("a", true)], false)]);
+        let instance = open_instance(json!([col]));
+        let col = &instance.collections()[0];
+        let mut ob = col.new_object_builder(None);
+        ob.write_string(col.property("a").unwrap().offset, Some("value"));
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        assert!(col.put_if_newer(&mut txn, 1, ob.finish(), 3).unwrap());
+        txn.commit().unwrap();
+
+        assert!(patch_object(&instance, 1, "b", json!(5)).unwrap());
+        let mut txn = instance.begin_txn(false, false).unwrap();
+        assert_eq!(col.get_version(&mut txn, 1).unwrap(), Some(3));
+        let mut qb = col.new_query_builder();
+        let prefix = [IndexValue::String(Some("value"))];
+        let index_id = xxh3_64(b"a");
+        qb.add_index_prefix_where_clause(index_id, &prefix, None, None, Sort::Ascending, false)
+            .unwrap();
+        assert_eq!(qb.build().count(&txn).unwrap(), 1);
+        instance.verify(&mut txn).unwrap();
+        drop(txn);
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_patch_checks_unique_constraints() {
+        let mut col = collection("col", json!([{"name": "email", "type": "String"}]));
+        col["uniqueConstraints"] =
+            json!([{"name": "email", "properties": ["email"], "caseSensitive": false}]);
+        let instance = open_instance(json!([col]));
+        let a = put_json(&instance, json!({"email": "a@isar.dev"}));
+        let b = put_json(&instance, json!({"email": "b@isar.dev"}));
+
+        let result = patch_object(&instance, b, "email", json!("A@isar.dev"));
+        assert!(matches!(
+            result,
+            Err(IsarError::UniqueConstraintViolated { id, .. }) if id == a
+        ));
+
+        // the old value of a patched object is released
+        assert!(patch_object(&instance, a, "email", json!("c@isar.dev")).unwrap());
+        assert!(patch_object(&instance, b, "email", json!("a@isar.dev")).unwrap());
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_put_with_conflict_strategies() {
+        let mut col = collection(
+            "col",
+            json!([{"name": "email", "type": "String"}, {"name": "name", "type": "String"}]),
+        );
+        col["indexes"] = json!([index("email", &[("email", true)], true)]);
+        let instance = open_instance(json!([col]));
+        let col = &instance.collections()[0];
+        let name = col.property("name").unwrap().offset;
+        let put = |email: &str, value: &str, strategy: &ConflictStrategy| {
+            let mut ob = ObjectBuilder::new(&col.properties, None);
+            ob.write_string(col.property("email").unwrap().offset, Some(email));
+            ob.write_string(name, Some(value));
+            let mut txn = instance.begin_txn(true, false).unwrap();
+            let result = col.put_with_conflict(&mut txn, None, ob.finish(), strategy);
+            // failed writes close the transaction
+            if result.is_ok() {
+                txn.commit().unwrap();
+            }
+            result
+        };
+
+        let a = put("a", "first", &ConflictStrategy::Default)
+            .unwrap()
+            .unwrap();
+        assert!(matches!(
+            put("a", "second", &ConflictStrategy::Error),
+            Err(IsarError::UniqueViolated { id, .. }) if id == a
+        ));
+        assert_eq!(put("a", "second", &ConflictStrategy::Ignore).unwrap(), None);
+        assert_eq!(read_string(&instance, a, "name").as_deref(), Some("first"));
+
+        let merge = |existing: IsarObject, new: IsarObject, ob: &mut ObjectBuilder| {
+            let existing = existing.read_string(name).unwrap();
+            let merged = format!("{}+{}", existing, new.read_string(name).unwrap());
+            ob.write_string(name, Some(&merged));
+            Ok(())
+        };
+        let merged = put("a", "second", &ConflictStrategy::Merge(&merge)).unwrap();
+        assert_eq!(merged, Some(a));
+        assert_eq!(
+            read_string(&instance, a, "name").as_deref(),
+            Some("first+second")
+        );
+
+        let b = put("a", "third", &ConflictStrategy::Replace)
+            .unwrap()
+            .unwrap();
+        assert_ne!(a, b);
+        assert_eq!(read_string(&instance, a, "name"), None);
+        assert_eq!(read_string(&instance, b, "name").as_deref(), Some("third"));
+        let mut txn = instance.begin_txn(false, false).unwrap();
+        instance.verify(&mut txn).unwrap();
+        drop(txn);
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_soft_delete_hides_objects() {
+        let mut col = collection("col", json!([{"name": "name", "type": "String"}]));
+        col["softDelete"] = json!(true);
+        col["indexes"] = json!([index("name", &[("name", true)], true)]);
+        let instance = open_instance(json!([col]));
+        let col = &instance.collections()[0];
+        let offset = col.property("name").unwrap().offset;
+        let id = put_json(&instance, json!({"name": "a"}));
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        assert!(col.delete(&mut txn, id).unwrap());
+        txn.commit().unwrap();
+
+        let mut txn = instance.begin_txn(false, false).unwrap();
+        assert!(col.get(&mut txn, id).unwrap().is_none());
+        assert_eq!(col.count(&mut txn).unwrap(), 0);
+        let (object, deleted_at) = col.get_deleted(&mut txn, id).unwrap().unwrap();
+        assert_eq!(object.read_string(offset), Some("a"));
+        assert!(deleted_at > 0);
+        drop(txn);
+
+        // the hidden object does not block its unique value
+        let other = put_json(&instance, json!({"name": "a"}));
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        assert!(col.delete(&mut txn, other).unwrap());
+        assert!(col.restore(&mut txn, id).unwrap());
+        assert!(!col.restore(&mut txn, id).unwrap());
+        txn.commit().unwrap();
+        assert_eq!(read_string(&instance, id, "name").as_deref(), Some("a"));
+
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        assert_eq!(col.purge_tombstones(&mut txn, None).unwrap(), 1);
+        assert!(col.get_deleted(&mut txn, other).unwrap().is_none());
+        txn.commit().unwrap();
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_soft_delete_keeps_links_until_purge() {
+        let mut col = collection("col", json!([]));
+        col["softDelete"] = json!(true);
+        col["links"] = json!([{"name": "link", "target": "col"}]);
+        let instance = open_instance(json!([col]));
+        let col = &instance.collections()[0];
+        let link_id = col.link_id("link").unwrap();
+        let a = put_json(&instance, json!({}));
+        let b = put_json(&instance, json!({}));
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        col.link(&mut txn, link_id, a, b).unwrap();
+        assert!(col.delete(&mut txn, a).unwrap());
+        assert!(instance.check_integrity(&mut txn).unwrap().is_empty());
+        assert!(col.restore(&mut txn, a).unwrap());
+        assert_eq!(col.get_link_ids(&mut txn, link_id, a).unwrap(), vec![b]);
+
+        assert!(col.delete(&mut txn, b).unwrap());
+        assert_eq!(col.purge_tombstones(&mut txn, None).unwrap(), 1);
+        assert_eq!(col.link_count(&mut txn, link_id, a).unwrap(), 0);
+        assert!(instance.check_integrity(&mut txn).unwrap().is_empty());
+        txn.commit().unwrap();
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_never_reuse_ids_after_reopen() {
+        for never_reuse_ids in [false, true] {
+            let mut col = collection("col", json!([]));
+            col["neverReuseIds"] = json!(never_reuse_ids);
+            let instance = open_instance(json!([col.clone()]));
+            let ids = (0..3).map(|_| put_json(&instance, json!({}))).collect_vec();
+            let mut txn = instance.begin_txn(true, false).unwrap();
+            assert!(instance.collections()[0].delete(&mut txn, ids[2]).unwrap());
+            txn.commit().unwrap();
+
+            let instance = reopen(instance, json!([col]));
+            let next = if never_reuse_ids { ids[2] + 1 } else { ids[2] };
+            assert_eq!(put_json(&instance, json!({})), next);
+            assert!(instance.close_and_delete());
+        }
+    }
+
+    #[test]
+    fn test_random_ids_keep_auto_increment() {
+        let mut col = collection("col", json!([]));
+        col["idStrategy"] = json!("Random");
+        col["neverReuseIds"] = json!(true);
+        let instance = open_instance(json!([col]));
+        let col = &instance.collections()[0];
+        for _ in 0..3 {
+            assert!(put_json(&instance, json!({})) > 0);
+        }
+        assert_eq!(col.auto_increment.get(), 0);
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        assert_ne!(col.auto_increment(&mut txn).unwrap(), 1);
+        txn.commit().unwrap();
+        assert_eq!(col.auto_increment.get(), 0);
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_delete_deletes_blobs() {
+        let col = collection("col", json!([{"name": "data", "type": "Blob"}]));
+        let instance = open_instance(json!([col]));
+        let col = &instance.collections()[0];
+        let id = put_json(&instance, json!({}));
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        col.append_blob(&mut txn, id, "data", b"blob").unwrap();
+        assert!(col.delete(&mut txn, id).unwrap());
+        let ob = ObjectBuilder::new(&col.properties, None);
+        col.put(&mut txn, Some(id), ob.finish()).unwrap();
+        let exists = col.read_blob(&mut txn, id, "data", |_| Ok(true));
+        assert!(!exists.unwrap());
+        txn.commit().unwrap();
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_object_cache_skips_stale_snapshots() {
+        let instance = open_instance(json!([collection(
+            "col",
+            json!([{"name": "a", "type": "String"}])
+        )]));
+        let col = &instance.collections()[0];
+        instance.set_object_cache_size(col, 1024).unwrap();
+        let id = put_json(&instance, json!({"a": "old"}));
+
+        let mut old_txn = instance.begin_txn(false, false).unwrap();
+        assert!(patch_object(&instance, id, "a", json!("new")).unwrap());
+        let object = col.get(&mut old_txn, id).unwrap().unwrap();
+        assert_eq!(object.read_string(col.properties[0].offset), Some("old"));
+        drop(old_txn);
+
+        assert_eq!(read_string(&instance, id, "a").as_deref(), Some("new"));
+        assert_eq!(col.object_cache_stats().unwrap().0, 1);
+        assert_eq!(read_string(&instance, id, "a").as_deref(), Some("new"));
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_property_watcher_ignores_other_properties() {
+        let instance = open_instance(json!([collection(
+            "col",
+            json!([{"name": "a", "type": "String"}, {"name": "b", "type": "String"}]),
+        )]));
+        let col = &instance.collections()[0];
+        let id = put_json(&instance, json!({"a": "x", "b": "1"}));
+        let notified = Arc::new(AtomicUsize::new(0));
+        let counter = notified.clone();
+        let _handle = instance.watch_property(
+            col,
+            id,
+            col.property("a").unwrap(),
+            Box::new(move |_| {
+                counter.fetch_add(1, Ordering::SeqCst);
+            }),
+            None,
+        );
+
+        assert!(patch_object(&instance, id, "b", json!("2")).unwrap());
+        assert!(patch_object(&instance, id, "a", json!("x")).unwrap());
+        assert_eq!(notified.load(Ordering::SeqCst), 0);
+
+        assert!(patch_object(&instance, id, "a", json!("y")).unwrap());
+        assert_eq!(notified.load(Ordering::SeqCst), 1);
+
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        assert!(col.delete(&mut txn, id).unwrap());
+        txn.commit().unwrap();
+        assert_eq!(notified.load(Ordering::SeqCst), 2);
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_stats_per_db() {
+        let mut col = collection("col", json!([{"name": "name", "type": "String"}]));
+        col["indexes"] = json!([index("name", &[("name", true)], false)]);
+        col["links"] = json!([{"name": "link", "target": "col"}]);
+        let instance = open_instance(json!([col]));
+        let col = &instance.collections()[0];
+        let a = put_json(&instance, json!({"name": "a"}));
+        let b = put_json(&instance, json!({"name": "b"}));
+        put_json(&instance, json!({"name": "c"}));
+        let link_id = col.link_id("link").unwrap();
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        col.link(&mut txn, link_id, a, b).unwrap();
+
+        let stats = col.stats(&mut txn).unwrap();
+        let names = stats.iter().map(|s| s.name.as_str()).collect_vec();
+        assert_eq!(names, ["col", "_i_col_name", "_l_col_link", "_b_col_link"]);
+        let entries = stats.iter().map(|s| s.entries).collect_vec();
+        assert_eq!(entries, [3, 3, 1, 1]);
+        assert!(stats.iter().all(|s| s.leaf_pages == 1 && s.size() > 0));
+        txn.commit().unwrap();
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_size_histogram() {
+        let props = json!([{"name": "name", "type": "String"}, {"name": "value", "type": "Long"}]);
+        let instance = open_instance(json!([collection("col", props)]));
+        let col = &instance.collections()[0];
+        put_json(&instance, json!({"name": "a", "value": 1}));
+        put_json(&instance, json!({"name": "bc", "value": 2}));
+        put_json(&instance, json!({"name": "x".repeat(100), "value": 3}));
+
+        let mut txn = instance.begin_txn(false, false).unwrap();
+        let histogram = col.size_histogram(&mut txn).unwrap();
+        txn.abort();
+        assert_eq!(histogram.count, 3);
+        assert_eq!(histogram.total_bytes, 151);
+        assert_eq!(histogram.max_bytes, 116);
+        assert_eq!(histogram.buckets, [0, 0, 0, 0, 2, 0, 1]);
+        let property_bytes = [("name".to_string(), 121), ("value".to_string(), 24)];
+        assert_eq!(histogram.property_bytes, property_bytes);
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_delete_all_by_ids_and_index() {
+        let mut col = collection("col", json!([{"name": "name", "type": "String"}]));
+        col["indexes"] = json!([index("name", &[("name", true)], true)]);
+        let instance = open_instance(json!([col]));
+        let col = &instance.collections()[0];
+        let ids = ["a", "b", "c", "d"].map(|name| put_json(&instance, json!({ "name": name })));
+        let key = |name: &str| {
+            let mut key = IndexKey::new();
+            key.add_string(Some(name), true);
+            key
+        };
+
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        assert_eq!(col.delete_all(&mut txn, &[ids[0], 99, ids[1]]).unwrap(), 2);
+        let index_id = col.indexes[0].id;
+        let keys = [key("c"), key("x")];
+        assert_eq!(
+            col.delete_all_by_index(&mut txn, index_id, &keys).unwrap(),
+            1
+        );
+        assert_eq!(col.count(&mut txn).unwrap(), 1);
+        assert!(col.get(&mut txn, ids[3]).unwrap().is_some());
+        instance.verify(&mut txn).unwrap();
+        txn.commit().unwrap();
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_get_all_keeps_input_order() {
+        let mut col = collection("col", json!([{"name": "name", "type": "String"}]));
+        col["indexes"] = json!([index("name", &[("name", true)], true)]);
+        let instance = open_instance(json!([col]));
+        let col = &instance.collections()[0];
+        let offset = col.property("name").unwrap().offset;
+        let ids = ["a", "b", "c"].map(|name| put_json(&instance, json!({ "name": name })));
+        let key = |name: &str| {
+            let mut key = IndexKey::new();
+            key.add_string(Some(name), true);
+            key
+        };
+
+        let mut txn = instance.begin_txn(false, false).unwrap();
+        let objects = col.get_all(&mut txn, &[ids[2], 99, ids[0]]).unwrap();
+        let names = objects
+            .iter()
+            .map(|o| o.map(|o| o.read_string(offset).unwrap()))
+            .collect_vec();
+        assert_eq!(names, [Some("c"), None, Some("a")]);
+
+        let keys = [key("b"), key("x"), key("a")];
+        let objects = col.get_all_by_index(&mut txn, col.indexes[0].id, &keys);
+        let ids_and_names = objects
+            .unwrap()
+            .iter()
+            .map(|o| o.map(|(id, o)| (id, o.read_string(offset).unwrap())))
+            .collect_vec();
+        assert_eq!(
+            ids_and_names,
+            [Some((ids[1], "b")), None, Some((ids[0], "a"))]
+        );
+        drop(txn);
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_link_and_unlink_all_targets() {
+        let mut col = collection("col", json!([]));
+        col["links"] = json!([{"name": "link", "target": "col"}]);
+        let instance = open_instance(json!([col]));
+        let col = &instance.collections()[0];
+        let ids = (0..4).map(|_| put_json(&instance, json!({}))).collect_vec();
+        let link_id = col.link_id("link").unwrap();
+
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        let targets = [ids[1], ids[2], ids[1]];
+        assert_eq!(
+            col.link_all(&mut txn, link_id, ids[0], &targets).unwrap(),
+            2
+        );
+        let targets = [ids[1], ids[3]];
+        let unlinked = col.unlink_all_targets(&mut txn, link_id, ids[0], &targets);
+        assert_eq!(unlinked.unwrap(), 1);
+        assert_eq!(
+            col.get_link_ids(&mut txn, link_id, ids[0]).unwrap(),
+            vec![ids[2]]
+        );
+        assert!(instance.check_integrity(&mut txn).unwrap().is_empty());
+        txn.commit().unwrap();
+        assert!(instance.close_and_delete());
+    }
+
+    /// Deletes the object without updating its index entries and links.
+    fn delete_object_only(instance: &IsarInstance, id: i64) {
+        let col = &instance.collections()[0];
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        txn.write(instance.instance_id, |cursors, _| {
+            let mut cursor = cursors.get_cursor(col.db)?;
+            cursor.move_to(&id)?.unwrap();
+            cursor.delete_current()
+        })
+        .unwrap();
+        txn.commit().unwrap();
+    }
+
+    #[test]
+    fn test_check_integrity_finds_dangling_entries() {
+        let mut col = collection("col", json!([{"name": "name", "type": "String"}]));
+        col["indexes"] = json!([index("name", &[("name", true)], false)]);
+        col["links"] = json!([{"name": "link", "target": "col"}]);
+        let instance = open_instance(json!([col]));
+        let col = &instance.collections()[0];
+        let a = put_json(&instance, json!({"name": "a"}));
+        let b = put_json(&instance, json!({"name": "b"}));
+        let link_id = col.link_id("link").unwrap();
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        col.link(&mut txn, link_id, a, b).unwrap();
+        assert!(instance.check_integrity(&mut txn).unwrap().is_empty());
+        txn.commit().unwrap();
+
+        delete_object_only(&instance, b);
+        let mut txn = instance.begin_txn(false, false).unwrap();
+        let problems = instance.check_integrity(&mut txn).unwrap();
+        assert_eq!(
+            problems,
+            vec![
+                IntegrityProblem::new(ProblemKind::ObsoleteIndexEntry, "col", Some("name"), b),
+                IntegrityProblem::new(ProblemKind::MissingLinkedObject, "col", Some("link"), a)
+                    .with_target(b),
+            ]
+        );
+        drop(txn);
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_repair_rebuilds_indexes_and_removes_dangling_links() {
+        let mut col = collection("col", json!([{"name": "name", "type": "String"}]));
+        col["indexes"] = json!([index("name", &[("name", true)], false)]);
+        col["links"] = json!([{"name": "link", "target": "col"}]);
+        let instance = open_instance(json!([col]));
+        let col = &instance.collections()[0];
+        let a = put_json(&instance, json!({"name": "a"}));
+        let b = put_json(&instance, json!({"name": "b"}));
+        let link_id = col.link_id("link").unwrap();
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        col.link(&mut txn, link_id, a, b).unwrap();
+        txn.write(instance.instance_id, |cursors, _| {
+            col.indexes[0].clear(cursors)
+        })
+        .unwrap();
+        txn.commit().unwrap();
+        delete_object_only(&instance, b);
+
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        let problems = instance.repair(&mut txn).unwrap();
+        let kinds = problems.iter().map(|p| p.kind).collect_vec();
+        assert_eq!(
+            kinds,
+            [
+                ProblemKind::MissingIndexEntry,
+                ProblemKind::MissingLinkedObject
+            ]
+        );
+        assert!(instance.check_integrity(&mut txn).unwrap().is_empty());
+        assert_eq!(col.link_count(&mut txn, link_id, a).unwrap(), 0);
+        txn.commit().unwrap();
+
+        let mut qb = col.new_query_builder();
+        let prefix = [IndexValue::String(Some("a"))];
+        qb.add_index_prefix_where_clause(
+            col.indexes[0].id,
+            &prefix,
+            None,
+            None,
+            Sort::Ascending,
+            false,
+        )
+        .unwrap();
+        assert_eq!(find_ids(&instance, &qb.build()), vec![a]);
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_raw_export_import() {
+        let schema = json!([collection(
+            "col",
+            json!([{"name": "name", "type": "String"}])
+        )]);
+        let source = open_instance(schema.clone());
+        let a = put_json(&source, json!({"name": "a"}));
+        let b = put_json(&source, json!({"name": "b"}));
+        let mut txn = source.begin_txn(false, false).unwrap();
+        let bytes = source.collections()[0].export_raw(&mut txn).unwrap();
+        drop(txn);
+
+        let target = open_instance(schema);
+        let col = &target.collections()[0];
+        let mut txn = target.begin_txn(true, false).unwrap();
+        assert!(matches!(
+            col.import_raw(&mut txn, &bytes[..bytes.len() - 1]),
+            Err(IsarError::InvalidObject {})
+        ));
+        drop(txn);
+        let mut txn = target.begin_txn(true, false).unwrap();
+        assert_eq!(col.import_raw(&mut txn, &bytes).unwrap(), 2);
+        txn.commit().unwrap();
+        assert_eq!(read_string(&target, a, "name").as_deref(), Some("a"));
+        assert_eq!(read_string(&target, b, "name").as_deref(), Some("b"));
+
+        let other = open_instance(json!([collection(
+            "col",
+            json!([{"name": "value", "type": "Long"}])
+        )]));
+        let mut txn = other.begin_txn(true, false).unwrap();
+        let result = other.collections()[0].import_raw(&mut txn, &bytes);
+        assert!(matches!(result, Err(IsarError::SchemaMismatch {})));
+        drop(txn);
+        assert!(source.close_and_delete());
+        assert!(target.close_and_delete());
+        assert!(other.close_and_delete());
+    }
+
+    #[test]
+    fn test_get_all_by_index_key() {
+        let mut col = collection("col", json!([{"name": "name", "type": "String"}]));
+        col["indexes"] = json!([index("name", &[("name", false)], false)]);
+        let instance = open_instance(json!([col]));
+        let col = &instance.collections()[0];
+        let offset = col.property("name").unwrap().offset;
+        let ids = ["a", "B", "b", "c"].map(|name| put_json(&instance, json!({ "name": name })));
+
+        let mut key = IndexKey::new();
+        key.add_string(Some("b"), false);
+        let mut txn = instance.begin_txn(false, false).unwrap();
+        let objects = col.get_all_by_index_key(&mut txn, col.indexes[0].id, &key);
+        let found = objects
+            .unwrap()
+            .iter()
+            .map(|(id, object)| (*id, object.read_string(offset).unwrap()))
+            .collect_vec();
+        assert_eq!(found, [(ids[1], "B"), (ids[2], "b")]);
+        drop(txn);
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_index_keys_are_validated() {
+        let props = json!([{"name": "name", "type": "String"}, {"name": "value", "type": "Long"}]);
+        let mut col = collection("col", props);
+        col["indexes"] = json!([index("key", &[("value", false), ("name", true)], true)]);
+        let instance = open_instance(json!([col]));
+        let col = &instance.collections()[0];
+        let index_id = col.indexes[0].id;
+        let id = put_json(&instance, json!({"name": "a", "value": 1}));
+
+        let mut key = IndexKey::new();
+        key.add_long(1);
+        key.add_string(Some("a"), true);
+        let mut int_key = IndexKey::new();
+        int_key.add_int(1);
+        let mut long_key = IndexKey::new();
+        long_key.add_long(1);
+
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        for invalid in [&int_key, &long_key] {
+            let result = col.get_by_index(&mut txn, index_id, invalid);
+            assert!(matches!(result, Err(IsarError::IllegalArg { .. })));
+            let result = col.delete_by_index(&mut txn, index_id, invalid);
+            assert!(matches!(result, Err(IsarError::IllegalArg { .. })));
+        }
+        let found = col.get_by_index(&mut txn, index_id, &key).unwrap();
+        assert_eq!(found.map(|(id, _)| id), Some(id));
+        assert!(col.delete_by_index(&mut txn, index_id, &key).unwrap());
+        txn.commit().unwrap();
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_validate_objects_reports_invalid_strings() {
+        let col = collection("col", json!([{"name": "name", "type": "String"}]));
+        let instance = open_instance(json!([col]));
+        let col = &instance.collections()[0];
+        let offset = col.property("name").unwrap().offset;
+        let id = put_json(&instance, json!({"name": "abc"}));
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        txn.write(instance.instance_id, |cursors, _| {
+            let mut cursor = cursors.get_cursor(col.db)?;
+            let mut bytes = cursor.move_to(&id)?.unwrap().1.to_vec();
+            assert_eq!(bytes.last(), Some(&b'c'));
+            *bytes.last_mut().unwrap() = 0xff;
+            cursor.put(&id, &bytes)
+        })
+        .unwrap();
+        txn.commit().unwrap();
+
+        instance.set_validate_objects(true);
+        let mut txn = instance.begin_txn(false, false).unwrap();
+        let result = col.get(&mut txn, id);
+        assert!(matches!(result, Err(IsarError::DbCorrupted { .. })));
+        drop(txn);
+
+        instance.set_validate_objects(false);
+        let mut txn = instance.begin_txn(false, false).unwrap();
+        let object = col.get(&mut txn, id).unwrap().unwrap();
+        let result = object.read_string_checked(offset);
+        assert!(matches!(result, Err(IsarError::DbCorrupted { .. })));
+        drop(txn);
+        assert!(instance.close_and_delete());
+    }
+}
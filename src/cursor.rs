@@ -1,15 +1,85 @@
 use crate::error::Result;
-use crate::mdbx::cursor::{Cursor, UnboundCursor};
-use crate::mdbx::db::Db;
-use crate::mdbx::txn::Txn;
+use crate::instance::CursorStats;
+use crate::isar_log;
+use crate::logging::LogEvent;
+use crate::storage::{Cursor, Db, DbStat, LazyDb, StorageDb, StorageTxn, Txn, UnboundCursor};
 use intmap::IntMap;
 use std::cell::RefCell;
 use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Cursor usage of an instance per db. Only collected while tracking is enabled.
+#[derive(Default)]
+pub(crate) struct CursorCounters {
+    enabled: AtomicBool,
+    dbs: Mutex<IntMap<CursorStats>>,
+}
+
+impl CursorCounters {
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    fn update(&self, db_id: u64, update: impl FnOnce(&mut CursorStats)) {
+        let mut dbs = self.dbs.lock().unwrap();
+        if !dbs.contains_key(db_id) {
+            dbs.insert(
+                db_id,
+                CursorStats {
+                    db: db_id,
+                    open: 0,
+                    high_water: 0,
+                    created: 0,
+                    leaked: 0,
+                },
+            );
+        }
+        update(dbs.get_mut(db_id).unwrap());
+    }
+
+    fn checkout(&self, db_id: u64, created: bool) {
+        self.update(db_id, |stats| {
+            stats.open += 1;
+            stats.high_water = stats.high_water.max(stats.open);
+            if created {
+                stats.created += 1;
+            }
+        });
+    }
+
+    fn checkin(&self, db_id: u64) {
+        self.update(db_id, |stats| stats.open = stats.open.saturating_sub(1));
+    }
+
+    fn leak(&self, db_id: u64, count: u32) {
+        self.update(db_id, |stats| stats.leaked += count);
+    }
+
+    pub fn stats(&self) -> Vec<CursorStats> {
+        let dbs = self.dbs.lock().unwrap();
+        let mut stats: Vec<CursorStats> = dbs.iter().map(|(_, stats)| *stats).collect();
+        stats.sort_unstable_by_key(|stats| stats.db);
+        stats
+    }
+}
+
+struct CursorTracking {
+    counters: Arc<CursorCounters>,
+    instance_id: u64,
+    /// Cursors of this `IsarCursors` that have not been returned yet.
+    checked_out: RefCell<IntMap<u32>>,
+}
 
 pub(crate) struct IsarCursors<'txn, 'env> {
     txn: &'txn Txn<'env>,
     unbound_cursors: RefCell<Vec<UnboundCursor>>,
     cursors: RefCell<IntMap<Cursor<'txn>>>,
+    tracking: Option<CursorTracking>,
 }
 
 impl<'txn, 'env> IsarCursors<'txn, 'env> {
@@ -21,21 +91,44 @@ impl<'txn, 'env> IsarCursors<'txn, 'env> {
             txn,
             unbound_cursors: RefCell::new(unbound_cursors),
             cursors: RefCell::new(IntMap::new()),
+            tracking: None,
         }
     }
 
+    /// Records the cursor usage in `counters` if tracking is enabled.
+    pub fn track(mut self, counters: &Arc<CursorCounters>, instance_id: u64) -> Self {
+        if counters.is_enabled() {
+            self.tracking = Some(CursorTracking {
+                counters: counters.clone(),
+                instance_id,
+                checked_out: RefCell::new(IntMap::new()),
+            });
+        }
+        self
+    }
+
     pub fn get_cursor<'a>(&'a self, db: Db) -> Result<IsarCursor<'a, 'txn, 'env>> {
+        let mut created = false;
         let cursor = if let Some(cursor) = self.cursors.borrow_mut().remove(db.runtime_id()) {
             cursor
         } else {
-            let unbound = self
-                .unbound_cursors
-                .borrow_mut()
-                .pop()
-                .unwrap_or_else(UnboundCursor::new);
+            let unbound = self.unbound_cursors.borrow_mut().pop().unwrap_or_else(|| {
+                created = true;
+                UnboundCursor::new()
+            });
             unbound.bind(self.txn, db)?
         };
 
+        if let Some(tracking) = &self.tracking {
+            tracking.counters.checkout(db.runtime_id(), created);
+            let mut checked_out = tracking.checked_out.borrow_mut();
+            if let Some(count) = checked_out.get_mut(db.runtime_id()) {
+                *count += 1;
+            } else {
+                checked_out.insert(db.runtime_id(), 1);
+            }
+        }
+
         Ok(IsarCursor {
             cursors: self,
             cursor: Some(cursor),
@@ -43,15 +136,44 @@ impl<'txn, 'env> IsarCursors<'txn, 'env> {
         })
     }
 
+    pub fn get_lazy_cursor<'a>(&'a self, db: &LazyDb) -> Result<IsarCursor<'a, 'txn, 'env>> {
+        self.get_cursor(db.get(self.txn)?)
+    }
+
+    pub fn open_db(&self, db: &LazyDb) -> Result<Db> {
+        db.get(self.txn)
+    }
+
+    pub fn txn_id(&self) -> u64 {
+        self.txn.id()
+    }
+
     pub fn db_stat(&self, db: Db) -> Result<(u64, u64)> {
-        db.stat(&self.txn)
+        let stat = db.stat(self.txn)?;
+        Ok((stat.entries, stat.size()))
+    }
+
+    pub fn db_raw_stat(&self, db: Db) -> Result<DbStat> {
+        db.stat(self.txn)
     }
 
     pub fn clear_db(&self, db: Db) -> Result<()> {
-        db.clear(&self.txn)
+        db.clear(self.txn)
     }
 
+    /// Reports cursors that have not been returned as leaked.
     pub fn close(self) -> Vec<UnboundCursor> {
+        if let Some(tracking) = &self.tracking {
+            for (db_id, count) in tracking.checked_out.borrow().iter() {
+                if *count > 0 {
+                    tracking.counters.leak(*db_id, *count);
+                    isar_log!(LogEvent::CursorLeak {
+                        instance_id: tracking.instance_id,
+                        db: *db_id,
+                    });
+                }
+            }
+        }
         let mut unbound_cursors = self.unbound_cursors.take();
         for (_, cursor) in self.cursors.borrow_mut().drain() {
             unbound_cursors.push(cursor.unbind())
@@ -83,6 +205,12 @@ impl<'a, 'txn, 'env> DerefMut for IsarCursor<'a, 'txn, 'env> {
 impl<'a, 'txn, 'env> Drop for IsarCursor<'a, 'txn, 'env> {
     fn drop(&mut self) {
         let cursor = self.cursor.take().unwrap();
+        if let Some(tracking) = &self.cursors.tracking {
+            tracking.counters.checkin(self.db_id);
+            if let Some(count) = tracking.checked_out.borrow_mut().get_mut(self.db_id) {
+                *count -= 1;
+            }
+        }
         let cursors = &self.cursors.cursors;
         if !cursors.borrow().contains_key(self.db_id) {
             cursors.borrow_mut().insert(self.db_id, cursor);
@@ -18,12 +18,18 @@ pub enum IsarError {
     #[snafu(display("The database is full."))]
     DbFull {},
 
-    #[snafu(display("Unique index violated."))]
-    UniqueViolated {},
+    #[snafu(display("Unique index {} violated by existing object {}.", index, id))]
+    UniqueViolated { index: String, id: i64 },
+
+    #[snafu(display("Unique constraint {} violated by existing object {}.", constraint, id))]
+    UniqueConstraintViolated { constraint: String, id: i64 },
 
     #[snafu(display("Write transaction required."))]
     WriteTxnRequired {},
 
+    #[snafu(display("The instance was opened in read-only mode."))]
+    ReadOnly {},
+
     #[snafu(display("Auto increment id cannot be generated because the limit is reached."))]
     AutoIncrementOverflow {},
 
@@ -36,6 +42,9 @@ pub enum IsarError {
     #[snafu(display("Transaction closed."))]
     TransactionClosed {},
 
+    #[snafu(display("The read transaction exceeded its maximum age."))]
+    TransactionExpired {},
+
     #[snafu(display("IllegalArg: {}.", message))]
     IllegalArg { message: String },
 
@@ -57,6 +66,12 @@ pub enum IsarError {
     #[snafu(display("InstanceMismatch: The transaction is from a different instance."))]
     InstanceMismatch {},
 
+    #[snafu(display("The operation was cancelled."))]
+    Cancelled {},
+
+    #[snafu(display("The query exceeded its memory limit."))]
+    QueryTooLarge {},
+
     #[snafu(display("MdbxError ({}): {}", code, message))]
     MdbxError { code: i32, message: String },
 }
@@ -0,0 +1,24 @@
+use crate::schema::index_schema::Collation;
+
+/// Bytes of `value` that sort according to the collation.
+pub(crate) fn collate(value: &str, case_sensitive: bool, collation: &Collation) -> Vec<u8> {
+    if value.is_empty() {
+        return vec![];
+    }
+    match collation {
+        Collation::Binary => value.as_bytes().to_vec(),
+        Collation::CaseInsensitive => {
+            if case_sensitive {
+                // case variants are sorted next to each other and ordered by their original value
+                let lower = value.to_lowercase();
+                let mut bytes = Vec::with_capacity(lower.len() + value.len() + 1);
+                bytes.extend_from_slice(lower.as_bytes());
+                bytes.push(0);
+                bytes.extend_from_slice(value.as_bytes());
+                bytes
+            } else {
+                value.as_bytes().to_vec()
+            }
+        }
+    }
+}
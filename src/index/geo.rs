@@ -0,0 +1,207 @@
+const EARTH_RADIUS: f64 = 6_371_008.8;
+
+/// Bounding box in degrees. Boxes crossing the antimeridian have `min_lon > max_lon`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GeoBox {
+    pub min_lat: f64,
+    pub min_lon: f64,
+    pub max_lat: f64,
+    pub max_lon: f64,
+}
+
+impl GeoBox {
+    pub fn new(min_lat: f64, min_lon: f64, max_lat: f64, max_lon: f64) -> Self {
+        GeoBox {
+            min_lat,
+            min_lon,
+            max_lat,
+            max_lon,
+        }
+    }
+
+    pub fn around(lat: f64, lon: f64, radius: f64) -> Self {
+        let delta_lat = (radius / EARTH_RADIUS).to_degrees();
+        let min_lat = lat - delta_lat;
+        let max_lat = lat + delta_lat;
+        if min_lat <= -90.0 || max_lat >= 90.0 {
+            return GeoBox::new(min_lat.max(-90.0), -180.0, max_lat.min(90.0), 180.0);
+        }
+
+        let delta_lon = (delta_lat.to_radians().sin() / lat.to_radians().cos())
+            .min(1.0)
+            .asin()
+            .to_degrees();
+        GeoBox::new(
+            min_lat,
+            wrap_lon(lon - delta_lon),
+            max_lat,
+            wrap_lon(lon + delta_lon),
+        )
+    }
+
+    pub fn contains(&self, lat: f64, lon: f64) -> bool {
+        let lat_matches = self.min_lat <= lat && self.max_lat >= lat;
+        let lon_matches = if self.min_lon <= self.max_lon {
+            self.min_lon <= lon && self.max_lon >= lon
+        } else {
+            self.min_lon <= lon || self.max_lon >= lon
+        };
+        lat_matches && lon_matches
+    }
+
+    fn split(&self) -> Vec<GeoBox> {
+        if self.min_lon <= self.max_lon {
+            vec![*self]
+        } else {
+            vec![
+                GeoBox::new(self.min_lat, self.min_lon, self.max_lat, 180.0),
+                GeoBox::new(self.min_lat, -180.0, self.max_lat, self.max_lon),
+            ]
+        }
+    }
+}
+
+fn wrap_lon(lon: f64) -> f64 {
+    if lon < -180.0 {
+        lon + 360.0
+    } else if lon > 180.0 {
+        lon - 360.0
+    } else {
+        lon
+    }
+}
+
+/// Great-circle distance in meters.
+pub fn distance(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let d_lat = (lat2 - lat1).to_radians();
+    let d_lon = (lon2 - lon1).to_radians();
+    let a = (d_lat / 2.0).sin().powi(2)
+        + lat1.to_radians().cos() * lat2.to_radians().cos() * (d_lon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS * a.sqrt().min(1.0).asin()
+}
+
+fn quantize(value: f64, min: f64, max: f64) -> u32 {
+    let normalized = (value.clamp(min, max) - min) / (max - min);
+    (normalized * u32::MAX as f64) as u32
+}
+
+fn spread(value: u32) -> u64 {
+    let mut x = value as u64;
+    x = (x | (x << 16)) & 0x0000_FFFF_0000_FFFF;
+    x = (x | (x << 8)) & 0x00FF_00FF_00FF_00FF;
+    x = (x | (x << 4)) & 0x0F0F_0F0F_0F0F_0F0F;
+    x = (x | (x << 2)) & 0x3333_3333_3333_3333;
+    x = (x | (x << 1)) & 0x5555_5555_5555_5555;
+    x
+}
+
+fn interleave(x: u32, y: u32) -> u64 {
+    spread(x) | (spread(y) << 1)
+}
+
+/// Z-order curve hash of a coordinate. Close coordinates share a common prefix.
+pub fn geo_hash(lat: f64, lon: f64) -> Option<u64> {
+    if lat.is_nan() || lon.is_nan() {
+        None
+    } else {
+        let x = quantize(lon, -180.0, 180.0);
+        let y = quantize(lat, -90.0, 90.0);
+        Some(interleave(x, y))
+    }
+}
+
+/// Hash ranges that cover the box. The ranges may also contain coordinates outside of the box.
+pub fn geo_hash_ranges(geo_box: &GeoBox) -> Vec<(u64, u64)> {
+    let mut ranges = vec![];
+    for geo_box in geo_box.split() {
+        let x0 = quantize(geo_box.min_lon, -180.0, 180.0);
+        let x1 = quantize(geo_box.max_lon, -180.0, 180.0);
+        let y0 = quantize(geo_box.min_lat, -90.0, 90.0);
+        let y1 = quantize(geo_box.max_lat, -90.0, 90.0);
+        if x0 > x1 || y0 > y1 {
+            continue;
+        }
+
+        // the smallest cells so that the box spans at most two cells per axis
+        let shift = (0..32)
+            .find(|s| (x1 >> s) - (x0 >> s) <= 1 && (y1 >> s) - (y0 >> s) <= 1)
+            .unwrap_or(32);
+        if shift == 32 {
+            ranges.push((0, u64::MAX));
+            continue;
+        }
+
+        let mask = (1u64 << (2 * shift)) - 1;
+        for cx in (x0 >> shift)..=(x1 >> shift) {
+            for cy in (y0 >> shift)..=(y1 >> shift) {
+                let lower = interleave(cx, cy) << (2 * shift);
+                ranges.push((lower, lower | mask));
+            }
+        }
+    }
+
+    ranges.sort_unstable();
+    let mut merged: Vec<(u64, u64)> = vec![];
+    for (lower, upper) in ranges {
+        if let Some(last) = merged.last_mut() {
+            if last.1 == u64::MAX || lower <= last.1 + 1 {
+                last.1 = last.1.max(upper);
+                continue;
+            }
+        }
+        merged.push((lower, upper));
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instance::tests::{collection, find_ids, open_instance, put_object};
+    use crate::query::query_builder::QueryBuilder;
+    use serde_json::json;
+    use xxhash_rust::xxh3::xxh3_64;
+
+    #[test]
+    fn test_geo_where_clauses() {
+        let mut col = collection(
+            "col",
+            json!([{"name": "lat", "type": "Double"}, {"name": "lon", "type": "Double"}]),
+        );
+        let geo = |name| json!({"name": name, "type": "Geo", "caseSensitive": false});
+        let properties = [geo("lat"), geo("lon")];
+        col["indexes"] = json!([{"name": "pos", "unique": false, "properties": properties}]);
+        let instance = open_instance(json!([col]));
+        let col = &instance.collections()[0];
+        let berlin = put_object(&instance, col, None, json!({"lat": 52.52, "lon": 13.405}));
+        let potsdam = put_object(&instance, col, None, json!({"lat": 52.39, "lon": 13.06}));
+        let munich = put_object(&instance, col, None, json!({"lat": 48.14, "lon": 11.58}));
+        let fiji = put_object(&instance, col, None, json!({"lat": -17.7, "lon": 179.9}));
+        put_object(&instance, col, None, json!({}));
+
+        let find = |add: &dyn Fn(&mut QueryBuilder)| {
+            let mut qb = col.new_query_builder();
+            add(&mut qb);
+            let mut ids = find_ids(&instance, &qb.build());
+            ids.sort_unstable();
+            ids
+        };
+        let index_id = xxh3_64(b"pos");
+        let in_radius = find(&|qb| {
+            qb.add_geo_radius_where_clause(index_id, 52.52, 13.405, 50_000.0)
+                .unwrap()
+        });
+        assert_eq!(in_radius, vec![berlin, potsdam]);
+        let in_box = find(&|qb| {
+            let geo_box = GeoBox::new(48.0, 11.0, 53.0, 13.2);
+            qb.add_geo_box_where_clause(index_id, geo_box).unwrap()
+        });
+        assert_eq!(in_box, vec![potsdam, munich]);
+        let across_antimeridian = find(&|qb| {
+            let geo_box = GeoBox::new(-20.0, 179.0, -15.0, -179.0);
+            qb.add_geo_box_where_clause(index_id, geo_box).unwrap()
+        });
+        assert_eq!(across_antimeridian, vec![fiji]);
+        assert!(instance.close_and_delete());
+    }
+}
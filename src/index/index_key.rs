@@ -1,10 +1,23 @@
+use crate::index::collation::collate;
 use crate::index::IsarIndex;
-use crate::mdbx::Key;
+use crate::schema::index_schema::Collation;
+use crate::storage::Key;
 use std::borrow::Cow;
 use std::cmp;
 use std::cmp::Ordering;
 use xxhash_rust::xxh3::xxh3_64;
 
+/// A single component of an index key.
+#[derive(Clone, Copy, Debug)]
+pub enum IndexValue<'a> {
+    Byte(u8),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    String(Option<&'a str>),
+}
+
 #[derive(Clone, Eq, PartialEq)]
 pub struct IndexKey {
     bytes: Vec<u8>,
@@ -64,33 +77,175 @@ impl IndexKey {
     }
 
     pub fn add_string(&mut self, value: Option<&str>, case_sensitive: bool) {
+        self.add_string_value(value, case_sensitive, &Collation::Binary)
+    }
+
+    pub fn add_string_value(
+        &mut self,
+        value: Option<&str>,
+        case_sensitive: bool,
+        collation: &Collation,
+    ) {
         if let Some(value) = value {
             let value = if case_sensitive {
                 value.to_string()
             } else {
                 value.to_lowercase()
             };
-            let bytes = value.as_bytes();
-            if bytes.len() >= IsarIndex::MAX_STRING_INDEX_SIZE {
-                let index_bytes = &bytes[0..IsarIndex::MAX_STRING_INDEX_SIZE];
-                self.bytes.extend_from_slice(index_bytes);
-                let hash = xxh3_64(bytes);
-                self.bytes.extend_from_slice(&u64::to_le_bytes(hash));
-            } else if bytes.is_empty() {
-                self.bytes.push(1);
-            } else {
-                self.bytes.extend_from_slice(bytes);
-            }
+            let bytes = collate(&value, case_sensitive, collation);
+            self.add_string_bytes(&bytes);
         } else {
             self.bytes.push(0);
         }
     }
 
+    /// Adds the lowest key of all values that are equal to `value` in the collation.
+    pub fn add_string_lower_bound(
+        &mut self,
+        value: Option<&str>,
+        case_sensitive: bool,
+        collation: &Collation,
+    ) {
+        match value {
+            Some(value) if case_sensitive && *collation == Collation::CaseInsensitive => {
+                self.add_string_bound_bytes(value.to_lowercase().as_bytes(), false);
+            }
+            Some(value) => {
+                let value = if case_sensitive {
+                    value.to_string()
+                } else {
+                    value.to_lowercase()
+                };
+                let bytes = collate(&value, case_sensitive, collation);
+                self.add_string_bound_bytes(&bytes, false);
+            }
+            None => self.bytes.push(0),
+        }
+    }
+
+    /// Adds the highest key of all values that are equal to `value` in the collation. With
+    /// case-insensitive collation the bound includes all case variants of `value`.
+    pub fn add_string_upper_bound(
+        &mut self,
+        value: Option<&str>,
+        case_sensitive: bool,
+        collation: &Collation,
+    ) {
+        match value {
+            Some(value)
+                if case_sensitive
+                    && *collation == Collation::CaseInsensitive
+                    && !value.is_empty() =>
+            {
+                let mut bytes = value.to_lowercase().into_bytes();
+                bytes.extend_from_slice(&[0, u8::MAX]);
+                self.add_string_bound_bytes(&bytes, true);
+            }
+            Some(value) => {
+                let value = if case_sensitive {
+                    value.to_string()
+                } else {
+                    value.to_lowercase()
+                };
+                let bytes = collate(&value, case_sensitive, collation);
+                self.add_string_bound_bytes(&bytes, true);
+            }
+            None => self.bytes.push(0),
+        }
+    }
+
+    /// Adds the lowest key of all strings starting with `prefix`.
+    pub fn add_string_prefix(&mut self, prefix: &str, case_sensitive: bool) {
+        let prefix = if case_sensitive {
+            prefix.to_string()
+        } else {
+            prefix.to_lowercase()
+        };
+        if prefix.is_empty() {
+            // the lowest key that is not null
+            self.bytes.push(1);
+        } else {
+            let len = prefix.len().min(IsarIndex::MAX_STRING_INDEX_SIZE);
+            self.bytes.extend_from_slice(&prefix.as_bytes()[..len]);
+        }
+    }
+
+    /// Adds the highest key of all strings starting with `prefix` including strings whose tail
+    /// was replaced by a hash.
+    pub fn add_string_prefix_upper_bound(&mut self, prefix: &str, case_sensitive: bool) {
+        let prefix = if case_sensitive {
+            prefix.to_string()
+        } else {
+            prefix.to_lowercase()
+        };
+        let len = prefix.len().min(IsarIndex::MAX_STRING_INDEX_SIZE);
+        self.bytes.extend_from_slice(&prefix.as_bytes()[..len]);
+        let padding = IsarIndex::MAX_STRING_INDEX_SIZE + 8 - len;
+        self.bytes.resize(self.bytes.len() + padding, u8::MAX);
+    }
+
+    /// Truncated values are compared without their hash so the bound covers all values that share
+    /// the truncated prefix.
+    fn add_string_bound_bytes(&mut self, bytes: &[u8], upper: bool) {
+        if bytes.len() >= IsarIndex::MAX_STRING_INDEX_SIZE {
+            self.bytes
+                .extend_from_slice(&bytes[0..IsarIndex::MAX_STRING_INDEX_SIZE]);
+            if upper {
+                self.bytes.extend_from_slice(&[u8::MAX; 8]);
+            }
+        } else {
+            self.add_string_bytes(bytes);
+        }
+    }
+
+    fn add_string_bytes(&mut self, bytes: &[u8]) {
+        if bytes.len() >= IsarIndex::MAX_STRING_INDEX_SIZE {
+            let index_bytes = &bytes[0..IsarIndex::MAX_STRING_INDEX_SIZE];
+            self.bytes.extend_from_slice(index_bytes);
+            let hash = xxh3_64(bytes);
+            self.bytes.extend_from_slice(&u64::to_le_bytes(hash));
+        } else if bytes.is_empty() {
+            self.bytes.push(1);
+        } else {
+            self.bytes.extend_from_slice(bytes);
+        }
+    }
+
     pub fn add_hash(&mut self, value: u64) {
         let bytes: [u8; 8] = value.to_be_bytes();
         self.bytes.extend_from_slice(&bytes);
     }
 
+    pub(crate) fn read_int(bytes: &[u8]) -> i32 {
+        (u32::from_be_bytes(bytes[..4].try_into().unwrap()) ^ 1 << 31) as i32
+    }
+
+    pub(crate) fn read_long(bytes: &[u8]) -> i64 {
+        (u64::from_be_bytes(bytes[..8].try_into().unwrap()) ^ 1 << 63) as i64
+    }
+
+    pub(crate) fn read_float(bytes: &[u8]) -> f32 {
+        let bits = u32::from_be_bytes(bytes[..4].try_into().unwrap());
+        if bits == 0 {
+            f32::NAN
+        } else if bits >= 1 << 31 {
+            f32::from_bits(bits - (1 << 31))
+        } else {
+            -f32::from_bits(!(bits + (1 << 31)))
+        }
+    }
+
+    pub(crate) fn read_double(bytes: &[u8]) -> f64 {
+        let bits = u64::from_be_bytes(bytes[..8].try_into().unwrap());
+        if bits == 0 {
+            f64::NAN
+        } else if bits >= 1 << 63 {
+            f64::from_bits(bits - (1 << 63))
+        } else {
+            -f64::from_bits(!(bits + (1 << 63)))
+        }
+    }
+
     #[allow(clippy::len_without_is_empty)]
     pub fn len(&self) -> usize {
         self.bytes.len()
@@ -329,4 +484,51 @@ mod tests {
             assert_eq!(index_key.bytes, bytes_lc);
         }
     }
+
+    #[test]
+    fn test_add_string_prefix() {
+        let long_str = (0..850).map(|_| "he").collect::<String>();
+        let mut lower = IndexKey::new();
+        lower.add_string_prefix("He", false);
+        let mut upper = IndexKey::new();
+        upper.add_string_prefix_upper_bound("He", false);
+
+        for str in ["he", "hello", "hel\u{10FFFF}", &long_str] {
+            let mut key = IndexKey::new();
+            key.add_string(Some(str), false);
+            assert!(lower <= key && key <= upper, "{}", str);
+        }
+        for str in [None, Some(""), Some("h"), Some("hf"), Some("hd\u{10FFFF}")] {
+            let mut key = IndexKey::new();
+            key.add_string(str, false);
+            assert!(key < lower || key > upper);
+        }
+    }
+
+    #[test]
+    fn test_read_values_from_key() {
+        for value in [i32::MIN, -1, 0, 1, i32::MAX] {
+            let mut key = IndexKey::new();
+            key.add_int(value);
+            assert_eq!(IndexKey::read_int(&key.bytes), value);
+        }
+        for value in [i64::MIN, -1, 0, 1, i64::MAX] {
+            let mut key = IndexKey::new();
+            key.add_long(value);
+            assert_eq!(IndexKey::read_long(&key.bytes), value);
+        }
+        for value in [f32::MIN, -1.5, -0.0, 0.0, 2.5, f32::INFINITY] {
+            let mut key = IndexKey::new();
+            key.add_float(value);
+            assert_eq!(IndexKey::read_float(&key.bytes).to_bits(), value.to_bits());
+        }
+        for value in [f64::NEG_INFINITY, -1.5, -0.0, 0.0, 2.5, f64::MAX] {
+            let mut key = IndexKey::new();
+            key.add_double(value);
+            assert_eq!(IndexKey::read_double(&key.bytes).to_bits(), value.to_bits());
+        }
+        let mut key = IndexKey::new();
+        key.add_double(f64::NAN);
+        assert!(IndexKey::read_double(&key.bytes).is_nan());
+    }
 }
@@ -1,9 +1,10 @@
-use crate::error::Result;
-use crate::index::index_key::IndexKey;
-use crate::index::IndexProperty;
+use crate::error::{illegal_arg, Result};
+use crate::index::geo::geo_hash;
+use crate::index::index_key::{IndexKey, IndexValue};
+use crate::index::{IndexProperty, IsarIndex};
 use crate::object::data_type::DataType;
 use crate::object::isar_object::IsarObject;
-use crate::schema::index_schema::IndexType;
+use crate::schema::index_schema::{Collation, IndexType};
 
 pub(crate) struct IndexKeyBuilder<'a> {
     properties: &'a [IndexProperty],
@@ -20,7 +21,12 @@ impl<'a> IndexKeyBuilder<'a> {
         mut callback: impl FnMut(&IndexKey) -> Result<bool>,
     ) -> Result<bool> {
         let first = self.properties.first().unwrap();
-        if !first.is_multi_entry() {
+        if first.index_type == IndexType::Geo {
+            if let Some(key) = self.create_geo_key(object) {
+                callback(&key)?;
+            }
+            Ok(true)
+        } else if !first.is_multi_entry() {
             let key = self.create_primitive_key(object);
             callback(&key)?;
             Ok(true)
@@ -53,9 +59,10 @@ impl<'a> IndexKeyBuilder<'a> {
                     DataType::Float => key.add_float(object.read_float(property.offset)),
                     DataType::Long => key.add_long(object.read_long(property.offset)),
                     DataType::Double => key.add_double(object.read_double(property.offset)),
-                    DataType::String => key.add_string(
+                    DataType::String => key.add_string_value(
                         object.read_string(property.offset),
                         index_property.case_sensitive,
+                        &index_property.collation,
                     ),
                     _ => unreachable!(),
                 }
@@ -64,6 +71,196 @@ impl<'a> IndexKeyBuilder<'a> {
         key
     }
 
+    /// Creates the bounds for keys whose first components equal `prefix` and whose next component
+    /// is within `lower` and `upper`. The flags mark inclusive bounds. Returns `None` if no key
+    /// can match.
+    pub fn create_prefix_bounds(
+        &self,
+        prefix: &[IndexValue],
+        lower: Option<(IndexValue, bool)>,
+        upper: Option<(IndexValue, bool)>,
+    ) -> Result<Option<(IndexKey, IndexKey)>> {
+        let ranged = (lower.is_some() || upper.is_some()) as usize;
+        if prefix.len() + ranged > self.properties.len() {
+            illegal_arg("More values than index properties.")?;
+        }
+
+        let mut prefix_key = IndexKey::new();
+        for (index_property, value) in self.properties.iter().zip(prefix) {
+            Self::add_value(&mut prefix_key, index_property, *value, None)?;
+        }
+
+        let next = prefix.len();
+        let mut lower_key = prefix_key.clone();
+        if let Some((value, include)) = lower {
+            let index_property = &self.properties[next];
+            if include {
+                Self::add_value(&mut lower_key, index_property, value, Some(false))?;
+            } else {
+                Self::add_value(&mut lower_key, index_property, value, Some(true))?;
+                if next + 1 == self.properties.len() && index_property.get_key_size().is_none() {
+                    // strings are not terminated so the next key extends the value
+                    lower_key.add_byte(0);
+                } else {
+                    self.add_upper_padding(&mut lower_key, next + 1);
+                    if !lower_key.increase() {
+                        return Ok(None);
+                    }
+                }
+            }
+        }
+
+        let mut upper_key = prefix_key;
+        if let Some((value, include)) = upper {
+            let index_property = &self.properties[next];
+            if include {
+                Self::add_value(&mut upper_key, index_property, value, Some(true))?;
+                self.add_upper_padding(&mut upper_key, next + 1);
+            } else {
+                Self::add_value(&mut upper_key, index_property, value, Some(false))?;
+                if !upper_key.decrease() {
+                    return Ok(None);
+                }
+                self.add_upper_padding(&mut upper_key, next);
+            }
+        } else {
+            self.add_upper_padding(&mut upper_key, next);
+        }
+
+        Ok(Some((lower_key, upper_key)))
+    }
+
+    /// Creates the bounds for keys whose first components equal `prefix` and whose next string
+    /// component starts with `value_prefix`. The bool is `false` if the bounds also contain other
+    /// values and the results have to be filtered.
+    pub fn create_string_prefix_bounds(
+        &self,
+        prefix: &[IndexValue],
+        value_prefix: &str,
+    ) -> Result<(IndexKey, IndexKey, bool)> {
+        if prefix.len() >= self.properties.len() {
+            illegal_arg("More values than index properties.")?;
+        }
+
+        let mut prefix_key = IndexKey::new();
+        for (index_property, value) in self.properties.iter().zip(prefix) {
+            Self::add_value(&mut prefix_key, index_property, *value, None)?;
+        }
+
+        let next = prefix.len();
+        let index_property = &self.properties[next];
+        let data_type = index_property.property.data_type;
+        if index_property.index_type != IndexType::Value
+            || data_type.get_element_type().unwrap_or(data_type) != DataType::String
+        {
+            illegal_arg("Prefixes require a string value index property.")?;
+        }
+        // case-insensitive collations start with the lowercase value
+        let case_sensitive = match index_property.collation {
+            Collation::Binary => index_property.case_sensitive,
+            Collation::CaseInsensitive => false,
+        };
+        let prefix_len = if case_sensitive {
+            value_prefix.len()
+        } else {
+            value_prefix.to_lowercase().len()
+        };
+        let exact = case_sensitive == index_property.case_sensitive
+            && prefix_len < IsarIndex::MAX_STRING_INDEX_SIZE;
+
+        let mut lower_key = prefix_key.clone();
+        lower_key.add_string_prefix(value_prefix, case_sensitive);
+        let mut upper_key = prefix_key;
+        upper_key.add_string_prefix_upper_bound(value_prefix, case_sensitive);
+        self.add_upper_padding(&mut upper_key, next + 1);
+
+        Ok((lower_key, upper_key, exact))
+    }
+
+    /// `bound` is `None` for exact values, `Some(false)` for lower and `Some(true)` for upper
+    /// bounds.
+    fn add_value(
+        key: &mut IndexKey,
+        index_property: &IndexProperty,
+        value: IndexValue,
+        bound: Option<bool>,
+    ) -> Result<()> {
+        let property = &index_property.property;
+        match index_property.index_type {
+            IndexType::Geo => illegal_arg("Use a geo where clause for geo indexes.")?,
+            IndexType::Hash | IndexType::HashElements => {
+                let value = match value {
+                    IndexValue::String(value) => value,
+                    _ => illegal_arg("Only string values can be hashed.")?,
+                };
+                if index_property.index_type == IndexType::Hash
+                    && property.data_type != DataType::String
+                {
+                    illegal_arg("Hashed lists require a raw key.")?;
+                }
+                if bound.is_some() {
+                    illegal_arg("Hashed index properties only support equality.")?;
+                }
+                key.add_hash(IsarObject::hash_string(
+                    value,
+                    index_property.case_sensitive,
+                    0,
+                ));
+            }
+            IndexType::Value => {
+                let data_type = property
+                    .data_type
+                    .get_element_type()
+                    .unwrap_or(property.data_type);
+                match (data_type, value) {
+                    (DataType::Bool | DataType::Byte, IndexValue::Byte(value)) => {
+                        key.add_byte(value)
+                    }
+                    (DataType::Int, IndexValue::Int(value)) => key.add_int(value),
+                    (DataType::Long, IndexValue::Long(value)) => key.add_long(value),
+                    (DataType::Float, IndexValue::Float(value)) => key.add_float(value),
+                    (DataType::Double, IndexValue::Double(value)) => key.add_double(value),
+                    (DataType::String, IndexValue::String(value)) => {
+                        let case_sensitive = index_property.case_sensitive;
+                        let collation = &index_property.collation;
+                        match bound {
+                            None => key.add_string_value(value, case_sensitive, collation),
+                            Some(false) => {
+                                key.add_string_lower_bound(value, case_sensitive, collation)
+                            }
+                            Some(true) => {
+                                key.add_string_upper_bound(value, case_sensitive, collation)
+                            }
+                        }
+                    }
+                    _ => illegal_arg("Value does not match the index property type.")?,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Pads the key with the highest possible bytes for all properties starting at `from`.
+    fn add_upper_padding(&self, key: &mut IndexKey, from: usize) {
+        for index_property in self.properties.iter().skip(from) {
+            let len = index_property
+                .get_key_size()
+                .unwrap_or(IsarIndex::MAX_STRING_INDEX_SIZE + 8);
+            for _ in 0..len {
+                key.add_byte(u8::MAX);
+            }
+        }
+    }
+
+    fn create_geo_key(&self, object: IsarObject) -> Option<IndexKey> {
+        let lat = object.read_double(self.properties[0].property.offset);
+        let lon = object.read_double(self.properties[1].property.offset);
+        let hash = geo_hash(lat, lon)?;
+        let mut key = IndexKey::new();
+        key.add_hash(hash);
+        Some(key)
+    }
+
     fn create_list_keys(
         index_property: &IndexProperty,
         object: IsarObject,
@@ -127,7 +324,11 @@ impl<'a> IndexKeyBuilder<'a> {
                         let hash = IsarObject::hash_string(value, index_property.case_sensitive, 0);
                         key.add_hash(hash);
                     } else {
-                        key.add_string(value, index_property.case_sensitive);
+                        key.add_string_value(
+                            value,
+                            index_property.case_sensitive,
+                            &index_property.collation,
+                        );
                     }
                     if !callback(&key)? {
                         return Ok(false);
@@ -0,0 +1,116 @@
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use xxhash_rust::xxh3::xxh3_64;
+
+/// Estimates the number of distinct values with a relative error of about 3%.
+pub(crate) struct HyperLogLog {
+    registers: Vec<u8>,
+}
+
+impl HyperLogLog {
+    const BITS: u32 = 10;
+
+    pub fn new() -> Self {
+        HyperLogLog {
+            registers: vec![0; 1 << Self::BITS],
+        }
+    }
+
+    pub fn add(&mut self, bytes: &[u8]) {
+        let hash = xxh3_64(bytes);
+        let index = (hash >> (64 - Self::BITS)) as usize;
+        // the guard bit limits the rank if all remaining bits are zero
+        let rank = ((hash << Self::BITS) | (1 << (Self::BITS - 1))).leading_zeros() + 1;
+        self.registers[index] = self.registers[index].max(rank as u8);
+    }
+
+    pub fn count(&self) -> u64 {
+        let m = self.registers.len() as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = self.registers.iter().map(|r| 2f64.powi(-(*r as i32))).sum();
+        let estimate = alpha * m * m / sum;
+        let zeros = self.registers.iter().filter(|r| **r == 0).count();
+        if estimate <= 2.5 * m && zeros > 0 {
+            // linear counting is more accurate for small cardinalities
+            (m * (m / zeros as f64).ln()).round() as u64
+        } else {
+            estimate.round() as u64
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexStats {
+    pub entries: u64,
+    /// Approximate number of distinct keys.
+    pub distinct_keys: u64,
+    pub min_key: Option<Vec<u8>>,
+    pub max_key: Option<Vec<u8>>,
+}
+
+impl IndexStats {
+    /// Average number of entries per key. Lower values make the index more selective.
+    pub fn entries_per_key(&self) -> f64 {
+        if self.distinct_keys == 0 {
+            0.0
+        } else {
+            (self.entries as f64 / self.distinct_keys as f64).max(1.0)
+        }
+    }
+
+    /// Stats are recomputed once the number of entries changed by more than 10%.
+    pub(crate) fn is_outdated(&self, entries: u64) -> bool {
+        self.entries.abs_diff(entries) * 10 > self.entries
+    }
+}
+
+/// Lazily computed stats that are shared by all clones of an index.
+#[derive(Clone, Default)]
+pub(crate) struct IndexStatsCache(pub Arc<Mutex<Option<IndexStats>>>);
+
+impl PartialEq for IndexStatsCache {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for IndexStatsCache {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hyper_log_log_count() {
+        assert_eq!(HyperLogLog::new().count(), 0);
+        for distinct in [10u64, 1000, 100_000] {
+            let mut hll = HyperLogLog::new();
+            for i in 0..distinct * 2 {
+                hll.add(&(i % distinct).to_le_bytes());
+            }
+            let error = hll.count().abs_diff(distinct) as f64 / distinct as f64;
+            assert!(error < 0.05, "{} distinct values", distinct);
+        }
+    }
+
+    #[test]
+    fn test_entries_per_key_and_outdated() {
+        let stats = IndexStats {
+            entries: 100,
+            distinct_keys: 20,
+            min_key: None,
+            max_key: None,
+        };
+        assert_eq!(stats.entries_per_key(), 5.0);
+        assert!(!stats.is_outdated(110));
+        assert!(stats.is_outdated(111));
+        assert!(stats.is_outdated(89));
+        let empty = IndexStats {
+            entries: 0,
+            distinct_keys: 0,
+            ..stats
+        };
+        assert_eq!(empty.entries_per_key(), 0.0);
+    }
+}
@@ -1,31 +1,49 @@
+use crate::collection::DbStats;
 use crate::cursor::IsarCursors;
-use crate::error::{IsarError, Result};
+use crate::error::{illegal_arg, IsarError, Result};
 use crate::index::index_key::IndexKey;
 use crate::index::index_key_builder::IndexKeyBuilder;
-use crate::mdbx::db::Db;
+use crate::index::index_stats::{HyperLogLog, IndexStats, IndexStatsCache};
+use crate::integrity::{IntegrityProblem, ProblemKind};
+use crate::object::data_type::DataType;
 use crate::object::id::{BytesToId, IdToBytes};
 use crate::object::isar_object::IsarObject;
 use crate::object::property::Property;
-use crate::schema::index_schema::IndexType;
+use crate::schema::index_schema::{Collation, IndexType};
+use crate::storage::Key;
+use crate::storage::{Db, LazyDb, StorageCursor};
 use intmap::IntMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use xxhash_rust::xxh3::xxh3_64;
 
+pub(crate) mod collation;
+pub mod geo;
 pub mod index_key;
 pub(crate) mod index_key_builder;
+pub mod index_stats;
+pub(crate) mod unique_constraint;
 
 #[derive(Clone, Eq, PartialEq)]
 pub struct IndexProperty {
     pub property: Property,
     pub index_type: IndexType,
     pub case_sensitive: bool,
+    pub collation: Collation,
 }
 
 impl IndexProperty {
-    pub(crate) fn new(property: Property, index_type: IndexType, case_sensitive: bool) -> Self {
+    pub(crate) fn new(
+        property: Property,
+        index_type: IndexType,
+        case_sensitive: bool,
+        collation: Collation,
+    ) -> Self {
         IndexProperty {
             property,
             index_type,
             case_sensitive,
+            collation,
         }
     }
 
@@ -39,11 +57,36 @@ impl IndexProperty {
         })
     }
 
+    /// Size of the key component. `None` for strings which have a variable size.
+    pub(crate) fn get_key_size(&self) -> Option<usize> {
+        let data_type = self.property.data_type;
+        match self.index_type {
+            IndexType::Hash | IndexType::HashElements | IndexType::Geo => Some(8),
+            IndexType::Value => match data_type.get_element_type().unwrap_or(data_type) {
+                DataType::Bool | DataType::Byte => Some(1),
+                DataType::Int | DataType::Float => Some(4),
+                DataType::Long | DataType::Double => Some(8),
+                _ => None,
+            },
+        }
+    }
+
     fn is_multi_entry(&self) -> bool {
         self.property.data_type.get_element_type().is_some() && self.index_type != IndexType::Hash
     }
 }
 
+#[derive(Clone)]
+struct IndexState(Arc<AtomicBool>);
+
+impl PartialEq for IndexState {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for IndexState {}
+
 #[derive(Clone, Eq, PartialEq)]
 pub(crate) struct IsarIndex {
     pub name: String,
@@ -52,7 +95,9 @@ pub(crate) struct IsarIndex {
     pub unique: bool,
     pub replace: bool,
     pub multi_entry: bool,
-    db: Db,
+    db: LazyDb,
+    pending: IndexState,
+    stats: IndexStatsCache,
 }
 
 impl IsarIndex {
@@ -60,10 +105,11 @@ impl IsarIndex {
 
     pub fn new(
         name: &str,
-        db: Db,
+        db: LazyDb,
         properties: Vec<IndexProperty>,
         unique: bool,
         replace: bool,
+        pending: bool,
     ) -> Self {
         let id = xxh3_64(name.as_bytes());
         let multi_entry = properties.first().unwrap().is_multi_entry();
@@ -75,7 +121,39 @@ impl IsarIndex {
             replace,
             multi_entry,
             db,
+            pending: IndexState(Arc::new(AtomicBool::new(pending))),
+            stats: IndexStatsCache::default(),
+        }
+    }
+
+    /// Pending indexes are not filled yet and must not be used to look up objects.
+    pub fn is_pending(&self) -> bool {
+        self.pending.0.load(Ordering::Acquire)
+    }
+
+    pub fn set_complete(&self) {
+        self.pending.0.store(false, Ordering::Release)
+    }
+
+    /// Offset of the property value in the keys if it can be read without loading the object.
+    pub fn covered_offset(&self, property: &Property) -> Option<usize> {
+        if self.multi_entry {
+            return None;
         }
+        let mut offset = 0;
+        for index_property in &self.properties {
+            let size = index_property.get_key_size()?;
+            if &index_property.property == property {
+                return (index_property.index_type == IndexType::Value).then_some(offset);
+            }
+            offset += size;
+        }
+        None
+    }
+
+    pub fn warm_up(&self, cursors: &IsarCursors) -> Result<()> {
+        cursors.open_db(&self.db)?;
+        Ok(())
     }
 
     pub fn create_for_object<F>(
@@ -83,22 +161,40 @@ impl IsarIndex {
         cursors: &IsarCursors,
         id: i64,
         object: IsarObject,
+        delete: F,
+    ) -> Result<()>
+    where
+        F: FnMut(i64) -> Result<()>,
+    {
+        self.create_for_object_with(cursors, id, object, self.replace, delete)
+    }
+
+    /// Like `create_for_object` but `replace` overrides the replace flag of the schema.
+    pub fn create_for_object_with<F>(
+        &self,
+        cursors: &IsarCursors,
+        id: i64,
+        object: IsarObject,
+        replace: bool,
         mut delete: F,
     ) -> Result<()>
     where
         F: FnMut(i64) -> Result<()>,
     {
-        let mut cursor = cursors.get_cursor(self.db)?;
+        let mut cursor = cursors.get_lazy_cursor(&self.db)?;
         let key_builder = IndexKeyBuilder::new(&self.properties);
         key_builder.create_keys(object, |key| {
             if self.unique {
                 let existing = cursor.move_to(key)?;
                 if let Some((_, existing_id_bytes)) = existing {
                     let existing_id = existing_id_bytes.to_id();
-                    if self.replace && existing_id != id {
+                    if replace && existing_id != id {
                         delete(existing_id)?;
                     } else {
-                        return Err(IsarError::UniqueViolated {});
+                        return Err(IsarError::UniqueViolated {
+                            index: self.name.clone(),
+                            id: existing_id,
+                        });
                     }
                 }
             }
@@ -109,13 +205,39 @@ impl IsarIndex {
         Ok(())
     }
 
+    /// Returns the id of another object that has the same unique key as `object`.
+    pub fn find_conflict(
+        &self,
+        cursors: &IsarCursors,
+        id: Option<i64>,
+        object: IsarObject,
+    ) -> Result<Option<i64>> {
+        if !self.unique {
+            return Ok(None);
+        }
+        let mut cursor = cursors.get_lazy_cursor(&self.db)?;
+        let key_builder = IndexKeyBuilder::new(&self.properties);
+        let mut conflict = None;
+        key_builder.create_keys(object, |key| {
+            if let Some((_, existing_id_bytes)) = cursor.move_to(key)? {
+                let existing_id = existing_id_bytes.to_id();
+                if Some(existing_id) != id {
+                    conflict = Some(existing_id);
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        })?;
+        Ok(conflict)
+    }
+
     pub fn delete_for_object(
         &self,
         cursors: &IsarCursors,
         id: i64,
         object: IsarObject,
     ) -> Result<()> {
-        let mut cursor = cursors.get_cursor(self.db)?;
+        let mut cursor = cursors.get_lazy_cursor(&self.db)?;
         let key_builder = IndexKeyBuilder::new(&self.properties);
         key_builder.create_keys(object, |key| {
             let entry = if self.unique {
@@ -140,17 +262,48 @@ impl IsarIndex {
         ascending: bool,
         mut callback: impl FnMut(i64) -> Result<bool>,
     ) -> Result<bool> {
-        let mut cursor = cursors.get_cursor(self.db)?;
+        self.iter_between_with_key(
+            cursors,
+            lower_key,
+            upper_key,
+            skip_duplicates,
+            ascending,
+            |_, id| callback(id),
+        )
+    }
+
+    pub fn iter_between_with_key<'txn, 'env>(
+        &self,
+        cursors: &IsarCursors<'txn, 'env>,
+        lower_key: &IndexKey,
+        upper_key: &IndexKey,
+        skip_duplicates: bool,
+        ascending: bool,
+        mut callback: impl FnMut(&'txn [u8], i64) -> Result<bool>,
+    ) -> Result<bool> {
+        let mut cursor = cursors.get_lazy_cursor(&self.db)?;
         cursor.iter_between(
             lower_key,
             upper_key,
             !self.unique,
             skip_duplicates,
             ascending,
-            |_, _, id_bytes| callback(id_bytes.to_id()),
+            |_, key, id_bytes| callback(key, id_bytes.to_id()),
         )
     }
 
+    pub fn iter_all<'txn, 'env>(
+        &self,
+        cursors: &IsarCursors<'txn, 'env>,
+        skip_duplicates: bool,
+        mut callback: impl FnMut(i64) -> Result<bool>,
+    ) -> Result<bool> {
+        let mut cursor = cursors.get_lazy_cursor(&self.db)?;
+        cursor.iter_all(skip_duplicates, true, |_, _, id_bytes| {
+            callback(id_bytes.to_id())
+        })
+    }
+
     pub fn get_id<'txn, 'env>(
         &self,
         cursors: &IsarCursors<'txn, 'env>,
@@ -164,18 +317,143 @@ impl IsarIndex {
         Ok(result)
     }
 
+    /// All ids with exactly this key in ascending order.
+    pub fn get_ids(&self, cursors: &IsarCursors, key: &IndexKey) -> Result<Vec<i64>> {
+        let mut ids = vec![];
+        let mut cursor = cursors.get_lazy_cursor(&self.db)?;
+        cursor.iter_dups(key, |_, id_bytes| {
+            ids.push(id_bytes.to_id());
+            Ok(true)
+        })?;
+        Ok(ids)
+    }
+
+    /// Checks that the size of the key matches the index properties. Keys do not contain type
+    /// information so only the size can be validated.
+    pub fn validate_key(&self, key: &IndexKey) -> Result<()> {
+        let geo = self.properties[0].index_type == IndexType::Geo;
+        let sizes = if geo {
+            vec![Some(8)]
+        } else {
+            self.properties.iter().map(|p| p.get_key_size()).collect()
+        };
+        let fixed_size: usize = sizes.iter().flatten().sum();
+        let strings = sizes.iter().filter(|s| s.is_none()).count();
+        let min_size = fixed_size + strings;
+        let max_size = fixed_size + strings * (Self::MAX_STRING_INDEX_SIZE + 8);
+        if key.len() < min_size || key.len() > max_size {
+            illegal_arg("Key does not match the index properties.")
+        } else {
+            Ok(())
+        }
+    }
+
     pub fn get_size(&self, cursors: &IsarCursors) -> Result<u64> {
-        Ok(cursors.db_stat(self.db)?.1)
+        Ok(cursors.db_stat(cursors.open_db(&self.db)?)?.1)
+    }
+
+    pub fn get_stats(&self, cursors: &IsarCursors, col_name: &str) -> Result<DbStats> {
+        let name = format!("_i_{}_{}", col_name, self.name);
+        Ok(DbStats::new(
+            name,
+            cursors.db_raw_stat(cursors.open_db(&self.db)?)?,
+        ))
+    }
+
+    /// Returns the cached stats unless the number of entries changed too much.
+    pub fn get_index_stats(&self, cursors: &IsarCursors) -> Result<IndexStats> {
+        let (entries, _) = cursors.db_stat(cursors.open_db(&self.db)?)?;
+        let mut cached = self.stats.0.lock().unwrap();
+        if let Some(stats) = cached.as_ref() {
+            if !stats.is_outdated(entries) {
+                return Ok(stats.clone());
+            }
+        }
+
+        let mut hll = HyperLogLog::new();
+        let mut min_key = None;
+        let mut max_key: Option<&[u8]> = None;
+        let mut cursor = cursors.get_lazy_cursor(&self.db)?;
+        cursor.iter_all(true, true, |_, key, _| {
+            if min_key.is_none() {
+                min_key = Some(key.to_vec());
+            }
+            max_key = Some(key);
+            hll.add(key);
+            Ok(true)
+        })?;
+        let stats = IndexStats {
+            entries,
+            distinct_keys: hll.count().min(entries),
+            min_key,
+            max_key: max_key.map(|k| k.to_vec()),
+        };
+        cached.replace(stats.clone());
+        Ok(stats)
     }
 
     pub fn clear(&self, cursors: &IsarCursors) -> Result<()> {
-        cursors.clear_db(self.db)
+        cursors.clear_db(cursors.open_db(&self.db)?)
+    }
+
+    /// Checks that every object has all of its keys and that every entry belongs to an object.
+    pub fn check_integrity(
+        &self,
+        cursors: &IsarCursors,
+        data_db: Db,
+        col_name: &str,
+        problems: &mut Vec<IntegrityProblem>,
+    ) -> Result<()> {
+        let key_builder = IndexKeyBuilder::new(&self.properties);
+        let mut index_cursor = cursors.get_lazy_cursor(&self.db)?;
+        let mut data_cursor = cursors.get_cursor(data_db)?;
+        data_cursor.iter_all(false, true, |_, id_bytes, bytes| {
+            let id = id_bytes.to_id();
+            let mut missing = false;
+            key_builder.create_keys(IsarObject::from_bytes(bytes), |key| {
+                missing = index_cursor
+                    .move_to_key_val(key, &id.to_id_bytes())?
+                    .is_none();
+                Ok(!missing)
+            })?;
+            if missing {
+                problems.push(IntegrityProblem::new(
+                    ProblemKind::MissingIndexEntry,
+                    col_name,
+                    Some(&self.name),
+                    id,
+                ));
+            }
+            Ok(true)
+        })?;
+
+        let mut object_cursor = cursors.get_cursor(data_db)?;
+        index_cursor.iter_all(false, true, |_, key, id_bytes| {
+            let id = id_bytes.to_id();
+            let mut found = false;
+            if let Some((_, bytes)) = object_cursor.move_to(&id)? {
+                key_builder.create_keys(IsarObject::from_bytes(bytes), |object_key| {
+                    found = object_key.as_bytes().as_ref() == key;
+                    Ok(!found)
+                })?;
+            }
+            if !found {
+                problems.push(IntegrityProblem::new(
+                    ProblemKind::ObsoleteIndexEntry,
+                    col_name,
+                    Some(&self.name),
+                    id,
+                ));
+            }
+            Ok(true)
+        })?;
+        Ok(())
     }
 
     pub fn verify(&self, cursors: &IsarCursors, objects: &IntMap<IsarObject>) -> Result<()> {
         let mut count = 0;
 
-        let mut cursor = cursors.get_cursor(self.db)?;
+        let mut cursor = cursors.get_lazy_cursor(&self.db)?;
         for id in objects.keys() {
             let id = *id;
             let object = *objects.get(id).unwrap();
@@ -194,7 +472,7 @@ impl IsarIndex {
             })?;
         }
 
-        if cursors.db_stat(self.db)?.0 != count {
+        if cursors.db_stat(cursors.open_db(&self.db)?)?.0 != count {
             Err(IsarError::DbCorrupted {
                 message: "Obsolete index entry.".to_string(),
             })
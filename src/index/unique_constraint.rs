@@ -0,0 +1,124 @@
+use crate::cursor::IsarCursors;
+use crate::error::{IsarError, Result};
+use crate::object::data_type::DataType;
+use crate::object::id::{BytesToId, IdToBytes};
+use crate::object::isar_object::IsarObject;
+use crate::object::property::Property;
+use crate::storage::{Db, StorageCursor};
+use xxhash_rust::xxh3::xxh3_64;
+
+/// Maps the hash of the constrained values to the ids of the objects. Objects with the same hash
+/// are compared to rule out hash collisions.
+#[derive(Clone)]
+pub(crate) struct UniqueConstraint {
+    pub name: String,
+    pub properties: Vec<Property>,
+    case_sensitive: bool,
+    db: Db,
+}
+
+impl UniqueConstraint {
+    pub fn new(name: &str, properties: Vec<Property>, case_sensitive: bool, db: Db) -> Self {
+        UniqueConstraint {
+            name: name.to_string(),
+            properties,
+            case_sensitive,
+            db,
+        }
+    }
+
+    fn hash(&self, object: IsarObject) -> i64 {
+        let mut hash = xxh3_64(self.name.as_bytes());
+        for property in &self.properties {
+            hash = object.hash_property(
+                property.offset,
+                property.data_type,
+                self.case_sensitive,
+                hash,
+            );
+        }
+        hash as i64
+    }
+
+    fn values_equal(&self, object: IsarObject, other: IsarObject) -> bool {
+        self.properties.iter().all(|p| {
+            if p.data_type == DataType::String {
+                let s1 = object.read_string(p.offset);
+                let s2 = other.read_string(p.offset);
+                if self.case_sensitive {
+                    s1 == s2
+                } else {
+                    s1.map(|s| s.to_lowercase()) == s2.map(|s| s.to_lowercase())
+                }
+            } else {
+                let size = p.data_type.get_static_size();
+                let b1 = object.as_bytes().get(p.offset..p.offset + size);
+                let b2 = other.as_bytes().get(p.offset..p.offset + size);
+                b1 == b2
+            }
+        })
+    }
+
+    /// Returns the id of another object of `col_db` that has the same values as `object`.
+    pub fn find_conflict(
+        &self,
+        cursors: &IsarCursors,
+        col_db: Db,
+        id: Option<i64>,
+        object: IsarObject,
+    ) -> Result<Option<i64>> {
+        let hash = self.hash(object);
+        let mut cursor = cursors.get_cursor(self.db)?;
+        let mut col_cursor = cursors.get_cursor(col_db)?;
+        let mut conflict = None;
+        cursor.iter_dups(&hash, |_, existing_id| {
+            let existing_id = existing_id.to_id();
+            if Some(existing_id) != id {
+                if let Some((_, existing)) = col_cursor.move_to(&existing_id)? {
+                    if self.values_equal(object, IsarObject::from_bytes(existing)) {
+                        conflict = Some(existing_id);
+                        return Ok(false);
+                    }
+                }
+            }
+            Ok(true)
+        })?;
+        Ok(conflict)
+    }
+
+    /// Fails with `UniqueConstraintViolated` if another object of `col_db` has the same values.
+    pub fn create_for_object(
+        &self,
+        cursors: &IsarCursors,
+        col_db: Db,
+        id: i64,
+        object: IsarObject,
+    ) -> Result<()> {
+        if let Some(existing_id) = self.find_conflict(cursors, col_db, Some(id), object)? {
+            return Err(IsarError::UniqueConstraintViolated {
+                constraint: self.name.clone(),
+                id: existing_id,
+            });
+        }
+        let mut cursor = cursors.get_cursor(self.db)?;
+        cursor.put(&self.hash(object), &id.to_id_bytes())
+    }
+
+    pub fn delete_for_object(
+        &self,
+        cursors: &IsarCursors,
+        id: i64,
+        object: IsarObject,
+    ) -> Result<()> {
+        let hash = self.hash(object);
+        let mut cursor = cursors.get_cursor(self.db)?;
+        if cursor.move_to_key_val(&hash, &id.to_id_bytes())?.is_some() {
+            cursor.delete_current()?;
+        }
+        Ok(())
+    }
+
+    pub fn clear(&self, cursors: &IsarCursors) -> Result<()> {
+        cursors.clear_db(self.db)
+    }
+}
@@ -1,45 +1,313 @@
+use crate::change_journal::{ChangeJournal, ChangeJournalCallback, ChangeJournalEntry, ChangeOp};
 use crate::collection::IsarCollection;
+use crate::cursor::IsarCursors;
 use crate::error::*;
-use crate::mdbx::env::Env;
+use crate::integrity::IntegrityProblem;
+use crate::object::buffer_pool::{get_buffer_pool, remove_buffer_pool, BufferPool};
+use crate::object::id::BytesToId;
+use crate::object::isar_object::IsarObject;
+use crate::object::object_cache::ObjectCache;
+use crate::object::property::Property;
+use crate::object::property_transform::PropertyTransform;
 use crate::query::Query;
+use crate::schema::db_info::DbInfo;
+use crate::schema::migration_plan::MigrationPlan;
 use crate::schema::schema_manager::SchemaManager;
 use crate::schema::Schema;
-use crate::txn::IsarTxn;
+use crate::storage::{Db, Env, StorageCursor, StorageDb, StorageEngine, StorageTxn};
+use crate::txn::{IsarTxn, OpenReaders, OwnCommits, ReaderGuard, TxnCounters};
 use crate::watch::change_set::ChangeSet;
 use crate::watch::isar_watchers::{IsarWatchers, WatcherModifier};
-use crate::watch::watcher::WatcherCallback;
-use crate::watch::WatchHandle;
-use crossbeam_channel::{unbounded, Sender};
+use crate::watch::lazy_watcher::LazyQueryWatcher;
+use crate::watch::query_diff::{QueryDiffCallback, QueryDiffState};
+use crate::watch::read_pool::WatcherReadPool;
+use crate::watch::watcher::{pausable, WatcherCallback};
+use crate::watch::{WatchHandle, WatcherInfo, WatcherKind};
+use crossbeam_channel::{bounded, unbounded, Sender};
 use intmap::IntMap;
+use itertools::Itertools;
 use once_cell::sync::Lazy;
-use std::fs::remove_file;
-use std::fs::{self, metadata};
+use serde::Serialize;
+use std::fs::{self, remove_file};
 use std::path::PathBuf;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
 use xxhash_rust::xxh3::xxh3_64;
 
-static INSTANCES: Lazy<RwLock<IntMap<Arc<IsarInstance>>>> =
-    Lazy::new(|| RwLock::new(IntMap::new()));
+/// Open instances and how often they have been opened.
+type Instances = IntMap<(Arc<IsarInstance>, usize)>;
+
+/// Active watchers and whether they are paused.
+type ActiveWatchers = IntMap<(WatcherInfo, Arc<AtomicBool>)>;
+
+static INSTANCES: Lazy<RwLock<Instances>> = Lazy::new(|| RwLock::new(IntMap::new()));
 
 static WATCHER_ID: AtomicU64 = AtomicU64::new(0);
 
+static READER_ID: AtomicU64 = AtomicU64::new(0);
+
 pub struct CompactCondition {
     pub min_file_size: u64,
     pub min_bytes: u64,
     pub min_ratio: f64,
 }
 
+/// How commits are written to disk.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Default)]
+pub enum Durability {
+    /// Every commit is synced to disk.
+    #[default]
+    Safe,
+    /// The meta page is not synced. A system crash may roll back the last commit.
+    NoMetaSync,
+    /// Commits are only synced by `IsarInstance::flush`. A system crash may roll back all
+    /// commits since the last flush but the database stays consistent.
+    AsyncFlush,
+    /// Commits are only synced by `IsarInstance::flush`. A system crash may corrupt the
+    /// database.
+    NoSync,
+}
+
+impl Durability {
+    /// Whether commits are synced to disk before they return.
+    pub fn syncs_on_commit(self) -> bool {
+        matches!(self, Durability::Safe | Durability::NoMetaSync)
+    }
+}
+
+pub struct EnvGeometry {
+    pub max_size: usize,
+    pub growth_step: usize,
+    pub shrink_threshold: usize,
+}
+
+pub type IndexFillCallback = Box<dyn Fn(&str, u64, u64) + Send + Sync>;
+
+pub struct IndexFillOptions {
+    pub progress: Option<IndexFillCallback>,
+    pub cancel: Option<Arc<AtomicBool>>,
+}
+
+/// Options of `IsarInstance::open`.
+#[derive(Default)]
+pub struct OpenOptions {
+    pub durability: Durability,
+    pub compact_condition: Option<CompactCondition>,
+    pub geometry: Option<EnvGeometry>,
+    pub read_only: bool,
+    pub in_memory: bool,
+    pub change_feed: bool,
+    pub multi_process: bool,
+    pub index_fill: Option<IndexFillOptions>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReaderInfo {
+    pub pid: i32,
+    pub thread: u64,
+    pub txn_id: u64,
+    /// Number of transactions the reader is behind the latest one.
+    pub lag: u64,
+    pub bytes_retained: u64,
+    /// Only known for readers of this process.
+    pub age_ms: Option<u64>,
+}
+
+/// IO counters of an instance since it was opened.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PerfCounters {
+    pub commits: u64,
+    /// Syncs of commits and flushes.
+    pub syncs: u64,
+    /// Size of the pages written by commits.
+    pub bytes_written: u64,
+}
+
+/// Cursor usage of a db since cursor tracking was enabled.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CursorStats {
+    /// Runtime id of the db.
+    pub db: u64,
+    /// Cursors that are currently in use.
+    pub open: u32,
+    /// Highest number of cursors that were in use at the same time.
+    pub high_water: u32,
+    /// Cursors that were created because no pooled cursor was available.
+    pub created: u64,
+    /// Cursors that were not returned before their transaction ended.
+    pub leaked: u32,
+}
+
+/// An operation of `IsarInstance::write_batch`.
+pub enum BatchOp<'a> {
+    Put {
+        collection: &'a IsarCollection,
+        id: Option<i64>,
+        object: IsarObject<'a>,
+    },
+    Delete {
+        collection: &'a IsarCollection,
+        id: i64,
+    },
+    Link {
+        collection: &'a IsarCollection,
+        link_id: u64,
+        id: i64,
+        target_id: i64,
+    },
+    Unlink {
+        collection: &'a IsarCollection,
+        link_id: u64,
+        id: i64,
+        target_id: i64,
+    },
+}
+
+pub type WriteJob = Box<dyn FnOnce(&mut IsarTxn) -> Result<()> + Send>;
+
+pub type WriteCallback = Box<dyn FnOnce(Result<()>) + Send>;
+
+#[derive(Default)]
+struct WriteQueueCounters {
+    pending: AtomicUsize,
+    completed: AtomicU64,
+    failed: AtomicU64,
+}
+
+pub struct WriteQueueStats {
+    pub pending: usize,
+    pub completed: u64,
+    pub failed: u64,
+}
+
+/// Executes write jobs one after another on a dedicated writer thread. Each job runs in its own
+/// write transaction which is committed if the job succeeds. Dropping the queue waits for all
+/// submitted jobs.
+pub struct WriteQueue {
+    sender: Option<Sender<(WriteJob, WriteCallback)>>,
+    counters: Arc<WriteQueueCounters>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl WriteQueue {
+    fn new(instance: Arc<IsarInstance>, capacity: Option<usize>) -> Self {
+        let (sender, receiver) = if let Some(capacity) = capacity {
+            bounded::<(WriteJob, WriteCallback)>(capacity)
+        } else {
+            unbounded()
+        };
+        let counters = Arc::new(WriteQueueCounters::default());
+        let thread_counters = counters.clone();
+        let thread = thread::Builder::new()
+            .name("isarwriter".to_string())
+            .spawn(move || {
+                for (job, callback) in receiver {
+                    let result =
+                        instance
+                            .begin_txn(true, false)
+                            .and_then(|mut txn| match job(&mut txn) {
+                                Ok(()) => txn.commit(),
+                                Err(e) => {
+                                    txn.abort();
+                                    Err(e)
+                                }
+                            });
+                    thread_counters.pending.fetch_sub(1, Ordering::Relaxed);
+                    if result.is_ok() {
+                        thread_counters.completed.fetch_add(1, Ordering::Relaxed);
+                    } else {
+                        thread_counters.failed.fetch_add(1, Ordering::Relaxed);
+                    }
+                    callback(result);
+                }
+            })
+            .unwrap();
+        WriteQueue {
+            sender: Some(sender),
+            counters,
+            thread: Some(thread),
+        }
+    }
+
+    /// Blocks while the queue is full.
+    pub fn submit(&self, job: WriteJob, callback: WriteCallback) -> Result<()> {
+        self.counters.pending.fetch_add(1, Ordering::Relaxed);
+        if self.sender.as_ref().unwrap().send((job, callback)).is_err() {
+            self.counters.pending.fetch_sub(1, Ordering::Relaxed);
+            return Err(IsarError::TransactionClosed {});
+        }
+        Ok(())
+    }
+
+    /// Returns the job and callback if the queue is full.
+    pub fn try_submit(
+        &self,
+        job: WriteJob,
+        callback: WriteCallback,
+    ) -> std::result::Result<(), (WriteJob, WriteCallback)> {
+        self.counters.pending.fetch_add(1, Ordering::Relaxed);
+        let result = self.sender.as_ref().unwrap().try_send((job, callback));
+        result.map_err(|e| {
+            self.counters.pending.fetch_sub(1, Ordering::Relaxed);
+            e.into_inner()
+        })
+    }
+
+    pub fn stats(&self) -> WriteQueueStats {
+        WriteQueueStats {
+            pending: self.counters.pending.load(Ordering::Relaxed),
+            completed: self.counters.completed.load(Ordering::Relaxed),
+            failed: self.counters.failed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Drop for WriteQueue {
+    fn drop(&mut self) {
+        self.sender.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Collections and schemas of an instance. Renaming a collection replaces the state.
+struct InstanceState {
+    collections: Vec<IsarCollection>,
+    /// Schema after the migration.
+    schema: Schema,
+    /// Schema the instance was opened with.
+    requested_schema: Schema,
+    schema_hash: u64,
+}
+
 pub struct IsarInstance {
     pub name: String,
     pub dir: String,
-    pub collections: Vec<IsarCollection>,
+    pub in_memory: bool,
     pub(crate) instance_id: u64,
-    pub(crate) schema_hash: u64,
+    /// Replaced states are kept until the instance is dropped because their collections may
+    /// still be borrowed.
+    states: RwLock<Vec<Box<InstanceState>>>,
 
     env: Env,
     watchers: Mutex<IsarWatchers>,
     watcher_modifier_sender: Sender<WatcherModifier>,
+    active_watchers: Arc<Mutex<ActiveWatchers>>,
+    watcher_read_pool: Lazy<WatcherReadPool>,
+    journal_callback: Mutex<Option<Arc<ChangeJournalCallback>>>,
+    max_read_txn_age: Mutex<Option<Duration>>,
+    open_readers: OpenReaders,
+    own_commits: Option<OwnCommits>,
+    txn_counters: Arc<TxnCounters>,
+    last_seen_txn_id: AtomicU64,
+    change_feed_db: Option<Db>,
+    db_info: Option<DbInfo>,
 }
 
 impl IsarInstance {
@@ -47,29 +315,34 @@ impl IsarInstance {
         name: &str,
         dir: Option<&str>,
         mut schema: Schema,
-        relaxed_durability: bool,
-        compact_condition: Option<CompactCondition>,
+        options: OpenOptions,
     ) -> Result<Arc<Self>> {
+        // every instance is stored in its own file of the directory
+        if name.is_empty() || name.contains(['/', '\\']) {
+            return illegal_arg("Invalid instance name.");
+        }
         let mut lock = INSTANCES.write().unwrap();
         let instance_id = xxh3_64(name.as_bytes());
-        if let Some(instance) = lock.get(instance_id) {
-            if instance.schema_hash == schema.hash() {
+        let memory_dir = if options.in_memory {
+            Some(Self::get_memory_dir()?)
+        } else {
+            None
+        };
+        let dir = memory_dir.as_deref().or(dir);
+        if let Some((instance, open_count)) = lock.get_mut(instance_id) {
+            if dir.is_some_and(|dir| dir != instance.dir) {
+                illegal_arg("Instance is already open in a different directory.")
+            } else if instance.schema_hash() == schema.hash() {
+                *open_count += 1;
                 Ok(instance.clone())
             } else {
                 Err(IsarError::SchemaMismatch {})
             }
         } else {
             if let Some(dir) = dir {
-                let new_instance = Self::open_internal(
-                    name,
-                    dir,
-                    instance_id,
-                    schema,
-                    relaxed_durability,
-                    compact_condition,
-                )?;
+                let new_instance = Self::open_internal(name, dir, instance_id, schema, options)?;
                 let new_instance = Arc::new(new_instance);
-                lock.insert(instance_id, new_instance.clone());
+                lock.insert(instance_id, (new_instance.clone(), 1));
                 Ok(new_instance)
             } else {
                 Err(IsarError::IllegalArg {
@@ -79,6 +352,161 @@ impl IsarInstance {
         }
     }
 
+    pub fn plan_migration(name: &str, dir: &str, schema: &Schema) -> Result<MigrationPlan> {
+        if let Some(instance) = Self::get_instance(name) {
+            let txn = instance.env.txn(false)?;
+            let manager = SchemaManager::create(instance.instance_id, &txn)?;
+            return manager.plan_migration(&txn, schema);
+        }
+
+        let isar_file = Self::get_isar_path(name, dir);
+        if !Env::exists(&isar_file) {
+            return Ok(MigrationPlan::new_instance(schema));
+        }
+
+        let db_count = schema.count_dbs() as u64 + 6;
+        let env = Self::open_read_only_env(&isar_file, db_count)?;
+        let txn = env.txn(false)?;
+        let manager = SchemaManager::create(xxh3_64(name.as_bytes()), &txn)?;
+        manager.plan_migration(&txn, schema)
+    }
+
+    pub fn read_db_info(name: &str, dir: &str) -> Result<Option<DbInfo>> {
+        if let Some(instance) = Self::get_instance(name) {
+            let txn = instance.env.txn(false)?;
+            let manager = SchemaManager::create(instance.instance_id, &txn)?;
+            return Ok(manager.db_info);
+        }
+
+        let isar_file = Self::get_isar_path(name, dir);
+        if !Env::exists(&isar_file) {
+            return Ok(None);
+        }
+
+        let env = Self::open_read_only_env(&isar_file, 5)?;
+        let txn = env.txn(false)?;
+        let manager = SchemaManager::create(xxh3_64(name.as_bytes()), &txn)?;
+        Ok(manager.db_info)
+    }
+
+    /// Renames a collection in a single write transaction. The data, indexes and links are moved
+    /// and the links of other collections are updated. Collections that were obtained before
+    /// the rename must not be used afterwards.
+    pub fn rename_collection(&self, old_name: &str, new_name: &str) -> Result<()> {
+        // the write transaction is taken first because writers may read the state
+        let txn = self.env.txn(true)?;
+        let mut states = self.states.write().unwrap();
+        let state = states.last().unwrap();
+        let mut manager = SchemaManager::create(self.instance_id, &txn)?;
+        manager.rename_stored_collection(&txn, old_name, new_name)?;
+
+        let mut schema = state.schema.clone();
+        schema.rename_collection(old_name, new_name);
+        let collections = manager.reopen_collections(&txn, &schema)?;
+        let mut requested_schema = state.requested_schema.clone();
+        requested_schema.rename_collection(old_name, new_name);
+        let schema_hash = requested_schema.hash();
+        manager.save_db_info(&txn, schema_hash)?;
+        txn.commit()?;
+
+        let new_id = xxh3_64(new_name.as_bytes());
+        for (col, previous) in collections.iter().zip(&state.collections) {
+            debug_assert!(col.id == previous.id || col.id == new_id);
+            col.copy_settings(previous);
+        }
+        states.push(Box::new(InstanceState {
+            collections,
+            schema: Schema {
+                collections: manager.opened,
+            },
+            requested_schema,
+            schema_hash,
+        }));
+        Ok(())
+    }
+
+    /// Names of all instances that are stored in the directory.
+    pub fn list_instances(dir: &str) -> Result<Vec<String>> {
+        let entries = fs::read_dir(dir).map_err(|_| IsarError::PathError {})?;
+        let mut names = entries
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                if path.is_file() && path.extension()? == "isar" {
+                    Some(path.file_stem()?.to_str()?.to_string())
+                } else {
+                    None
+                }
+            })
+            .collect_vec();
+        names.sort();
+        Ok(names)
+    }
+
+    /// Names of the instances that are currently open in this process.
+    pub fn open_instances() -> Vec<String> {
+        INSTANCES
+            .read()
+            .unwrap()
+            .values()
+            .map(|(instance, _)| instance.name.clone())
+            .sorted()
+            .collect()
+    }
+
+    /// Sequence of the latest commit. It increases with every commit of any process and watchers
+    /// are called with the sequence of the commit that triggered them.
+    pub fn commit_sequence(&self) -> Result<u64> {
+        Ok(self.env.txn(false)?.id())
+    }
+
+    /// Schema of the open collections after the migration. Removed properties are kept without a
+    /// name because they still occupy their space in the objects.
+    pub fn schema(&self) -> &Schema {
+        &self.state().schema
+    }
+
+    /// Hash of the schema this instance was opened with.
+    pub fn schema_hash(&self) -> u64 {
+        self.state().schema_hash
+    }
+
+    pub fn collections(&self) -> &[IsarCollection] {
+        &self.state().collections
+    }
+
+    fn state(&self) -> &InstanceState {
+        let states = self.states.read().unwrap();
+        let state: *const InstanceState = &**states.last().unwrap();
+        // states are boxed and only dropped together with the instance
+        unsafe { &*state }
+    }
+
+    /// Info that was stored in the database before this instance was opened.
+    pub fn db_info(&self) -> Option<&DbInfo> {
+        self.db_info.as_ref()
+    }
+
+    fn open_read_only_env(isar_file: &str, db_count: u64) -> Result<Env> {
+        Env::create(
+            isar_file,
+            db_count,
+            Durability::Safe,
+            true,
+            false,
+            Env::DEFAULT_MAX_SIZE,
+            Env::DEFAULT_GROWTH_STEP,
+            Env::DEFAULT_SHRINK_THRESHOLD,
+        )
+        .map_err(|e| IsarError::EnvError { error: Box::new(e) })
+    }
+
+    fn get_memory_dir() -> Result<String> {
+        let mut path_buf = std::env::temp_dir();
+        path_buf.push(format!("isar_memory_{}", std::process::id()));
+        fs::create_dir_all(&path_buf).map_err(|_| IsarError::PathError {})?;
+        Ok(path_buf.as_path().to_str().unwrap().to_string())
+    }
+
     fn get_isar_path(name: &str, dir: &str) -> String {
         let mut file_name = name.to_string();
         file_name.push_str(".isar");
@@ -107,54 +535,137 @@ impl IsarInstance {
         dir: &str,
         instance_id: u64,
         mut schema: Schema,
-        relaxed_durability: bool,
-        compact_condition: Option<CompactCondition>,
+        mut options: OpenOptions,
     ) -> Result<Self> {
+        let OpenOptions {
+            durability,
+            read_only,
+            in_memory,
+            change_feed,
+            multi_process,
+            ..
+        } = options;
         let isar_file = Self::get_isar_path(name, dir);
 
-        Self::move_old_database(name, dir, &isar_file);
+        if in_memory {
+            Env::delete(&isar_file);
+        } else if !read_only {
+            Self::move_old_database(name, dir, &isar_file);
+        }
 
-        let db_count = schema.count_dbs() as u64 + 3;
-        let env = Env::create(&isar_file, db_count, relaxed_durability)
-            .map_err(|e| IsarError::EnvError { error: Box::new(e) })?;
+        let db_count = schema.count_dbs() as u64 + 6;
+        let (max_size, growth_step, shrink_threshold) = if let Some(geometry) = &options.geometry {
+            (
+                geometry.max_size as isize,
+                geometry.growth_step as isize,
+                geometry.shrink_threshold as isize,
+            )
+        } else {
+            (
+                Env::DEFAULT_MAX_SIZE,
+                Env::DEFAULT_GROWTH_STEP,
+                Env::DEFAULT_SHRINK_THRESHOLD,
+            )
+        };
+        let env = Env::create(
+            &isar_file,
+            db_count,
+            durability,
+            read_only,
+            multi_process,
+            max_size,
+            growth_step,
+            shrink_threshold,
+        )
+        .map_err(|e| IsarError::EnvError { error: Box::new(e) })?;
 
-        let txn = env.txn(true)?;
+        let txn = env.txn(!read_only)?;
         let mut manager = SchemaManager::create(instance_id, &txn)?;
+        manager.set_schema_hash(schema.hash());
+        if !read_only {
+            manager.rename_collections(&txn, &schema)?;
+        }
+        let change_feed_db = if change_feed {
+            let db = Db::open(&txn, Some("_changes"), true, false, false);
+            if read_only {
+                db.ok()
+            } else {
+                Some(db?)
+            }
+        } else {
+            None
+        };
         txn.commit()?;
 
         let mut collections = vec![];
         for col_schema in &schema.collections {
-            let txn = env.txn(true)?;
-            let col = manager.open_collection(&txn, col_schema.clone(), &schema)?;
+            let txn = env.txn(!read_only)?;
+            let col = manager.open_collection(
+                &txn,
+                col_schema.clone(),
+                &schema,
+                options.index_fill.as_ref(),
+            )?;
             collections.push(col);
             txn.commit()?;
         }
 
-        if !manager.schemas.is_empty() {
+        if !read_only && !manager.schemas.is_empty() {
             let txn = env.txn(true)?;
             manager.delete_unopened_collections(&txn)?;
             txn.commit()?;
         }
 
+        let db_info = manager.db_info.take();
+        let opened_schema = Schema {
+            collections: std::mem::take(&mut manager.opened),
+        };
+        if !read_only {
+            let txn = env.txn(true)?;
+            manager.save_db_info(&txn, schema.hash())?;
+            txn.commit()?;
+        }
+
         let (tx, rx) = unbounded();
+        let last_seen_txn_id = env.txn(false)?.id();
 
         let instance = IsarInstance {
             env,
             name: name.to_string(),
             dir: dir.to_string(),
-            collections,
+            in_memory,
             instance_id,
-            schema_hash: schema.hash(),
+            states: RwLock::new(vec![Box::new(InstanceState {
+                collections,
+                schema: opened_schema,
+                requested_schema: schema.clone(),
+                schema_hash: schema.hash(),
+            })]),
             watchers: Mutex::new(IsarWatchers::new(rx)),
             watcher_modifier_sender: tx,
+            active_watchers: Arc::new(Mutex::new(IntMap::new())),
+            watcher_read_pool: Lazy::new(|| WatcherReadPool::new(WatcherReadPool::DEFAULT_WORKERS)),
+            journal_callback: Mutex::new(None),
+            max_read_txn_age: Mutex::new(None),
+            open_readers: Arc::new(Mutex::new(IntMap::new())),
+            own_commits: multi_process.then(|| Arc::new(Mutex::new(vec![]))),
+            txn_counters: Arc::new(TxnCounters::new(durability.syncs_on_commit())),
+            last_seen_txn_id: AtomicU64::new(last_seen_txn_id),
+            change_feed_db,
+            db_info,
         };
 
+        let compact_condition = options.compact_condition.take().filter(|_| !read_only);
         if let Some(compact_condition) = compact_condition {
             let instance = instance.compact(compact_condition)?;
             if let Some(instance) = instance {
                 Ok(instance)
             } else {
-                Self::open_internal(name, dir, instance_id, schema, relaxed_durability, None)
+                let options = OpenOptions {
+                    index_fill: None,
+                    ..options
+                };
+                Self::open_internal(name, dir, instance_id, schema, options)
             }
         } else {
             Ok(instance)
@@ -167,9 +678,7 @@ impl IsarInstance {
         txn.abort();
 
         let isar_file = Self::get_isar_path(&self.name, &self.dir);
-        let file_size = metadata(&isar_file)
-            .map_err(|_| IsarError::PathError {})?
-            .len();
+        let file_size = Env::file_size(&isar_file).ok_or(IsarError::PathError {})?;
 
         let compact_bytes = file_size.saturating_sub(instance_size);
         let compact_ratio = if instance_size == 0 {
@@ -183,7 +692,12 @@ impl IsarInstance {
 
         if should_compact {
             let compact_file = format!("{}.compact", &isar_file);
-            self.copy_to_file(&compact_file)?;
+            // remove leftovers of an interrupted compaction
+            let _ = remove_file(&compact_file);
+            if self.copy_to_file(&compact_file).is_err() {
+                let _ = remove_file(&compact_file);
+                return Ok(Some(self));
+            }
             drop(self);
 
             let _ = fs::rename(&compact_file, &isar_file);
@@ -195,21 +709,284 @@ impl IsarInstance {
 
     pub fn get_instance(name: &str) -> Option<Arc<Self>> {
         let instance_id = xxh3_64(name.as_bytes());
-        INSTANCES.read().unwrap().get(instance_id).cloned()
+        INSTANCES
+            .read()
+            .unwrap()
+            .get(instance_id)
+            .map(|(instance, _)| instance.clone())
     }
 
     pub fn begin_txn(&self, write: bool, silent: bool) -> Result<IsarTxn> {
-        let change_set = if write && !silent {
+        let watchers = if write && !silent {
             let mut watchers_lock = self.watchers.lock().unwrap();
             watchers_lock.sync();
-            let change_set = ChangeSet::new(watchers_lock);
-            Some(change_set)
+            Some(watchers_lock)
+        } else {
+            None
+        };
+        let journal = if write {
+            let callback = self.journal_callback.lock().unwrap().clone();
+            if callback.is_some() || self.change_feed_db.is_some() {
+                Some(ChangeJournal::new(callback, self.change_feed_db))
+            } else {
+                None
+            }
+        } else {
+            None
+        };
+        let change_set = if watchers.is_some() || journal.is_some() {
+            Some(ChangeSet::new(watchers, journal))
         } else {
             None
         };
 
         let txn = self.env.txn(write)?;
-        IsarTxn::new(self.instance_id, txn, write, change_set)
+        let (max_age, reader) = if write {
+            (None, None)
+        } else {
+            let key = READER_ID.fetch_add(1, Ordering::Relaxed);
+            let reader = ReaderGuard::new(&self.open_readers, key, txn.id());
+            (*self.max_read_txn_age.lock().unwrap(), Some(reader))
+        };
+        let own_commits = self.own_commits.clone().filter(|_| write);
+        IsarTxn::new(
+            self.instance_id,
+            txn,
+            write,
+            change_set,
+            max_age,
+            reader,
+            own_commits,
+            self.txn_counters.clone(),
+        )
+    }
+
+    /// Starts a read transaction that keeps a consistent snapshot for several queries while
+    /// writes continue. A snapshot pins old pages, so its age is logged once it exceeds
+    /// `warn_after`.
+    pub fn begin_snapshot(&self, warn_after: Duration) -> Result<IsarTxn<'_>> {
+        let txn = self.begin_txn(false, false)?;
+        Ok(txn.into_snapshot(warn_after))
+    }
+
+    /// Buffers that are reused by object builders, imports and migrations of this instance.
+    pub fn buffer_pool(&self) -> Arc<BufferPool> {
+        get_buffer_pool(self.instance_id)
+    }
+
+    /// Executes a filter query on `threads` threads that each scan a part of the id range with
+    /// their own read transaction. Returns the results of `map` in the order of the query.
+    pub fn find_parallel<T, F>(
+        &self,
+        collection: &IsarCollection,
+        query: &Query,
+        threads: usize,
+        map: F,
+    ) -> Result<Vec<(i64, T)>>
+    where
+        T: Send,
+        F: Fn(i64, IsarObject) -> T + Sync,
+    {
+        let mut txn = self.begin_txn(false, true)?;
+        let bounds = txn.read(collection.instance_id, |cursors| {
+            let mut cursor = cursors.get_cursor(collection.db)?;
+            let min = cursor.move_to_first()?.map(|(key, _)| key.to_id());
+            let max = cursor.move_to_last()?.map(|(key, _)| key.to_id());
+            Ok(min.zip(max))
+        });
+        txn.abort();
+        let (min_id, max_id) = if let Some(bounds) = bounds? {
+            bounds
+        } else {
+            return Ok(vec![]);
+        };
+
+        let parts = query.split_by_id(min_id, max_id, threads)?;
+        let map = &map;
+        let part_results = thread::scope(|scope| {
+            let workers = parts
+                .into_iter()
+                .map(|part| {
+                    scope.spawn(move || -> Result<Vec<(i64, T)>> {
+                        let txn = self.begin_txn(false, true)?;
+                        let mut results = vec![];
+                        part.find_while(&txn, |id, object| {
+                            results.push((id, map(id, object)));
+                            true
+                        })?;
+                        txn.abort();
+                        Ok(results)
+                    })
+                })
+                .collect_vec();
+            workers
+                .into_iter()
+                .map(|worker| worker.join().unwrap())
+                .collect::<Result<Vec<_>>>()
+        })?;
+
+        let results = part_results.into_iter().flatten().collect();
+        Ok(query.apply_offset_limit(results))
+    }
+
+    /// Checks whether another process committed changes since the last call and notifies all
+    /// watchers if it did. Only supported for instances opened with `multi_process`. This is cheap
+    /// enough to be called periodically.
+    pub fn poll_external_changes(&self) -> Result<bool> {
+        let own_commits = if let Some(own_commits) = &self.own_commits {
+            own_commits
+        } else {
+            return illegal_arg("The instance was not opened in multi process mode.");
+        };
+
+        let latest_txn_id = self.env.txn(false)?.id();
+        let previous_txn_id = self.last_seen_txn_id.swap(latest_txn_id, Ordering::SeqCst);
+        let external = {
+            let mut own_commits = own_commits.lock().unwrap();
+            let external =
+                (previous_txn_id + 1..=latest_txn_id).any(|id| !own_commits.contains(&id));
+            own_commits.retain(|id| *id > latest_txn_id);
+            external
+        };
+
+        if external {
+            let mut watchers = self.watchers.lock().unwrap();
+            watchers.sync();
+            watchers.notify_all(latest_txn_id);
+        }
+        Ok(external)
+    }
+
+    /// The queue keeps the instance alive until it is dropped. `capacity` limits the number of
+    /// pending jobs.
+    pub fn write_queue(self: &Arc<Self>, capacity: Option<usize>) -> WriteQueue {
+        WriteQueue::new(self.clone(), capacity)
+    }
+
+    /// Read transactions older than `max_age` fail with `TransactionExpired` and have to be
+    /// renewed. This prevents long running readers from pinning old pages.
+    pub fn set_max_read_txn_age(&self, max_age: Option<Duration>) {
+        *self.max_read_txn_age.lock().unwrap() = max_age;
+    }
+
+    /// Validates objects that are returned by `get` operations and fails with `DbCorrupted`
+    /// instead of returning invalid strings. Enabled by default in debug builds.
+    pub fn set_validate_objects(&self, validate: bool) {
+        for col in self.collections() {
+            col.set_validate_objects(validate);
+        }
+    }
+
+    /// Sorted and distinct queries fail with `QueryTooLarge` once their buffered results exceed
+    /// `max_bytes`. Queries can override the limit.
+    pub fn set_query_memory_limit(&self, max_bytes: Option<usize>) {
+        for col in self.collections() {
+            col.set_query_memory_limit(max_bytes.unwrap_or(usize::MAX));
+        }
+    }
+
+    /// Sorted queries that exceed the memory limit are sorted in a temporary database in `dir`
+    /// instead of failing. Defaults to the directory of the instance.
+    pub fn set_query_spill(&self, enabled: bool, dir: Option<&str>) {
+        let dir = if enabled {
+            Some(dir.unwrap_or(&self.dir))
+        } else {
+            None
+        };
+        for col in self.collections() {
+            col.set_query_spill_dir(dir);
+        }
+    }
+
+    /// Lists the open read transactions of all processes using the database.
+    pub fn reader_info(&self) -> Result<Vec<ReaderInfo>> {
+        let open_readers = self.open_readers.lock().unwrap();
+        let readers = self
+            .env
+            .readers()?
+            .into_iter()
+            .map(|slot| {
+                let age = open_readers
+                    .values()
+                    .filter(|(txn_id, _)| *txn_id == slot.txn_id)
+                    .map(|(_, started)| started.elapsed())
+                    .max();
+                ReaderInfo {
+                    pid: slot.pid,
+                    thread: slot.thread,
+                    txn_id: slot.txn_id,
+                    lag: slot.lag,
+                    bytes_retained: slot.bytes_retained,
+                    age_ms: age.map(|age| age.as_millis() as u64),
+                }
+            })
+            .collect();
+        Ok(readers)
+    }
+
+    /// Releases reader slots of crashed processes. Returns the number of released slots.
+    pub fn reset_stale_readers(&self) -> Result<u32> {
+        self.env.check_readers()
+    }
+
+    pub fn set_change_journal_callback(&self, callback: Option<ChangeJournalCallback>) {
+        *self.journal_callback.lock().unwrap() = callback.map(Arc::new);
+    }
+
+    fn get_change_feed_db(&self) -> Result<Db> {
+        if let Some(db) = self.change_feed_db {
+            Ok(db)
+        } else {
+            illegal_arg("The change feed is not enabled for this instance.")
+        }
+    }
+
+    pub fn read_change_feed(
+        &self,
+        txn: &mut IsarTxn,
+        from_sequence: i64,
+        limit: usize,
+    ) -> Result<Vec<(i64, ChangeJournalEntry)>> {
+        let db = self.get_change_feed_db()?;
+        txn.read(self.instance_id, |cursors| {
+            let mut entries = vec![];
+            if limit == 0 {
+                return Ok(entries);
+            }
+            let mut cursor = cursors.get_cursor(db)?;
+            cursor.iter_between(
+                &from_sequence,
+                &i64::MAX,
+                false,
+                false,
+                true,
+                |_, sequence, entry| {
+                    let entry = ChangeJournal::decode_entry(entry)?;
+                    entries.push((sequence.to_id(), entry));
+                    Ok(entries.len() < limit)
+                },
+            )?;
+            Ok(entries)
+        })
+    }
+
+    pub fn trim_change_feed(&self, txn: &mut IsarTxn, to_sequence: i64) -> Result<()> {
+        let db = self.get_change_feed_db()?;
+        txn.write(self.instance_id, |cursors, _| {
+            let mut cursor = cursors.get_cursor(db)?;
+            cursor.iter_between(
+                &i64::MIN,
+                &to_sequence,
+                false,
+                false,
+                true,
+                |cursor, _, _| {
+                    cursor.delete_current()?;
+                    Ok(true)
+                },
+            )?;
+            Ok(())
+        })
     }
 
     pub fn get_size(
@@ -220,37 +997,181 @@ impl IsarInstance {
     ) -> Result<u64> {
         let mut size = 0;
 
-        for col in &self.collections {
+        for col in self.collections() {
             size += col.get_size(txn, include_indexes, include_links)?;
         }
 
         Ok(size)
     }
 
+    /// Fills indexes that were added lazily. Every batch is written in its own transaction so
+    /// other writers are not blocked for long.
+    pub fn build_pending_indexes(&self, batch_size: usize) -> Result<()> {
+        let batch_size = batch_size.max(1);
+        for col in self.collections() {
+            let index_ids = col.get_pending_index_ids();
+            if index_ids.is_empty() {
+                continue;
+            }
+
+            let mut start = Some(i64::MIN);
+            while let Some(batch_start) = start {
+                let txn = self.env.txn(true)?;
+                start = {
+                    let cursors = IsarCursors::new(&txn, vec![]);
+                    col.fill_pending_indexes(&index_ids, &cursors, batch_start, batch_size)?
+                };
+                txn.commit()?;
+            }
+
+            let txn = self.env.txn(true)?;
+            let mut manager = SchemaManager::create(self.instance_id, &txn)?;
+            manager.complete_pending_indexes(&txn, &col.name)?;
+            txn.commit()?;
+
+            for index_id in index_ids {
+                col.get_index_by_id(index_id)?.set_complete();
+            }
+        }
+        Ok(())
+    }
+
+    /// Caches up to `max_bytes` of recently fetched objects of the collection for `get` and
+    /// `get_by_index`. A size of zero disables the cache.
+    pub fn set_object_cache_size(
+        &self,
+        collection: &IsarCollection,
+        max_bytes: usize,
+    ) -> Result<()> {
+        if self.own_commits.is_some() {
+            return illegal_arg("The object cache is not supported in multi process mode.");
+        }
+        let cache = if max_bytes > 0 {
+            // the next write transaction may already be running
+            let next_txn_id = self.env.txn(false)?.id() + 1;
+            Some(ObjectCache::new(max_bytes, next_txn_id))
+        } else {
+            None
+        };
+        collection.set_object_cache(cache);
+        Ok(())
+    }
+
+    /// Encodes the values of the String property before they are written and decodes them when
+    /// objects are read. The transform has to be registered before objects are written because
+    /// existing values are not transformed.
+    pub fn register_property_transform(
+        &self,
+        collection: &IsarCollection,
+        property_name: &str,
+        transform: Arc<dyn PropertyTransform>,
+    ) -> Result<()> {
+        collection.set_property_transform(property_name, transform)
+    }
+
+    /// Truncates the collection in its own transaction. If `shrink` is set, the file is shrunk to
+    /// the pages that are still in use afterwards.
+    pub fn truncate_collection(&self, collection: &IsarCollection, shrink: bool) -> Result<()> {
+        let mut txn = self.begin_txn(true, false)?;
+        collection.truncate(&mut txn)?;
+        txn.commit()?;
+        if shrink {
+            self.env.shrink()?;
+        }
+        Ok(())
+    }
+
+    /// Index and link dbs are opened when they are used first. Opens all of them at once instead.
+    pub fn warm_up(&self) -> Result<()> {
+        let mut txn = self.begin_txn(false, true)?;
+        for collection in self.collections() {
+            collection.warm_up(&mut txn)?;
+        }
+        txn.abort();
+        Ok(())
+    }
+
+    /// Syncs all commits to disk. Only needed if the durability is not `Durability::Safe`.
+    pub fn flush(&self) -> Result<()> {
+        self.env.sync()?;
+        self.txn_counters.syncs.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn perf_counters(&self) -> PerfCounters {
+        self.txn_counters.snapshot()
+    }
+
+    /// Enables the collection of `cursor_stats`. Cursors that are not returned before the end of
+    /// a transaction are reported as `LogEvent::CursorLeak`.
+    pub fn set_cursor_tracking(&self, enabled: bool) {
+        self.txn_counters.cursors.set_enabled(enabled);
+    }
+
+    pub fn cursor_stats(&self) -> Vec<CursorStats> {
+        self.txn_counters.cursors.stats()
+    }
+
     pub fn copy_to_file(&self, path: &str) -> Result<()> {
         self.env.copy(path)
     }
 
-    fn new_watcher(&self, start: WatcherModifier, stop: WatcherModifier) -> WatchHandle {
+    fn new_watcher(
+        &self,
+        info: WatcherInfo,
+        paused: Arc<AtomicBool>,
+        start: WatcherModifier,
+        stop: WatcherModifier,
+    ) -> WatchHandle {
         self.watcher_modifier_sender.try_send(start).unwrap();
+        let watcher_id = info.id;
+        self.active_watchers
+            .lock()
+            .unwrap()
+            .insert(watcher_id, (info, paused.clone()));
 
         let sender = self.watcher_modifier_sender.clone();
-        WatchHandle::new(Box::new(move || {
-            let _ = sender.try_send(stop);
-        }))
+        let active_watchers = self.active_watchers.clone();
+        WatchHandle::new(
+            paused,
+            Box::new(move || {
+                active_watchers.lock().unwrap().remove(watcher_id);
+                let _ = sender.try_send(stop);
+            }),
+        )
+    }
+
+    /// Lists all watchers whose handles have not been dropped yet.
+    pub fn active_watchers(&self) -> Vec<WatcherInfo> {
+        self.active_watchers
+            .lock()
+            .unwrap()
+            .values()
+            .map(|(info, paused)| WatcherInfo {
+                paused: paused.load(Ordering::SeqCst),
+                ..info.clone()
+            })
+            .sorted_by_key(|info| info.id)
+            .collect()
     }
 
     pub fn watch_collection(
         &self,
         collection: &IsarCollection,
         callback: WatcherCallback,
+        debounce: Option<Duration>,
     ) -> WatchHandle {
         let watcher_id = WATCHER_ID.fetch_add(1, Ordering::SeqCst);
         let col_id = collection.id;
+        let paused = Arc::new(AtomicBool::new(false));
+        let callback = pausable(callback, &paused);
+        let info = WatcherInfo::new(watcher_id, WatcherKind::Collection, collection, None, None);
         self.new_watcher(
+            info,
+            paused,
             Box::new(move |iw| {
                 iw.get_col_watchers(col_id)
-                    .add_watcher(watcher_id, callback);
+                    .add_watcher(watcher_id, callback, debounce);
             }),
             Box::new(move |iw| {
                 iw.get_col_watchers(col_id).remove_watcher(watcher_id);
@@ -258,18 +1179,55 @@ impl IsarInstance {
         )
     }
 
+    /// Notifies the callback only if the collection was truncated.
+    pub fn watch_collection_clear(
+        &self,
+        collection: &IsarCollection,
+        callback: WatcherCallback,
+        debounce: Option<Duration>,
+    ) -> WatchHandle {
+        let watcher_id = WATCHER_ID.fetch_add(1, Ordering::SeqCst);
+        let col_id = collection.id;
+        let paused = Arc::new(AtomicBool::new(false));
+        let callback = pausable(callback, &paused);
+        let info = WatcherInfo::new(
+            watcher_id,
+            WatcherKind::CollectionClear,
+            collection,
+            None,
+            None,
+        );
+        self.new_watcher(
+            info,
+            paused,
+            Box::new(move |iw| {
+                iw.get_col_watchers(col_id)
+                    .add_clear_watcher(watcher_id, callback, debounce);
+            }),
+            Box::new(move |iw| {
+                iw.get_col_watchers(col_id).remove_clear_watcher(watcher_id);
+            }),
+        )
+    }
+
     pub fn watch_object(
         &self,
         collection: &IsarCollection,
         oid: i64,
         callback: WatcherCallback,
+        debounce: Option<Duration>,
     ) -> WatchHandle {
         let watcher_id = WATCHER_ID.fetch_add(1, Ordering::SeqCst);
         let col_id = collection.id;
+        let paused = Arc::new(AtomicBool::new(false));
+        let callback = pausable(callback, &paused);
+        let info = WatcherInfo::new(watcher_id, WatcherKind::Object, collection, Some(oid), None);
         self.new_watcher(
+            info,
+            paused,
             Box::new(move |iw| {
                 iw.get_col_watchers(col_id)
-                    .add_object_watcher(watcher_id, oid, callback);
+                    .add_object_watcher(watcher_id, oid, callback, debounce);
             }),
             Box::new(move |iw| {
                 iw.get_col_watchers(col_id)
@@ -278,18 +1236,66 @@ impl IsarInstance {
         )
     }
 
+    pub fn watch_property(
+        &self,
+        collection: &IsarCollection,
+        oid: i64,
+        property: &Property,
+        callback: WatcherCallback,
+        debounce: Option<Duration>,
+    ) -> WatchHandle {
+        let watcher_id = WATCHER_ID.fetch_add(1, Ordering::SeqCst);
+        let col_id = collection.id;
+        let paused = Arc::new(AtomicBool::new(false));
+        let callback = pausable(callback, &paused);
+        let info = WatcherInfo::new(
+            watcher_id,
+            WatcherKind::Property,
+            collection,
+            Some(oid),
+            None,
+        );
+        let property = property.clone();
+        self.new_watcher(
+            info,
+            paused,
+            Box::new(move |iw| {
+                iw.get_col_watchers(col_id)
+                    .add_property_watcher(watcher_id, oid, property, callback, debounce);
+            }),
+            Box::new(move |iw| {
+                iw.get_col_watchers(col_id)
+                    .remove_property_watcher(oid, watcher_id);
+            }),
+        )
+    }
+
+    /// Calls `callback` after every commit that changes an object which matches the where clauses
+    /// and filter of `query` before or after the change.
     pub fn watch_query(
         &self,
         collection: &IsarCollection,
         query: Query,
         callback: WatcherCallback,
+        debounce: Option<Duration>,
     ) -> WatchHandle {
         let watcher_id = WATCHER_ID.fetch_add(1, Ordering::SeqCst);
         let col_id = collection.id;
+        let paused = Arc::new(AtomicBool::new(false));
+        let callback = pausable(callback, &paused);
+        let info = WatcherInfo::new(
+            watcher_id,
+            WatcherKind::Query,
+            collection,
+            None,
+            Some(query.summary()),
+        );
         self.new_watcher(
+            info,
+            paused,
             Box::new(move |iw| {
                 iw.get_col_watchers(col_id)
-                    .add_query_watcher(watcher_id, query, callback);
+                    .add_query_watcher(watcher_id, query, callback, debounce);
             }),
             Box::new(move |iw| {
                 iw.get_col_watchers(col_id).remove_query_watcher(watcher_id);
@@ -297,25 +1303,112 @@ impl IsarInstance {
         )
     }
 
-    fn close_internal(self: Arc<Self>, delete_from_disk: bool) -> bool {
-        // Check whether all other references are gone
-        if Arc::strong_count(&self) == 2 {
-            let mut lock = INSTANCES.write().unwrap();
-            // Check again to make sure there are no new references
-            if Arc::strong_count(&self) == 2 {
-                lock.remove(self.instance_id);
-
-                if delete_from_disk {
-                    let mut path = Self::get_isar_path(&self.name, &self.dir);
-                    drop(self);
-                    let _ = remove_file(&path);
-                    path.push_str(".lock");
-                    let _ = remove_file(&path);
+    /// Watches a query without evaluating changes immediately. Use
+    /// `LazyQueryWatcher::poll` to check whether the results might have changed.
+    pub fn watch_query_lazy(
+        &self,
+        collection: &IsarCollection,
+        query: Query,
+    ) -> (WatchHandle, Arc<LazyQueryWatcher>) {
+        let watcher_id = WATCHER_ID.fetch_add(1, Ordering::SeqCst);
+        let col_id = collection.id;
+        let paused = Arc::new(AtomicBool::new(false));
+        let info = WatcherInfo::new(
+            watcher_id,
+            WatcherKind::LazyQuery,
+            collection,
+            None,
+            Some(query.summary()),
+        );
+        let watcher = Arc::new(LazyQueryWatcher::new(watcher_id, query, paused.clone()));
+        let watcher_clone = watcher.clone();
+        let handle = self.new_watcher(
+            info,
+            paused,
+            Box::new(move |iw| {
+                iw.get_col_watchers(col_id)
+                    .add_lazy_query_watcher(watcher_clone);
+            }),
+            Box::new(move |iw| {
+                iw.get_col_watchers(col_id)
+                    .remove_lazy_query_watcher(watcher_id);
+            }),
+        );
+        (handle, watcher)
+    }
+
+    pub fn watch_query_diff(
+        &self,
+        collection: &IsarCollection,
+        query: Query,
+        callback: QueryDiffCallback,
+        debounce: Option<Duration>,
+    ) -> Result<WatchHandle> {
+        let mut txn = self.begin_txn(false, true)?;
+        let state = QueryDiffState::new(query.clone(), &mut txn);
+        txn.abort();
+        let state = Arc::new(Mutex::new(state?));
+        let callback = Arc::new(callback);
+        let queued = Arc::new(AtomicBool::new(false));
+
+        let name = self.name.clone();
+        let handle = self.watch_query(
+            collection,
+            query,
+            Box::new(move |_| {
+                // the query is only re-evaluated once a change might affect its results
+                let isar = if let Some(isar) = Self::get_instance(&name) {
+                    isar
+                } else {
+                    return;
+                };
+                // a queued evaluation will also see this change
+                if queued.swap(true, Ordering::SeqCst) {
+                    return;
                 }
-                return true;
+                let state = state.clone();
+                let callback = callback.clone();
+                let queued = queued.clone();
+                let name = name.clone();
+                isar.watcher_read_pool.submit(Box::new(move || {
+                    queued.store(false, Ordering::SeqCst);
+                    if let Some(isar) = Self::get_instance(&name) {
+                        if let Ok(mut txn) = isar.begin_txn(false, true) {
+                            let diff = state.lock().unwrap().update(&mut txn);
+                            txn.abort();
+                            if let Ok(diff) = diff {
+                                if !diff.is_empty() {
+                                    callback(diff);
+                                }
+                            }
+                        }
+                    }
+                }));
+            }),
+            debounce,
+        );
+        Ok(handle)
+    }
+
+    fn close_internal(self: Arc<Self>, delete_from_disk: bool) -> bool {
+        let mut lock = INSTANCES.write().unwrap();
+        if let Some((_, open_count)) = lock.get_mut(self.instance_id) {
+            *open_count = open_count.saturating_sub(1);
+            // write queues, watchers and get_instance() may still use the instance
+            if *open_count > 0 || Arc::strong_count(&self) > 2 {
+                return false;
             }
+            lock.remove(self.instance_id);
+        }
+        drop(lock);
+        remove_buffer_pool(self.instance_id);
+
+        if delete_from_disk || self.in_memory {
+            let path = Self::get_isar_path(&self.name, &self.dir);
+            drop(self);
+            Env::delete(&path);
         }
-        false
+        true
     }
 
     pub fn close(self: Arc<Self>) -> bool {
@@ -326,10 +1419,88 @@ impl IsarInstance {
         self.close_internal(true)
     }
 
+    /// Applies all operations in a single write. Returns the ids of the put operations.
+    pub fn write_batch(&self, txn: &mut IsarTxn, ops: &[BatchOp]) -> Result<Vec<i64>> {
+        txn.write(self.instance_id, |cursors, mut change_set| {
+            let mut ids = vec![];
+            for op in ops {
+                match op {
+                    BatchOp::Put {
+                        collection,
+                        id,
+                        object,
+                    } => {
+                        let id = collection.put_internal(
+                            cursors,
+                            change_set.as_deref_mut(),
+                            *id,
+                            *object,
+                        )?;
+                        ids.push(id);
+                    }
+                    BatchOp::Delete { collection, id } => {
+                        collection.delete_internal(
+                            cursors,
+                            true,
+                            change_set.as_deref_mut(),
+                            *id,
+                        )?;
+                    }
+                    BatchOp::Link {
+                        collection,
+                        link_id,
+                        id,
+                        target_id,
+                    }
+                    | BatchOp::Unlink {
+                        collection,
+                        link_id,
+                        id,
+                        target_id,
+                    } => {
+                        let link = collection.get_link_backlink(*link_id)?;
+                        let changed = if matches!(op, BatchOp::Link { .. }) {
+                            link.create(cursors, *id, *target_id)?
+                        } else {
+                            link.delete(cursors, *id, *target_id)?
+                        };
+                        if let Some(change_set) = change_set.as_deref_mut().filter(|_| changed) {
+                            change_set.record(collection.id, Some(*id), ChangeOp::Updated);
+                        }
+                    }
+                }
+            }
+            Ok(ids)
+        })
+    }
+
+    /// Validates all objects, indexes and links and returns the problems that were found.
+    pub fn check_integrity(&self, txn: &mut IsarTxn) -> Result<Vec<IntegrityProblem>> {
+        txn.read(self.instance_id, |cursors| {
+            let mut problems = vec![];
+            for col in self.collections() {
+                col.check_integrity(cursors, &mut problems)?;
+            }
+            Ok(problems)
+        })
+    }
+
+    /// Removes dangling index and link entries and rebuilds broken indexes. Returns the problems
+    /// that were found before repairing.
+    pub fn repair(&self, txn: &mut IsarTxn) -> Result<Vec<IntegrityProblem>> {
+        txn.write(self.instance_id, |cursors, _| {
+            let mut problems = vec![];
+            for col in self.collections() {
+                col.repair(cursors, &mut problems)?;
+            }
+            Ok(problems)
+        })
+    }
+
     pub fn verify(&self, txn: &mut IsarTxn) -> Result<()> {
         let mut db_names = vec![];
         db_names.push("_info".to_string());
-        for col in &self.collections {
+        for col in self.collections() {
             db_names.push(col.name.clone());
             for index in &col.indexes {
                 db_names.push(format!("_i_{}_{}", col.name, index.name));
@@ -338,9 +1509,23 @@ impl IsarInstance {
             for link in &col.links {
                 db_names.push(format!("_l_{}_{}", col.name, link.name));
                 db_names.push(format!("_b_{}_{}", col.name, link.name));
+                if link.is_ordered() {
+                    db_names.push(format!("_o_{}_{}", col.name, link.name));
+                }
+            }
+
+            for constraint in &col.unique_constraints {
+                db_names.push(format!("_u_{}_{}", col.name, constraint.name));
             }
         }
         let mut actual_db_names = txn.db_names()?;
+        // shared dbs only exist after a write open and the change feed db is kept if the feed
+        // is disabled again
+        for db_name in ["_changes", "_versions", "_tombstones", "_blobs"] {
+            if actual_db_names.iter().any(|name| name == db_name) {
+                db_names.push(db_name.to_string());
+            }
+        }
 
         db_names.sort();
         actual_db_names.sort();
@@ -354,3 +1539,1014 @@ impl IsarInstance {
         }
     }
 }
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::object::json_encode_decode::JsonEncodeDecode;
+    use crate::object::object_builder::ObjectBuilder;
+    use crate::query::filter::Filter;
+    use crate::query::Sort;
+    use serde_json::{json, Value};
+    use std::env;
+
+    /// Opens a new instance with the collections in a new temporary directory.
+    pub fn open_instance(collections: Value) -> Arc<IsarInstance> {
+        let name = format!("test_{}", rand::random::<u64>());
+        let mut dir = std::env::temp_dir();
+        dir.push(&name);
+        fs::create_dir_all(&dir).unwrap();
+        let schema = Schema::from_json(&serde_json::to_vec(&collections).unwrap()).unwrap();
+        IsarInstance::open(&name, dir.to_str(), schema, OpenOptions::default()).unwrap()
+    }
+
+    /// Closes the instance and opens it again with the collections.
+    pub fn reopen(instance: Arc<IsarInstance>, collections: Value) -> Arc<IsarInstance> {
+        reopen_with(instance, collections, OpenOptions::default()).unwrap()
+    }
+
+    /// Closes the instance and opens it again with the collections and options.
+    pub fn reopen_with(
+        instance: Arc<IsarInstance>,
+        collections: Value,
+        options: OpenOptions,
+    ) -> Result<Arc<IsarInstance>> {
+        let (name, dir) = (instance.name.clone(), instance.dir.clone());
+        assert!(instance.close());
+        let schema = Schema::from_json(&serde_json::to_vec(&collections).unwrap()).unwrap();
+        IsarInstance::open(&name, Some(&dir), schema, options)
+    }
+
+    /// A collection without indexes or links. They can be added to the returned value.
+    pub fn collection(name: &str, properties: Value) -> Value {
+        json!({"name": name, "embedded": false, "properties": properties})
+    }
+
+    /// An index of the values of the properties. Each property is paired with whether it is
+    /// case sensitive, which only String properties can be.
+    pub fn index(name: &str, properties: &[(&str, bool)], unique: bool) -> Value {
+        let properties = properties
+            .iter()
+            .map(|(name, case_sensitive)| {
+                json!({"name": name, "type": "Value", "caseSensitive": case_sensitive})
+            })
+            .collect_vec();
+        json!({"name": name, "unique": unique, "replace": false, "properties": properties})
+    }
+
+    /// The collection `col` with a single Long property `value` and an optional index of it.
+    pub fn collections() -> Value {
+        collections_with_index(None)
+    }
+
+    /// Like [collections] but the `value` index is added if `lazy` is set.
+    pub fn collections_with_index(lazy: Option<bool>) -> Value {
+        let mut col = collection("col", json!([{"name": "value", "type": "Long"}]));
+        if let Some(lazy) = lazy {
+            let mut index = index("value", &[("value", false)], false);
+            index["lazy"] = json!(lazy);
+            col["indexes"] = json!([index]);
+        }
+        json!([col])
+    }
+
+    /// Puts an object of the first collection for each value of its first property.
+    pub fn put_values(instance: &IsarInstance, values: &[i64]) -> Vec<i64> {
+        let col = &instance.collections()[0];
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        let ids = values
+            .iter()
+            .map(|value| {
+                let mut ob = ObjectBuilder::new(&col.properties, None);
+                ob.write_long(col.properties[0].offset, *value);
+                col.put(&mut txn, None, ob.finish()).unwrap()
+            })
+            .collect();
+        txn.commit().unwrap();
+        ids
+    }
+
+    /// Puts an object that is decoded from the JSON values in its own transaction.
+    pub fn put_object(
+        instance: &IsarInstance,
+        col: &IsarCollection,
+        id: Option<i64>,
+        values: Value,
+    ) -> i64 {
+        let mut ob = ObjectBuilder::new(&col.properties, None);
+        JsonEncodeDecode::decode(&col.properties, &col.embedded_properties, &mut ob, &values)
+            .unwrap();
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        let id = col.put(&mut txn, id, ob.finish()).unwrap();
+        txn.commit().unwrap();
+        id
+    }
+
+    /// Ids of the results of the query in a new read transaction.
+    pub fn find_ids(instance: &IsarInstance, query: &Query) -> Vec<i64> {
+        let txn = instance.begin_txn(false, false).unwrap();
+        let results = query.find_all_vec(&txn).unwrap();
+        results.iter().map(|(id, _)| *id).collect()
+    }
+
+    #[test]
+    fn test_close_counts_opens() {
+        let instance = open_instance(collections());
+        let schema = Schema::from_json(&serde_json::to_vec(&collections()).unwrap()).unwrap();
+        let second = IsarInstance::open(&instance.name, None, schema, OpenOptions::default());
+        let second = second.unwrap();
+        let name = instance.name.clone();
+
+        assert!(!second.close());
+        assert!(IsarInstance::get_instance(&name).is_some());
+        assert!(instance.close());
+        assert!(IsarInstance::get_instance(&name).is_none());
+    }
+
+    #[test]
+    fn test_close_keeps_referenced_instance() {
+        let instance = open_instance(collections());
+        let name = instance.name.clone();
+        let isar_file = IsarInstance::get_isar_path(&name, &instance.dir);
+
+        let queue = instance.write_queue(None);
+        assert!(!instance.close_and_delete());
+        assert!(IsarInstance::get_instance(&name).is_some());
+        assert!(Env::exists(&isar_file));
+
+        drop(queue);
+        let instance = IsarInstance::get_instance(&name).unwrap();
+        assert!(instance.close_and_delete());
+        assert!(!Env::exists(&isar_file));
+    }
+
+    #[test]
+    fn test_rename_open_collection() {
+        let linked = |target_name: &str| {
+            let mut source = collection("source", json!([]));
+            source["links"] = json!([{"name": "link", "target": target_name}]);
+            let mut target = collections_with_index(Some(false))[0].clone();
+            target["name"] = json!(target_name);
+            json!([source, target])
+        };
+        let instance = open_instance(linked("col"));
+        let find = |name: &str| {
+            instance
+                .collections()
+                .iter()
+                .find(|c| c.name == name)
+                .unwrap()
+        };
+        let source = find("source");
+        put_object(&instance, source, Some(1), json!({}));
+        let target = find("col");
+        for value in [3, 1, 2] {
+            put_object(&instance, target, None, json!({ "value": value }));
+        }
+        let link_id = source.link_id("link").unwrap();
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        source.link(&mut txn, link_id, 1, 2).unwrap();
+        txn.commit().unwrap();
+
+        instance.rename_collection("col", "renamed").unwrap();
+        let source = find("source");
+        let target = find("renamed");
+        let mut qb = target.new_query_builder();
+        qb.add_index_prefix_where_clause(
+            xxh3_64(b"value"),
+            &[],
+            None,
+            None,
+            Sort::Ascending,
+            false,
+        )
+        .unwrap();
+        assert_eq!(find_ids(&instance, &qb.build()), vec![2, 3, 1]);
+        assert_eq!(put_object(&instance, target, None, json!({"value": 0})), 4);
+        let mut txn = instance.begin_txn(false, false).unwrap();
+        assert_eq!(source.get_link_ids(&mut txn, link_id, 1).unwrap(), vec![2]);
+        drop(txn);
+
+        let schema = Schema::from_json(&serde_json::to_vec(&linked("renamed")).unwrap()).unwrap();
+        let second = IsarInstance::open(&instance.name, None, schema, OpenOptions::default());
+        assert!(!second.unwrap().close());
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_compact_on_open() {
+        let instance = open_instance(collections());
+        put_values(&instance, &(0..400_000).collect_vec());
+        let col = &instance.collections()[0];
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        col.clear(&mut txn).unwrap();
+        txn.commit().unwrap();
+        put_values(&instance, &[1, 2]);
+
+        let isar_file = IsarInstance::get_isar_path(&instance.name, &instance.dir);
+        let file_size = Env::file_size(&isar_file).unwrap();
+        // the copy of an interrupted compaction must not be used
+        fs::write(format!("{}.compact", isar_file), b"stale").unwrap();
+        let options = OpenOptions {
+            compact_condition: Some(CompactCondition {
+                min_file_size: 0,
+                min_bytes: 0,
+                min_ratio: 1.0,
+            }),
+            ..Default::default()
+        };
+        let instance = reopen_with(instance, collections(), options).unwrap();
+        assert!(Env::file_size(&isar_file).unwrap() < file_size);
+        assert!(!Env::exists(&format!("{}.compact", isar_file)));
+
+        let col = &instance.collections()[0];
+        let mut txn = instance.begin_txn(false, false).unwrap();
+        assert_eq!(col.count(&mut txn).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_geometry_limits_size() {
+        let instance = open_instance(collections());
+        let options = OpenOptions {
+            geometry: Some(EnvGeometry {
+                max_size: 2 << 20,
+                growth_step: 1 << 20,
+                shrink_threshold: 2 << 20,
+            }),
+            ..Default::default()
+        };
+        let instance = reopen_with(instance, collections(), options).unwrap();
+        let col = &instance.collections()[0];
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        let result = (0..100_000).try_for_each(|value| {
+            let mut ob = ObjectBuilder::new(&col.properties, None);
+            ob.write_long(col.properties[0].offset, value);
+            col.put(&mut txn, None, ob.finish()).map(|_| ())
+        });
+        assert!(matches!(result, Err(IsarError::DbFull {})));
+    }
+
+    #[test]
+    fn test_in_memory_instance_is_deleted_on_close() {
+        let open = || {
+            let schema = Schema::from_json(&serde_json::to_vec(&collections()).unwrap()).unwrap();
+            let options = OpenOptions {
+                in_memory: true,
+                ..Default::default()
+            };
+            IsarInstance::open("test_in_memory", None, schema, options).unwrap()
+        };
+        let instance = open();
+        put_values(&instance, &[1, 2]);
+        let isar_file = IsarInstance::get_isar_path(&instance.name, &instance.dir);
+        assert!(Env::exists(&isar_file));
+        assert!(instance.close());
+        assert!(!Env::exists(&isar_file));
+
+        let instance = open();
+        let col = &instance.collections()[0];
+        let mut txn = instance.begin_txn(false, false).unwrap();
+        assert_eq!(col.count(&mut txn).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_change_feed_is_persisted() {
+        let feed_options = || OpenOptions {
+            change_feed: true,
+            ..Default::default()
+        };
+        let instance = open_instance(collections());
+        let instance = reopen_with(instance, collections(), feed_options()).unwrap();
+        let ids = put_values(&instance, &[1, 2]);
+        let col = &instance.collections()[0];
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        col.delete(&mut txn, ids[0]).unwrap();
+        txn.commit().unwrap();
+
+        let instance = reopen_with(instance, collections(), feed_options()).unwrap();
+        let read = |from_sequence, limit| {
+            let mut txn = instance.begin_txn(false, false).unwrap();
+            let entries = instance
+                .read_change_feed(&mut txn, from_sequence, limit)
+                .unwrap();
+            entries
+                .iter()
+                .map(|(seq, e)| (*seq, e.id, e.op))
+                .collect_vec()
+        };
+        assert_eq!(
+            read(0, 10),
+            vec![
+                (1, Some(ids[0]), ChangeOp::Created),
+                (2, Some(ids[1]), ChangeOp::Created),
+                (3, Some(ids[0]), ChangeOp::Deleted),
+            ]
+        );
+        assert_eq!(read(2, 1), vec![(2, Some(ids[1]), ChangeOp::Created)]);
+
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        instance.trim_change_feed(&mut txn, 2).unwrap();
+        txn.commit().unwrap();
+        assert_eq!(read(0, 10), vec![(3, Some(ids[0]), ChangeOp::Deleted)]);
+    }
+
+    #[test]
+    fn test_db_info_of_last_open() {
+        let instance = open_instance(collections());
+        assert!(instance.db_info().is_none());
+        let (name, dir) = (instance.name.clone(), instance.dir.clone());
+        let schema_hash = instance.schema_hash();
+
+        let instance = reopen(instance, collections_with_index(Some(false)));
+        let db_info = instance.db_info().unwrap();
+        assert_eq!(db_info.isar_version, SchemaManager::ISAR_VERSION);
+        assert_eq!(db_info.core_version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(db_info.schema_hash, schema_hash);
+        assert!(db_info.last_opened > 0);
+
+        let schema_hash = instance.schema_hash();
+        assert!(instance.close());
+        let db_info = IsarInstance::read_db_info(&name, &dir).unwrap().unwrap();
+        assert_eq!(db_info.schema_hash, schema_hash);
+        assert!(!db_info.is_newer(SchemaManager::ISAR_VERSION));
+    }
+
+    #[test]
+    fn test_index_fill_progress_and_cancel() {
+        let instance = open_instance(collections());
+        put_values(&instance, &(0..2500).collect_vec());
+        let (name, dir) = (instance.name.clone(), instance.dir.clone());
+        let options = OpenOptions {
+            index_fill: Some(IndexFillOptions {
+                progress: None,
+                cancel: Some(Arc::new(AtomicBool::new(true))),
+            }),
+            ..Default::default()
+        };
+        let result = reopen_with(instance, collections_with_index(Some(false)), options);
+        assert!(matches!(result, Err(IsarError::Cancelled {})));
+
+        let progress = Arc::new(Mutex::new(vec![]));
+        let recorded = progress.clone();
+        let options = OpenOptions {
+            index_fill: Some(IndexFillOptions {
+                progress: Some(Box::new(move |col, count, total| {
+                    recorded.lock().unwrap().push((col == "col", count, total));
+                })),
+                cancel: Some(Arc::new(AtomicBool::new(false))),
+            }),
+            ..Default::default()
+        };
+        let json = serde_json::to_vec(&collections_with_index(Some(false))).unwrap();
+        let schema = Schema::from_json(&json).unwrap();
+        let instance = IsarInstance::open(&name, Some(&dir), schema, options).unwrap();
+        assert_eq!(
+            *progress.lock().unwrap(),
+            vec![(true, 1000, 2500), (true, 2000, 2500), (true, 2500, 2500)]
+        );
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_read_txn_max_age_and_reader_info() {
+        let instance = open_instance(collections());
+        let col = &instance.collections()[0];
+        instance.set_max_read_txn_age(Some(Duration::from_millis(20)));
+        let mut txn = instance.begin_txn(false, false).unwrap();
+        assert_eq!(col.count(&mut txn).unwrap(), 0);
+        let readers = instance.reader_info().unwrap();
+        assert_eq!(readers.len(), 1);
+        assert_eq!(readers[0].pid, std::process::id() as i32);
+        assert!(readers[0].age_ms.is_some());
+
+        thread::sleep(Duration::from_millis(40));
+        assert!(matches!(
+            col.count(&mut txn),
+            Err(IsarError::TransactionExpired {})
+        ));
+        drop(txn);
+        assert!(instance.reader_info().unwrap().is_empty());
+        assert_eq!(instance.reset_stale_readers().unwrap(), 0);
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_snapshot_keeps_consistent_reads() {
+        let instance = open_instance(collections());
+        let col = &instance.collections()[0];
+        put_values(&instance, &[1, 2]);
+        instance.set_max_read_txn_age(Some(Duration::from_millis(20)));
+        let mut snapshot = instance.begin_snapshot(Duration::from_secs(60)).unwrap();
+        assert!(snapshot.is_snapshot());
+        assert!(!instance.begin_txn(false, false).unwrap().is_snapshot());
+
+        // writes continue while the snapshot is open
+        put_values(&instance, &[3]);
+        thread::sleep(Duration::from_millis(40));
+        assert!(snapshot.age() >= Duration::from_millis(40));
+        assert_eq!(col.count(&mut snapshot).unwrap(), 2);
+        let query = col.new_query_builder().build();
+        assert_eq!(query.count(&snapshot).unwrap(), 2);
+        drop(snapshot);
+
+        let mut txn = instance.begin_txn(false, false).unwrap();
+        assert_eq!(col.count(&mut txn).unwrap(), 3);
+        drop(txn);
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_write_queue_runs_jobs_in_order() {
+        let instance = open_instance(collections());
+        let put_job = |value: i64| -> WriteJob {
+            let instance = instance.clone();
+            Box::new(move |txn| {
+                let col = &instance.collections()[0];
+                let mut ob = ObjectBuilder::new(&col.properties, None);
+                ob.write_long(col.properties[0].offset, value);
+                col.put(txn, None, ob.finish())?;
+                if value < 0 {
+                    illegal_arg("Negative value.")
+                } else {
+                    Ok(())
+                }
+            })
+        };
+        let (done_tx, done_rx) = unbounded();
+        let callback = |value: i64| -> WriteCallback {
+            let done_tx = done_tx.clone();
+            Box::new(move |result| done_tx.send((value, result.is_ok())).unwrap())
+        };
+
+        let queue = instance.write_queue(Some(1));
+        let (started_tx, started_rx) = bounded(0);
+        let (release_tx, release_rx) = bounded(0);
+        let first = put_job(1);
+        let blocking_job: WriteJob = Box::new(move |txn| {
+            started_tx.send(()).unwrap();
+            release_rx.recv().unwrap();
+            first(txn)
+        });
+        queue.submit(blocking_job, callback(1)).unwrap();
+        started_rx.recv().unwrap();
+        queue.submit(put_job(-1), callback(-1)).unwrap();
+
+        // the writer is busy and the queue is full
+        assert!(queue.try_submit(put_job(2), callback(2)).is_err());
+        assert_eq!(queue.stats().pending, 2);
+
+        release_tx.send(()).unwrap();
+        let results = [done_rx.recv().unwrap(), done_rx.recv().unwrap()];
+        assert_eq!(results, [(1, true), (-1, false)]);
+        let stats = queue.stats();
+        assert_eq!((stats.pending, stats.completed, stats.failed), (0, 1, 1));
+        drop(queue);
+
+        let col = &instance.collections()[0];
+        let mut txn = instance.begin_txn(false, false).unwrap();
+        assert_eq!(col.count(&mut txn).unwrap(), 1);
+        txn.abort();
+        assert!(instance.close_and_delete());
+    }
+
+    /// Writes to the instance of `test_poll_external_changes` from a child process.
+    #[test]
+    #[ignore]
+    fn put_from_other_process() {
+        let (name, dir) = match (env::var("ISAR_TEST_NAME"), env::var("ISAR_TEST_DIR")) {
+            (Ok(name), Ok(dir)) => (name, dir),
+            _ => return,
+        };
+        let schema = Schema::from_json(&serde_json::to_vec(&collections()).unwrap()).unwrap();
+        let options = OpenOptions {
+            multi_process: true,
+            ..OpenOptions::default()
+        };
+        let instance = IsarInstance::open(&name, Some(&dir), schema, options).unwrap();
+        put_values(&instance, &[3]);
+        assert!(instance.close());
+    }
+
+    #[cfg(feature = "mdbx")]
+    #[test]
+    fn test_poll_external_changes() {
+        let instance = open_instance(collections());
+        assert!(instance.poll_external_changes().is_err());
+        let options = OpenOptions {
+            multi_process: true,
+            ..OpenOptions::default()
+        };
+        let instance = reopen_with(instance, collections(), options).unwrap();
+        let notified = Arc::new(AtomicU64::new(0));
+        let counter = notified.clone();
+        let col = &instance.collections()[0];
+        let _handle = instance.watch_collection(
+            col,
+            Box::new(move |_| {
+                counter.fetch_add(1, Ordering::SeqCst);
+            }),
+            None,
+        );
+
+        // own commits are reported by the regular watchers
+        put_values(&instance, &[1, 2]);
+        let own_notifications = notified.load(Ordering::SeqCst);
+        assert!(!instance.poll_external_changes().unwrap());
+        assert_eq!(notified.load(Ordering::SeqCst), own_notifications);
+
+        let helper = "instance::tests::put_from_other_process";
+        let status = std::process::Command::new(env::current_exe().unwrap())
+            .args(["--ignored", "--exact", helper])
+            .env("ISAR_TEST_NAME", &instance.name)
+            .env("ISAR_TEST_DIR", &instance.dir)
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        assert!(instance.poll_external_changes().unwrap());
+        assert_eq!(notified.load(Ordering::SeqCst), own_notifications + 1);
+        assert!(!instance.poll_external_changes().unwrap());
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_write_batch_is_atomic() {
+        let mut a = collection("a", json!([]));
+        a["links"] = json!([{"name": "link", "target": "b"}]);
+        let instance = open_instance(json!([a, collection("b", json!([]))]));
+        let (a, b) = (&instance.collections()[0], &instance.collections()[1]);
+        let link_id = a.link_id("link").unwrap();
+        let existing = put_object(&instance, b, None, json!({}));
+        let notified = Arc::new(AtomicU64::new(0));
+        let counter = notified.clone();
+        let _handle = instance.watch_collection(
+            a,
+            Box::new(move |_| {
+                counter.fetch_add(1, Ordering::SeqCst);
+            }),
+            None,
+        );
+
+        let ob = ObjectBuilder::new(&a.properties, None);
+        let object = ob.finish();
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        let ops = [
+            BatchOp::Put {
+                collection: a,
+                id: Some(1),
+                object,
+            },
+            BatchOp::Put {
+                collection: a,
+                id: Some(2),
+                object,
+            },
+            BatchOp::Put {
+                collection: b,
+                id: Some(10),
+                object,
+            },
+            BatchOp::Link {
+                collection: a,
+                link_id,
+                id: 1,
+                target_id: 10,
+            },
+            BatchOp::Delete {
+                collection: b,
+                id: existing,
+            },
+        ];
+        let ids = instance.write_batch(&mut txn, &ops).unwrap();
+        assert_eq!(ids, [1, 2, 10]);
+        txn.commit().unwrap();
+        assert_eq!(notified.load(Ordering::SeqCst), 1);
+
+        let mut txn = instance.begin_txn(false, false).unwrap();
+        assert_eq!(a.count(&mut txn).unwrap(), 2);
+        assert_eq!(b.count(&mut txn).unwrap(), 1);
+        assert_eq!(a.get_link_ids(&mut txn, link_id, 1).unwrap(), vec![10]);
+        txn.abort();
+
+        // a failing operation discards the whole batch
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        let ops = [
+            BatchOp::Put {
+                collection: a,
+                id: Some(3),
+                object,
+            },
+            BatchOp::Unlink {
+                collection: a,
+                link_id: link_id + 1,
+                id: 1,
+                target_id: 10,
+            },
+        ];
+        assert!(instance.write_batch(&mut txn, &ops).is_err());
+        txn.abort();
+        let mut txn = instance.begin_txn(false, false).unwrap();
+        assert_eq!(a.count(&mut txn).unwrap(), 2);
+        txn.abort();
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_lazy_query_watcher_evaluates_on_poll() {
+        let instance = open_instance(collections());
+        let col = &instance.collections()[0];
+        put_values(&instance, &[1]);
+        let mut qb = col.new_query_builder();
+        let property = col.property("value").unwrap();
+        qb.set_filter(Filter::long(property, 10, i64::MAX).unwrap());
+        let (_handle, watcher) = instance.watch_query_lazy(col, qb.build());
+        assert!(!watcher.is_dirty());
+
+        put_values(&instance, &[5]);
+        assert!(watcher.is_dirty());
+        assert!(!watcher.poll());
+        assert!(!watcher.is_dirty());
+
+        put_values(&instance, &[6, 20]);
+        assert!(watcher.poll());
+        assert!(!watcher.poll());
+
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        col.clear(&mut txn).unwrap();
+        txn.commit().unwrap();
+        assert!(watcher.poll());
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_watch_query_matches_old_and_new_objects() {
+        let instance = open_instance(collections());
+        let col = &instance.collections()[0];
+        let mut qb = col.new_query_builder();
+        let property = col.property("value").unwrap();
+        qb.set_filter(Filter::long(property, 10, i64::MAX).unwrap());
+        let notified = Arc::new(AtomicU64::new(0));
+        let counter = notified.clone();
+        let callback = Box::new(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        let handle = instance.watch_query(col, qb.build(), callback, None);
+
+        let id = put_object(&instance, col, None, json!({"value": 5}));
+        assert_eq!(notified.load(Ordering::SeqCst), 0);
+        put_object(&instance, col, Some(id), json!({"value": 20}));
+        assert_eq!(notified.load(Ordering::SeqCst), 1);
+        // the object matched before the change
+        put_object(&instance, col, Some(id), json!({"value": 6}));
+        assert_eq!(notified.load(Ordering::SeqCst), 2);
+
+        drop(handle);
+        put_object(&instance, col, Some(id), json!({"value": 30}));
+        assert_eq!(notified.load(Ordering::SeqCst), 2);
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_find_parallel_matches_sequential_find() {
+        let instance = open_instance(collections());
+        let col = &instance.collections()[0];
+        let values = (0..1000).map(|i| i * 7 % 1000).collect_vec();
+        put_values(&instance, &values);
+        let property = col.property("value").unwrap();
+
+        for sort in [Sort::Ascending, Sort::Descending] {
+            let mut qb = col.new_query_builder();
+            qb.add_sorted_id_where_clause(0, i64::MAX, sort).unwrap();
+            qb.set_filter(Filter::long(property, 500, i64::MAX).unwrap());
+            qb.set_offset(10);
+            qb.set_limit(200);
+            let query = qb.build();
+            let read = |_, object: IsarObject| object.read_long(property.offset);
+            let results = instance.find_parallel(col, &query, 4, read).unwrap();
+            let ids = results.iter().map(|(id, _)| *id).collect_vec();
+            assert_eq!(ids.len(), 200);
+            assert_eq!(ids, find_ids(&instance, &query));
+            assert!(results.iter().all(|(_, value)| *value >= 500));
+        }
+
+        let mut qb = col.new_query_builder();
+        qb.add_sort(property, Sort::Ascending).unwrap();
+        let result = instance.find_parallel(col, &qb.build(), 4, |id, _| id);
+        assert!(result.is_err());
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_truncate_collection() {
+        let mut col = collection("col", json!([{"name": "value", "type": "Long"}]));
+        col["links"] = json!([{"name": "link", "target": "col"}]);
+        col["neverReuseIds"] = json!(true);
+        let instance = open_instance(json!([col]));
+        let col = &instance.collections()[0];
+        let link_id = col.link_id("link").unwrap();
+        let cleared = Arc::new(AtomicU64::new(0));
+        let counter = cleared.clone();
+        let callback = Box::new(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        let _handle = instance.watch_collection_clear(col, callback, None);
+
+        let ids = put_values(&instance, &[1, 2]);
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        col.link(&mut txn, link_id, ids[0], ids[1]).unwrap();
+        col.clear(&mut txn).unwrap();
+        txn.commit().unwrap();
+        assert_eq!(cleared.load(Ordering::SeqCst), 0);
+        assert_eq!(put_values(&instance, &[3]), [3]);
+
+        let ids = put_values(&instance, &[4, 5]);
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        col.link(&mut txn, link_id, ids[0], ids[1]).unwrap();
+        txn.commit().unwrap();
+        instance.truncate_collection(col, true).unwrap();
+        assert_eq!(cleared.load(Ordering::SeqCst), 1);
+
+        let mut txn = instance.begin_txn(false, false).unwrap();
+        let stats = col.stats(&mut txn).unwrap();
+        assert!(stats.iter().all(|s| s.entries == 0));
+        txn.abort();
+        // the auto increment is reset even though ids are never reused
+        assert_eq!(put_values(&instance, &[6]), [1]);
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_durability_modes_keep_flushed_commits() {
+        let durabilities = [
+            Durability::Safe,
+            Durability::NoMetaSync,
+            Durability::AsyncFlush,
+        ];
+        for durability in durabilities {
+            let instance = open_instance(collections());
+            let options = OpenOptions {
+                durability,
+                ..OpenOptions::default()
+            };
+            let instance = reopen_with(instance, collections(), options).unwrap();
+            put_values(&instance, &[1, 2, 3]);
+            instance.flush().unwrap();
+
+            let instance = reopen(instance, collections());
+            let mut txn = instance.begin_txn(false, false).unwrap();
+            assert_eq!(instance.collections()[0].count(&mut txn).unwrap(), 3);
+            txn.abort();
+            assert!(instance.close_and_delete());
+        }
+    }
+
+    #[test]
+    fn test_perf_counters() {
+        for durability in [Durability::Safe, Durability::NoSync] {
+            let instance = open_instance(collections());
+            let options = OpenOptions {
+                durability,
+                ..OpenOptions::default()
+            };
+            let instance = reopen_with(instance, collections(), options).unwrap();
+            let before = instance.perf_counters();
+            put_values(&instance, &[1]);
+            put_values(&instance, &[2, 3]);
+            let txn = instance.begin_txn(true, false).unwrap();
+            txn.abort();
+
+            let counters = instance.perf_counters();
+            assert_eq!(counters.commits, before.commits + 2);
+            assert!(counters.bytes_written > before.bytes_written);
+            let syncs = if durability == Durability::Safe { 2 } else { 0 };
+            assert_eq!(counters.syncs, before.syncs + syncs);
+            instance.flush().unwrap();
+            assert_eq!(instance.perf_counters().syncs, counters.syncs + 1);
+            assert!(instance.close_and_delete());
+        }
+    }
+
+    #[test]
+    fn test_pause_and_list_active_watchers() {
+        let instance = open_instance(collections());
+        let col = &instance.collections()[0];
+        let id = put_values(&instance, &[1])[0];
+        let notified = Arc::new(AtomicU64::new(0));
+        let counter = notified.clone();
+        let callback = Box::new(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        let collection_handle = instance.watch_collection(col, callback, None);
+        let object_handle = instance.watch_object(col, id, Box::new(|_| {}), None);
+        let query = col.new_query_builder().build();
+        let (query_handle, _) = instance.watch_query_lazy(col, query);
+
+        let watchers = instance.active_watchers();
+        let kinds = watchers.iter().map(|w| w.kind).collect_vec();
+        let expected = [
+            WatcherKind::Collection,
+            WatcherKind::Object,
+            WatcherKind::LazyQuery,
+        ];
+        assert_eq!(kinds, expected);
+        assert!(watchers.iter().all(|w| w.collection == "col" && !w.paused));
+        assert_eq!(watchers[1].object_id, Some(id));
+        assert!(watchers[2].query.is_some());
+
+        collection_handle.pause();
+        put_values(&instance, &[2]);
+        assert_eq!(notified.load(Ordering::SeqCst), 0);
+        assert!(instance.active_watchers()[0].paused);
+        collection_handle.resume();
+        put_values(&instance, &[3]);
+        assert_eq!(notified.load(Ordering::SeqCst), 1);
+
+        drop(object_handle);
+        query_handle.stop();
+        let watchers = instance.active_watchers();
+        assert_eq!(watchers.len(), 1);
+        assert_eq!(watchers[0].kind, WatcherKind::Collection);
+        drop(collection_handle);
+        assert!(instance.active_watchers().is_empty());
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_query_diff_is_evaluated_on_read_pool() {
+        let instance = open_instance(collections());
+        let col = &instance.collections()[0];
+        let (diff_tx, diff_rx) = unbounded();
+        let (release_tx, release_rx) = bounded::<()>(0);
+        let callback: QueryDiffCallback = Box::new(move |diff| {
+            let thread = thread::current().name().unwrap_or_default().to_string();
+            diff_tx.send((thread, diff.added)).unwrap();
+            release_rx.recv().unwrap();
+        });
+        let query = col.new_query_builder().build();
+        let handle = instance.watch_query_diff(col, query, callback, None);
+        let _handle = handle.unwrap();
+        let strong_count = Arc::strong_count(&instance);
+
+        // commits do not wait for the blocked evaluation
+        let first = put_values(&instance, &[1]);
+        let (thread, added) = diff_rx.recv().unwrap();
+        assert!(thread.starts_with("isarwatcher"));
+        assert_eq!(added, first);
+        let second = put_values(&instance, &[2]);
+        release_tx.send(()).unwrap();
+        let (_, added) = diff_rx.recv().unwrap();
+        assert_eq!(added, second);
+        release_tx.send(()).unwrap();
+        // the pool holds the instance until the evaluation is done
+        while Arc::strong_count(&instance) > strong_count {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_schema_hash() {
+        let mut a = collection("a", json!([{"name": "value", "type": "Long"}]));
+        let b = collection("b", json!([]));
+        let instance = open_instance(json!([a.clone(), b.clone()]));
+        let hash = instance.schema_hash();
+        let col = &instance.collections()[0];
+        put_object(&instance, col, None, json!({"value": 1}));
+
+        // the order of the collections does not matter
+        let instance = reopen(instance, json!([b.clone(), a.clone()]));
+        assert_eq!(instance.schema_hash(), hash);
+        let mut txn = instance.begin_txn(false, false).unwrap();
+        assert_eq!(instance.collections()[0].count(&mut txn).unwrap(), 1);
+        txn.abort();
+
+        let properties = a["properties"].as_array_mut().unwrap();
+        properties.push(json!({"name": "x", "type": "Int"}));
+        let instance = reopen(instance, json!([a, b]));
+        assert_ne!(instance.schema_hash(), hash);
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_multiple_instances_in_one_directory() {
+        let first = open_instance(collections());
+        let dir = first.dir.clone();
+        let open = |name: &str| {
+            let schema = Schema::from_json(&serde_json::to_vec(&collections()).unwrap()).unwrap();
+            IsarInstance::open(name, Some(&dir), schema, OpenOptions::default())
+        };
+        let second_name = format!("{}_second", first.name);
+        let second = open(&second_name).unwrap();
+        assert!(open("a/b").is_err());
+        put_values(&first, &[1]);
+        let query = second.collections()[0].new_query_builder().build();
+        assert!(find_ids(&second, &query).is_empty());
+
+        let names = [first.name.clone(), second_name.clone()];
+        assert_eq!(IsarInstance::list_instances(&dir).unwrap(), names);
+        let open_names = IsarInstance::open_instances();
+        assert!(names.iter().all(|name| open_names.contains(name)));
+
+        assert!(second.close());
+        assert!(!IsarInstance::open_instances().contains(&second_name));
+        assert_eq!(IsarInstance::list_instances(&dir).unwrap(), names);
+        assert!(first.close_and_delete());
+        assert!(open(&second_name).unwrap().close_and_delete());
+        assert!(IsarInstance::list_instances(&dir).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_watchers_receive_commit_sequence() {
+        let instance = open_instance(collections());
+        let col = &instance.collections()[0];
+        let sequences = Arc::new(Mutex::new(vec![]));
+        let recorded = sequences.clone();
+        let callback = Box::new(move |sequence| recorded.lock().unwrap().push(sequence));
+        let _handle = instance.watch_collection(col, callback, None);
+
+        let before = instance.commit_sequence().unwrap();
+        put_values(&instance, &[1]);
+        let first = instance.commit_sequence().unwrap();
+        put_values(&instance, &[2]);
+        let second = instance.commit_sequence().unwrap();
+        // read transactions do not advance the sequence
+        instance.begin_txn(false, false).unwrap().abort();
+        assert_eq!(instance.commit_sequence().unwrap(), second);
+
+        assert!(before < first && first < second);
+        assert_eq!(*sequences.lock().unwrap(), vec![first, second]);
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_cursor_stats_and_leaks() {
+        let instance = open_instance(collections());
+        let col = &instance.collections()[0];
+        let db_id = col.db.runtime_id();
+        let stats = |instance: &IsarInstance| {
+            let stats = instance.cursor_stats();
+            stats.into_iter().find(|stats| stats.db == db_id)
+        };
+        put_values(&instance, &[1]);
+        assert!(stats(&instance).is_none());
+
+        instance.set_cursor_tracking(true);
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        txn.write(instance.instance_id, |cursors, _| {
+            let first = cursors.get_cursor(col.db)?;
+            let second = cursors.get_cursor(col.db)?;
+            drop((first, second));
+            // the pooled cursor is reused
+            let third = cursors.get_cursor(col.db)?;
+            drop(third);
+            Ok(())
+        })
+        .unwrap();
+        txn.commit().unwrap();
+        let expected = CursorStats {
+            db: db_id,
+            open: 0,
+            high_water: 2,
+            created: 2,
+            leaked: 0,
+        };
+        assert_eq!(stats(&instance), Some(expected));
+
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        txn.write(instance.instance_id, |cursors, _| {
+            std::mem::forget(cursors.get_cursor(col.db)?);
+            Ok(())
+        })
+        .unwrap();
+        txn.commit().unwrap();
+        assert_eq!(stats(&instance).unwrap().leaked, 1);
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_read_only_open_rejects_writes() {
+        let instance = open_instance(collections());
+        put_values(&instance, &[1, 2]);
+        let options = OpenOptions {
+            read_only: true,
+            ..Default::default()
+        };
+        let instance = reopen_with(instance, collections(), options).unwrap();
+        let col = &instance.collections()[0];
+        let mut txn = instance.begin_txn(false, false).unwrap();
+        assert_eq!(col.count(&mut txn).unwrap(), 2);
+        drop(txn);
+        assert!(matches!(
+            instance.begin_txn(true, false),
+            Err(IsarError::ReadOnly {})
+        ));
+
+        // new indexes cannot be built without writing
+        let options = OpenOptions {
+            read_only: true,
+            ..Default::default()
+        };
+        let result = reopen_with(instance, collections_with_index(Some(false)), options);
+        assert!(matches!(result, Err(IsarError::ReadOnly {})));
+    }
+}
@@ -0,0 +1,41 @@
+use serde::Serialize;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ProblemKind {
+    InvalidObject,
+    MissingIndexEntry,
+    ObsoleteIndexEntry,
+    MissingBacklink,
+    ObsoleteBacklink,
+    MissingLinkedObject,
+}
+
+/// A single inconsistency found by `IsarInstance::check_integrity`.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IntegrityProblem {
+    pub kind: ProblemKind,
+    pub collection: String,
+    /// Name of the index or link.
+    pub name: Option<String>,
+    pub id: i64,
+    pub target_id: Option<i64>,
+}
+
+impl IntegrityProblem {
+    pub(crate) fn new(kind: ProblemKind, collection: &str, name: Option<&str>, id: i64) -> Self {
+        IntegrityProblem {
+            kind,
+            collection: collection.to_string(),
+            name: name.map(|n| n.to_string()),
+            id,
+            target_id: None,
+        }
+    }
+
+    pub(crate) fn with_target(mut self, target_id: i64) -> Self {
+        self.target_id = Some(target_id);
+        self
+    }
+}
@@ -3,17 +3,25 @@
 #[cfg(not(target_endian = "little"))]
 compile_error!("Only little endian systems are supported.");
 
+mod blob;
+pub mod change_journal;
 pub mod collection;
 mod cursor;
 pub mod error;
 pub mod index;
 pub mod instance;
+pub mod integrity;
 mod legacy;
 mod link;
+pub mod logging;
+#[cfg(feature = "mdbx")]
 mod mdbx;
+#[cfg(not(feature = "mdbx"))]
+mod memory;
 pub mod object;
 pub mod query;
 pub mod schema;
+mod storage;
 pub mod txn;
 pub mod watch;
 
@@ -1,31 +1,42 @@
+use crate::collection::{DbStats, IsarCollection};
 use crate::cursor::IsarCursors;
-use crate::error::{IsarError, Result};
-use crate::mdbx::cursor::Cursor;
-use crate::mdbx::db::Db;
+use crate::error::{illegal_arg, IsarError, Result};
+use crate::integrity::{IntegrityProblem, ProblemKind};
 use crate::object::id::{BytesToId, IdToBytes};
 use crate::object::isar_object::IsarObject;
+use crate::storage::{Cursor, Db, LazyDb, StorageCursor};
 use std::ops::Deref;
-use xxhash_rust::xxh3::xxh3_64_with_seed;
+use xxhash_rust::xxh3::{xxh3_64, xxh3_64_with_seed};
+
+const POSITION_STEP: u64 = 1 << 32;
 
 #[derive(Clone)]
 pub(crate) struct IsarLink {
     pub name: String,
     pub id: u64,
-    db: Db,
-    bl_db: Db,
+    backlink: bool,
+    db: LazyDb,
+    bl_db: LazyDb,
     source_db: Db,
     target_db: Db,
+    order_db: Option<Db>,
+    source_col_id: u64,
+    target_col_id: u64,
+    /// Hidden objects keep their links until they are purged.
+    tombstones_db: Option<Db>,
 }
 
 impl IsarLink {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         collection: &str,
         name: &str,
         backlink: bool,
-        db: Db,
-        bl_db: Db,
+        db: LazyDb,
+        bl_db: LazyDb,
         source_db: Db,
         target_db: Db,
+        order_db: Option<Db>,
     ) -> IsarLink {
         let seed = if backlink { 1 } else { 0 };
         let seed = xxh3_64_with_seed(collection.as_bytes(), seed);
@@ -33,10 +44,198 @@ impl IsarLink {
         IsarLink {
             name: name.to_string(),
             id,
+            backlink,
             db,
             bl_db,
             source_db,
             target_db,
+            order_db,
+            source_col_id: 0,
+            target_col_id: 0,
+            tombstones_db: None,
+        }
+    }
+
+    /// Links to objects that have a tombstone are not reported as broken.
+    pub fn with_tombstones(
+        mut self,
+        source_col: &str,
+        target_col: &str,
+        tombstones_db: Option<Db>,
+    ) -> Self {
+        self.source_col_id = xxh3_64(source_col.as_bytes());
+        self.target_col_id = xxh3_64(target_col.as_bytes());
+        self.tombstones_db = tombstones_db;
+        self
+    }
+
+    pub fn warm_up(&self, cursors: &IsarCursors) -> Result<()> {
+        cursors.open_db(&self.db)?;
+        cursors.open_db(&self.bl_db)?;
+        Ok(())
+    }
+
+    /// Ordered links keep the position of each target. Backlinks are never ordered.
+    pub fn is_ordered(&self) -> bool {
+        self.order_db.is_some() && !self.backlink
+    }
+
+    /// Source and target id in the direction of the link.
+    fn link_ids(&self, id: i64, other_id: i64) -> (i64, i64) {
+        if self.backlink {
+            (other_id, id)
+        } else {
+            (id, other_id)
+        }
+    }
+
+    /// Position of the target at `index` if the positions are evenly spaced.
+    pub(crate) fn initial_position(index: usize) -> u64 {
+        (index as u64 + 1) * POSITION_STEP
+    }
+
+    pub(crate) fn position_value(position: u64, target_id: i64) -> [u8; 16] {
+        let mut value = [0; 16];
+        value[..8].copy_from_slice(&position.to_be_bytes());
+        value[8..].copy_from_slice(&target_id.to_id_bytes());
+        value
+    }
+
+    fn read_positions(
+        &self,
+        cursors: &IsarCursors,
+        order_db: Db,
+        source_id: i64,
+    ) -> Result<Vec<(u64, i64)>> {
+        let mut cursor = cursors.get_cursor(order_db)?;
+        let mut positions = vec![];
+        cursor.iter_dups(&source_id, |_, value| {
+            let position = u64::from_be_bytes(value[..8].try_into().unwrap());
+            positions.push((position, (&value[8..]).to_id()));
+            Ok(true)
+        })?;
+        Ok(positions)
+    }
+
+    fn delete_position(
+        cursors: &IsarCursors,
+        order_db: Db,
+        source_id: i64,
+        position: u64,
+        target_id: i64,
+    ) -> Result<()> {
+        let mut cursor = cursors.get_cursor(order_db)?;
+        let value = Self::position_value(position, target_id);
+        if cursor.move_to_key_val(&source_id, &value)?.is_some() {
+            cursor.delete_current()?;
+        }
+        Ok(())
+    }
+
+    /// Moves the target to `index` in the order of the source. Positions are renumbered if there
+    /// is no gap left.
+    fn place(
+        &self,
+        cursors: &IsarCursors,
+        order_db: Db,
+        source_id: i64,
+        target_id: i64,
+        index: usize,
+    ) -> Result<()> {
+        let mut positions = self.read_positions(cursors, order_db, source_id)?;
+        if let Some(i) = positions.iter().position(|(_, id)| *id == target_id) {
+            let (position, _) = positions.remove(i);
+            Self::delete_position(cursors, order_db, source_id, position, target_id)?;
+        }
+
+        let index = index.min(positions.len());
+        let lower = if index == 0 {
+            0
+        } else {
+            positions[index - 1].0
+        };
+        let upper = positions.get(index).map_or(u64::MAX, |(p, _)| *p);
+        let position = if index == positions.len() {
+            lower.checked_add(POSITION_STEP)
+        } else {
+            None
+        }
+        .or_else(|| (upper - lower > 1).then(|| lower + (upper - lower) / 2));
+
+        let mut cursor = cursors.get_cursor(order_db)?;
+        if let Some(position) = position {
+            cursor.put(&source_id, &Self::position_value(position, target_id))?;
+        } else {
+            for (position, id) in &positions {
+                Self::delete_position(cursors, order_db, source_id, *position, *id)?;
+            }
+            positions.insert(index, (0, target_id));
+            for (i, (_, id)) in positions.iter().enumerate() {
+                let position = Self::initial_position(i);
+                cursor.put(&source_id, &Self::position_value(position, *id))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn remove_position(&self, cursors: &IsarCursors, id: i64, other_id: i64) -> Result<()> {
+        if let Some(order_db) = self.order_db {
+            let (source_id, target_id) = self.link_ids(id, other_id);
+            let positions = self.read_positions(cursors, order_db, source_id)?;
+            if let Some((position, _)) = positions.iter().find(|(_, id)| *id == target_id) {
+                Self::delete_position(cursors, order_db, source_id, *position, target_id)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn exists(&self, cursors: &IsarCursors, source_id: i64, target_id: i64) -> Result<bool> {
+        let mut link_cursor = cursors.get_lazy_cursor(&self.db)?;
+        let exists = link_cursor
+            .move_to_key_val(&source_id, &target_id.to_id_bytes())?
+            .is_some();
+        Ok(exists)
+    }
+
+    /// Links the target at `index` or moves it there if it is already linked.
+    pub fn insert_at(
+        &self,
+        cursors: &IsarCursors,
+        source_id: i64,
+        target_id: i64,
+        index: usize,
+    ) -> Result<bool> {
+        let order_db = self.get_order_db()?;
+        if !self.exists(cursors, source_id, target_id)?
+            && !self.create_link(cursors, source_id, target_id)?
+        {
+            return Ok(false);
+        }
+        self.place(cursors, order_db, source_id, target_id, index)?;
+        Ok(true)
+    }
+
+    /// Moves an existing link to `index`. Returns `false` if the target is not linked.
+    pub fn move_to(
+        &self,
+        cursors: &IsarCursors,
+        source_id: i64,
+        target_id: i64,
+        index: usize,
+    ) -> Result<bool> {
+        let order_db = self.get_order_db()?;
+        if !self.exists(cursors, source_id, target_id)? {
+            return Ok(false);
+        }
+        self.place(cursors, order_db, source_id, target_id, index)?;
+        Ok(true)
+    }
+
+    fn get_order_db(&self) -> Result<Db> {
+        if let Some(order_db) = self.order_db.filter(|_| !self.backlink) {
+            Ok(order_db)
+        } else {
+            illegal_arg("Only ordered links support positions.")
         }
     }
 
@@ -44,12 +243,13 @@ impl IsarLink {
     where
         F: FnMut(&mut Cursor, i64) -> Result<bool>,
     {
-        let mut cursor = cursors.get_cursor(self.db)?;
+        let mut cursor = cursors.get_lazy_cursor(&self.db)?;
         cursor.iter_dups(&id, |cursor, link_target_key| {
             callback(cursor, link_target_key.to_id())
         })
     }
 
+    /// Ordered links are iterated by position, all others by target id.
     pub fn iter<'txn, 'env, F>(
         &self,
         cursors: &IsarCursors<'txn, 'env>,
@@ -60,7 +260,7 @@ impl IsarLink {
         F: FnMut(i64, IsarObject<'txn>) -> Result<bool>,
     {
         let mut target_cursor = cursors.get_cursor(self.target_db)?;
-        self.iter_ids(cursors, id, |_, link_target_key| {
+        let mut visit = |link_target_key: i64| {
             if let Some((id_bytes, object)) = target_cursor.move_to(&link_target_key)? {
                 callback(id_bytes.deref().to_id(), IsarObject::from_bytes(&object))
             } else {
@@ -68,10 +268,42 @@ impl IsarLink {
                     message: "Target object does not exist".to_string(),
                 })
             }
-        })
+        };
+        if let Some(order_db) = self.order_db.filter(|_| !self.backlink) {
+            for (_, target_id) in self.read_positions(cursors, order_db, id)? {
+                if !visit(target_id)? {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        } else {
+            self.iter_ids(cursors, id, |_, link_target_key| visit(link_target_key))
+        }
+    }
+
+    pub fn count(&self, cursors: &IsarCursors, id: i64) -> Result<usize> {
+        let mut cursor = cursors.get_lazy_cursor(&self.db)?;
+        cursor.count_dups(&id)
     }
 
+    /// New targets of ordered links are added at the end.
     pub fn create(&self, cursors: &IsarCursors, source_id: i64, target_id: i64) -> Result<bool> {
+        if let Some(order_db) = self.order_db {
+            if self.exists(cursors, source_id, target_id)? {
+                return Ok(true);
+            }
+            if !self.create_link(cursors, source_id, target_id)? {
+                return Ok(false);
+            }
+            let (source_id, target_id) = self.link_ids(source_id, target_id);
+            self.place(cursors, order_db, source_id, target_id, usize::MAX)?;
+            Ok(true)
+        } else {
+            self.create_link(cursors, source_id, target_id)
+        }
+    }
+
+    fn create_link(&self, cursors: &IsarCursors, source_id: i64, target_id: i64) -> Result<bool> {
         let mut source_cursor = cursors.get_cursor(self.source_db)?;
         let mut target_cursor = cursors.get_cursor(self.target_db)?;
 
@@ -81,28 +313,29 @@ impl IsarLink {
             return Ok(false);
         }
 
-        let mut link_cursor = cursors.get_cursor(self.db)?;
+        let mut link_cursor = cursors.get_lazy_cursor(&self.db)?;
         link_cursor.put(&source_id, &target_id.to_id_bytes())?;
 
-        let mut backlink_cursor = cursors.get_cursor(self.bl_db)?;
+        let mut backlink_cursor = cursors.get_lazy_cursor(&self.bl_db)?;
         backlink_cursor.put(&target_id, &source_id.to_id_bytes())?;
         Ok(true)
     }
 
     pub fn delete(&self, cursors: &IsarCursors, source_id: i64, target_id: i64) -> Result<bool> {
-        let mut link_cursor = cursors.get_cursor(self.db)?;
+        let mut link_cursor = cursors.get_lazy_cursor(&self.db)?;
         let exists = link_cursor
             .move_to_key_val(&source_id, &target_id.to_id_bytes())?
             .is_some();
 
         if exists {
-            let mut backlink_cursor = cursors.get_cursor(self.bl_db)?;
+            let mut backlink_cursor = cursors.get_lazy_cursor(&self.bl_db)?;
             let backlink_exists = backlink_cursor
                 .move_to_key_val(&target_id, &source_id.to_id_bytes())?
                 .is_some();
             if backlink_exists {
                 link_cursor.delete_current()?;
                 backlink_cursor.delete_current()?;
+                self.remove_position(cursors, source_id, target_id)?;
                 Ok(true)
             } else {
                 Err(IsarError::DbCorrupted {
@@ -117,7 +350,7 @@ impl IsarLink {
     pub fn delete_all_for_object(&self, cursors: &IsarCursors, id: i64) -> Result<()> {
         let id_bytes = id.to_id_bytes();
 
-        let mut backlink_cursor = cursors.get_cursor(self.bl_db)?;
+        let mut backlink_cursor = cursors.get_lazy_cursor(&self.bl_db)?;
         self.iter_ids(cursors, id, |cursor, link_target_key| {
             let exists = backlink_cursor
                 .move_to_key_val(&link_target_key, &id_bytes)?
@@ -125,6 +358,9 @@ impl IsarLink {
             if exists {
                 cursor.delete_current()?;
                 backlink_cursor.delete_current()?;
+                if self.backlink {
+                    self.remove_position(cursors, id, link_target_key)?;
+                }
                 Ok(true)
             } else {
                 Err(IsarError::DbCorrupted {
@@ -132,28 +368,156 @@ impl IsarLink {
                 })
             }
         })?;
+        if let Some(order_db) = self.order_db.filter(|_| !self.backlink) {
+            for (position, target_id) in self.read_positions(cursors, order_db, id)? {
+                Self::delete_position(cursors, order_db, id, position, target_id)?;
+            }
+        }
         Ok(())
     }
 
     pub fn get_size(&self, cursors: &IsarCursors) -> Result<u64> {
-        Ok(cursors.db_stat(self.db)?.1)
+        Ok(cursors.db_stat(cursors.open_db(&self.db)?)?.1)
+    }
+
+    pub fn get_stats(&self, cursors: &IsarCursors, col_name: &str) -> Result<[DbStats; 2]> {
+        let name = format!("_l_{}_{}", col_name, self.name);
+        let bl_name = format!("_b_{}_{}", col_name, self.name);
+        Ok([
+            DbStats::new(name, cursors.db_raw_stat(cursors.open_db(&self.db)?)?),
+            DbStats::new(bl_name, cursors.db_raw_stat(cursors.open_db(&self.bl_db)?)?),
+        ])
     }
 
     pub fn clear(&self, cursors: &IsarCursors) -> Result<()> {
-        cursors.clear_db(self.db)?;
-        cursors.clear_db(self.bl_db)
+        cursors.clear_db(cursors.open_db(&self.db)?)?;
+        if let Some(order_db) = self.order_db {
+            cursors.clear_db(order_db)?;
+        }
+        cursors.clear_db(cursors.open_db(&self.bl_db)?)
+    }
+
+    /// Checks that links and backlinks are symmetric and that both linked objects exist.
+    fn is_hidden(&self, cursors: &IsarCursors, col_id: u64, id: i64) -> Result<bool> {
+        if let Some(tombstones_db) = self.tombstones_db {
+            let mut cursor = cursors.get_cursor(tombstones_db)?;
+            let key = IsarCollection::get_id_key(col_id, id);
+            Ok(cursor.move_to(&key)?.is_some())
+        } else {
+            Ok(false)
+        }
+    }
+
+    pub fn check_integrity(
+        &self,
+        cursors: &IsarCursors,
+        col_name: &str,
+        problems: &mut Vec<IntegrityProblem>,
+    ) -> Result<()> {
+        let mut source_cursor = cursors.get_cursor(self.source_db)?;
+        let mut target_cursor = cursors.get_cursor(self.target_db)?;
+        let mut backlink_cursor = cursors.get_lazy_cursor(&self.bl_db)?;
+        let mut cursor = cursors.get_lazy_cursor(&self.db)?;
+        cursor.iter_all(false, true, |_, id_bytes, target_id_bytes| {
+            let id = id_bytes.to_id();
+            let target_id = target_id_bytes.to_id();
+            let problem = |kind| {
+                IntegrityProblem::new(kind, col_name, Some(&self.name), id).with_target(target_id)
+            };
+            if backlink_cursor
+                .move_to_key_val(&target_id, id_bytes)?
+                .is_none()
+            {
+                problems.push(problem(ProblemKind::MissingBacklink));
+            }
+            let source_missing = source_cursor.move_to(&id)?.is_none()
+                && !self.is_hidden(cursors, self.source_col_id, id)?;
+            let target_missing = target_cursor.move_to(&target_id)?.is_none()
+                && !self.is_hidden(cursors, self.target_col_id, target_id)?;
+            if source_missing || target_missing {
+                problems.push(problem(ProblemKind::MissingLinkedObject));
+            }
+            Ok(true)
+        })?;
+
+        let mut link_cursor = cursors.get_lazy_cursor(&self.db)?;
+        backlink_cursor.iter_all(false, true, |_, target_id_bytes, id_bytes| {
+            let id = id_bytes.to_id();
+            let target_id = target_id_bytes.to_id();
+            if link_cursor.move_to_key_val(&id, target_id_bytes)?.is_none() {
+                problems.push(
+                    IntegrityProblem::new(
+                        ProblemKind::ObsoleteBacklink,
+                        col_name,
+                        Some(&self.name),
+                        id,
+                    )
+                    .with_target(target_id),
+                );
+            }
+            Ok(true)
+        })?;
+        Ok(())
+    }
+
+    /// Removes links to missing objects and restores the symmetry of links and backlinks.
+    pub fn repair(
+        &self,
+        cursors: &IsarCursors,
+        col_name: &str,
+        problems: &mut Vec<IntegrityProblem>,
+    ) -> Result<()> {
+        let mut found = vec![];
+        self.check_integrity(cursors, col_name, &mut found)?;
+
+        let mut link_cursor = cursors.get_lazy_cursor(&self.db)?;
+        let mut backlink_cursor = cursors.get_lazy_cursor(&self.bl_db)?;
+        for problem in &found {
+            let id = problem.id;
+            let target_id = problem.target_id.unwrap();
+            match problem.kind {
+                ProblemKind::MissingBacklink => {
+                    backlink_cursor.put(&target_id, &id.to_id_bytes())?;
+                }
+                ProblemKind::MissingLinkedObject => {
+                    if link_cursor
+                        .move_to_key_val(&id, &target_id.to_id_bytes())?
+                        .is_some()
+                    {
+                        link_cursor.delete_current()?;
+                    }
+                    if backlink_cursor
+                        .move_to_key_val(&target_id, &id.to_id_bytes())?
+                        .is_some()
+                    {
+                        backlink_cursor.delete_current()?;
+                    }
+                    self.remove_position(cursors, id, target_id)?;
+                }
+                ProblemKind::ObsoleteBacklink
+                    if backlink_cursor
+                        .move_to_key_val(&target_id, &id.to_id_bytes())?
+                        .is_some() =>
+                {
+                    backlink_cursor.delete_current()?;
+                }
+                _ => {}
+            }
+        }
+        problems.extend(found);
+        Ok(())
     }
 
     pub fn verify(&self, cursors: &IsarCursors, links: &[(i64, i64)]) -> Result<()> {
-        let link_count = cursors.db_stat(self.db)?.0 as usize;
-        let backlink_count = cursors.db_stat(self.db)?.0 as usize;
+        let link_count = cursors.db_stat(cursors.open_db(&self.db)?)?.0 as usize;
+        let backlink_count = cursors.db_stat(cursors.open_db(&self.db)?)?.0 as usize;
         if link_count != links.len() || backlink_count != links.len() {
             return Err(IsarError::DbCorrupted {
                 message: "Link or Backlink count mismatch.".to_string(),
             });
         }
 
-        let mut cursor = cursors.get_cursor(self.db)?;
+        let mut cursor = cursors.get_lazy_cursor(&self.db)?;
         cursor.iter_all(false, true, |_, id_bytes, target_id_bytes| {
             let id = id_bytes.to_id();
             let target_id = target_id_bytes.to_id();
@@ -166,7 +530,7 @@ impl IsarLink {
             }
         })?;
 
-        let mut cursor = cursors.get_cursor(self.bl_db)?;
+        let mut cursor = cursors.get_lazy_cursor(&self.bl_db)?;
         cursor.iter_all(false, true, |_, target_id_bytes, id_bytes| {
             let id = id_bytes.to_id();
             let target_id = target_id_bytes.to_id();
@@ -182,3 +546,68 @@ impl IsarLink {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::instance::tests::{collection, open_instance, put_object};
+    use crate::instance::IsarInstance;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    fn open_with_objects(count: i64) -> Arc<IsarInstance> {
+        let mut col = collection("col", json!([]));
+        col["links"] = json!([{"name": "link", "target": "col", "ordered": true}]);
+        let instance = open_instance(json!([col]));
+        for id in 1..=count {
+            put_object(&instance, &instance.collections()[0], Some(id), json!({}));
+        }
+        instance
+    }
+
+    #[test]
+    fn test_ordered_link_insert_and_move() {
+        let instance = open_with_objects(4);
+        let col = &instance.collections()[0];
+        let link_id = col.link_id("link").unwrap();
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        assert!(col.link(&mut txn, link_id, 1, 3).unwrap());
+        assert!(col.link(&mut txn, link_id, 1, 2).unwrap());
+        assert!(col.link_at(&mut txn, link_id, 1, 4, 1).unwrap());
+        assert_eq!(
+            col.get_link_ids(&mut txn, link_id, 1).unwrap(),
+            vec![3, 4, 2]
+        );
+
+        assert!(col.move_link(&mut txn, link_id, 1, 2, 0).unwrap());
+        assert_eq!(
+            col.get_link_ids(&mut txn, link_id, 1).unwrap(),
+            vec![2, 3, 4]
+        );
+        assert!(!col.move_link(&mut txn, link_id, 1, 1, 0).unwrap());
+
+        assert!(col.unlink(&mut txn, link_id, 1, 3).unwrap());
+        assert!(col.link(&mut txn, link_id, 1, 3).unwrap());
+        assert_eq!(
+            col.get_link_ids(&mut txn, link_id, 1).unwrap(),
+            vec![2, 4, 3]
+        );
+        txn.commit().unwrap();
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_ordered_link_renumbers_positions() {
+        // inserting at the front halves the gap every time until it is exhausted
+        let instance = open_with_objects(40);
+        let col = &instance.collections()[0];
+        let link_id = col.link_id("link").unwrap();
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        for id in 1..=40 {
+            assert!(col.link_at(&mut txn, link_id, 1, id, 0).unwrap());
+        }
+        let expected = (1..=40).rev().collect::<Vec<_>>();
+        assert_eq!(col.get_link_ids(&mut txn, link_id, 1).unwrap(), expected);
+        txn.commit().unwrap();
+        assert!(instance.close_and_delete());
+    }
+}
@@ -0,0 +1,120 @@
+use once_cell::sync::Lazy;
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::RwLock;
+
+pub enum LogEvent<'a> {
+    TxnBegin { instance_id: u64, write: bool },
+    TxnCommit { instance_id: u64 },
+    TxnAbort { instance_id: u64 },
+    Migration { collection: &'a str, step: &'a str },
+    IndexFill { collection: &'a str, objects: usize },
+    WatcherNotify { watchers: usize },
+    SnapshotAge { instance_id: u64, age_ms: u128 },
+    CursorLeak { instance_id: u64, db: u64 },
+}
+
+impl<'a> Display for LogEvent<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LogEvent::TxnBegin { instance_id, write } => {
+                write!(f, "txn_begin instance={} write={}", instance_id, write)
+            }
+            LogEvent::TxnCommit { instance_id } => {
+                write!(f, "txn_commit instance={}", instance_id)
+            }
+            LogEvent::TxnAbort { instance_id } => write!(f, "txn_abort instance={}", instance_id),
+            LogEvent::Migration { collection, step } => {
+                write!(f, "migration collection={} step={}", collection, step)
+            }
+            LogEvent::IndexFill {
+                collection,
+                objects,
+            } => write!(
+                f,
+                "index_fill collection={} objects={}",
+                collection, objects
+            ),
+            LogEvent::WatcherNotify { watchers } => {
+                write!(f, "watcher_notify watchers={}", watchers)
+            }
+            LogEvent::SnapshotAge {
+                instance_id,
+                age_ms,
+            } => write!(f, "snapshot_age instance={} age_ms={}", instance_id, age_ms),
+            LogEvent::CursorLeak { instance_id, db } => {
+                write!(f, "cursor_leak instance={} db={}", instance_id, db)
+            }
+        }
+    }
+}
+
+pub type LogCallback = Box<dyn Fn(&LogEvent) + Send + Sync>;
+
+static LOG_ENABLED: AtomicBool = AtomicBool::new(false);
+
+static LOG_CALLBACK: Lazy<RwLock<Option<LogCallback>>> = Lazy::new(|| RwLock::new(None));
+
+pub fn set_log_callback(callback: Option<LogCallback>) {
+    let mut lock = LOG_CALLBACK.write().unwrap();
+    LOG_ENABLED.store(callback.is_some(), Ordering::Release);
+    *lock = callback;
+}
+
+#[inline]
+pub(crate) fn log_enabled() -> bool {
+    LOG_ENABLED.load(Ordering::Relaxed)
+}
+
+pub(crate) fn log(event: LogEvent) {
+    if let Some(callback) = LOG_CALLBACK.read().unwrap().as_ref() {
+        callback(&event);
+    }
+}
+
+#[macro_export]
+macro_rules! isar_log {
+    ($event:expr) => {
+        if $crate::logging::log_enabled() {
+            $crate::logging::log($event);
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instance::tests::{collections, open_instance};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_log_txn_events() {
+        let instance = open_instance(collections());
+        let suffix = format!("instance={}", instance.instance_id);
+        let events = Arc::new(Mutex::new(vec![]));
+        let recorded = events.clone();
+        set_log_callback(Some(Box::new(move |event| {
+            // other tests may log concurrently
+            let event = event.to_string();
+            if event.contains(&suffix) {
+                recorded.lock().unwrap().push(event);
+            }
+        })));
+
+        instance.begin_txn(true, false).unwrap().commit().unwrap();
+        instance.begin_txn(false, false).unwrap().abort();
+        set_log_callback(None);
+        instance.begin_txn(false, false).unwrap().abort();
+
+        let id = instance.instance_id;
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                format!("txn_begin instance={} write=true", id),
+                format!("txn_commit instance={}", id),
+                format!("txn_begin instance={} write=false", id),
+                format!("txn_abort instance={}", id),
+            ]
+        );
+    }
+}
@@ -2,14 +2,15 @@ use crate::error::Result;
 use crate::mdbx::db::Db;
 use crate::mdbx::txn::Txn;
 use crate::mdbx::{from_mdb_val, mdbx_result, to_mdb_val, Key, KeyVal, EMPTY_KEY, EMPTY_VAL};
+use crate::storage::StorageCursor;
 use core::ptr;
-use std::cmp::Ordering;
-use std::marker::PhantomData;
 
 pub struct UnboundCursor {
     cursor: *mut ffi::MDBX_cursor,
 }
 
+unsafe impl Send for UnboundCursor {}
+
 impl UnboundCursor {
     pub(crate) fn new() -> Self {
         let cursor = unsafe { ffi::mdbx_cursor_create(ptr::null_mut()) };
@@ -18,14 +19,14 @@ impl UnboundCursor {
     }
 
     pub fn bind<'txn>(self, txn: &'txn Txn, db: Db) -> Result<Cursor<'txn>> {
-        unsafe {
-            mdbx_result(ffi::mdbx_cursor_bind(txn.txn, self.cursor, db.dbi))?;
+        {
+            let _lock = txn.lock();
+            unsafe {
+                mdbx_result(ffi::mdbx_cursor_bind(txn.txn, self.cursor, db.dbi))?;
+            }
         }
 
-        Ok(Cursor {
-            cursor: self,
-            _marker: PhantomData::default(),
-        })
+        Ok(Cursor { cursor: self, txn })
     }
 }
 
@@ -37,12 +38,13 @@ impl Drop for UnboundCursor {
 
 pub struct Cursor<'txn> {
     cursor: UnboundCursor,
-    _marker: PhantomData<&'txn ()>,
+    txn: &'txn Txn<'txn>,
 }
 
 impl<'txn> Cursor<'txn> {
-    pub fn unbind(self) -> UnboundCursor {
-        self.cursor
+    pub fn unbind(mut self) -> UnboundCursor {
+        let cursor = std::mem::replace(&mut self.cursor.cursor, ptr::null_mut());
+        UnboundCursor { cursor }
     }
 
     #[allow(clippy::try_err)]
@@ -70,8 +72,10 @@ impl<'txn> Cursor<'txn> {
             }
         }
     }
+}
 
-    pub fn move_to<K: Key>(&mut self, key: &K) -> Result<Option<KeyVal<'txn>>> {
+impl<'txn> StorageCursor<'txn> for Cursor<'txn> {
+    fn move_to<K: Key>(&mut self, key: &K) -> Result<Option<KeyVal<'txn>>> {
         self.op_get(
             ffi::MDBX_cursor_op::MDBX_SET_KEY,
             Some(&key.as_bytes()),
@@ -79,7 +83,7 @@ impl<'txn> Cursor<'txn> {
         )
     }
 
-    pub fn move_to_key_val<K: Key>(&mut self, key: &K, val: &[u8]) -> Result<Option<KeyVal<'txn>>> {
+    fn move_to_key_val<K: Key>(&mut self, key: &K, val: &[u8]) -> Result<Option<KeyVal<'txn>>> {
         self.op_get(
             ffi::MDBX_cursor_op::MDBX_GET_BOTH,
             Some(&key.as_bytes()),
@@ -100,192 +104,73 @@ impl<'txn> Cursor<'txn> {
     }
 
     fn move_to_last_dup(&mut self) -> Result<Option<KeyVal<'txn>>> {
-        self.op_get(ffi::MDBX_cursor_op::MDBX_LAST_DUP, None, None)
+        // MDBX_LAST_DUP only returns the value
+        if self
+            .op_get(ffi::MDBX_cursor_op::MDBX_LAST_DUP, None, None)?
+            .is_some()
+        {
+            self.op_get(ffi::MDBX_cursor_op::MDBX_GET_CURRENT, None, None)
+        } else {
+            Ok(None)
+        }
     }
 
     fn move_to_prev_no_dup(&mut self) -> Result<Option<KeyVal<'txn>>> {
         self.op_get(ffi::MDBX_cursor_op::MDBX_PREV_NODUP, None, None)
     }
 
-    pub fn move_to_next(&mut self) -> Result<Option<KeyVal<'txn>>> {
-        self.op_get(ffi::MDBX_cursor_op::MDBX_NEXT, None, None)
-    }
-
-    pub fn move_to_first(&mut self) -> Result<Option<KeyVal<'txn>>> {
+    fn move_to_first(&mut self) -> Result<Option<KeyVal<'txn>>> {
         self.op_get(ffi::MDBX_cursor_op::MDBX_FIRST, None, None)
     }
 
-    pub fn move_to_last(&mut self) -> Result<Option<KeyVal<'txn>>> {
+    fn move_to_last(&mut self) -> Result<Option<KeyVal<'txn>>> {
         self.op_get(ffi::MDBX_cursor_op::MDBX_LAST, None, None)
     }
 
-    pub fn put<K: Key>(&mut self, key: &K, data: &[u8]) -> Result<()> {
-        unsafe {
-            // make sure that bytes are not dropped before the call to mdbx_cursor_put
-            let bytes = &key.as_bytes();
-            let key = to_mdb_val(bytes);
-            let mut data = to_mdb_val(data);
-            mdbx_result(ffi::mdbx_cursor_put(self.cursor.cursor, &key, &mut data, 0))?;
-        }
-        Ok(())
-    }
-
-    /// Requires the cursor to have a valid position
-    pub fn delete_current(&mut self) -> Result<()> {
-        unsafe { mdbx_result(ffi::mdbx_cursor_del(self.cursor.cursor, 0))? };
-
-        Ok(())
-    }
-
-    fn iter(
-        &mut self,
-        skip_duplicates: bool,
-        ascending: bool,
-        mut callback: impl FnMut(&mut Self, &'txn [u8], &'txn [u8]) -> Result<bool>,
-    ) -> Result<bool> {
-        let next = match (ascending, skip_duplicates) {
+    fn step(&mut self, ascending: bool, skip_duplicates: bool) -> Result<Option<KeyVal<'txn>>> {
+        let op = match (ascending, skip_duplicates) {
             (true, true) => ffi::MDBX_cursor_op::MDBX_NEXT_NODUP,
             (true, false) => ffi::MDBX_cursor_op::MDBX_NEXT,
             (false, true) => ffi::MDBX_cursor_op::MDBX_PREV_NODUP,
             (false, false) => ffi::MDBX_cursor_op::MDBX_PREV,
         };
-        loop {
-            if let Some((key, val)) = self.op_get(next, None, None)? {
-                if !callback(self, key, val)? {
-                    return Ok(false);
-                }
-            } else {
-                return Ok(true);
-            }
-        }
+        self.op_get(op, None, None)
     }
 
-    pub fn iter_all(
-        &mut self,
-        skip_duplicates: bool,
-        ascending: bool,
-        mut callback: impl FnMut(&mut Self, &'txn [u8], &'txn [u8]) -> Result<bool>,
-    ) -> Result<bool> {
-        let first = if ascending {
-            self.move_to_first()?
-        } else {
-            self.move_to_last()?
-        };
-
-        if let Some((key, val)) = first {
-            if !callback(self, key, val)? {
-                return Ok(false);
-            }
-        } else {
-            return Ok(true);
+    fn put<K: Key>(&mut self, key: &K, data: &[u8]) -> Result<()> {
+        unsafe {
+            // make sure that bytes are not dropped before the call to mdbx_cursor_put
+            let bytes = &key.as_bytes();
+            let key = to_mdb_val(bytes);
+            let mut data = to_mdb_val(data);
+            mdbx_result(ffi::mdbx_cursor_put(self.cursor.cursor, &key, &mut data, 0))?;
         }
-
-        self.iter(skip_duplicates, ascending, callback)
+        Ok(())
     }
 
-    fn iter_between_first<K: Key>(
-        &mut self,
-        lower_key: &K,
-        upper_key: &K,
-        ascending: bool,
-        duplicates: bool,
-    ) -> Result<Option<KeyVal<'txn>>> {
-        let first_entry = if !ascending {
-            if let Some(first_entry) = self.move_to_gte(upper_key)? {
-                if duplicates {
-                    self.move_to_last_dup()?.or(Some(first_entry))
-                } else {
-                    Some(first_entry)
-                }
-            } else if let Some(last) = self.move_to_last()? {
-                // If some key between upper_key and lower_key happens to be the last key in the db
-                if lower_key.cmp_bytes(&last.0) != Ordering::Greater {
-                    Some(last)
-                } else {
-                    None
-                }
-            } else {
-                None
-            }
-        } else {
-            self.move_to_gte(lower_key)?
-        };
-
-        if let Some(first_entry) = first_entry {
-            if upper_key.cmp_bytes(&first_entry.0) == Ordering::Less {
-                if !ascending {
-                    if let Some(prev) = self.move_to_prev_no_dup()? {
-                        if lower_key.cmp_bytes(&prev.0) != Ordering::Greater {
-                            return Ok(Some(prev));
-                        }
-                    }
-                }
-                Ok(None)
-            } else {
-                Ok(Some(first_entry))
-            }
-        } else {
-            Ok(None)
+    fn count_dups<K: Key>(&mut self, key: &K) -> Result<usize> {
+        if self.move_to(key)?.is_none() {
+            return Ok(0);
         }
+        let mut count: ffi::size_t = 0;
+        unsafe { mdbx_result(ffi::mdbx_cursor_count(self.cursor.cursor, &mut count))? };
+        Ok(count as usize)
     }
 
-    pub fn iter_between<K: Key>(
-        &mut self,
-        lower_key: &K,
-        upper_key: &K,
-        duplicates: bool,
-        skip_duplicates: bool,
-        ascending: bool,
-        mut callback: impl FnMut(&mut Self, &'txn [u8], &'txn [u8]) -> Result<bool>,
-    ) -> Result<bool> {
-        if upper_key.cmp_bytes(&lower_key.as_bytes()) == Ordering::Less {
-            return Ok(true);
-        }
-
-        if let Some((key, val)) =
-            self.iter_between_first(lower_key, upper_key, ascending, duplicates)?
-        {
-            if !callback(self, key, val)? {
-                return Ok(false);
-            }
-        } else {
-            return Ok(true);
-        }
+    fn delete_current(&mut self) -> Result<()> {
+        unsafe { mdbx_result(ffi::mdbx_cursor_del(self.cursor.cursor, 0))? };
 
-        self.iter(skip_duplicates, ascending, |cursor, key, val| {
-            let abort = if ascending {
-                upper_key.cmp_bytes(&key) == Ordering::Less
-            } else {
-                lower_key.cmp_bytes(&key) == Ordering::Greater
-            };
-            if abort {
-                Ok(true)
-            } else {
-                callback(cursor, key, val)
-            }
-        })
+        Ok(())
     }
+}
 
-    pub fn iter_dups<K: Key>(
-        &mut self,
-        key: &K,
-        mut callback: impl FnMut(&mut Self, &'txn [u8]) -> Result<bool>,
-    ) -> Result<bool> {
-        if let Some((_, val)) = self.move_to(key)? {
-            if !callback(self, &val)? {
-                return Ok(false);
-            }
-        } else {
-            return Ok(true);
-        }
-        loop {
-            if let Some((_, val)) = self.move_to_next_dup()? {
-                if !callback(self, &val)? {
-                    return Ok(false);
-                }
-            } else {
-                return Ok(true);
-            }
+impl<'txn> Drop for Cursor<'txn> {
+    fn drop(&mut self) {
+        if !self.cursor.cursor.is_null() {
+            // closing a bound cursor removes it from the transaction
+            let _lock = self.txn.lock();
+            unsafe { ffi::mdbx_cursor_close(self.cursor.cursor) }
+            self.cursor.cursor = ptr::null_mut();
         }
     }
 }
@@ -1,6 +1,7 @@
 use crate::error::Result;
 use crate::mdbx::mdbx_result;
 use crate::mdbx::txn::Txn;
+use crate::storage::{DbStat, StorageDb};
 use std::ffi::CString;
 use std::mem::size_of;
 use std::ptr;
@@ -11,19 +12,20 @@ pub struct Db {
     pub dup: bool,
 }
 
-impl Db {
-    pub fn runtime_id(&self) -> u64 {
+impl<'env> StorageDb<Txn<'env>> for Db {
+    fn runtime_id(&self) -> u64 {
         self.dbi as u64
     }
 
-    pub fn open(
-        txn: &Txn,
+    fn open(
+        txn: &Txn<'env>,
         name: Option<&str>,
         int_key: bool,
         dup: bool,
         int_dup: bool,
     ) -> Result<Self> {
-        let mut flags = ffi::MDBX_CREATE;
+        // read transactions cannot create dbs
+        let mut flags = if txn.write { ffi::MDBX_CREATE } else { 0 };
         if int_key {
             flags |= ffi::MDBX_INTEGERKEY;
         }
@@ -35,6 +37,7 @@ impl Db {
         }
 
         let mut dbi: ffi::MDBX_dbi = 0;
+        let _lock = txn.lock();
         if let Some(name) = name {
             let name = CString::new(name.as_bytes()).unwrap();
             unsafe {
@@ -49,7 +52,7 @@ impl Db {
         Ok(Self { dbi, dup })
     }
 
-    pub fn stat(&self, txn: &Txn) -> Result<(u64, u64)> {
+    fn stat(&self, txn: &Txn<'env>) -> Result<DbStat> {
         let mut stat = ffi::MDBX_stat {
             ms_psize: 0,
             ms_depth: 0,
@@ -60,25 +63,32 @@ impl Db {
             ms_mod_txnid: 0,
         };
         let stat_ptr = &mut stat as *mut ffi::MDBX_stat;
+        // reading the stat of a db loads it into the transaction
+        let _lock = txn.lock();
         unsafe {
-            ffi::mdbx_dbi_stat(
+            mdbx_result(ffi::mdbx_dbi_stat(
                 txn.txn,
                 self.dbi,
                 stat_ptr,
                 size_of::<ffi::MDBX_stat>() as ffi::size_t,
-            );
+            ))?;
         }
-        let size = (stat.ms_branch_pages + stat.ms_leaf_pages + stat.ms_overflow_pages)
-            * stat.ms_psize as u64;
-        Ok((stat.ms_entries, size))
+        Ok(DbStat {
+            entries: stat.ms_entries,
+            depth: stat.ms_depth,
+            branch_pages: stat.ms_branch_pages,
+            leaf_pages: stat.ms_leaf_pages,
+            overflow_pages: stat.ms_overflow_pages,
+            page_size: stat.ms_psize,
+        })
     }
 
-    pub fn clear(&self, txn: &Txn) -> Result<()> {
+    fn clear(&self, txn: &Txn<'env>) -> Result<()> {
         unsafe { mdbx_result(ffi::mdbx_drop(txn.txn, self.dbi, false)) }?;
         Ok(())
     }
 
-    pub fn drop(self, txn: &Txn) -> Result<()> {
+    fn drop(self, txn: &Txn<'env>) -> Result<()> {
         unsafe { mdbx_result(ffi::mdbx_drop(txn.txn, self.dbi, true)) }?;
         Ok(())
     }
@@ -86,6 +96,21 @@ impl Db {
 
 #[cfg(test)]
 mod tests {
+    use crate::mdbx::env::tests::get_env;
+    use crate::storage::{LazyDb, StorageEngine, StorageTxn};
+
+    #[test]
+    fn test_lazy_db_get_in_write_txn() {
+        let env = get_env();
+        let lazy_db = LazyDb::new("lazy", true, false, false);
+        let txn = env.txn(true).unwrap();
+        let db = lazy_db.get(&txn).unwrap();
+        txn.commit().unwrap();
+
+        let txn = env.txn(false).unwrap();
+        assert!(lazy_db.get(&txn).unwrap() == db);
+        txn.abort();
+    }
 
     /*#[test]
     fn test_open() {
@@ -1,11 +1,39 @@
 use crate::error::{IsarError, Result};
+use crate::instance::Durability;
 use crate::mdbx::mdbx_result;
 use crate::mdbx::txn::Txn;
+use crate::storage::{ReaderSlot, StorageEngine};
 use core::ptr;
 use std::ffi::CString;
+use std::fs::{metadata, remove_file};
+
+// the pid and thread id types differ between platforms
+#[allow(clippy::too_many_arguments, clippy::unnecessary_cast)]
+unsafe extern "C" fn reader_list_callback(
+    ctx: *mut libc::c_void,
+    _num: libc::c_int,
+    _slot: libc::c_int,
+    pid: ffi::mdbx_pid_t,
+    thread: ffi::mdbx_tid_t,
+    txnid: u64,
+    lag: u64,
+    _bytes_used: usize,
+    bytes_retained: usize,
+) -> libc::c_int {
+    let readers = &mut *(ctx as *mut Vec<ReaderSlot>);
+    readers.push(ReaderSlot {
+        pid: pid as i32,
+        thread: thread as u64,
+        txn_id: txnid,
+        lag,
+        bytes_retained: bytes_retained as u64,
+    });
+    0
+}
 
 pub struct Env {
     env: *mut ffi::MDBX_env,
+    read_only: bool,
 }
 
 unsafe impl Sync for Env {}
@@ -14,7 +42,21 @@ unsafe impl Send for Env {}
 const MB: isize = 1 << 20;
 
 impl Env {
-    pub fn create(path: &str, max_dbs: u64, relaxed_durability: bool) -> Result<Env> {
+    pub const DEFAULT_MAX_SIZE: isize = 2000 * MB;
+    pub const DEFAULT_GROWTH_STEP: isize = 5 * MB;
+    pub const DEFAULT_SHRINK_THRESHOLD: isize = 20 * MB;
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn create(
+        path: &str,
+        max_dbs: u64,
+        durability: Durability,
+        read_only: bool,
+        multi_process: bool,
+        max_size: isize,
+        growth_step: isize,
+        shrink_threshold: isize,
+    ) -> Result<Env> {
         let path = CString::new(path.as_bytes()).unwrap();
         let mut env: *mut ffi::MDBX_env = ptr::null_mut();
         unsafe {
@@ -25,13 +67,19 @@ impl Env {
                 max_dbs,
             ))?;
 
-            let mut flags = ffi::MDBX_NOTLS
-                | ffi::MDBX_EXCLUSIVE
-                | ffi::MDBX_NOMEMINIT
-                | ffi::MDBX_COALESCE
-                | ffi::MDBX_NOSUBDIR;
-            if relaxed_durability {
-                flags |= ffi::MDBX_NOMETASYNC;
+            let mut flags =
+                ffi::MDBX_NOTLS | ffi::MDBX_NOMEMINIT | ffi::MDBX_COALESCE | ffi::MDBX_NOSUBDIR;
+            if !multi_process {
+                flags |= ffi::MDBX_EXCLUSIVE;
+            }
+            match durability {
+                Durability::Safe => {}
+                Durability::NoMetaSync => flags |= ffi::MDBX_NOMETASYNC,
+                Durability::AsyncFlush => flags |= ffi::MDBX_SAFE_NOSYNC,
+                Durability::NoSync => flags |= ffi::MDBX_UTTERLY_NOSYNC,
+            }
+            if read_only {
+                flags |= ffi::MDBX_RDONLY;
             }
 
             let mut err_code = 0;
@@ -40,9 +88,9 @@ impl Env {
                     env,
                     MB,
                     0,
-                    (2000 - i * 200) * MB,
-                    5 * MB,
-                    20 * MB,
+                    max_size - i * (max_size / 10),
+                    growth_step,
+                    shrink_threshold,
                     -1,
                 ))?;
 
@@ -53,7 +101,7 @@ impl Env {
             }
 
             match err_code {
-                ffi::MDBX_SUCCESS => Ok(Env { env }),
+                ffi::MDBX_SUCCESS => Ok(Env { env, read_only }),
                 ffi::MDBX_EPERM | ffi::MDBX_ENOFILE => Err(IsarError::PathError {}),
                 e => {
                     mdbx_result(e)?;
@@ -63,7 +111,28 @@ impl Env {
         }
     }
 
-    pub fn txn(&self, write: bool) -> Result<Txn> {
+    pub fn exists(path: &str) -> bool {
+        metadata(path).is_ok()
+    }
+
+    pub fn file_size(path: &str) -> Option<u64> {
+        metadata(path).ok().map(|metadata| metadata.len())
+    }
+
+    /// Removes the database file and its lock file.
+    pub fn delete(path: &str) {
+        let _ = remove_file(path);
+        let _ = remove_file(format!("{}.lock", path));
+    }
+}
+
+impl StorageEngine for Env {
+    type Txn<'env> = Txn<'env>;
+
+    fn txn(&self, write: bool) -> Result<Txn<'_>> {
+        if write && self.read_only {
+            return Err(IsarError::ReadOnly {});
+        }
         let flags = if write { 0 } else { ffi::MDBX_TXN_RDONLY };
         let mut txn: *mut ffi::MDBX_txn = ptr::null_mut();
         unsafe {
@@ -78,7 +147,7 @@ impl Env {
         Ok(Txn::new(txn, write))
     }
 
-    pub fn copy(&self, path: &str) -> Result<()> {
+    fn copy(&self, path: &str) -> Result<()> {
         let path = CString::new(path.as_bytes()).unwrap();
         unsafe {
             mdbx_result(ffi::mdbx_env_copy(
@@ -88,6 +157,34 @@ impl Env {
             ))
         }
     }
+
+    fn shrink(&self) -> Result<()> {
+        unsafe { mdbx_result(ffi::mdbx_env_set_geometry(self.env, -1, 0, -1, -1, -1, -1)) }
+    }
+
+    fn sync(&self) -> Result<()> {
+        unsafe { mdbx_result(ffi::mdbx_env_sync_ex(self.env, true, false)) }
+    }
+
+    fn readers(&self) -> Result<Vec<ReaderSlot>> {
+        let mut readers: Vec<ReaderSlot> = vec![];
+        unsafe {
+            mdbx_result(ffi::mdbx_reader_list(
+                self.env,
+                Some(reader_list_callback),
+                &mut readers as *mut _ as *mut libc::c_void,
+            ))?;
+        }
+        Ok(readers)
+    }
+
+    fn check_readers(&self) -> Result<u32> {
+        let mut dead = 0;
+        unsafe {
+            mdbx_result(ffi::mdbx_reader_check(self.env, &mut dead))?;
+        }
+        Ok(dead as u32)
+    }
 }
 
 impl Drop for Env {
@@ -115,6 +212,16 @@ pub mod tests {
         let mut dir = std::env::temp_dir();
         let r: u64 = rand::random();
         dir.push(&r.to_string());
-        Env::create(dir.to_str().unwrap(), 50, false).unwrap()
+        Env::create(
+            dir.to_str().unwrap(),
+            50,
+            Durability::Safe,
+            false,
+            false,
+            Env::DEFAULT_MAX_SIZE,
+            Env::DEFAULT_GROWTH_STEP,
+            Env::DEFAULT_SHRINK_THRESHOLD,
+        )
+        .unwrap()
     }
 }
@@ -3,8 +3,6 @@
 use crate::error::{IsarError, Result};
 use core::slice;
 use libc::c_int;
-use std::borrow::Cow;
-use std::cmp::Ordering;
 use std::ffi::{c_void, CStr};
 
 pub mod cursor;
@@ -12,7 +10,7 @@ pub mod db;
 pub mod env;
 pub mod txn;
 
-pub type KeyVal<'txn> = (&'txn [u8], &'txn [u8]);
+pub use crate::storage::{Key, KeyVal};
 
 pub const EMPTY_KEY: ffi::MDBX_val = ffi::MDBX_val {
     iov_len: 0,
@@ -26,7 +24,11 @@ pub const EMPTY_VAL: ffi::MDBX_val = ffi::MDBX_val {
 
 #[inline]
 pub unsafe fn from_mdb_val<'a>(val: &ffi::MDBX_val) -> &'a [u8] {
-    slice::from_raw_parts(val.iov_base as *const u8, val.iov_len as usize)
+    if val.iov_base.is_null() {
+        &[]
+    } else {
+        slice::from_raw_parts(val.iov_base as *const u8, val.iov_len as usize)
+    }
 }
 
 #[inline]
@@ -52,9 +54,3 @@ pub fn mdbx_result(err_code: c_int) -> Result<()> {
         },
     }
 }
-
-pub trait Key {
-    fn as_bytes(&self) -> Cow<[u8]>;
-
-    fn cmp_bytes(&self, other: &[u8]) -> Ordering;
-}
@@ -1,31 +1,75 @@
 use crate::error::Result;
 use crate::mdbx::mdbx_result;
+use crate::storage::StorageTxn;
 use core::ptr;
 use std::marker::PhantomData;
+use std::sync::{Mutex, MutexGuard};
 
 pub struct Txn<'env> {
     pub(crate) txn: *mut ffi::MDBX_txn,
     pub write: bool,
+    /// Binding and closing cursors and opening dbs modify the transaction. Read transactions
+    /// may be shared between threads so these calls have to be serialized.
+    lock: Mutex<()>,
     _marker: PhantomData<&'env ()>,
 }
 
+// Read transactions are started with MDBX_NOTLS and can be used by several threads. IsarTxn
+// makes sure that write transactions are only used by the thread that started them.
+unsafe impl Sync for Txn<'_> {}
+
 impl<'env> Txn<'env> {
     pub(crate) fn new(txn: *mut ffi::MDBX_txn, write: bool) -> Self {
         Txn {
             txn,
             write,
+            lock: Mutex::new(()),
             _marker: PhantomData::default(),
         }
     }
 
-    pub fn commit(mut self) -> Result<()> {
+    pub(crate) fn lock(&self) -> MutexGuard<'_, ()> {
+        self.lock.lock().unwrap()
+    }
+}
+
+impl<'env> StorageTxn for Txn<'env> {
+    fn id(&self) -> u64 {
+        unsafe { ffi::mdbx_txn_id(self.txn) }
+    }
+
+    fn is_write(&self) -> bool {
+        self.write
+    }
+
+    fn dirty_bytes(&self) -> Result<u64> {
+        let mut info: ffi::MDBX_txn_info = unsafe { std::mem::zeroed() };
+        unsafe { mdbx_result(ffi::mdbx_txn_info(self.txn, &mut info, false))? };
+        Ok(info.txn_space_dirty)
+    }
+
+    fn begin_nested(&self) -> Result<Txn<'env>> {
+        let mut txn: *mut ffi::MDBX_txn = ptr::null_mut();
+        unsafe {
+            mdbx_result(ffi::mdbx_txn_begin_ex(
+                ffi::mdbx_txn_env(self.txn),
+                self.txn,
+                0,
+                &mut txn,
+                ptr::null_mut(),
+            ))?;
+        }
+        Ok(Txn::new(txn, true))
+    }
+
+    fn commit(mut self) -> Result<()> {
         let result = unsafe { mdbx_result(ffi::mdbx_txn_commit_ex(self.txn, ptr::null_mut())) };
         self.txn = ptr::null_mut();
         result?;
         Ok(())
     }
 
-    pub fn abort(self) {}
+    fn abort(self) {}
 }
 
 impl<'a> Drop for Txn<'a> {
@@ -0,0 +1,318 @@
+use crate::error::{illegal_arg, Result};
+use crate::memory::db::Db;
+use crate::memory::txn::Txn;
+use crate::memory::{Entries, Entry, Key, KeyVal};
+use crate::storage::StorageCursor;
+use std::ops::Bound::{Excluded, Unbounded};
+
+pub struct UnboundCursor;
+
+impl UnboundCursor {
+    pub(crate) fn new() -> Self {
+        UnboundCursor
+    }
+
+    pub fn bind<'txn>(self, txn: &'txn Txn, db: Db) -> Result<Cursor<'txn>> {
+        txn.state().entries(db)?;
+        Ok(Cursor {
+            txn,
+            db,
+            position: None,
+        })
+    }
+}
+
+pub struct Cursor<'txn> {
+    txn: &'txn Txn<'txn>,
+    db: Db,
+    position: Option<Entry>,
+}
+
+impl<'txn> Cursor<'txn> {
+    pub fn unbind(self) -> UnboundCursor {
+        UnboundCursor
+    }
+
+    fn entries(&self) -> Result<Entries> {
+        self.txn.state().entries(self.db)
+    }
+
+    fn probe(&self, key: &[u8]) -> Entry {
+        Entry::new(key, &[], self.db)
+    }
+
+    fn move_to_entry(&mut self, entry: Option<&Entry>) -> Option<KeyVal<'txn>> {
+        let entry = entry?;
+        self.position = Some(entry.clone());
+        // the entry is part of the transaction or kept by it after it was removed
+        Some(unsafe { entry.key_val() })
+    }
+}
+
+impl<'txn> StorageCursor<'txn> for Cursor<'txn> {
+    fn move_to<K: Key>(&mut self, key: &K) -> Result<Option<KeyVal<'txn>>> {
+        let key = key.as_bytes();
+        let entries = self.entries()?;
+        let entry = entries
+            .range(&self.probe(&key)..)
+            .next()
+            .filter(|e| e.has_key(&key));
+        Ok(self.move_to_entry(entry))
+    }
+
+    fn move_to_key_val<K: Key>(&mut self, key: &K, val: &[u8]) -> Result<Option<KeyVal<'txn>>> {
+        let entries = self.entries()?;
+        let entry = entries
+            .get(&Entry::new(&key.as_bytes(), val, self.db))
+            .filter(|e| *e.val == *val);
+        Ok(self.move_to_entry(entry))
+    }
+
+    fn move_to_gte<K: Key>(&mut self, key: &K) -> Result<Option<KeyVal<'txn>>> {
+        let entries = self.entries()?;
+        let entry = entries.range(&self.probe(&key.as_bytes())..).next();
+        Ok(self.move_to_entry(entry))
+    }
+
+    fn move_to_next_dup(&mut self) -> Result<Option<KeyVal<'txn>>> {
+        if let Some(position) = self.position.clone() {
+            let entries = self.entries()?;
+            let entry = entries
+                .range((Excluded(&position), Unbounded))
+                .next()
+                .filter(|e| e.has_key(&position.key));
+            Ok(self.move_to_entry(entry))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn move_to_last_dup(&mut self) -> Result<Option<KeyVal<'txn>>> {
+        if let Some(position) = self.position.clone() {
+            let entries = self.entries()?;
+            let entry = entries
+                .range(&self.probe(&position.key)..)
+                .take_while(|e| e.has_key(&position.key))
+                .last();
+            Ok(self.move_to_entry(entry))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn move_to_prev_no_dup(&mut self) -> Result<Option<KeyVal<'txn>>> {
+        self.step(false, true)
+    }
+
+    fn move_to_first(&mut self) -> Result<Option<KeyVal<'txn>>> {
+        let entries = self.entries()?;
+        Ok(self.move_to_entry(entries.first()))
+    }
+
+    fn move_to_last(&mut self) -> Result<Option<KeyVal<'txn>>> {
+        let entries = self.entries()?;
+        Ok(self.move_to_entry(entries.last()))
+    }
+
+    fn step(&mut self, ascending: bool, skip_duplicates: bool) -> Result<Option<KeyVal<'txn>>> {
+        let position = if let Some(position) = self.position.clone() {
+            position
+        } else if ascending {
+            return self.move_to_first();
+        } else {
+            return self.move_to_last();
+        };
+        let entries = self.entries()?;
+        let entry = match (ascending, skip_duplicates) {
+            (true, true) => entries
+                .range((Excluded(&position), Unbounded))
+                .find(|e| !e.has_key(&position.key)),
+            (true, false) => entries.range((Excluded(&position), Unbounded)).next(),
+            (false, true) => entries.range(..&self.probe(&position.key)).next_back(),
+            (false, false) => entries.range(..&position).next_back(),
+        };
+        Ok(self.move_to_entry(entry))
+    }
+
+    fn put<K: Key>(&mut self, key: &K, data: &[u8]) -> Result<()> {
+        let key = key.as_bytes();
+        let entry = Entry::new(&key, data, self.db);
+        let mut state = self.txn.write_state()?;
+        // values of dbs without duplicates are replaced
+        if let Some(existing) = state.entries_mut(self.db)?.replace(entry.clone()) {
+            state.removed.push(existing);
+        }
+        state.dirty_bytes += entry.len() as u64;
+        self.position = Some(entry);
+        Ok(())
+    }
+
+    fn count_dups<K: Key>(&mut self, key: &K) -> Result<usize> {
+        if self.move_to(key)?.is_none() {
+            return Ok(0);
+        }
+        let key = key.as_bytes();
+        let entries = self.entries()?;
+        let count = entries
+            .range(&self.probe(&key)..)
+            .take_while(|e| e.has_key(&key))
+            .count();
+        Ok(count)
+    }
+
+    fn delete_current(&mut self) -> Result<()> {
+        if let Some(position) = &self.position {
+            let mut state = self.txn.write_state()?;
+            if let Some(entry) = state.entries_mut(self.db)?.take(position) {
+                state.removed.push(entry);
+            }
+            // the position is kept so the next entry can be found
+            Ok(())
+        } else {
+            illegal_arg("The cursor has no position.")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::index::index_key::IndexKey;
+    use crate::instance::Durability;
+    use crate::memory::env::Env;
+    use crate::object::id::IdToBytes;
+    use crate::storage::{Db, StorageCursor, StorageDb, StorageEngine, StorageTxn, UnboundCursor};
+
+    fn get_env(path: &str) -> Env {
+        Env::delete(path);
+        Env::create(
+            path,
+            50,
+            Durability::Safe,
+            false,
+            false,
+            Env::DEFAULT_MAX_SIZE,
+            Env::DEFAULT_GROWTH_STEP,
+            Env::DEFAULT_SHRINK_THRESHOLD,
+        )
+        .unwrap()
+    }
+
+    fn key(key: &str) -> IndexKey {
+        IndexKey::from_bytes(key.as_bytes().to_vec())
+    }
+
+    fn collect<'txn>(
+        cursor: &mut impl StorageCursor<'txn>,
+        ascending: bool,
+    ) -> Vec<(&'txn [u8], &'txn [u8])> {
+        let mut entries = vec![];
+        cursor
+            .iter_all(false, ascending, |_, key, val| {
+                entries.push((key, val));
+                Ok(true)
+            })
+            .unwrap();
+        entries
+    }
+
+    #[test]
+    fn test_int_keys_are_sorted_as_numbers() {
+        let env = get_env("int_keys_are_sorted_as_numbers");
+        let txn = env.txn(true).unwrap();
+        let db = Db::open(&txn, Some("test"), true, true, true).unwrap();
+        let mut cursor = UnboundCursor::new().bind(&txn, db).unwrap();
+        for (id, val) in [(256i64, 2i64), (-1, 3), (256, -1), (-1, 300)] {
+            cursor.put(&id, &val.to_id_bytes()).unwrap();
+        }
+
+        let expected: Vec<_> = [(-1i64, 3i64), (-1, 300), (256, -1), (256, 2)]
+            .iter()
+            .map(|(id, val)| (id.to_id_bytes(), val.to_id_bytes()))
+            .collect();
+        let expected: Vec<_> = expected.iter().map(|(k, v)| (&k[..], &v[..])).collect();
+        assert_eq!(collect(&mut cursor, true), expected);
+        let reversed: Vec<_> = expected.iter().rev().copied().collect();
+        assert_eq!(collect(&mut cursor, false), reversed);
+        assert_eq!(cursor.count_dups(&256i64).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_delete_while_iterating() {
+        let env = get_env("delete_while_iterating");
+        let txn = env.txn(true).unwrap();
+        let db = Db::open(&txn, Some("test"), false, false, false).unwrap();
+        let mut cursor = UnboundCursor::new().bind(&txn, db).unwrap();
+        for k in ["a", "b", "c", "d"] {
+            cursor.put(&key(k), b"val").unwrap();
+        }
+        cursor.put(&key("b"), b"new").unwrap();
+        assert_eq!(
+            cursor.move_to(&key("b")).unwrap(),
+            Some((&b"b"[..], &b"new"[..]))
+        );
+
+        cursor
+            .iter_all(false, true, |cursor, key, _| {
+                if key != b"c" {
+                    cursor.delete_current()?;
+                }
+                Ok(true)
+            })
+            .unwrap();
+        assert_eq!(collect(&mut cursor, true), vec![(&b"c"[..], &b"val"[..])]);
+        txn.commit().unwrap();
+    }
+
+    #[test]
+    fn test_put_while_iterating_visits_entries_once() {
+        let env = get_env("put_while_iterating_visits_entries_once");
+        let txn = env.txn(true).unwrap();
+        let db = Db::open(&txn, Some("test"), false, false, false).unwrap();
+        let mut cursor = UnboundCursor::new().bind(&txn, db).unwrap();
+        let mut write_cursor = UnboundCursor::new().bind(&txn, db).unwrap();
+        for k in ["a", "b"] {
+            cursor.put(&key(k), b"1").unwrap();
+        }
+
+        let mut visited = 0;
+        cursor
+            .iter_all(false, true, |_, key, val| {
+                visited += 1;
+                let mut val = val.to_vec();
+                val.push(b'0');
+                write_cursor.put(&IndexKey::from_bytes(key.to_vec()), &val)?;
+                Ok(true)
+            })
+            .unwrap();
+        assert_eq!(visited, 2);
+        assert_eq!(
+            collect(&mut cursor, true),
+            vec![(&b"a"[..], &b"10"[..]), (&b"b"[..], &b"10"[..])]
+        );
+    }
+
+    #[test]
+    fn test_read_txn_keeps_snapshot() {
+        let env = get_env("read_txn_keeps_snapshot");
+        let txn = env.txn(true).unwrap();
+        let db = Db::open(&txn, Some("test"), false, false, false).unwrap();
+        let mut cursor = UnboundCursor::new().bind(&txn, db).unwrap();
+        cursor.put(&key("a"), b"1").unwrap();
+        txn.commit().unwrap();
+
+        let read_txn = env.txn(false).unwrap();
+        let txn = env.txn(true).unwrap();
+        let mut cursor = UnboundCursor::new().bind(&txn, db).unwrap();
+        cursor.put(&key("a"), b"2").unwrap();
+        let nested = txn.begin_nested().unwrap();
+        db.clear(&nested).unwrap();
+        nested.abort();
+        txn.commit().unwrap();
+
+        let mut cursor = UnboundCursor::new().bind(&read_txn, db).unwrap();
+        assert_eq!(collect(&mut cursor, true), vec![(&b"a"[..], &b"1"[..])]);
+        let txn = env.txn(false).unwrap();
+        let mut cursor = UnboundCursor::new().bind(&txn, db).unwrap();
+        assert_eq!(collect(&mut cursor, true), vec![(&b"a"[..], &b"2"[..])]);
+    }
+}
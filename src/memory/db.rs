@@ -0,0 +1,115 @@
+use crate::error::Result;
+use crate::memory::env::DbInfo;
+use crate::memory::txn::Txn;
+use crate::memory::{db_not_found, Entries, Entry};
+use crate::storage::{DbStat, StorageDb};
+
+const UNNAMED_DB: Db = Db {
+    dbi: 0,
+    dup: false,
+    int_key: false,
+    int_dup: false,
+};
+
+const PAGE_SIZE: u32 = 4096;
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub struct Db {
+    pub(crate) dbi: u32,
+    pub dup: bool,
+    pub(crate) int_key: bool,
+    pub(crate) int_dup: bool,
+}
+
+impl<'env> StorageDb<Txn<'env>> for Db {
+    fn runtime_id(&self) -> u64 {
+        self.dbi as u64
+    }
+
+    fn open(
+        txn: &Txn<'env>,
+        name: Option<&str>,
+        int_key: bool,
+        dup: bool,
+        int_dup: bool,
+    ) -> Result<Self> {
+        let mut dbs = txn.env.state.dbs.lock().unwrap();
+        let mut state = txn.state();
+        let dbi = dbs.iter().position(|db| db.name.as_deref() == name);
+        let exists = dbi.is_some_and(|dbi| state.dbs.get(dbi).is_some_and(Option::is_some));
+        let dbi = if exists {
+            dbi.unwrap()
+        } else if txn.write {
+            // read transactions cannot create dbs
+            let info = DbInfo {
+                name: name.map(str::to_string),
+                int_key,
+                dup,
+                int_dup,
+            };
+            let dbi = if let Some(dbi) = dbi {
+                dbs[dbi] = info;
+                dbi
+            } else {
+                dbs.push(info);
+                dbs.len() - 1
+            };
+            if state.dbs.len() <= dbi {
+                state.dbs.resize(dbi + 1, None);
+            }
+            state.dbs[dbi] = Some(Entries::default());
+            if let Some(name) = name {
+                let entry = Entry::new(name.as_bytes(), &[], UNNAMED_DB);
+                state.entries_mut(UNNAMED_DB)?.insert(entry);
+            }
+            dbi
+        } else {
+            return db_not_found();
+        };
+
+        let info = &dbs[dbi];
+        Ok(Db {
+            dbi: dbi as u32,
+            dup: info.dup,
+            int_key: info.int_key,
+            int_dup: info.dup && info.int_dup,
+        })
+    }
+
+    fn stat(&self, txn: &Txn<'env>) -> Result<DbStat> {
+        let entries = txn.state().entries(*self)?;
+        let bytes: usize = entries.iter().map(Entry::len).sum();
+        Ok(DbStat {
+            entries: entries.len() as u64,
+            depth: 1,
+            branch_pages: 0,
+            leaf_pages: (bytes as u64).div_ceil(PAGE_SIZE as u64),
+            overflow_pages: 0,
+            page_size: PAGE_SIZE,
+        })
+    }
+
+    fn clear(&self, txn: &Txn<'env>) -> Result<()> {
+        let mut state = txn.write_state()?;
+        let entries = std::mem::take(state.entries_mut(*self)?);
+        state.removed.extend(entries);
+        Ok(())
+    }
+
+    fn drop(self, txn: &Txn<'env>) -> Result<()> {
+        self.clear(txn)?;
+        let name = txn.env.state.dbs.lock().unwrap()[self.dbi as usize]
+            .name
+            .clone();
+        let mut state = txn.write_state()?;
+        state.dbs[self.dbi as usize] = None;
+        if let Some(name) = name {
+            let entry = Entry::new(name.as_bytes(), &[], UNNAMED_DB);
+            let unnamed_db = state.entries_mut(UNNAMED_DB)?;
+            if let Some(entry) = unnamed_db.take(&entry) {
+                state.removed.push(entry);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+use crate::error::{illegal_arg, IsarError, Result};
+use crate::instance::Durability;
+use crate::memory::txn::Txn;
+use crate::memory::Entries;
+use crate::storage::{ReaderSlot, StorageEngine};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+static ENVS: Lazy<Mutex<HashMap<String, Arc<EnvState>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+pub(crate) struct DbInfo {
+    pub name: Option<String>,
+    pub int_key: bool,
+    pub dup: bool,
+    pub int_dup: bool,
+}
+
+/// The last committed state of the dbs.
+#[derive(Clone)]
+pub(crate) struct Snapshot {
+    pub id: u64,
+    pub dbs: Vec<Option<Entries>>,
+}
+
+/// The dbs of a path. They are kept until the path is deleted so an environment can be opened
+/// again in the same process.
+pub(crate) struct EnvState {
+    /// The index is the runtime id of the db. The first db is the unnamed db that contains the
+    /// names of the other dbs.
+    pub dbs: Mutex<Vec<DbInfo>>,
+    pub committed: Mutex<Snapshot>,
+    write_lock: Mutex<()>,
+}
+
+/// An environment that keeps all dbs in memory. It is used if the mdbx feature is disabled and
+/// nothing is persisted.
+pub struct Env {
+    read_only: bool,
+    pub(crate) state: Arc<EnvState>,
+}
+
+const MB: isize = 1 << 20;
+
+impl Env {
+    pub const DEFAULT_MAX_SIZE: isize = 2000 * MB;
+    pub const DEFAULT_GROWTH_STEP: isize = 5 * MB;
+    pub const DEFAULT_SHRINK_THRESHOLD: isize = 20 * MB;
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn create(
+        path: &str,
+        _max_dbs: u64,
+        _durability: Durability,
+        read_only: bool,
+        _multi_process: bool,
+        _max_size: isize,
+        _growth_step: isize,
+        _shrink_threshold: isize,
+    ) -> Result<Env> {
+        let mut envs = ENVS.lock().unwrap();
+        let state = if let Some(state) = envs.get(path) {
+            state.clone()
+        } else if read_only {
+            return Err(IsarError::PathError {});
+        } else {
+            let unnamed_db = DbInfo {
+                name: None,
+                int_key: false,
+                dup: false,
+                int_dup: false,
+            };
+            let state = Arc::new(EnvState {
+                dbs: Mutex::new(vec![unnamed_db]),
+                committed: Mutex::new(Snapshot {
+                    id: 1,
+                    dbs: vec![Some(Entries::default())],
+                }),
+                write_lock: Mutex::new(()),
+            });
+            envs.insert(path.to_string(), state.clone());
+            state
+        };
+        Ok(Env { read_only, state })
+    }
+
+    pub fn exists(path: &str) -> bool {
+        ENVS.lock().unwrap().contains_key(path)
+    }
+
+    /// The size of the committed keys and values.
+    pub fn file_size(path: &str) -> Option<u64> {
+        let state = ENVS.lock().unwrap().get(path)?.clone();
+        let committed = state.committed.lock().unwrap();
+        let bytes = committed
+            .dbs
+            .iter()
+            .flatten()
+            .flat_map(|entries| entries.iter())
+            .map(|entry| entry.len() as u64)
+            .sum();
+        Some(bytes)
+    }
+
+    /// Open environments keep their dbs until they are dropped.
+    pub fn delete(path: &str) {
+        ENVS.lock().unwrap().remove(path);
+    }
+}
+
+impl StorageEngine for Env {
+    type Txn<'env> = Txn<'env>;
+
+    fn txn(&self, write: bool) -> Result<Txn<'_>> {
+        if write && self.read_only {
+            return Err(IsarError::ReadOnly {});
+        }
+        // only one write transaction can be active at a time
+        let write_lock = if write {
+            Some(self.state.write_lock.lock().unwrap())
+        } else {
+            None
+        };
+        let snapshot = self.state.committed.lock().unwrap().clone();
+        Ok(Txn::new(self, snapshot, write_lock))
+    }
+
+    fn copy(&self, _path: &str) -> Result<()> {
+        illegal_arg("In-memory dbs cannot be copied.")
+    }
+
+    fn shrink(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn sync(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn readers(&self) -> Result<Vec<ReaderSlot>> {
+        Ok(vec![])
+    }
+
+    fn check_readers(&self) -> Result<u32> {
+        Ok(0)
+    }
+}
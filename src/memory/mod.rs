@@ -0,0 +1,93 @@
+use crate::error::{illegal_arg, Result};
+use crate::memory::db::Db;
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+pub mod cursor;
+pub mod db;
+pub mod env;
+pub mod txn;
+
+pub use crate::storage::{Key, KeyVal};
+
+/// The entries of a db sorted by key and value. Transactions share unchanged dbs.
+pub(crate) type Entries = Arc<BTreeSet<Entry>>;
+
+/// A key value pair. Integer keys and values are little endian like in MDBX and are sorted as
+/// numbers. Values are only compared in dbs with duplicate keys.
+#[derive(Clone)]
+pub(crate) struct Entry {
+    key: Arc<[u8]>,
+    val: Arc<[u8]>,
+    int_key: bool,
+    dup: bool,
+    int_val: bool,
+}
+
+impl Entry {
+    fn new(key: &[u8], val: &[u8], db: Db) -> Self {
+        Entry {
+            key: key.into(),
+            val: val.into(),
+            int_key: db.int_key,
+            dup: db.dup,
+            int_val: db.int_dup,
+        }
+    }
+
+    fn has_key(&self, key: &[u8]) -> bool {
+        *self.key == *key
+    }
+
+    fn len(&self) -> usize {
+        self.key.len() + self.val.len()
+    }
+
+    /// The caller has to keep the entry alive for `'txn`. Transactions keep removed entries until
+    /// they end.
+    unsafe fn key_val<'txn>(&self) -> KeyVal<'txn> {
+        let key: *const [u8] = &*self.key;
+        let val: *const [u8] = &*self.val;
+        (&*key, &*val)
+    }
+}
+
+fn cmp_bytes(a: &[u8], b: &[u8], int: bool) -> Ordering {
+    if int {
+        a.len()
+            .cmp(&b.len())
+            .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+    } else {
+        a.cmp(b)
+    }
+}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let ordering = cmp_bytes(&self.key, &other.key, self.int_key);
+        if self.dup {
+            ordering.then_with(|| cmp_bytes(&self.val, &other.val, self.int_val))
+        } else {
+            ordering
+        }
+    }
+}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Entry {}
+
+fn db_not_found<T>() -> Result<T> {
+    illegal_arg("The db does not exist.")
+}
@@ -0,0 +1,121 @@
+use crate::error::{IsarError, Result};
+use crate::memory::db::Db;
+use crate::memory::env::{Env, Snapshot};
+use crate::memory::{db_not_found, Entries, Entry};
+use crate::storage::StorageTxn;
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+pub(crate) struct TxnState {
+    pub dbs: Vec<Option<Entries>>,
+    /// Returned keys and values point into the entries so removed entries are kept until the
+    /// transaction ends.
+    pub removed: Vec<Entry>,
+    pub dirty_bytes: u64,
+}
+
+impl TxnState {
+    pub fn entries(&self, db: Db) -> Result<Entries> {
+        match self.dbs.get(db.dbi as usize) {
+            Some(Some(entries)) => Ok(entries.clone()),
+            _ => db_not_found(),
+        }
+    }
+
+    pub fn entries_mut(&mut self, db: Db) -> Result<&mut BTreeSet<Entry>> {
+        match self.dbs.get_mut(db.dbi as usize) {
+            Some(Some(entries)) => Ok(Arc::make_mut(entries)),
+            _ => db_not_found(),
+        }
+    }
+}
+
+pub struct Txn<'env> {
+    pub(crate) env: &'env Env,
+    pub write: bool,
+    id: u64,
+    pub(crate) state: Arc<Mutex<TxnState>>,
+    parent: Option<Arc<Mutex<TxnState>>>,
+    _write_lock: Option<MutexGuard<'env, ()>>,
+}
+
+impl<'env> Txn<'env> {
+    pub(crate) fn new(
+        env: &'env Env,
+        snapshot: Snapshot,
+        write_lock: Option<MutexGuard<'env, ()>>,
+    ) -> Self {
+        let write = write_lock.is_some();
+        Txn {
+            env,
+            write,
+            id: if write { snapshot.id + 1 } else { snapshot.id },
+            state: Arc::new(Mutex::new(TxnState {
+                dbs: snapshot.dbs,
+                removed: vec![],
+                dirty_bytes: 0,
+            })),
+            parent: None,
+            _write_lock: write_lock,
+        }
+    }
+
+    pub(crate) fn state(&self) -> MutexGuard<'_, TxnState> {
+        self.state.lock().unwrap()
+    }
+
+    pub(crate) fn write_state(&self) -> Result<MutexGuard<'_, TxnState>> {
+        if self.write {
+            Ok(self.state())
+        } else {
+            Err(IsarError::WriteTxnRequired {})
+        }
+    }
+}
+
+impl<'env> StorageTxn for Txn<'env> {
+    fn id(&self) -> u64 {
+        self.id
+    }
+
+    fn is_write(&self) -> bool {
+        self.write
+    }
+
+    fn dirty_bytes(&self) -> Result<u64> {
+        Ok(self.state().dirty_bytes)
+    }
+
+    fn begin_nested(&self) -> Result<Txn<'env>> {
+        let state = self.write_state()?;
+        Ok(Txn {
+            env: self.env,
+            write: true,
+            id: self.id,
+            state: Arc::new(Mutex::new(TxnState {
+                dbs: state.dbs.clone(),
+                removed: vec![],
+                dirty_bytes: 0,
+            })),
+            parent: Some(self.state.clone()),
+            _write_lock: None,
+        })
+    }
+
+    fn commit(self) -> Result<()> {
+        let mut state = self.state();
+        if let Some(parent) = &self.parent {
+            let mut parent = parent.lock().unwrap();
+            parent.dbs = std::mem::take(&mut state.dbs);
+            parent.removed.append(&mut state.removed);
+            parent.dirty_bytes += state.dirty_bytes;
+        } else if self.write {
+            let mut committed = self.env.state.committed.lock().unwrap();
+            committed.id = self.id;
+            committed.dbs = std::mem::take(&mut state.dbs);
+        }
+        Ok(())
+    }
+
+    fn abort(self) {}
+}
@@ -0,0 +1,134 @@
+use intmap::IntMap;
+use once_cell::sync::Lazy;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+static BUFFER_POOLS: Lazy<RwLock<IntMap<Arc<BufferPool>>>> =
+    Lazy::new(|| RwLock::new(IntMap::new()));
+
+/// Reuses object buffers of bulk operations. Buffers are grouped in power of two size classes and
+/// every class keeps at most `max_per_class` buffers.
+pub struct BufferPool {
+    classes: Mutex<Vec<Vec<Vec<u8>>>>,
+    max_per_class: AtomicUsize,
+}
+
+impl BufferPool {
+    const MIN_CLASS: u32 = 6;
+    const MAX_CLASS: u32 = 24;
+    pub const DEFAULT_MAX_PER_CLASS: usize = 8;
+
+    pub fn new(max_per_class: usize) -> Self {
+        let classes = (Self::MIN_CLASS..=Self::MAX_CLASS)
+            .map(|_| vec![])
+            .collect();
+        BufferPool {
+            classes: Mutex::new(classes),
+            max_per_class: AtomicUsize::new(max_per_class),
+        }
+    }
+
+    /// Changes the limit and drops buffers that exceed it.
+    pub fn set_max_per_class(&self, max_per_class: usize) {
+        self.max_per_class.store(max_per_class, Ordering::Relaxed);
+        for class in self.classes.lock().unwrap().iter_mut() {
+            class.truncate(max_per_class);
+        }
+    }
+
+    /// Returns an empty buffer with a capacity of at least `capacity` bytes.
+    pub fn get(&self, capacity: usize) -> Vec<u8> {
+        let class = capacity
+            .next_power_of_two()
+            .trailing_zeros()
+            .max(Self::MIN_CLASS);
+        if class > Self::MAX_CLASS {
+            return Vec::with_capacity(capacity);
+        }
+        let index = (class - Self::MIN_CLASS) as usize;
+        if let Some(buffer) = self.classes.lock().unwrap()[index].pop() {
+            buffer
+        } else {
+            Vec::with_capacity(1 << class)
+        }
+    }
+
+    pub fn put(&self, mut buffer: Vec<u8>) {
+        let capacity = buffer.capacity();
+        if capacity < 1 << Self::MIN_CLASS {
+            return;
+        }
+        // the largest class the buffer can serve
+        let class = (usize::BITS - 1 - capacity.leading_zeros()).min(Self::MAX_CLASS);
+        let index = (class - Self::MIN_CLASS) as usize;
+        let mut classes = self.classes.lock().unwrap();
+        if classes[index].len() < self.max_per_class.load(Ordering::Relaxed) {
+            buffer.clear();
+            classes[index].push(buffer);
+        }
+    }
+
+    /// Number of buffers that are currently pooled.
+    pub fn pooled(&self) -> usize {
+        self.classes.lock().unwrap().iter().map(|c| c.len()).sum()
+    }
+}
+
+pub(crate) fn get_buffer_pool(instance_id: u64) -> Arc<BufferPool> {
+    if let Some(pool) = BUFFER_POOLS.read().unwrap().get(instance_id) {
+        return pool.clone();
+    }
+    let mut lock = BUFFER_POOLS.write().unwrap();
+    if let Some(pool) = lock.get(instance_id) {
+        pool.clone()
+    } else {
+        let pool = Arc::new(BufferPool::new(BufferPool::DEFAULT_MAX_PER_CLASS));
+        lock.insert(instance_id, pool.clone());
+        pool
+    }
+}
+
+pub(crate) fn remove_buffer_pool(instance_id: u64) {
+    BUFFER_POOLS.write().unwrap().remove(instance_id);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reuse_by_size_class() {
+        let pool = BufferPool::new(2);
+        let mut buffer = pool.get(100);
+        assert!(buffer.capacity() >= 128);
+        buffer.extend_from_slice(&[1, 2, 3]);
+        let ptr = buffer.as_ptr();
+        pool.put(buffer);
+        assert_eq!(pool.pooled(), 1);
+
+        // a larger class is not served by the smaller buffer
+        assert!(pool.get(200).capacity() >= 256);
+        let buffer = pool.get(70);
+        assert_eq!(buffer.as_ptr(), ptr);
+        assert!(buffer.is_empty());
+        assert_eq!(pool.pooled(), 0);
+
+        pool.put(Vec::with_capacity(10));
+        assert_eq!(pool.pooled(), 0);
+    }
+
+    #[test]
+    fn test_max_per_class() {
+        let pool = BufferPool::new(2);
+        for _ in 0..3 {
+            pool.put(Vec::with_capacity(64));
+        }
+        pool.put(Vec::with_capacity(1024));
+        assert_eq!(pool.pooled(), 3);
+
+        pool.set_max_per_class(1);
+        assert_eq!(pool.pooled(), 2);
+        pool.put(Vec::with_capacity(64));
+        assert_eq!(pool.pooled(), 2);
+    }
+}
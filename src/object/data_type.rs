@@ -20,6 +20,8 @@ pub enum DataType {
     DoubleList,
     StringList,
     ObjectList,
+    /// Stored in chunks outside of the object. Blob properties take no space in the objects.
+    Blob,
 }
 
 impl DataType {
@@ -44,6 +46,7 @@ impl DataType {
             DataType::Bool | DataType::Byte => 1,
             DataType::Int | DataType::Float => 4,
             DataType::Long | DataType::Double => 8,
+            DataType::Blob => 0,
             _ => 3,
         }
     }
@@ -52,6 +55,16 @@ impl DataType {
         self.get_element_type().is_none()
     }
 
+    pub fn can_widen_to(&self, other: DataType) -> bool {
+        matches!(
+            (self, other),
+            (DataType::Byte, DataType::Int)
+                | (DataType::Byte, DataType::Long)
+                | (DataType::Int, DataType::Long)
+                | (DataType::Float, DataType::Double)
+        )
+    }
+
     pub fn get_element_type(&self) -> Option<DataType> {
         match self {
             DataType::BoolList => Some(DataType::Bool),
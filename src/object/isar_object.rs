@@ -1,7 +1,11 @@
+use crate::error::{IsarError, Result};
 use crate::object::data_type::DataType;
 use crate::object::object_builder::ObjectBuilder;
+use crate::object::property::Property;
 use byteorder::{ByteOrder, LittleEndian};
-use std::{cmp::Ordering, str::from_utf8_unchecked};
+use intmap::IntMap;
+use std::cmp::Ordering;
+use std::str::{from_utf8, from_utf8_unchecked};
 use xxhash_rust::xxh3::xxh3_64_with_seed;
 
 #[derive(Copy, Clone, Eq, PartialEq)]
@@ -124,6 +128,127 @@ impl<'a> IsarObject<'a> {
         None
     }
 
+    /// Number of bytes used by the property including its dynamic data. Embedded objects and
+    /// list items are included in the size.
+    pub fn property_size(&self, property: &Property) -> usize {
+        let data_type = property.data_type;
+        if !self.contains_offset(property.offset) {
+            return 0;
+        }
+        let static_size = data_type.get_static_size();
+        if data_type.is_static() {
+            return static_size;
+        }
+
+        let dynamic_size = if let Some((offset, length)) = self.get_offset_length(property.offset) {
+            let data_size = match data_type {
+                DataType::StringList | DataType::ObjectList => {
+                    let items_size: usize = (0..length)
+                        .map(|i| self.read_u24(offset + i * 3).saturating_sub(1))
+                        .sum();
+                    length * 3 + items_size
+                }
+                DataType::String | DataType::Object => length,
+                _ => length * data_type.get_element_type().unwrap().get_static_size(),
+            };
+            3 + data_size
+        } else {
+            0
+        };
+        static_size + dynamic_size
+    }
+
+    /// Checks that all dynamic data is in bounds and that strings are valid UTF-8.
+    pub fn is_valid(
+        &self,
+        properties: &[Property],
+        embedded_properties: &IntMap<Vec<Property>>,
+    ) -> bool {
+        if self.bytes.len() < 2 || self.static_size > self.bytes.len() {
+            return false;
+        }
+        properties
+            .iter()
+            .all(|p| self.is_property_valid(p, embedded_properties))
+    }
+
+    fn is_property_valid(
+        &self,
+        property: &Property,
+        embedded_properties: &IntMap<Vec<Property>>,
+    ) -> bool {
+        let offset = property.offset;
+        let data_type = property.data_type;
+        if !self.contains_offset(offset) {
+            return true;
+        }
+        if offset + data_type.get_static_size() > self.static_size {
+            return false;
+        }
+        if data_type.is_static() {
+            return true;
+        }
+
+        let length_offset = self.read_u24(offset);
+        if length_offset == 0 {
+            return true;
+        } else if length_offset + 3 > self.bytes.len() {
+            return false;
+        }
+        let offset = length_offset + 3;
+        let length = self.read_u24(length_offset);
+        let in_bounds = |size: usize| offset + size <= self.bytes.len();
+        let is_object_valid = |bytes: &[u8]| {
+            let properties = property
+                .target_id
+                .and_then(|id| embedded_properties.get(id));
+            bytes.len() >= 2
+                && properties.is_some_and(|properties| {
+                    IsarObject::from_bytes(bytes).is_valid(properties, embedded_properties)
+                })
+        };
+
+        match data_type {
+            DataType::String => {
+                in_bounds(length) && from_utf8(&self.bytes[offset..offset + length]).is_ok()
+            }
+            DataType::Object => {
+                in_bounds(length) && is_object_valid(&self.bytes[offset..offset + length])
+            }
+            DataType::BoolList | DataType::ByteList => in_bounds(length),
+            DataType::IntList | DataType::FloatList => in_bounds(length * 4),
+            DataType::LongList | DataType::DoubleList => in_bounds(length * 8),
+            DataType::StringList | DataType::ObjectList => {
+                if !in_bounds(length * 3) {
+                    return false;
+                }
+                let mut content_offset = offset + length * 3;
+                for i in 0..length {
+                    let item_size = self.read_u24(offset + i * 3);
+                    if item_size == 0 {
+                        continue;
+                    }
+                    let item_size = item_size - 1;
+                    if content_offset + item_size > self.bytes.len() {
+                        return false;
+                    }
+                    let bytes = &self.bytes[content_offset..content_offset + item_size];
+                    let valid = if data_type == DataType::StringList {
+                        from_utf8(bytes).is_ok()
+                    } else {
+                        is_object_valid(bytes)
+                    };
+                    if !valid {
+                        return false;
+                    }
+                    content_offset += item_size;
+                }
+                true
+            }
+            _ => true,
+        }
+    }
+
     pub fn read_length(&self, offset: usize) -> Option<usize> {
         let (_, length) = self.get_offset_length(offset)?;
         Some(length)
@@ -134,13 +259,25 @@ impl<'a> IsarObject<'a> {
         Some(&self.bytes[offset..offset + length])
     }
 
-    pub fn read_string(&'a self, offset: usize) -> Option<&'a str> {
+    pub fn read_string(&self, offset: usize) -> Option<&'a str> {
         let bytes = self.read_byte_list(offset)?;
         let str = unsafe { from_utf8_unchecked(bytes) };
         Some(str)
     }
 
-    pub fn read_object(&'a self, offset: usize) -> Option<IsarObject> {
+    /// Like `read_string` but fails with `DbCorrupted` if the string is not valid UTF-8.
+    pub fn read_string_checked(&self, offset: usize) -> Result<Option<&'a str>> {
+        if let Some(bytes) = self.read_byte_list(offset) {
+            let str = from_utf8(bytes).map_err(|_| IsarError::DbCorrupted {
+                message: "Invalid UTF-8 string.".to_string(),
+            })?;
+            Ok(Some(str))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub fn read_object(&self, offset: usize) -> Option<IsarObject<'a>> {
         let bytes = self.read_byte_list(offset)?;
         Some(IsarObject::from_bytes(bytes))
     }
@@ -406,6 +543,99 @@ impl<'a> IsarObject<'a> {
             _ => Ordering::Equal,
         }
     }
+
+    /// Like `compare_property` but strings are compared ignoring case first. Case variants are
+    /// ordered by their original value.
+    pub fn compare_property_case_insensitive(
+        &self,
+        other: &IsarObject,
+        offset: usize,
+        data_type: DataType,
+    ) -> Ordering {
+        if data_type == DataType::String {
+            let s1 = self.read_string(offset).map(|s| s.to_lowercase());
+            let s2 = other.read_string(offset).map(|s| s.to_lowercase());
+            let ord = s1.cmp(&s2);
+            if ord != Ordering::Equal {
+                return ord;
+            }
+        }
+        self.compare_property(other, offset, data_type)
+    }
+
+    fn float_eq(f1: f32, f2: f32) -> bool {
+        f1 == f2 || (f1.is_nan() && f2.is_nan())
+    }
+
+    fn double_eq(f1: f64, f2: f64) -> bool {
+        f1 == f2 || (f1.is_nan() && f2.is_nan())
+    }
+
+    fn list_eq<T>(l1: Option<Vec<T>>, l2: Option<Vec<T>>, eq: impl Fn(&T, &T) -> bool) -> bool {
+        match (l1, l2) {
+            (Some(l1), Some(l2)) => {
+                l1.len() == l2.len() && l1.iter().zip(&l2).all(|(a, b)| eq(a, b))
+            }
+            (l1, l2) => l1.is_none() && l2.is_none(),
+        }
+    }
+
+    /// Compares the values of a property. All NaN values are equal. Embedded objects are compared
+    /// by their bytes.
+    pub fn property_eq(&self, other: &IsarObject, property: &Property) -> bool {
+        let offset = property.offset;
+        match property.data_type {
+            DataType::Bool | DataType::Byte => self.read_byte(offset) == other.read_byte(offset),
+            DataType::Int => self.read_int(offset) == other.read_int(offset),
+            DataType::Float => Self::float_eq(self.read_float(offset), other.read_float(offset)),
+            DataType::Long => self.read_long(offset) == other.read_long(offset),
+            DataType::Double => {
+                Self::double_eq(self.read_double(offset), other.read_double(offset))
+            }
+            DataType::String => self.read_string(offset) == other.read_string(offset),
+            DataType::Object => {
+                self.read_object(offset).map(|o| o.as_bytes())
+                    == other.read_object(offset).map(|o| o.as_bytes())
+            }
+            DataType::BoolList => self.read_bool_list(offset) == other.read_bool_list(offset),
+            DataType::ByteList => self.read_byte_list(offset) == other.read_byte_list(offset),
+            DataType::IntList => self.read_int_list(offset) == other.read_int_list(offset),
+            DataType::FloatList => Self::list_eq(
+                self.read_float_list(offset),
+                other.read_float_list(offset),
+                |f1, f2| Self::float_eq(*f1, *f2),
+            ),
+            DataType::LongList => self.read_long_list(offset) == other.read_long_list(offset),
+            DataType::DoubleList => Self::list_eq(
+                self.read_double_list(offset),
+                other.read_double_list(offset),
+                |f1, f2| Self::double_eq(*f1, *f2),
+            ),
+            DataType::StringList => self.read_string_list(offset) == other.read_string_list(offset),
+            DataType::ObjectList => Self::list_eq(
+                self.read_object_list(offset),
+                other.read_object_list(offset),
+                |o1, o2| o1.map(|o| o.as_bytes()) == o2.map(|o| o.as_bytes()),
+            ),
+            // blobs are not stored in the objects
+            DataType::Blob => true,
+        }
+    }
+
+    /// Whether all given properties have equal values. Unlike comparing the bytes this ignores
+    /// the layout of the dynamic data.
+    pub fn eq_semantic(&self, other: &IsarObject, properties: &[Property]) -> bool {
+        properties.iter().all(|p| self.property_eq(other, p))
+    }
+
+    /// Names of the given properties whose values differ.
+    pub fn diff(&self, other: &IsarObject, properties: &[Property]) -> Vec<String> {
+        properties
+            .iter()
+            .filter(|p| !self.property_eq(other, p))
+            .map(|p| p.name.clone())
+            .collect()
+    }
 }
 
 #[cfg(test)]
@@ -689,4 +919,60 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_eq_semantic() {
+        let props = vec![
+            Property::new("a", Double, 2, None),
+            Property::new("b", String, 10, None),
+            Property::new("c", FloatList, 13, None),
+        ];
+        let mut b1 = ObjectBuilder::new(&props, None);
+        b1.write_double(2, f64::NAN);
+        b1.write_string(10, Some("abc"));
+        b1.write_float_list(13, Some(&[1.0, f32::NAN]));
+        let mut b2 = ObjectBuilder::new(&props, None);
+        b2.write_double(2, f64::NAN);
+        b2.write_float_list(13, Some(&[1.0, f32::NAN]));
+        b2.write_string(10, Some("abc"));
+
+        let (o1, o2) = (b1.finish(), b2.finish());
+        assert_ne!(o1.as_bytes(), o2.as_bytes());
+        assert!(o1.eq_semantic(&o2, &props));
+        assert!(o1.diff(&o2, &props).is_empty());
+
+        let mut b3 = ObjectBuilder::new(&props, None);
+        b3.write_double(2, 1.0);
+        b3.write_string(10, Some("abc"));
+        b3.write_float_list(13, None);
+        let o3 = b3.finish();
+        assert!(!o1.eq_semantic(&o3, &props));
+        assert_eq!(o1.diff(&o3, &props), vec!["a", "c"]);
+    }
+
+    #[test]
+    fn test_property_size() {
+        builder!(b, p, Long);
+        b.write_long(p.offset, 123);
+        assert_eq!(b.finish().property_size(&p), 8);
+
+        builder!(b, p, String);
+        b.write_string(p.offset, None);
+        assert_eq!(b.finish().property_size(&p), 3);
+
+        // the only property uses everything but the static size header
+        let strings: &[Option<&str>] = &[Some("a"), None, Some("bcd")];
+        builder!(b, p, StringList);
+        b.write_string_list(p.offset, Some(strings));
+        let object = b.finish();
+        assert_eq!(object.property_size(&p), object.len() - 2);
+
+        builder!(b, p, String);
+        b.write_string(p.offset, Some("hello"));
+        let object = b.finish();
+        assert_eq!(object.property_size(&p), object.len() - 2);
+
+        let empty = vec![0, 0];
+        assert_eq!(IsarObject::from_bytes(&empty).property_size(&p), 0);
+    }
 }
@@ -8,6 +8,14 @@ use serde_json::{json, Map, Value};
 
 use super::property::Property;
 
+/// Lenient decoding accepts integral floats, numeric strings and bools for numbers and `0`, `1`,
+/// `"true"` and `"false"` for bools. Defaults are used for missing top level properties.
+#[derive(Default)]
+pub struct JsonDecodeOptions {
+    pub lenient: bool,
+    pub defaults: Map<String, Value>,
+}
+
 pub struct JsonEncodeDecode {}
 
 impl<'a> JsonEncodeDecode {
@@ -100,6 +108,8 @@ impl<'a> JsonEncodeDecode {
                             Value::Null
                         }
                     }
+                    // blobs are not stored in the objects
+                    DataType::Blob => Value::Null,
                 }
             };
             object_map.insert(property.name.clone(), value);
@@ -129,93 +139,31 @@ impl<'a> JsonEncodeDecode {
         embedded_properties: &IntMap<Vec<Property>>,
         ob: &mut ObjectBuilder,
         json: &Value,
+    ) -> Result<()> {
+        Self::decode_with_options(
+            properties,
+            embedded_properties,
+            ob,
+            json,
+            &JsonDecodeOptions::default(),
+        )
+    }
+
+    pub fn decode_with_options(
+        properties: &[Property],
+        embedded_properties: &IntMap<Vec<Property>>,
+        ob: &mut ObjectBuilder,
+        json: &Value,
+        options: &JsonDecodeOptions,
     ) -> Result<()> {
         let object = json.as_object().ok_or(IsarError::InvalidJson {})?;
 
         for property in properties {
-            if let Some(value) = object.get(&property.name) {
-                match property.data_type {
-                    DataType::Bool => ob.write_bool(property.offset, Self::value_to_bool(value)?),
-                    DataType::Byte => ob.write_byte(property.offset, Self::value_to_byte(value)?),
-                    DataType::Int => ob.write_int(property.offset, Self::value_to_int(value)?),
-                    DataType::Float => {
-                        ob.write_float(property.offset, Self::value_to_float(value)?)
-                    }
-                    DataType::Long => ob.write_long(property.offset, Self::value_to_long(value)?),
-                    DataType::Double => {
-                        ob.write_double(property.offset, Self::value_to_double(value)?)
-                    }
-                    DataType::String => {
-                        ob.write_string(property.offset, Self::value_to_string(value)?)
-                    }
-                    DataType::Object => {
-                        let builder = Self::value_to_object(
-                            value,
-                            embedded_properties,
-                            property.target_id.unwrap(),
-                        )?;
-                        ob.write_object(property.offset, builder.as_ref().map(|b| b.finish()));
-                    }
-                    DataType::BoolList => {
-                        let list = Self::value_to_array(value, Self::value_to_bool)?;
-                        ob.write_bool_list(property.offset, list.as_deref());
-                    }
-                    DataType::ByteList => {
-                        let list = Self::value_to_array(value, Self::value_to_byte)?;
-                        ob.write_byte_list(property.offset, list.as_deref());
-                    }
-                    DataType::IntList => {
-                        let list = Self::value_to_array(value, Self::value_to_int)?;
-                        ob.write_int_list(property.offset, list.as_deref());
-                    }
-                    DataType::FloatList => {
-                        let list = Self::value_to_array(value, Self::value_to_float)?;
-                        ob.write_float_list(property.offset, list.as_deref());
-                    }
-                    DataType::LongList => {
-                        let list = Self::value_to_array(value, Self::value_to_long)?;
-                        ob.write_long_list(property.offset, list.as_deref());
-                    }
-                    DataType::DoubleList => {
-                        let list = Self::value_to_array(value, Self::value_to_double)?;
-                        ob.write_double_list(property.offset, list.as_deref());
-                    }
-                    DataType::StringList => {
-                        if value.is_null() {
-                            ob.write_string_list(property.offset, None);
-                        } else if let Some(list) = value.as_array() {
-                            let list: Result<Vec<Option<&str>>> =
-                                list.iter().map(Self::value_to_string).collect();
-                            ob.write_string_list(property.offset, Some(list?.as_slice()));
-                        } else {
-                            return Err(IsarError::InvalidJson {});
-                        }
-                    }
-                    DataType::ObjectList => {
-                        if value.is_null() {
-                            ob.write_object_list(property.offset, None);
-                        } else if let Some(list) = value.as_array() {
-                            let list: Result<Vec<Option<ObjectBuilder>>> = list
-                                .iter()
-                                .map(|value| {
-                                    Self::value_to_object(
-                                        value,
-                                        embedded_properties,
-                                        property.target_id.unwrap(),
-                                    )
-                                })
-                                .collect();
-                            let list = list?;
-                            let objects = list
-                                .iter()
-                                .map(|o| o.as_ref().map(|o| o.finish()))
-                                .collect_vec();
-                            ob.write_object_list(property.offset, Some(objects.as_slice()));
-                        } else {
-                            return Err(IsarError::InvalidJson {});
-                        }
-                    }
-                }
+            let value = object
+                .get(&property.name)
+                .or_else(|| options.defaults.get(&property.name));
+            if let Some(value) = value {
+                Self::decode_property(property, embedded_properties, ob, value, options.lenient)?;
             } else {
                 ob.write_null(property.offset, property.data_type);
             }
@@ -224,19 +172,148 @@ impl<'a> JsonEncodeDecode {
         Ok(())
     }
 
-    fn value_to_bool(value: &Value) -> Result<Option<bool>> {
+    pub(crate) fn decode_property(
+        property: &Property,
+        embedded_properties: &IntMap<Vec<Property>>,
+        ob: &mut ObjectBuilder,
+        value: &Value,
+        lenient: bool,
+    ) -> Result<()> {
+        match property.data_type {
+            DataType::Bool => ob.write_bool(property.offset, Self::value_to_bool(value, lenient)?),
+            DataType::Byte => ob.write_byte(property.offset, Self::value_to_byte(value, lenient)?),
+            DataType::Int => ob.write_int(property.offset, Self::value_to_int(value, lenient)?),
+            DataType::Float => {
+                ob.write_float(property.offset, Self::value_to_float(value, lenient)?)
+            }
+            DataType::Long => ob.write_long(property.offset, Self::value_to_long(value, lenient)?),
+            DataType::Double => {
+                ob.write_double(property.offset, Self::value_to_double(value, lenient)?)
+            }
+            DataType::String => ob.write_string(property.offset, Self::value_to_string(value)?),
+            DataType::Object => {
+                let builder = Self::value_to_object(
+                    value,
+                    embedded_properties,
+                    property.target_id.unwrap(),
+                    lenient,
+                )?;
+                ob.write_object(property.offset, builder.as_ref().map(|b| b.finish()));
+            }
+            DataType::BoolList => {
+                let list = Self::value_to_array(value, |v| Self::value_to_bool(v, lenient))?;
+                ob.write_bool_list(property.offset, list.as_deref());
+            }
+            DataType::ByteList => {
+                let list = Self::value_to_array(value, |v| Self::value_to_byte(v, lenient))?;
+                ob.write_byte_list(property.offset, list.as_deref());
+            }
+            DataType::IntList => {
+                let list = Self::value_to_array(value, |v| Self::value_to_int(v, lenient))?;
+                ob.write_int_list(property.offset, list.as_deref());
+            }
+            DataType::FloatList => {
+                let list = Self::value_to_array(value, |v| Self::value_to_float(v, lenient))?;
+                ob.write_float_list(property.offset, list.as_deref());
+            }
+            DataType::LongList => {
+                let list = Self::value_to_array(value, |v| Self::value_to_long(v, lenient))?;
+                ob.write_long_list(property.offset, list.as_deref());
+            }
+            DataType::DoubleList => {
+                let list = Self::value_to_array(value, |v| Self::value_to_double(v, lenient))?;
+                ob.write_double_list(property.offset, list.as_deref());
+            }
+            DataType::StringList => {
+                if value.is_null() {
+                    ob.write_string_list(property.offset, None);
+                } else if let Some(list) = value.as_array() {
+                    let list: Result<Vec<Option<&str>>> =
+                        list.iter().map(Self::value_to_string).collect();
+                    ob.write_string_list(property.offset, Some(list?.as_slice()));
+                } else {
+                    return Err(IsarError::InvalidJson {});
+                }
+            }
+            DataType::ObjectList => {
+                if value.is_null() {
+                    ob.write_object_list(property.offset, None);
+                } else if let Some(list) = value.as_array() {
+                    let list: Result<Vec<Option<ObjectBuilder>>> = list
+                        .iter()
+                        .map(|value| {
+                            Self::value_to_object(
+                                value,
+                                embedded_properties,
+                                property.target_id.unwrap(),
+                                lenient,
+                            )
+                        })
+                        .collect();
+                    let list = list?;
+                    let objects = list
+                        .iter()
+                        .map(|o| o.as_ref().map(|o| o.finish()))
+                        .collect_vec();
+                    ob.write_object_list(property.offset, Some(objects.as_slice()));
+                } else {
+                    return Err(IsarError::InvalidJson {});
+                }
+            }
+            DataType::Blob => {}
+        }
+        Ok(())
+    }
+
+    /// Lenient integer conversion: integral floats, numeric strings and bools.
+    fn lenient_to_i64(value: &Value) -> Option<i64> {
+        if let Some(value) = value.as_f64() {
+            if value.fract() == 0.0 && value >= i64::MIN as f64 && value <= i64::MAX as f64 {
+                return Some(value as i64);
+            }
+        } else if let Some(value) = value.as_str() {
+            return value.trim().parse().ok();
+        } else if let Some(value) = value.as_bool() {
+            return Some(value as i64);
+        }
+        None
+    }
+
+    /// Lenient floating point conversion: numeric strings.
+    fn lenient_to_f64(value: &Value) -> Option<f64> {
+        value.as_str().and_then(|value| value.trim().parse().ok())
+    }
+
+    fn value_to_bool(value: &Value, lenient: bool) -> Result<Option<bool>> {
         if value.is_null() {
             return Ok(None);
         } else if let Some(value) = value.as_bool() {
             return Ok(Some(value));
+        } else if lenient {
+            match Self::lenient_to_i64(value) {
+                Some(0) => return Ok(Some(false)),
+                Some(1) => return Ok(Some(true)),
+                _ => {}
+            }
+            match value.as_str() {
+                Some("true") => return Ok(Some(true)),
+                Some("false") => return Ok(Some(false)),
+                _ => {}
+            }
         };
         Err(IsarError::InvalidJson {})
     }
 
-    fn value_to_byte(value: &Value) -> Result<u8> {
+    fn value_to_byte(value: &Value, lenient: bool) -> Result<u8> {
         if value.is_null() {
             return Ok(IsarObject::NULL_BYTE);
-        } else if let Some(value) = value.as_i64() {
+        }
+        let value = if lenient {
+            Self::lenient_to_i64(value)
+        } else {
+            value.as_i64()
+        };
+        if let Some(value) = value {
             if value >= 0 && value <= u8::MAX as i64 {
                 return Ok(value as u8);
             }
@@ -244,10 +321,16 @@ impl<'a> JsonEncodeDecode {
         Err(IsarError::InvalidJson {})
     }
 
-    fn value_to_int(value: &Value) -> Result<i32> {
+    fn value_to_int(value: &Value, lenient: bool) -> Result<i32> {
         if value.is_null() {
             return Ok(IsarObject::NULL_INT);
-        } else if let Some(value) = value.as_i64() {
+        }
+        let value = if lenient {
+            Self::lenient_to_i64(value)
+        } else {
+            value.as_i64()
+        };
+        if let Some(value) = value {
             if value >= i32::MIN as i64 && value <= i32::MAX as i64 {
                 return Ok(value as i32);
             }
@@ -255,10 +338,14 @@ impl<'a> JsonEncodeDecode {
         Err(IsarError::InvalidJson {})
     }
 
-    fn value_to_float(value: &Value) -> Result<f32> {
+    fn value_to_float(value: &Value, lenient: bool) -> Result<f32> {
         if value.is_null() {
             return Ok(IsarObject::NULL_FLOAT);
-        } else if let Some(value) = value.as_f64() {
+        }
+        let value = value
+            .as_f64()
+            .or_else(|| lenient.then(|| Self::lenient_to_f64(value)).flatten());
+        if let Some(value) = value {
             if value >= f32::MIN as f64 && value <= f32::MAX as f64 {
                 return Ok(value as f32);
             }
@@ -266,24 +353,26 @@ impl<'a> JsonEncodeDecode {
         Err(IsarError::InvalidJson {})
     }
 
-    fn value_to_long(value: &Value) -> Result<i64> {
+    fn value_to_long(value: &Value, lenient: bool) -> Result<i64> {
         if value.is_null() {
-            Ok(IsarObject::NULL_LONG)
-        } else if let Some(value) = value.as_i64() {
-            Ok(value)
-        } else {
-            Err(IsarError::InvalidJson {})
+            return Ok(IsarObject::NULL_LONG);
         }
+        let value = if lenient {
+            value.as_i64().or_else(|| Self::lenient_to_i64(value))
+        } else {
+            value.as_i64()
+        };
+        value.ok_or(IsarError::InvalidJson {})
     }
 
-    fn value_to_double(value: &Value) -> Result<f64> {
+    fn value_to_double(value: &Value, lenient: bool) -> Result<f64> {
         if value.is_null() {
-            Ok(IsarObject::NULL_DOUBLE)
-        } else if let Some(value) = value.as_f64() {
-            Ok(value)
-        } else {
-            Err(IsarError::InvalidJson {})
+            return Ok(IsarObject::NULL_DOUBLE);
         }
+        let value = value
+            .as_f64()
+            .or_else(|| lenient.then(|| Self::lenient_to_f64(value)).flatten());
+        value.ok_or(IsarError::InvalidJson {})
     }
 
     fn value_to_string(value: &Value) -> Result<Option<&str>> {
@@ -300,13 +389,24 @@ impl<'a> JsonEncodeDecode {
         value: &Value,
         embedded_properties: &IntMap<Vec<Property>>,
         target_id: u64,
+        lenient: bool,
     ) -> Result<Option<ObjectBuilder>> {
         if value.is_null() {
             Ok(None)
         } else {
             let properties = embedded_properties.get(target_id).unwrap();
             let mut embedded_ob = ObjectBuilder::new(properties, None);
-            Self::decode(properties, embedded_properties, &mut embedded_ob, value)?;
+            let options = JsonDecodeOptions {
+                lenient,
+                ..Default::default()
+            };
+            Self::decode_with_options(
+                properties,
+                embedded_properties,
+                &mut embedded_ob,
+                value,
+                &options,
+            )?;
             Ok(Some(embedded_ob))
         }
     }
@@ -325,3 +425,46 @@ impl<'a> JsonEncodeDecode {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instance::tests::{collection, open_instance};
+
+    #[test]
+    fn test_lenient_decode_with_defaults() {
+        let instance = open_instance(json!([collection(
+            "col",
+            json!([
+                {"name": "byte", "type": "Byte"},
+                {"name": "double", "type": "Double"},
+                {"name": "flag", "type": "Bool"},
+                {"name": "int", "type": "Int"},
+                {"name": "long", "type": "Long"},
+                {"name": "name", "type": "String"},
+            ])
+        )]));
+        let col = &instance.collections()[0];
+        let (properties, embedded) = (&col.properties, &col.embedded_properties);
+        let decode = |value: Value, lenient: bool| -> Result<Value> {
+            let mut defaults = Map::new();
+            defaults.insert("name".to_string(), json!("unknown"));
+            let options = JsonDecodeOptions { lenient, defaults };
+            let mut ob = ObjectBuilder::new(properties, None);
+            JsonEncodeDecode::decode_with_options(properties, embedded, &mut ob, &value, &options)?;
+            let map = JsonEncodeDecode::encode(properties, embedded, ob.finish(), false);
+            Ok(Value::Object(map))
+        };
+
+        let value = json!({"byte": true, "double": "1.5", "flag": 1, "int": 3.0, "long": " 42"});
+        let expected = json!({
+            "byte": 1, "double": 1.5, "flag": true, "int": 3, "long": 42, "name": "unknown"
+        });
+        assert_eq!(decode(value.clone(), true).unwrap(), expected);
+        let strict = decode(value, false);
+        assert!(matches!(strict, Err(IsarError::InvalidJson {})));
+        assert!(decode(json!({"int": 3.5}), true).is_err());
+        assert!(decode(json!({"flag": "yes"}), true).is_err());
+        assert!(instance.close_and_delete());
+    }
+}
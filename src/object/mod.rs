@@ -1,6 +1,10 @@
+pub mod buffer_pool;
 pub mod data_type;
 pub mod id;
 pub mod isar_object;
 pub mod json_encode_decode;
+pub mod ndjson_importer;
 pub mod object_builder;
+pub(crate) mod object_cache;
 pub mod property;
+pub mod property_transform;
@@ -0,0 +1,223 @@
+use crate::collection::IsarCollection;
+use crate::error::{IsarError, Result};
+use crate::object::json_encode_decode::JsonDecodeOptions;
+use crate::txn::IsarTxn;
+use serde::Serialize;
+use serde_json::Value;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ImportErrorPolicy {
+    Fail,
+    Skip,
+    Collect,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct ImportError {
+    pub line: u64,
+    pub message: String,
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct ImportProgress {
+    pub lines: u64,
+    pub bytes: u64,
+    pub imported: u64,
+}
+
+/// Imports newline delimited JSON that is provided in chunks of arbitrary size. Records that
+/// cannot be decoded or would violate a unique index are handled by the error policy. Storage
+/// errors always fail.
+pub struct NdjsonImporter<'a> {
+    collection: &'a IsarCollection,
+    id_name: Option<String>,
+    policy: ImportErrorPolicy,
+    pending: Vec<u8>,
+    progress: ImportProgress,
+    errors: Vec<ImportError>,
+}
+
+impl<'a> NdjsonImporter<'a> {
+    pub fn new(
+        collection: &'a IsarCollection,
+        id_name: Option<&str>,
+        policy: ImportErrorPolicy,
+    ) -> Self {
+        NdjsonImporter {
+            collection,
+            id_name: id_name.map(|n| n.to_string()),
+            policy,
+            pending: vec![],
+            progress: ImportProgress::default(),
+            errors: vec![],
+        }
+    }
+
+    pub fn imported(&self) -> u64 {
+        self.progress.imported
+    }
+
+    /// The lines and bytes consumed so far. Bytes of an incomplete last line are not included.
+    pub fn progress(&self) -> ImportProgress {
+        self.progress
+    }
+
+    pub fn errors(&self) -> &[ImportError] {
+        &self.errors
+    }
+
+    /// Imports all complete lines of the chunk. An incomplete last line is kept for the next
+    /// chunk.
+    pub fn add_chunk(&mut self, txn: &mut IsarTxn, chunk: &[u8]) -> Result<()> {
+        self.pending.extend_from_slice(chunk);
+        if let Some(end) = self.pending.iter().rposition(|b| *b == b'\n') {
+            let lines = self.pending.drain(..=end).collect::<Vec<_>>();
+            self.import_lines(txn, &lines)?;
+        }
+        Ok(())
+    }
+
+    /// Imports the remaining line that was not terminated by a newline.
+    pub fn finish(&mut self, txn: &mut IsarTxn) -> Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let lines = std::mem::take(&mut self.pending);
+        self.import_lines(txn, &lines)
+    }
+
+    /// Imports the lines of a chunk in one write. The progress is reset if the chunk fails.
+    fn import_lines(&mut self, txn: &mut IsarTxn, lines: &[u8]) -> Result<()> {
+        let progress = self.progress;
+        let errors = self.errors.len();
+        let result = self.write_lines(txn, lines);
+        if result.is_err() {
+            self.progress = progress;
+            self.errors.truncate(errors);
+        }
+        result
+    }
+
+    fn write_lines(&mut self, txn: &mut IsarTxn, lines: &[u8]) -> Result<()> {
+        let collection = self.collection;
+        txn.write(collection.instance_id, |cursors, mut change_set| {
+            let mut ob_result_cache = Some(collection.buffer_pool.get(0));
+            for line in lines.split_inclusive(|b| *b == b'\n') {
+                self.progress.lines += 1;
+                self.progress.bytes += line.len() as u64;
+                if line.iter().all(|b| b.is_ascii_whitespace()) {
+                    continue;
+                }
+
+                let decoded = serde_json::from_slice::<Value>(line)
+                    .map_err(|_| IsarError::InvalidJson {})
+                    .and_then(|value| {
+                        collection.decode_json(
+                            self.id_name.as_deref(),
+                            &value,
+                            &JsonDecodeOptions::default(),
+                            ob_result_cache.take(),
+                        )
+                    });
+                let result = match decoded {
+                    Ok((id, ob)) => {
+                        let object = ob.finish();
+                        let result = collection.check_put(cursors, id, object);
+                        if result.is_ok() {
+                            collection.put_internal(
+                                cursors,
+                                change_set.as_deref_mut(),
+                                id,
+                                object,
+                            )?;
+                            self.progress.imported += 1;
+                        }
+                        ob_result_cache = Some(ob.recycle());
+                        result
+                    }
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = result {
+                    match self.policy {
+                        ImportErrorPolicy::Fail => return Err(e),
+                        ImportErrorPolicy::Skip => {}
+                        ImportErrorPolicy::Collect => self.errors.push(ImportError {
+                            line: self.progress.lines,
+                            message: e.to_string(),
+                        }),
+                    }
+                }
+            }
+            if let Some(buffer) = ob_result_cache {
+                collection.buffer_pool.put(buffer);
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instance::tests::{collection, index, open_instance};
+    use serde_json::json;
+
+    fn unique_names() -> Value {
+        let mut col = collection("col", json!([{"name": "name", "type": "String"}]));
+        col["indexes"] = json!([index("name", &[("name", true)], true)]);
+        json!([col])
+    }
+
+    #[test]
+    fn test_collect_write_errors() {
+        let instance = open_instance(unique_names());
+        let col = &instance.collections()[0];
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        let mut importer = NdjsonImporter::new(col, None, ImportErrorPolicy::Collect);
+        importer
+            .add_chunk(&mut txn, b"{\"name\": \"a\"}\n{\"name\": \"a\"}\n{\"na")
+            .unwrap();
+        let progress = importer.progress();
+        assert_eq!(
+            (progress.lines, progress.bytes, progress.imported),
+            (2, 28, 1)
+        );
+
+        importer.add_chunk(&mut txn, b"me\": \"b\"}\n{").unwrap();
+        importer.finish(&mut txn).unwrap();
+        let progress = importer.progress();
+        assert_eq!(
+            (progress.lines, progress.bytes, progress.imported),
+            (4, 43, 2)
+        );
+        let lines = importer.errors().iter().map(|e| e.line).collect::<Vec<_>>();
+        assert_eq!(lines, vec![2, 4]);
+        assert!(importer.errors()[0].message.contains("Unique index"));
+
+        instance.verify(&mut txn).unwrap();
+        txn.commit().unwrap();
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_fail_resets_progress() {
+        let instance = open_instance(unique_names());
+        let col = &instance.collections()[0];
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        let mut importer = NdjsonImporter::new(col, None, ImportErrorPolicy::Fail);
+        importer
+            .add_chunk(&mut txn, b"{\"name\": \"a\"}\n")
+            .unwrap();
+
+        let result = importer.add_chunk(&mut txn, b"{\"name\": \"b\"}\n{\"name\": \"a\"}\n");
+        assert!(matches!(result, Err(IsarError::UniqueViolated { .. })));
+        let progress = importer.progress();
+        assert_eq!(
+            (progress.lines, progress.bytes, progress.imported),
+            (1, 14, 1)
+        );
+        assert!(importer.errors().is_empty());
+        txn.abort();
+        assert!(instance.close_and_delete());
+    }
+}
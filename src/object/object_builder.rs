@@ -87,6 +87,45 @@ impl ObjectBuilder {
             DataType::DoubleList => self.write_double_list(offset, None),
             DataType::StringList => self.write_string_list(offset, None),
             DataType::ObjectList => self.write_object_list(offset, None),
+            // blobs are not stored in the objects
+            DataType::Blob => {}
+        }
+    }
+
+    pub fn write_from(&mut self, property: &Property, object: IsarObject) {
+        let offset = property.offset;
+        match property.data_type {
+            DataType::Bool => self.write_bool(offset, object.read_bool(offset)),
+            DataType::Byte => self.write_byte(offset, object.read_byte(offset)),
+            DataType::Int => self.write_int(offset, object.read_int(offset)),
+            DataType::Float => self.write_float(offset, object.read_float(offset)),
+            DataType::Long => self.write_long(offset, object.read_long(offset)),
+            DataType::Double => self.write_double(offset, object.read_double(offset)),
+            DataType::String => self.write_string(offset, object.read_string(offset)),
+            DataType::Object => self.write_object(offset, object.read_object(offset)),
+            DataType::BoolList => {
+                self.write_bool_list(offset, object.read_bool_list(offset).as_deref())
+            }
+            DataType::ByteList => self.write_byte_list(offset, object.read_byte_list(offset)),
+            DataType::IntList => {
+                self.write_int_list(offset, object.read_int_list(offset).as_deref())
+            }
+            DataType::FloatList => {
+                self.write_float_list(offset, object.read_float_list(offset).as_deref())
+            }
+            DataType::LongList => {
+                self.write_long_list(offset, object.read_long_list(offset).as_deref())
+            }
+            DataType::DoubleList => {
+                self.write_double_list(offset, object.read_double_list(offset).as_deref())
+            }
+            DataType::StringList => {
+                self.write_string_list(offset, object.read_string_list(offset).as_deref())
+            }
+            DataType::ObjectList => {
+                self.write_object_list(offset, object.read_object_list(offset).as_deref())
+            }
+            DataType::Blob => {}
         }
     }
 
@@ -0,0 +1,152 @@
+use intmap::IntMap;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+struct CacheEntry {
+    bytes: Arc<[u8]>,
+    /// Id of the snapshot the object was read from.
+    txn_id: u64,
+    tick: u64,
+}
+
+#[derive(Default)]
+struct CacheState {
+    entries: IntMap<CacheEntry>,
+    lru: BTreeMap<u64, i64>,
+    tick: u64,
+    size: usize,
+    /// Id of the latest write transaction that changed objects.
+    invalidated_txn_id: u64,
+}
+
+/// Least recently used objects of a collection. Objects are only returned to transactions that
+/// see the same version of the object.
+pub(crate) struct ObjectCache {
+    max_bytes: usize,
+    state: Mutex<CacheState>,
+}
+
+impl ObjectCache {
+    /// Only transactions starting with `txn_id` may add objects because a write transaction may
+    /// have changed objects before the cache existed.
+    pub fn new(max_bytes: usize, txn_id: u64) -> Self {
+        ObjectCache {
+            max_bytes,
+            state: Mutex::new(CacheState {
+                invalidated_txn_id: txn_id,
+                ..Default::default()
+            }),
+        }
+    }
+
+    pub fn get(&self, id: i64, txn_id: u64) -> Option<Arc<[u8]>> {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        let entry = state.entries.get_mut(id as u64)?;
+        if entry.txn_id > txn_id {
+            return None;
+        }
+        state.tick += 1;
+        state.lru.remove(&entry.tick);
+        state.lru.insert(state.tick, id);
+        entry.tick = state.tick;
+        Some(entry.bytes.clone())
+    }
+
+    /// Caches an object that was read by the read transaction `txn_id`.
+    pub fn insert(&self, id: i64, txn_id: u64, bytes: &[u8]) {
+        if bytes.len() > self.max_bytes {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        // the snapshot may not contain the latest changes
+        if txn_id < state.invalidated_txn_id || state.entries.contains_key(id as u64) {
+            return;
+        }
+        while state.size + bytes.len() > self.max_bytes {
+            let oldest_tick = *state.lru.keys().next().unwrap();
+            let oldest = state.lru.remove(&oldest_tick).unwrap();
+            let entry = state.entries.remove(oldest as u64).unwrap();
+            state.size -= entry.bytes.len();
+        }
+        state.tick += 1;
+        let tick = state.tick;
+        state.lru.insert(tick, id);
+        state.size += bytes.len();
+        let entry = CacheEntry {
+            bytes: bytes.into(),
+            txn_id,
+            tick,
+        };
+        state.entries.insert(id as u64, entry);
+    }
+
+    /// Removes the object before it is changed by the write transaction `txn_id`.
+    pub fn invalidate(&self, id: i64, txn_id: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.invalidated_txn_id = state.invalidated_txn_id.max(txn_id);
+        if let Some(entry) = state.entries.remove(id as u64) {
+            state.lru.remove(&entry.tick);
+            state.size -= entry.bytes.len();
+        }
+    }
+
+    pub fn invalidate_all(&self, txn_id: u64) {
+        let mut state = self.state.lock().unwrap();
+        let invalidated_txn_id = state.invalidated_txn_id.max(txn_id);
+        *state = CacheState {
+            invalidated_txn_id,
+            ..Default::default()
+        };
+    }
+
+    /// Number of cached objects and their total size.
+    pub fn stats(&self) -> (usize, usize) {
+        let state = self.state.lock().unwrap();
+        (state.entries.len(), state.size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ObjectCache;
+
+    #[test]
+    fn test_insert_after_invalidate() {
+        let cache = ObjectCache::new(100, 5);
+        cache.insert(1, 4, b"old");
+        assert_eq!(cache.stats(), (0, 0));
+
+        cache.insert(1, 5, b"old");
+        assert_eq!(cache.get(1, 5).as_deref(), Some(&b"old"[..]));
+
+        // a reader of the old snapshot must not cache the object again
+        cache.invalidate(1, 6);
+        cache.insert(1, 5, b"old");
+        assert_eq!(cache.get(1, 5), None);
+
+        cache.insert(1, 6, b"new");
+        assert_eq!(cache.get(1, 5), None);
+        assert_eq!(cache.get(1, 6).as_deref(), Some(&b"new"[..]));
+
+        cache.invalidate_all(7);
+        assert_eq!(cache.stats(), (0, 0));
+        cache.insert(1, 6, b"new");
+        assert_eq!(cache.stats(), (0, 0));
+    }
+
+    #[test]
+    fn test_evict_least_recently_used() {
+        let cache = ObjectCache::new(4, 0);
+        cache.insert(1, 0, b"aa");
+        cache.insert(2, 0, b"bb");
+        cache.get(1, 0).unwrap();
+        cache.insert(3, 0, b"cc");
+        assert_eq!(cache.stats(), (2, 4));
+        assert_eq!(cache.get(2, 0), None);
+        assert!(cache.get(1, 0).is_some());
+
+        cache.insert(4, 0, b"too large");
+        assert_eq!(cache.get(4, 0), None);
+    }
+}
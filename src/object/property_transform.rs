@@ -0,0 +1,102 @@
+use crate::error::{illegal_arg, Result};
+use crate::object::data_type::DataType;
+use crate::object::isar_object::IsarObject;
+use crate::object::object_builder::ObjectBuilder;
+use crate::object::property::Property;
+use crate::txn::IsarTxn;
+use std::sync::Arc;
+
+/// Transforms the value of a String property before it is written and after it is read, for
+/// example to encrypt it. Indexes, filters and sorting use the encoded value.
+pub trait PropertyTransform: Send + Sync {
+    fn encode(&self, value: &str) -> Result<String>;
+
+    fn decode(&self, value: &str) -> Result<String>;
+}
+
+struct Transforms {
+    properties: Vec<Property>,
+    transforms: Vec<(Property, Arc<dyn PropertyTransform>)>,
+}
+
+/// The transforms of a collection. Queries keep the transforms they were built with.
+#[derive(Clone, Default)]
+pub(crate) struct PropertyTransforms(Option<Arc<Transforms>>);
+
+impl PropertyTransforms {
+    /// Returns a copy that uses `transform` for `property` instead of the previous transform.
+    pub fn with(
+        &self,
+        properties: &[Property],
+        property: &Property,
+        transform: Arc<dyn PropertyTransform>,
+    ) -> Result<Self> {
+        if property.data_type != DataType::String {
+            return illegal_arg("Only String properties can be transformed.");
+        }
+        let mut transforms = self
+            .0
+            .as_ref()
+            .map(|t| t.transforms.clone())
+            .unwrap_or_default();
+        transforms.retain(|(p, _)| p != property);
+        transforms.push((property.clone(), transform));
+        Ok(PropertyTransforms(Some(Arc::new(Transforms {
+            properties: properties.to_vec(),
+            transforms,
+        }))))
+    }
+
+    fn apply(transforms: &Transforms, object: IsarObject, encode: bool) -> Result<Vec<u8>> {
+        let mut ob = ObjectBuilder::new(&transforms.properties, None);
+        for property in &transforms.properties {
+            let transform = transforms.transforms.iter().find(|(p, _)| p == property);
+            if let Some((_, transform)) = transform {
+                let value = if let Some(value) = object.read_string(property.offset) {
+                    if encode {
+                        Some(transform.encode(value)?)
+                    } else {
+                        Some(transform.decode(value)?)
+                    }
+                } else {
+                    None
+                };
+                ob.write_string(property.offset, value.as_deref());
+            } else {
+                ob.write_from(property, object);
+            }
+        }
+        Ok(ob.finish().as_bytes().to_vec())
+    }
+
+    /// Returns `None` if there are no transforms.
+    pub fn encode(&self, object: IsarObject) -> Result<Option<Vec<u8>>> {
+        if let Some(transforms) = &self.0 {
+            Ok(Some(Self::apply(transforms, object, true)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Returns `None` if there are no transforms.
+    pub fn decode_bytes(&self, object: IsarObject) -> Result<Option<Vec<u8>>> {
+        if let Some(transforms) = &self.0 {
+            Ok(Some(Self::apply(transforms, object, false)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub fn decode<'txn>(
+        &self,
+        txn: &'txn IsarTxn,
+        object: IsarObject<'txn>,
+    ) -> Result<IsarObject<'txn>> {
+        if let Some(transforms) = &self.0 {
+            let bytes = Self::apply(transforms, object, false)?;
+            Ok(IsarObject::from_bytes(txn.pin(bytes.into())))
+        } else {
+            Ok(object)
+        }
+    }
+}
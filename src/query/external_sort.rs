@@ -0,0 +1,81 @@
+use crate::error::Result;
+use crate::index::index_key::IndexKey;
+use crate::instance::Durability;
+use crate::storage::{Db, Env, StorageCursor, StorageDb, StorageEngine, StorageTxn, UnboundCursor};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static SORT_DB_ID: AtomicU64 = AtomicU64::new(0);
+
+/// A temporary database that returns the inserted keys in bytewise order. It is deleted once it
+/// is dropped.
+pub(crate) struct SortDb {
+    env: Option<Env>,
+    db: Db,
+    path: String,
+}
+
+impl SortDb {
+    pub fn create(dir: &str) -> Result<Self> {
+        let id = SORT_DB_ID.fetch_add(1, Ordering::SeqCst);
+        let mut path_buf = PathBuf::from(dir);
+        path_buf.push(format!("isar_sort_{}_{}.tmp", std::process::id(), id));
+        let path = path_buf.as_path().to_str().unwrap().to_string();
+
+        let env = Env::create(
+            &path,
+            1,
+            Durability::NoSync,
+            false,
+            false,
+            Env::DEFAULT_MAX_SIZE,
+            Env::DEFAULT_GROWTH_STEP,
+            Env::DEFAULT_SHRINK_THRESHOLD,
+        )?;
+        let txn = env.txn(true)?;
+        let db = Db::open(&txn, None, false, false, false)?;
+        txn.commit()?;
+        Ok(SortDb {
+            env: Some(env),
+            db,
+            path,
+        })
+    }
+
+    /// Inserts all keys in a single transaction and clears `keys`.
+    pub fn insert(&self, keys: &mut Vec<Vec<u8>>) -> Result<()> {
+        let env = self.env.as_ref().unwrap();
+        let txn = env.txn(true)?;
+        {
+            let mut cursor = UnboundCursor::new().bind(&txn, self.db)?;
+            for key in keys.drain(..) {
+                cursor.put(&IndexKey::from_bytes(key), &[])?;
+            }
+        }
+        txn.commit()
+    }
+
+    pub fn iter<F>(&self, mut callback: F) -> Result<()>
+    where
+        F: FnMut(&[u8]) -> Result<bool>,
+    {
+        let env = self.env.as_ref().unwrap();
+        let txn = env.txn(false)?;
+        let mut cursor = UnboundCursor::new().bind(&txn, self.db)?;
+        let mut next = cursor.move_to_first()?;
+        while let Some((key, _)) = next {
+            if !callback(key)? {
+                break;
+            }
+            next = cursor.move_to_next()?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for SortDb {
+    fn drop(&mut self) {
+        drop(self.env.take());
+        Env::delete(&self.path);
+    }
+}
@@ -1,18 +1,21 @@
 use crate::collection::IsarCollection;
 use crate::cursor::IsarCursors;
 use crate::error::{illegal_arg, Result};
+use crate::index::geo::{distance, GeoBox};
 use crate::link::IsarLink;
 use crate::object::data_type::DataType;
 use crate::object::isar_object::IsarObject;
 use crate::object::property::Property;
 use crate::query::fast_wild_match::fast_wild_match;
+use crate::query::NanPolicy;
 use enum_dispatch::enum_dispatch;
 use itertools::Itertools;
+use memchr::memmem::Finder;
 use paste::paste;
 
 #[macro_export]
 macro_rules! primitive_create {
-    ($data_type:ident, $property:expr, $lower:expr, $upper:expr) => {
+    ($data_type:ident, $property:expr, $lower:expr, $upper:expr $(, $field:ident)*) => {
         paste! {
             if $property.data_type == DataType::$data_type || ($property.data_type == DataType::Bool && DataType::$data_type == DataType::Byte) {
                 Ok(Filter(
@@ -20,6 +23,7 @@ macro_rules! primitive_create {
                         offset: $property.offset,
                         $lower,
                         $upper,
+                        $($field,)*
                     })
                 ))
             } else if $property.data_type == DataType::[<$data_type List>] || ($property.data_type == DataType::BoolList && DataType::[<$data_type List>] == DataType::ByteList) {
@@ -28,6 +32,7 @@ macro_rules! primitive_create {
                         offset: $property.offset,
                         $lower,
                         $upper,
+                        $($field,)*
                     })
                 ))
             } else {
@@ -72,6 +77,14 @@ macro_rules! string_filter_create {
 pub struct Filter(FilterCond);
 
 impl Filter {
+    /// Looks up the property of the collection by its name and creates the filter for it.
+    pub fn by_name<F>(collection: &IsarCollection, name: &str, create: F) -> Result<Filter>
+    where
+        F: FnOnce(&Property) -> Result<Filter>,
+    {
+        create(collection.property(name)?)
+    }
+
     pub fn id(lower: i64, upper: i64) -> Filter {
         let filter_cond = FilterCond::IdBetween(IdBetweenCond { lower, upper });
         Filter(filter_cond)
@@ -89,12 +102,46 @@ impl Filter {
         primitive_create!(Long, property, lower, upper)
     }
 
+    /// NaN values only match if `lower` is NaN.
     pub fn float(property: &Property, lower: f32, upper: f32) -> Result<Filter> {
-        primitive_create!(Float, property, lower, upper)
+        let nan = Self::implicit_nan_policy(lower.is_nan());
+        Self::float_nan(property, lower, upper, nan)
     }
 
+    /// NaN values only match if `lower` is NaN.
     pub fn double(property: &Property, lower: f64, upper: f64) -> Result<Filter> {
-        primitive_create!(Double, property, lower, upper)
+        let nan = Self::implicit_nan_policy(lower.is_nan());
+        Self::double_nan(property, lower, upper, nan)
+    }
+
+    fn implicit_nan_policy(lower_nan: bool) -> NanPolicy {
+        if lower_nan {
+            NanPolicy::Include
+        } else {
+            NanPolicy::Exclude
+        }
+    }
+
+    /// Like `float` but NaN values are matched according to `nan` regardless of the bounds.
+    pub fn float_nan(
+        property: &Property,
+        lower: f32,
+        upper: f32,
+        nan: NanPolicy,
+    ) -> Result<Filter> {
+        let include_nan = nan == NanPolicy::Include;
+        primitive_create!(Float, property, lower, upper, include_nan)
+    }
+
+    /// Like `double` but NaN values are matched according to `nan` regardless of the bounds.
+    pub fn double_nan(
+        property: &Property,
+        lower: f64,
+        upper: f64,
+        nan: NanPolicy,
+    ) -> Result<Filter> {
+        let include_nan = nan == NanPolicy::Include;
+        primitive_create!(Double, property, lower, upper, include_nan)
     }
 
     pub fn string_to_bytes(str: Option<&str>, case_sensitive: bool) -> Option<Vec<u8>> {
@@ -166,7 +213,28 @@ impl Filter {
         value: &str,
         case_sensitive: bool,
     ) -> Result<Filter> {
-        string_filter_create!(Contains, property, value, case_sensitive)
+        let value = if case_sensitive {
+            value.to_string()
+        } else {
+            value.to_lowercase()
+        };
+        let finder = Finder::new(value.as_bytes()).into_owned();
+        let filter_cond = if property.data_type == DataType::String {
+            Ok(FilterCond::StringContains(StringContainsCond {
+                offset: property.offset,
+                finder,
+                case_sensitive,
+            }))
+        } else if property.data_type == DataType::StringList {
+            Ok(FilterCond::AnyStringContains(AnyStringContainsCond {
+                offset: property.offset,
+                finder,
+                case_sensitive,
+            }))
+        } else {
+            illegal_arg("Property does not support this filter.")
+        }?;
+        Ok(Filter(filter_cond))
     }
 
     pub fn string_matches(
@@ -190,6 +258,38 @@ impl Filter {
         Ok(Filter(filter_cond))
     }
 
+    pub fn geo_box(lat: &Property, lon: &Property, geo_box: GeoBox) -> Result<Filter> {
+        if lat.data_type != DataType::Double || lon.data_type != DataType::Double {
+            return illegal_arg("Property does not support this filter.");
+        }
+        let filter_cond = FilterCond::GeoWithinBox(GeoWithinBoxCond {
+            lat_offset: lat.offset,
+            lon_offset: lon.offset,
+            geo_box,
+        });
+        Ok(Filter(filter_cond))
+    }
+
+    pub fn geo_radius(
+        lat: &Property,
+        lon: &Property,
+        center_lat: f64,
+        center_lon: f64,
+        radius: f64,
+    ) -> Result<Filter> {
+        if lat.data_type != DataType::Double || lon.data_type != DataType::Double {
+            return illegal_arg("Property does not support this filter.");
+        }
+        let filter_cond = FilterCond::GeoWithinRadius(GeoWithinRadiusCond {
+            lat_offset: lat.offset,
+            lon_offset: lon.offset,
+            center_lat,
+            center_lon,
+            radius,
+        });
+        Ok(Filter(filter_cond))
+    }
+
     pub fn null(property: &Property) -> Filter {
         let filter_cond = FilterCond::Null(NullCond {
             offset: property.offset,
@@ -252,6 +352,17 @@ impl Filter {
         Ok(Filter(filter_cond))
     }
 
+    pub fn object_path(path: &[&Property], filter: Filter) -> Result<Filter> {
+        if path.is_empty() {
+            return illegal_arg("Object path must not be empty.");
+        }
+        let mut filter = filter;
+        for property in path.iter().rev() {
+            filter = Self::object(property, Some(filter))?;
+        }
+        Ok(filter)
+    }
+
     pub fn link(collection: &IsarCollection, link_id: u64, filter: Filter) -> Result<Filter> {
         let link = collection.get_link_backlink(link_id)?.clone();
         let filter_cond = FilterCond::AnyLink(AnyLinkCond {
@@ -272,6 +383,15 @@ impl Filter {
         Ok(Filter(filter_cond))
     }
 
+    pub fn link_count(
+        collection: &IsarCollection,
+        link_id: u64,
+        min: usize,
+        max: usize,
+    ) -> Result<Filter> {
+        Self::link_length(collection, link_id, min, max)
+    }
+
     pub(crate) fn evaluate(
         &self,
         id: i64,
@@ -312,6 +432,9 @@ enum FilterCond {
 
     ListLength(ListLengthCond),
 
+    GeoWithinBox(GeoWithinBoxCond),
+    GeoWithinRadius(GeoWithinRadiusCond),
+
     Null(NullCond),
     And(AndCond),
     Or(OrCond),
@@ -430,20 +553,37 @@ macro_rules! float_filter_between {
         impl Condition for $name {
             fn evaluate(&self, _id: i64, object: IsarObject, _: Option<&IsarCursors>) -> Result<bool> {
                 let val = object.$prop_accessor(self.offset);
-                Ok(float_filter_between!(eval val, self.lower, self.upper))
+                Ok(float_filter_between!(eval val, self.lower, self.upper, self.include_nan))
             }
         }
     };
 
-    (eval $val:expr, $lower:expr, $upper:expr) => {{
-        ($lower < $val || $lower.is_nan()) &&
-        ($upper > $val || $val.is_nan() || ($upper.is_infinite() && $upper.is_sign_positive()))
+    (eval $val:expr, $lower:expr, $upper:expr, $include_nan:expr) => {{
+        if $val.is_nan() {
+            $include_nan
+        } else {
+            ($lower < $val || $lower.is_nan()) &&
+            ($upper > $val || ($upper.is_infinite() && $upper.is_sign_positive()))
+        }
     }};
 }
 
-filter_between_struct!(FloatBetweenCond, Float, f32);
+#[macro_export]
+macro_rules! float_filter_between_struct {
+    ($name:ident, $data_type:ident, $type:ty) => {
+        #[derive(Clone)]
+        struct $name {
+            upper: $type,
+            lower: $type,
+            include_nan: bool,
+            offset: usize,
+        }
+    };
+}
+
+float_filter_between_struct!(FloatBetweenCond, Float, f32);
 float_filter_between!(FloatBetweenCond, read_float);
-filter_between_struct!(DoubleBetweenCond, Double, f64);
+float_filter_between_struct!(DoubleBetweenCond, Double, f64);
 float_filter_between!(DoubleBetweenCond, read_double);
 
 #[macro_export]
@@ -454,7 +594,7 @@ macro_rules! float_filter_between_list {
                 let vals = object.$prop_accessor(self.offset);
                 if let Some(vals) = vals {
                     for val in vals {
-                        if float_filter_between!(eval val, self.lower, self.upper) {
+                        if float_filter_between!(eval val, self.lower, self.upper, self.include_nan) {
                             return Ok(true);
                         }
                     }
@@ -465,9 +605,9 @@ macro_rules! float_filter_between_list {
     };
 }
 
-filter_between_struct!(AnyFloatBetweenCond, Float, f32);
+float_filter_between_struct!(AnyFloatBetweenCond, Float, f32);
 float_filter_between_list!(AnyFloatBetweenCond, read_float_list);
-filter_between_struct!(AnyDoubleBetweenCond, Double, f64);
+float_filter_between_struct!(AnyDoubleBetweenCond, Double, f64);
 float_filter_between_list!(AnyDoubleBetweenCond, read_double_list);
 
 #[derive(Clone)]
@@ -619,10 +759,6 @@ macro_rules! string_filter {
         $other_str.ends_with($filter_str)
     };
 
-    (StringContains $filter_str:expr, $other_str:ident) => {
-        $other_str.contains($filter_str)
-    };
-
     (StringMatches $filter_str:expr, $other_str:ident) => {
         fast_wild_match($other_str, $filter_str)
     };
@@ -630,9 +766,58 @@ macro_rules! string_filter {
 
 string_filter!(StringStartsWith);
 string_filter!(StringEndsWith);
-string_filter!(StringContains);
 string_filter!(StringMatches);
 
+#[derive(Clone)]
+struct StringContainsCond {
+    offset: usize,
+    finder: Finder<'static>,
+    case_sensitive: bool,
+}
+
+#[derive(Clone)]
+struct AnyStringContainsCond {
+    offset: usize,
+    finder: Finder<'static>,
+    case_sensitive: bool,
+}
+
+fn string_contains(value: Option<&str>, finder: &Finder, case_sensitive: bool) -> bool {
+    if let Some(other_str) = value {
+        let needs_lowercase = !case_sensitive
+            && (!other_str.is_ascii() || other_str.bytes().any(|b| b.is_ascii_uppercase()));
+        if needs_lowercase {
+            let lowercase_str = other_str.to_lowercase();
+            finder.find(lowercase_str.as_bytes()).is_some()
+        } else {
+            finder.find(other_str.as_bytes()).is_some()
+        }
+    } else {
+        false
+    }
+}
+
+impl Condition for StringContainsCond {
+    fn evaluate(&self, _id: i64, object: IsarObject, _: Option<&IsarCursors>) -> Result<bool> {
+        let value = object.read_string(self.offset);
+        Ok(string_contains(value, &self.finder, self.case_sensitive))
+    }
+}
+
+impl Condition for AnyStringContainsCond {
+    fn evaluate(&self, _id: i64, object: IsarObject, _: Option<&IsarCursors>) -> Result<bool> {
+        let list = object.read_string_list(self.offset);
+        if let Some(list) = list {
+            for value in list {
+                if string_contains(value, &self.finder, self.case_sensitive) {
+                    return Ok(true);
+                }
+            }
+        }
+        Ok(false)
+    }
+}
+
 #[derive(Clone)]
 struct ListLengthCond {
     offset: usize,
@@ -655,6 +840,41 @@ impl Condition for ListLengthCond {
     }
 }
 
+#[derive(Clone)]
+struct GeoWithinBoxCond {
+    lat_offset: usize,
+    lon_offset: usize,
+    geo_box: GeoBox,
+}
+
+impl Condition for GeoWithinBoxCond {
+    fn evaluate(&self, _id: i64, object: IsarObject, _: Option<&IsarCursors>) -> Result<bool> {
+        let lat = object.read_double(self.lat_offset);
+        let lon = object.read_double(self.lon_offset);
+        Ok(self.geo_box.contains(lat, lon))
+    }
+}
+
+#[derive(Clone)]
+struct GeoWithinRadiusCond {
+    lat_offset: usize,
+    lon_offset: usize,
+    center_lat: f64,
+    center_lon: f64,
+    radius: f64,
+}
+
+impl Condition for GeoWithinRadiusCond {
+    fn evaluate(&self, _id: i64, object: IsarObject, _: Option<&IsarCursors>) -> Result<bool> {
+        let lat = object.read_double(self.lat_offset);
+        let lon = object.read_double(self.lon_offset);
+        if lat.is_nan() || lon.is_nan() {
+            return Ok(false);
+        }
+        Ok(distance(self.center_lat, self.center_lon, lat, lon) <= self.radius)
+    }
+}
+
 #[derive(Clone)]
 struct NullCond {
     offset: usize,
@@ -839,15 +1059,193 @@ impl Condition for LinkLengthCond {
         cursors: Option<&IsarCursors>,
     ) -> Result<bool> {
         if let Some(cursors) = cursors {
-            let mut length = 0;
-            self.link.iter_ids(cursors, id, |_, _| {
-                length += 1;
-                Ok(true)
-            })?;
-
+            let length = self.link.count(cursors, id)?;
             Ok(self.lower <= length && self.upper >= length)
         } else {
             Ok(true)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instance::tests::{collection, find_ids, open_instance, put_object};
+    use crate::instance::IsarInstance;
+    use crate::query::Sort;
+    use serde_json::{json, Value};
+
+    /// Ids of the objects of the collection that match the filter.
+    fn filter_ids(instance: &IsarInstance, col: &IsarCollection, filter: Filter) -> Vec<i64> {
+        let mut qb = col.new_query_builder();
+        qb.set_filter(filter);
+        find_ids(instance, &qb.build())
+    }
+
+    #[test]
+    fn test_string_contains() {
+        let col = collection(
+            "col",
+            json!([{"name": "name", "type": "String"}, {"name": "tags", "type": "StringList"}]),
+        );
+        let instance = open_instance(json!([col]));
+        let col = &instance.collections()[0];
+        let put =
+            |name, tags| put_object(&instance, col, None, json!({"name": name, "tags": tags}));
+        let a = put(json!("Isar"), json!(["Fast"]));
+        let b = put(json!("isolate"), json!(null));
+        let c = put(json!(null), json!(["db", "fast"]));
+
+        let name = col.property("name").unwrap();
+        let contains = |value, case_sensitive| {
+            let filter = Filter::string_contains(name, value, case_sensitive).unwrap();
+            filter_ids(&instance, col, filter)
+        };
+        assert_eq!(contains("sa", true), vec![a]);
+        assert_eq!(contains("IS", false), vec![a, b]);
+        assert_eq!(contains("IS", true), Vec::<i64>::new());
+        assert_eq!(contains("", true), vec![a, b]);
+
+        let tags = col.property("tags").unwrap();
+        let filter = Filter::string_contains(tags, "fa", true).unwrap();
+        assert_eq!(filter_ids(&instance, col, filter), vec![c]);
+        let filter = Filter::string_contains(tags, "FA", false).unwrap();
+        assert_eq!(filter_ids(&instance, col, filter), vec![a, c]);
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_float_nan_policy() {
+        let col = collection(
+            "col",
+            json!([{"name": "value", "type": "Double"}, {"name": "values", "type": "FloatList"}]),
+        );
+        let instance = open_instance(json!([col]));
+        let col = &instance.collections()[0];
+        let put = |value: Value| {
+            let object = json!({"value": value, "values": [value]});
+            put_object(&instance, col, None, object)
+        };
+        // null is stored as NaN
+        let nan = put(json!(null));
+        let one = put(json!(1.0));
+
+        let value = col.property("value").unwrap();
+        let double = |lower, upper, nan| {
+            let filter = Filter::double_nan(value, lower, upper, nan).unwrap();
+            filter_ids(&instance, col, filter)
+        };
+        assert_eq!(double(0.0, 2.0, NanPolicy::Include), vec![nan, one]);
+        assert_eq!(double(0.0, 2.0, NanPolicy::Exclude), vec![one]);
+        assert_eq!(double(f64::NAN, 2.0, NanPolicy::Exclude), vec![one]);
+        assert_eq!(double(2.0, 3.0, NanPolicy::Include), vec![nan]);
+        let values = col.property("values").unwrap();
+        let filter = Filter::float_nan(values, 0.0, 2.0, NanPolicy::Include).unwrap();
+        assert_eq!(filter_ids(&instance, col, filter), vec![nan, one]);
+        let filter = Filter::float_nan(values, 0.0, 2.0, NanPolicy::Exclude).unwrap();
+        assert_eq!(filter_ids(&instance, col, filter), vec![one]);
+
+        // the implicit policy only matches NaN with a NaN lower bound
+        let filter = Filter::double(value, f64::NAN, 2.0).unwrap();
+        assert_eq!(filter_ids(&instance, col, filter), vec![nan, one]);
+        let filter = Filter::double(value, f64::NEG_INFINITY, 2.0).unwrap();
+        assert_eq!(filter_ids(&instance, col, filter), vec![one]);
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_object_path() {
+        let mut country = collection("country", json!([{"name": "name", "type": "String"}]));
+        country["embedded"] = json!(true);
+        let mut address = collection(
+            "address",
+            json!([
+                {"name": "city", "type": "String"},
+                {"name": "country", "type": "Object", "target": "country"},
+            ]),
+        );
+        address["embedded"] = json!(true);
+        let col = collection(
+            "col",
+            json!([{"name": "addresses", "type": "ObjectList", "target": "address"}]),
+        );
+        let instance = open_instance(json!([col, address, country]));
+        // the collections are sorted by name
+        let col = &instance.collections()[1];
+        let put = |city, country| {
+            let address = json!({"city": city, "country": {"name": country}});
+            put_object(&instance, col, None, json!({ "addresses": [address] }))
+        };
+        let berlin = put("Berlin", "Germany");
+        let paris = put("Paris", "France");
+
+        let addresses = col.property("addresses").unwrap();
+        let embedded = col.embedded_properties.get(addresses.target_id.unwrap());
+        let find = |name: &str| embedded.unwrap().iter().find(|p| p.name == name).unwrap();
+        let (city, country) = (find("city"), find("country"));
+        let country_names = col.embedded_properties.get(country.target_id.unwrap());
+        let country_name = &country_names.unwrap()[0];
+
+        let filter = Filter::string(city, Some("Paris"), Some("Paris"), true).unwrap();
+        let filter = Filter::object_path(&[addresses], filter).unwrap();
+        assert_eq!(filter_ids(&instance, col, filter), vec![paris]);
+        let filter = Filter::string(country_name, Some("Germany"), Some("Germany"), true);
+        let filter = Filter::object_path(&[addresses, country], filter.unwrap()).unwrap();
+        assert_eq!(filter_ids(&instance, col, filter), vec![berlin]);
+
+        assert!(Filter::object_path(&[], Filter::stat(true)).is_err());
+        assert!(Filter::object_path(&[city], Filter::stat(true)).is_err());
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_filter_by_name() {
+        let col = collection(
+            "col",
+            json!([{"name": "name", "type": "String"}, {"name": "value", "type": "Long"}]),
+        );
+        let instance = open_instance(json!([col]));
+        let col = &instance.collections()[0];
+        let a = put_object(&instance, col, None, json!({"name": "a", "value": 3}));
+        let b = put_object(&instance, col, None, json!({"name": "b", "value": 1}));
+        put_object(&instance, col, None, json!({"name": "c", "value": 9}));
+
+        let filter = Filter::by_name(col, "value", |p| Filter::long(p, 0, 5)).unwrap();
+        let mut qb = col.new_query_builder();
+        qb.set_filter(filter);
+        qb.add_sort_by_name("value", Sort::Ascending).unwrap();
+        assert_eq!(find_ids(&instance, &qb.build()), vec![b, a]);
+
+        assert!(Filter::by_name(col, "missing", |p| Ok(Filter::null(p))).is_err());
+        let string_filter = Filter::by_name(col, "value", |p| Filter::string(p, None, None, true));
+        assert!(string_filter.is_err());
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_link_count() {
+        let mut col = collection("col", json!([]));
+        col["links"] = json!([{"name": "link", "target": "col"}]);
+        let instance = open_instance(json!([col]));
+        let col = &instance.collections()[0];
+        let ids = (0..3)
+            .map(|_| put_object(&instance, col, None, json!({})))
+            .collect::<Vec<_>>();
+        let link_id = col.link_id("link").unwrap();
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        col.link_all(&mut txn, link_id, ids[0], &ids).unwrap();
+        col.link(&mut txn, link_id, ids[1], ids[2]).unwrap();
+        assert_eq!(col.link_count(&mut txn, link_id, ids[0]).unwrap(), 3);
+        assert_eq!(col.link_count(&mut txn, link_id, ids[2]).unwrap(), 0);
+        txn.commit().unwrap();
+
+        let count = |min, max| {
+            let filter = Filter::link_count(col, link_id, min, max).unwrap();
+            filter_ids(&instance, col, filter)
+        };
+        assert_eq!(count(1, usize::MAX), vec![ids[0], ids[1]]);
+        assert_eq!(count(0, 1), vec![ids[1], ids[2]]);
+        assert_eq!(count(3, 3), vec![ids[0]]);
+        assert!(instance.close_and_delete());
+    }
+}
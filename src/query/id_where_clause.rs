@@ -1,9 +1,9 @@
 use crate::cursor::IsarCursors;
 use crate::error::Result;
-use crate::mdbx::db::Db;
 use crate::object::id::BytesToId;
 use crate::object::isar_object::IsarObject;
 use crate::query::Sort;
+use crate::storage::{Db, StorageCursor};
 use intmap::IntMap;
 
 #[derive(Clone)]
@@ -28,6 +28,10 @@ impl IdWhereClause {
         self.upper < self.lower
     }
 
+    pub(crate) fn is_unrestricted(&self) -> bool {
+        self.lower == i64::MIN && self.upper == i64::MAX
+    }
+
     pub(crate) fn id_matches(&self, id: i64) -> bool {
         self.lower <= id && self.upper >= id
     }
@@ -35,19 +39,49 @@ impl IdWhereClause {
     pub(crate) fn iter<'txn, 'env, F>(
         &self,
         cursors: &IsarCursors<'txn, 'env>,
+        result_ids: Option<&mut IntMap<()>>,
+        callback: F,
+    ) -> Result<bool>
+    where
+        F: FnMut(i64, IsarObject<'txn>) -> Result<bool>,
+    {
+        self.iter_from(cursors, None, result_ids, callback)
+    }
+
+    pub(crate) fn iter_from<'txn, 'env, F>(
+        &self,
+        cursors: &IsarCursors<'txn, 'env>,
+        start: Option<i64>,
         mut result_ids: Option<&mut IntMap<()>>,
         mut callback: F,
     ) -> Result<bool>
     where
         F: FnMut(i64, IsarObject<'txn>) -> Result<bool>,
     {
+        let ascending = self.sort == Sort::Ascending;
+        let (lower, upper) = match start {
+            Some(start) if ascending => {
+                if start == i64::MAX {
+                    return Ok(true);
+                }
+                (self.lower.max(start + 1), self.upper)
+            }
+            Some(start) => {
+                if start == i64::MIN {
+                    return Ok(true);
+                }
+                (self.lower, self.upper.min(start - 1))
+            }
+            None => (self.lower, self.upper),
+        };
+
         let mut cursor = cursors.get_cursor(self.db)?;
         cursor.iter_between(
-            &self.lower,
-            &self.upper,
+            &lower,
+            &upper,
             false,
             false,
-            self.sort == Sort::Ascending,
+            ascending,
             |_, id_bytes, object| {
                 let id = id_bytes.to_id();
                 if let Some(result_ids) = result_ids.as_deref_mut() {
@@ -61,6 +95,34 @@ impl IdWhereClause {
         )
     }
 
+    /// Splits the part of the range between `min` and `max` into at most `parts` ranges. The
+    /// ranges are returned in the order they are iterated.
+    pub(crate) fn split(&self, min: i64, max: i64, parts: usize) -> Vec<IdWhereClause> {
+        let lower = self.lower.max(min);
+        let upper = self.upper.min(max);
+        if upper < lower {
+            return vec![];
+        }
+        let width = (upper as i128 - lower as i128 + 1) as u128;
+        let step = (width / parts.max(1) as u128).max(1);
+        let mut ranges = vec![];
+        let mut start = lower as i128;
+        while start <= upper as i128 {
+            let end = (start + step as i128 - 1).min(upper as i128);
+            ranges.push(IdWhereClause::new(
+                self.db,
+                start as i64,
+                end as i64,
+                self.sort,
+            ));
+            start = end + 1;
+        }
+        if self.sort == Sort::Descending {
+            ranges.reverse();
+        }
+        ranges
+    }
+
     pub(crate) fn is_overlapping(&self, other: &Self) -> bool {
         (self.lower <= other.lower && self.upper >= other.upper)
             || (other.lower <= self.lower && other.upper >= self.upper)
@@ -3,10 +3,13 @@ use crate::error::{IsarError, Result};
 use crate::index::index_key::IndexKey;
 use crate::index::index_key_builder::IndexKeyBuilder;
 use crate::index::IsarIndex;
-use crate::mdbx::db::Db;
+use crate::object::id::BytesToId;
 use crate::object::isar_object::IsarObject;
+use crate::query::filter::Filter;
 use crate::query::Sort;
+use crate::storage::{Db, Key, StorageCursor};
 use intmap::IntMap;
+use std::cmp::Ordering;
 
 #[derive(Clone)]
 pub(crate) struct IndexWhereClause {
@@ -16,6 +19,8 @@ pub(crate) struct IndexWhereClause {
     upper_key: IndexKey,
     skip_duplicates: bool,
     sort: Sort,
+    scan: bool,
+    filter: Option<Box<Filter>>,
 }
 
 impl IndexWhereClause {
@@ -27,6 +32,8 @@ impl IndexWhereClause {
         skip_duplicates: bool,
         sort: Sort,
     ) -> Result<Self> {
+        // pending indexes are incomplete so the collection has to be scanned instead
+        let scan = index.is_pending();
         Ok(IndexWhereClause {
             db,
             index,
@@ -34,9 +41,39 @@ impl IndexWhereClause {
             upper_key,
             skip_duplicates,
             sort,
+            scan,
+            filter: None,
         })
     }
 
+    pub fn index_id(&self) -> u64 {
+        self.index.id
+    }
+
+    pub fn index(&self) -> &IsarIndex {
+        &self.index
+    }
+
+    /// Whether the where clause can return keys and ids without loading the objects.
+    pub fn is_covering(&self) -> bool {
+        !self.scan && self.filter.is_none()
+    }
+
+    /// Only objects matching the filter are returned. Used for index ranges that over-approximate
+    /// the requested values.
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = Some(Box::new(filter));
+        self
+    }
+
+    fn filter_matches(&self, id: i64, object: IsarObject) -> Result<bool> {
+        if let Some(filter) = &self.filter {
+            filter.evaluate(id, object, None)
+        } else {
+            Ok(true)
+        }
+    }
+
     pub fn object_matches(&self, object: IsarObject) -> bool {
         let mut key_matches = false;
         let key_builder = IndexKeyBuilder::new(&self.index.properties);
@@ -46,52 +83,175 @@ impl IndexWhereClause {
                 Ok(!key_matches)
             })
             .unwrap();
-        key_matches
+        key_matches && self.filter_matches(0, object).unwrap_or(false)
     }
 
-    pub fn iter_ids<'txn, 'env, F>(
+    pub fn iter<'txn, 'env, F>(
         &self,
         cursors: &IsarCursors<'txn, 'env>,
-        callback: F,
+        result_ids: Option<&mut IntMap<()>>,
+        mut callback: F,
     ) -> Result<bool>
     where
-        F: FnMut(i64) -> Result<bool>,
+        F: FnMut(i64, IsarObject<'txn>) -> Result<bool>,
     {
-        self.index.iter_between(
+        self.iter_from(cursors, None, result_ids, |_, id, object| {
+            callback(id, object)
+        })
+    }
+
+    pub fn iter_from<'txn, 'env, F>(
+        &self,
+        cursors: &IsarCursors<'txn, 'env>,
+        start: Option<(&[u8], i64)>,
+        mut result_ids: Option<&mut IntMap<()>>,
+        mut callback: F,
+    ) -> Result<bool>
+    where
+        F: FnMut(&[u8], i64, IsarObject<'txn>) -> Result<bool>,
+    {
+        if self.scan {
+            return self.iter_scan(cursors, start, result_ids, callback);
+        }
+
+        let ascending = self.sort == Sort::Ascending;
+        let (lower_key, upper_key) = if let Some((start_key, _)) = start {
+            let start_key = IndexKey::from_bytes(start_key.to_vec());
+            if ascending {
+                (start_key, self.upper_key.clone())
+            } else {
+                (self.lower_key.clone(), start_key)
+            }
+        } else {
+            (self.lower_key.clone(), self.upper_key.clone())
+        };
+
+        let mut data_cursor = cursors.get_cursor(self.db)?;
+        self.index.iter_between_with_key(
+            cursors,
+            &lower_key,
+            &upper_key,
+            self.skip_duplicates,
+            ascending,
+            |key, id| {
+                if self.is_before_start(key, id, start) {
+                    return Ok(true);
+                }
+
+                let entry = data_cursor.move_to(&id)?;
+                let (_, object) = entry.ok_or(IsarError::DbCorrupted {
+                    message: "Could not find object specified in index.".to_string(),
+                })?;
+                let object = IsarObject::from_bytes(&object);
+                if !self.filter_matches(id, object)? {
+                    return Ok(true);
+                }
+
+                if let Some(result_ids) = result_ids.as_deref_mut() {
+                    if !result_ids.insert_checked(id as u64, ()) {
+                        return Ok(true);
+                    }
+                }
+
+                callback(key, id, object)
+            },
+        )
+    }
+
+    /// Whether the entry comes before the start position and has already been returned.
+    fn is_before_start(&self, key: &[u8], id: i64, start: Option<(&[u8], i64)>) -> bool {
+        if let Some((start_key, start_id)) = start {
+            let ascending = self.sort == Sort::Ascending;
+            match key.cmp(start_key) {
+                Ordering::Less => ascending,
+                Ordering::Greater => !ascending,
+                Ordering::Equal => {
+                    self.skip_duplicates
+                        || (ascending && id <= start_id)
+                        || (!ascending && id >= start_id)
+                }
+            }
+        } else {
+            false
+        }
+    }
+
+    /// Iterates the matching keys and ids without loading the objects. Requires a covering where
+    /// clause.
+    pub fn iter_keys<'txn, 'env, F>(
+        &self,
+        cursors: &IsarCursors<'txn, 'env>,
+        mut result_ids: Option<&mut IntMap<()>>,
+        mut callback: F,
+    ) -> Result<bool>
+    where
+        F: FnMut(&'txn [u8], i64) -> Result<bool>,
+    {
+        self.index.iter_between_with_key(
             cursors,
             &self.lower_key,
             &self.upper_key,
             self.skip_duplicates,
             self.sort == Sort::Ascending,
-            callback,
+            |key, id| {
+                if let Some(result_ids) = result_ids.as_deref_mut() {
+                    if !result_ids.insert_checked(id as u64, ()) {
+                        return Ok(true);
+                    }
+                }
+                callback(key, id)
+            },
         )
     }
 
-    pub fn iter<'txn, 'env, F>(
+    /// Builds the index entries of all objects and returns them in index order.
+    fn iter_scan<'txn, 'env, F>(
         &self,
         cursors: &IsarCursors<'txn, 'env>,
+        start: Option<(&[u8], i64)>,
         mut result_ids: Option<&mut IntMap<()>>,
         mut callback: F,
     ) -> Result<bool>
     where
-        F: FnMut(i64, IsarObject<'txn>) -> Result<bool>,
+        F: FnMut(&[u8], i64, IsarObject<'txn>) -> Result<bool>,
     {
-        let mut data_cursor = cursors.get_cursor(self.db)?;
-        self.iter_ids(cursors, |id| {
+        let key_builder = IndexKeyBuilder::new(&self.index.properties);
+        let mut entries = vec![];
+        let mut cursor = cursors.get_cursor(self.db)?;
+        cursor.iter_all(false, true, |_, id_bytes, object| {
+            let id = id_bytes.to_id();
+            let object = IsarObject::from_bytes(object);
+            key_builder.create_keys(object, |key| {
+                if key >= &self.lower_key && key <= &self.upper_key {
+                    entries.push((key.clone(), id, object));
+                }
+                Ok(true)
+            })
+        })?;
+
+        entries.sort_unstable_by(|(k1, id1, _), (k2, id2, _)| k1.cmp(k2).then(id1.cmp(id2)));
+        if self.sort == Sort::Descending {
+            entries.reverse();
+        }
+        if self.skip_duplicates {
+            entries.dedup_by(|(k1, _, _), (k2, _, _)| k1 == k2);
+        }
+
+        for (key, id, object) in entries {
+            let key = key.as_bytes();
+            if self.is_before_start(&key, id, start) || !self.filter_matches(id, object)? {
+                continue;
+            }
             if let Some(result_ids) = result_ids.as_deref_mut() {
                 if !result_ids.insert_checked(id as u64, ()) {
-                    return Ok(true);
+                    continue;
                 }
             }
-
-            let entry = data_cursor.move_to(&id)?;
-            let (_, object) = entry.ok_or(IsarError::DbCorrupted {
-                message: "Could not find object specified in index.".to_string(),
-            })?;
-            let object = IsarObject::from_bytes(&object);
-
-            callback(id, object)
-        })
+            if !callback(&key, id, object)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
     }
 
     pub fn is_overlapping(&self, other: &Self) -> bool {
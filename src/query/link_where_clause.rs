@@ -18,13 +18,37 @@ impl LinkWhereClause {
     pub fn iter<'txn, 'env, F>(
         &self,
         cursors: &IsarCursors<'txn, 'env>,
+        result_ids: Option<&mut IntMap<()>>,
+        callback: F,
+    ) -> Result<bool>
+    where
+        F: FnMut(i64, IsarObject<'txn>) -> Result<bool>,
+    {
+        self.iter_from(cursors, None, result_ids, callback)
+    }
+
+    pub fn iter_from<'txn, 'env, F>(
+        &self,
+        cursors: &IsarCursors<'txn, 'env>,
+        start: Option<i64>,
         mut result_ids: Option<&mut IntMap<()>>,
         mut callback: F,
     ) -> Result<bool>
     where
         F: FnMut(i64, IsarObject<'txn>) -> Result<bool>,
     {
+        // ordered links are not sorted by id so everything up to the start id is skipped
+        let mut skip = start.is_some() && self.link.is_ordered();
         self.link.iter(cursors, self.id, |id, object| {
+            if skip {
+                skip = Some(id) != start;
+                return Ok(true);
+            }
+            if let Some(start) = start.filter(|_| !self.link.is_ordered()) {
+                if id <= start {
+                    return Ok(true);
+                }
+            }
             if let Some(result_ids) = result_ids.as_deref_mut() {
                 if !result_ids.insert_checked(id as u64, ()) {
                     return Ok(true);
@@ -1,31 +1,56 @@
 use intmap::IntMap;
 use serde_json::{json, Value};
 use std::cmp::Ordering;
+use std::mem::size_of;
+use std::sync::atomic::{self, AtomicBool};
+use std::sync::Arc;
 
 use crate::collection::IsarCollection;
 use crate::cursor::IsarCursors;
-use crate::error::Result;
+use crate::error::{illegal_arg, IsarError, Result};
+use crate::index::index_key::IndexKey;
+use crate::link::IsarLink;
+use crate::object::data_type::DataType;
 use crate::object::isar_object::IsarObject;
 use crate::object::json_encode_decode::JsonEncodeDecode;
 use crate::object::property::Property;
+use crate::object::property_transform::PropertyTransforms;
+use crate::query::external_sort::SortDb;
 use crate::query::filter::Filter;
+use crate::query::projection::{ColumnBatch, PropertyValues};
+use crate::query::query_cursor::QueryCursor;
 use crate::query::where_clause::WhereClause;
+use crate::storage::{Db, Key, StorageCursor};
 use crate::txn::IsarTxn;
 
+mod external_sort;
 mod fast_wild_match;
 pub mod filter;
 mod id_where_clause;
 mod index_where_clause;
 mod link_where_clause;
+pub mod projection;
 pub mod query_builder;
+pub mod query_cursor;
 mod where_clause;
 
+pub type JoinedObjects<'txn> = ((i64, IsarObject<'txn>), (i64, IsarObject<'txn>));
+
 #[derive(Copy, Clone, Eq, PartialEq)]
 pub enum Sort {
     Ascending,
     Descending,
 }
 
+/// How NaN values are handled by float and double where clauses and filters.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum NanPolicy {
+    Include,
+    Exclude,
+}
+
+/// Whether strings are compared with or without their case.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum Case {
     Sensitive,
     Insensitive,
@@ -37,22 +62,40 @@ pub struct Query {
     where_clauses: Vec<WhereClause>,
     where_clauses_dup: bool,
     filter: Option<Filter>,
-    sort: Vec<(Property, Sort)>,
+    sort: Vec<(Property, Sort, Option<IsarLink>)>,
     distinct: Vec<(Property, bool)>,
     offset: usize,
     limit: usize,
+    cancel: Arc<AtomicBool>,
+    transforms: PropertyTransforms,
+    /// Maximum number of bytes buffered by sorted and distinct executions.
+    memory_limit: usize,
+    /// Sorted executions that exceed the memory limit are sorted in a temporary database in this
+    /// directory.
+    spill_dir: Option<String>,
+    db: Db,
 }
 
 impl<'txn> Query {
+    const CANCEL_CHECK_STEP: usize = 1000;
+    const DISTINCT_HASH_SIZE: usize = 16;
+    const SORT_DB_BATCH_SIZE: usize = 10000;
+    const MAX_SORT_KEY_LEN: usize = 1000;
+
     #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         instance_id: u64,
         where_clauses: Vec<WhereClause>,
         filter: Option<Filter>,
-        sort: Vec<(Property, Sort)>,
+        sort: Vec<(Property, Sort, Option<IsarLink>)>,
         distinct: Vec<(Property, bool)>,
         offset: usize,
         limit: usize,
+        cancel: Arc<AtomicBool>,
+        transforms: PropertyTransforms,
+        memory_limit: usize,
+        spill_dir: Option<String>,
+        db: Db,
     ) -> Self {
         let where_clauses_dup = Self::check_where_clauses_duplicates(&where_clauses);
         Query {
@@ -64,6 +107,33 @@ impl<'txn> Query {
             distinct,
             offset,
             limit,
+            cancel,
+            transforms,
+            memory_limit,
+            spill_dir,
+            db,
+        }
+    }
+
+    /// Stops running executions of the query. Executions fail with `Cancelled` until the query is
+    /// rebuilt.
+    pub fn cancel(&self) {
+        self.cancel.store(true, atomic::Ordering::Relaxed);
+    }
+
+    fn check_memory(&self, used: usize) -> Result<()> {
+        if used > self.memory_limit {
+            Err(IsarError::QueryTooLarge {})
+        } else {
+            Ok(())
+        }
+    }
+
+    fn check_cancelled(&self) -> Result<()> {
+        if self.cancel.load(atomic::Ordering::Relaxed) {
+            Err(IsarError::Cancelled {})
+        } else {
+            Ok(())
         }
     }
 
@@ -98,8 +168,14 @@ impl<'txn> Query {
         let static_filter = Filter::stat(true);
         let filter = self.filter.as_ref().unwrap_or(&static_filter);
 
+        self.check_cancelled()?;
+        let mut checked = 0;
         for where_clause in &self.where_clauses {
             let result = where_clause.iter(cursors, result_ids.as_mut(), |id, object| {
+                checked += 1;
+                if checked % Self::CANCEL_CHECK_STEP == 0 {
+                    self.check_cancelled()?;
+                }
                 if filter.evaluate(id, object, Some(cursors))? {
                     callback(id, object)
                 } else {
@@ -148,10 +224,17 @@ impl<'txn> Query {
         F: FnMut(i64, IsarObject<'txn>) -> Result<bool>,
     {
         let properties = self.distinct.clone();
+        let memory_limit = self.memory_limit;
         let mut hashes = IntMap::new();
         move |id, object| {
+            if properties.is_empty() {
+                return callback(id, object);
+            }
             let hash = Self::hash_properties(object, &properties);
             if hashes.insert_checked(hash, ()) {
+                if hashes.len() * Self::DISTINCT_HASH_SIZE > memory_limit {
+                    return Err(IsarError::QueryTooLarge {});
+                }
                 callback(id, object)
             } else {
                 Ok(true)
@@ -183,25 +266,17 @@ impl<'txn> Query {
         &self,
         cursors: &IsarCursors<'txn, 'env>,
     ) -> Result<Vec<(i64, IsarObject<'txn>)>> {
+        let entry_size = size_of::<(i64, IsarObject)>();
+        let mut used = 0;
         let mut results = vec![];
         self.execute_raw(cursors, |id, object| {
+            used += entry_size + object.len();
+            self.check_memory(used)?;
             results.push((id, object));
             Ok(true)
         })?;
 
-        results.sort_unstable_by(|(_, o1), (_, o2)| {
-            for (p, sort) in &self.sort {
-                let ord = o1.compare_property(o2, p.offset, p.data_type);
-                if ord != Ordering::Equal {
-                    return if *sort == Sort::Ascending {
-                        ord
-                    } else {
-                        ord.reverse()
-                    };
-                }
-            }
-            Ordering::Equal
-        });
+        self.sort_results(cursors, &mut results)?;
 
         if !self.distinct.is_empty() {
             Ok(self.add_distinct_sorted(results))
@@ -210,6 +285,236 @@ impl<'txn> Query {
         }
     }
 
+    fn sort_results<'env>(
+        &self,
+        cursors: &IsarCursors<'txn, 'env>,
+        results: &mut [(i64, IsarObject<'txn>)],
+    ) -> Result<()> {
+        let mut linked = IntMap::new();
+        if self.has_link_sort() {
+            for (id, _) in results.iter() {
+                linked.insert(*id as u64, self.get_sort_links(cursors, *id)?);
+            }
+        }
+
+        let case_insensitive = self.sort_case_insensitive();
+        results.sort_unstable_by(|(id1, o1), (id2, o2)| {
+            let l1 = linked
+                .get(*id1 as u64)
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            let l2 = linked
+                .get(*id2 as u64)
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            self.compare_sorted(&case_insensitive, (*o1, l1), (*o2, l2))
+        });
+        Ok(())
+    }
+
+    fn has_link_sort(&self) -> bool {
+        self.sort.iter().any(|(_, _, link)| link.is_some())
+    }
+
+    /// Sorting by a case-insensitive distinct property follows the case-insensitive collation.
+    fn sort_case_insensitive(&self) -> Vec<bool> {
+        self.sort
+            .iter()
+            .map(|(p, _, link)| link.is_none() && self.distinct.iter().any(|(d, cs)| d == p && !cs))
+            .collect()
+    }
+
+    fn compare_sorted(
+        &self,
+        case_insensitive: &[bool],
+        (o1, linked1): (IsarObject, &[Option<IsarObject>]),
+        (o2, linked2): (IsarObject, &[Option<IsarObject>]),
+    ) -> Ordering {
+        for (i, (p, sort, link)) in self.sort.iter().enumerate() {
+            let ord = if link.is_some() {
+                match (linked1[i], linked2[i]) {
+                    (Some(l1), Some(l2)) => l1.compare_property(&l2, p.offset, p.data_type),
+                    (l1, l2) => l1.is_some().cmp(&l2.is_some()),
+                }
+            } else if case_insensitive[i] {
+                o1.compare_property_case_insensitive(&o2, p.offset, p.data_type)
+            } else {
+                o1.compare_property(&o2, p.offset, p.data_type)
+            };
+            if ord != Ordering::Equal {
+                return if *sort == Sort::Ascending {
+                    ord
+                } else {
+                    ord.reverse()
+                };
+            }
+        }
+        Ordering::Equal
+    }
+
+    /// Sorts the results in a temporary database in `dir` so only the objects that share a
+    /// truncated sort key are kept in memory.
+    fn execute_sorted_external<'env, F>(
+        &self,
+        cursors: &IsarCursors<'txn, 'env>,
+        dir: &str,
+        callback: F,
+    ) -> Result<()>
+    where
+        F: FnMut(i64, IsarObject<'txn>) -> Result<bool>,
+    {
+        let sort_db = SortDb::create(dir)?;
+        let has_links = self.has_link_sort();
+        let case_insensitive = self.sort_case_insensitive();
+        let mut keys = vec![];
+        self.execute_raw(cursors, |id, object| {
+            let linked = if has_links {
+                self.get_sort_links(cursors, id)?
+            } else {
+                vec![]
+            };
+            keys.push(self.sort_key(id, object, &linked, &case_insensitive));
+            if keys.len() == Self::SORT_DB_BATCH_SIZE {
+                sort_db.insert(&mut keys)?;
+            }
+            Ok(true)
+        })?;
+        sort_db.insert(&mut keys)?;
+
+        let callback = self.add_distinct_unsorted(callback);
+        let mut callback = self.add_offset_limit_unsorted(callback);
+        let mut object_cursor = cursors.get_cursor(self.db)?;
+        let mut run = vec![];
+        let mut run_key = vec![];
+        sort_db.iter(|key| {
+            let (sort_key, id_bytes) = key.split_at(key.len() - 8);
+            let id = (u64::from_be_bytes(id_bytes.try_into().unwrap()) ^ 1 << 63) as i64;
+            if !run.is_empty() && sort_key != run_key {
+                self.sort_results(cursors, &mut run)?;
+                for (id, object) in run.drain(..) {
+                    if !callback(id, object)? {
+                        return Ok(false);
+                    }
+                }
+            }
+
+            let object = if let Some((_, bytes)) = object_cursor.move_to(&id)? {
+                IsarObject::from_bytes(bytes)
+            } else {
+                return Err(IsarError::DbCorrupted {
+                    message: "Sorted object does not exist".to_string(),
+                });
+            };
+            if sort_key.len() == Self::MAX_SORT_KEY_LEN {
+                // the order of truncated keys is only known after comparing the objects
+                if run.is_empty() {
+                    run_key = sort_key.to_vec();
+                }
+                run.push((id, object));
+                Ok(true)
+            } else {
+                callback(id, object)
+            }
+        })?;
+
+        self.sort_results(cursors, &mut run)?;
+        for (id, object) in run {
+            if !callback(id, object)? {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Encodes the sort properties so that the keys are ordered like `compare_sorted` orders the
+    /// objects. Ties are ordered by id.
+    fn sort_key(
+        &self,
+        id: i64,
+        object: IsarObject,
+        linked: &[Option<IsarObject>],
+        case_insensitive: &[bool],
+    ) -> Vec<u8> {
+        let mut key = vec![];
+        for (i, (p, sort, link)) in self.sort.iter().enumerate() {
+            let start = key.len();
+            if link.is_some() {
+                if let Some(linked) = linked[i] {
+                    key.push(1);
+                    Self::add_sort_value(&mut key, linked, p, false);
+                } else {
+                    key.push(0);
+                }
+            } else {
+                Self::add_sort_value(&mut key, object, p, case_insensitive[i]);
+            }
+            if *sort == Sort::Descending {
+                for byte in &mut key[start..] {
+                    *byte = !*byte;
+                }
+            }
+        }
+        key.truncate(Self::MAX_SORT_KEY_LEN);
+        key.extend_from_slice(&(id as u64 ^ 1 << 63).to_be_bytes());
+        key
+    }
+
+    /// Every value is prefix-free so the keys of multiple properties can be concatenated.
+    fn add_sort_value(key: &mut Vec<u8>, object: IsarObject, p: &Property, case_insensitive: bool) {
+        let mut index_key = IndexKey::new();
+        match p.data_type {
+            DataType::Bool | DataType::Byte => index_key.add_byte(object.read_byte(p.offset)),
+            DataType::Int => index_key.add_int(object.read_int(p.offset)),
+            DataType::Float => index_key.add_float(object.read_float(p.offset)),
+            DataType::Long => index_key.add_long(object.read_long(p.offset)),
+            DataType::Double => index_key.add_double(object.read_double(p.offset)),
+            DataType::String => {
+                if let Some(value) = object.read_string(p.offset) {
+                    key.push(1);
+                    if case_insensitive {
+                        Self::add_sort_string(key, &value.to_lowercase());
+                    }
+                    Self::add_sort_string(key, value);
+                } else {
+                    key.push(0);
+                }
+            }
+            _ => {}
+        }
+        key.extend_from_slice(&index_key.as_bytes());
+    }
+
+    /// Zero bytes are escaped so the string can be terminated by two zero bytes.
+    fn add_sort_string(key: &mut Vec<u8>, value: &str) {
+        for byte in value.bytes() {
+            key.push(byte);
+            if byte == 0 {
+                key.push(u8::MAX);
+            }
+        }
+        key.extend_from_slice(&[0, 0]);
+    }
+
+    /// First linked object of every link sort.
+    fn get_sort_links<'env>(
+        &self,
+        cursors: &IsarCursors<'txn, 'env>,
+        id: i64,
+    ) -> Result<Vec<Option<IsarObject<'txn>>>> {
+        let mut objects = vec![];
+        for (_, _, link) in &self.sort {
+            let mut first = None;
+            if let Some(link) = link {
+                link.iter(cursors, id, |_, object| {
+                    first = Some(object);
+                    Ok(false)
+                })?;
+            }
+            objects.push(first);
+        }
+        Ok(objects)
+    }
+
     fn add_distinct_sorted(
         &self,
         results: Vec<(i64, IsarObject<'txn>)>,
@@ -232,6 +537,47 @@ impl<'txn> Query {
         results.into_iter().skip(self.offset).take(self.limit)
     }
 
+    /// Splits the query into queries over consecutive id ranges that can be executed
+    /// independently. Only queries with a single id where clause and without sorting or distinct
+    /// can be split. Offset and limit have to be applied to the merged results.
+    pub(crate) fn split_by_id(&self, min_id: i64, max_id: i64, parts: usize) -> Result<Vec<Query>> {
+        let wc = match self.where_clauses.as_slice() {
+            [WhereClause::Id(wc)] if self.sort.is_empty() && self.distinct.is_empty() => wc,
+            _ => return illegal_arg("Only filter queries can be split."),
+        };
+        let queries = wc
+            .split(min_id, max_id, parts)
+            .into_iter()
+            .map(|wc| Query {
+                where_clauses: vec![WhereClause::Id(wc)],
+                where_clauses_dup: false,
+                offset: 0,
+                limit: usize::MAX,
+                ..self.clone()
+            })
+            .collect();
+        Ok(queries)
+    }
+
+    /// Short description of the query for debugging.
+    pub(crate) fn summary(&self) -> String {
+        format!(
+            "{} where clauses, filter: {}, sort: {}, distinct: {}",
+            self.where_clauses.len(),
+            self.filter.is_some(),
+            self.sort.len(),
+            self.distinct.len()
+        )
+    }
+
+    pub(crate) fn apply_offset_limit<T>(&self, results: Vec<T>) -> Vec<T> {
+        results
+            .into_iter()
+            .skip(self.offset)
+            .take(self.limit)
+            .collect()
+    }
+
     pub(crate) fn maybe_matches_wc_filter(&self, id: i64, object: IsarObject) -> bool {
         let maybe_matches = self
             .where_clauses
@@ -248,30 +594,71 @@ impl<'txn> Query {
         }
     }
 
-    pub fn find_while<F>(&self, txn: &'txn mut IsarTxn, mut callback: F) -> Result<()>
+    pub fn find_while<F>(&self, txn: &'txn IsarTxn, mut callback: F) -> Result<()>
     where
         F: FnMut(i64, IsarObject<'txn>) -> bool,
     {
-        txn.read(self.instance_id, |cursors| {
-            if self.sort.is_empty() {
-                self.execute_unsorted(cursors, |id, object| {
-                    let cont = callback(id, object);
-                    Ok(cont)
-                })?;
-            } else {
-                let results = self.execute_sorted(cursors)?;
-                let results_iter = self.add_offset_limit_sorted(results);
-                for (id, object) in results_iter {
-                    if !callback(id, object) {
-                        break;
+        txn.read_shared(self.instance_id, |cursors| {
+            self.find_while_internal(cursors, |id, object| {
+                let object = self.transforms.decode(txn, object)?;
+                Ok(callback(id, object))
+            })
+        })
+    }
+
+    fn find_while_internal<'env, F>(
+        &self,
+        cursors: &IsarCursors<'txn, 'env>,
+        mut callback: F,
+    ) -> Result<()>
+    where
+        F: FnMut(i64, IsarObject<'txn>) -> Result<bool>,
+    {
+        if self.sort.is_empty() {
+            self.execute_unsorted(cursors, callback)?;
+        } else {
+            match self.execute_sorted(cursors) {
+                Ok(results) => {
+                    let results_iter = self.add_offset_limit_sorted(results);
+                    for (id, object) in results_iter {
+                        if !callback(id, object)? {
+                            break;
+                        }
                     }
                 }
+                Err(IsarError::QueryTooLarge {}) if self.spill_dir.is_some() => {
+                    let dir = self.spill_dir.as_deref().unwrap();
+                    self.execute_sorted_external(cursors, dir, callback)?;
+                }
+                Err(e) => return Err(e),
             }
-            Ok(())
-        })
+        }
+        Ok(())
     }
 
-    pub fn find_all_vec(&self, txn: &'txn mut IsarTxn) -> Result<Vec<(i64, IsarObject<'txn>)>> {
+    /// Returns a (source, target) pair for every linked object of every matching object. Objects
+    /// without links are skipped.
+    pub fn find_all_joined(
+        &self,
+        txn: &'txn IsarTxn,
+        collection: &IsarCollection,
+        link_id: u64,
+    ) -> Result<Vec<JoinedObjects<'txn>>> {
+        let link = collection.get_link_backlink(link_id)?;
+        let mut results = vec![];
+        txn.read_shared(self.instance_id, |cursors| {
+            self.find_while_internal(cursors, |id, object| {
+                link.iter(cursors, id, |target_id, target| {
+                    results.push(((id, object), (target_id, target)));
+                    Ok(true)
+                })?;
+                Ok(true)
+            })
+        })?;
+        Ok(results)
+    }
+
+    pub fn find_all_vec(&self, txn: &'txn IsarTxn) -> Result<Vec<(i64, IsarObject<'txn>)>> {
         let mut results = vec![];
         self.find_while(txn, |id, object| {
             results.push((id, object));
@@ -280,7 +667,188 @@ impl<'txn> Query {
         Ok(results)
     }
 
-    pub fn count(&self, txn: &mut IsarTxn) -> Result<u32> {
+    /// Reads the values straight from the index keys if the index contains the property and
+    /// the query needs no other values. Returns `None` if the objects have to be loaded.
+    fn find_covered_values(
+        &self,
+        txn: &'txn IsarTxn,
+        property: Option<&Property>,
+    ) -> Result<Option<PropertyValues<'txn>>> {
+        if self.filter.is_some() || !self.sort.is_empty() || !self.distinct.is_empty() {
+            return Ok(None);
+        }
+        let mut where_clauses = vec![];
+        for wc in &self.where_clauses {
+            let wc = match wc {
+                WhereClause::Index(wc) if wc.is_covering() => wc,
+                _ => return Ok(None),
+            };
+            let key_offset = if let Some(property) = property {
+                if let Some(offset) = wc.index().covered_offset(property) {
+                    Some(offset)
+                } else {
+                    return Ok(None);
+                }
+            } else {
+                None
+            };
+            where_clauses.push((wc, key_offset));
+        }
+
+        let mut values = if let Some(property) = property {
+            PropertyValues::new(property.data_type)?
+        } else {
+            PropertyValues::Long(vec![])
+        };
+        let mut result_ids = if self.where_clauses_dup {
+            Some(IntMap::new())
+        } else {
+            None
+        };
+        let max_count = self.limit.saturating_add(self.offset);
+        let mut count = 0;
+        txn.read_shared(self.instance_id, |cursors| {
+            self.check_cancelled()?;
+            for (wc, key_offset) in where_clauses {
+                let result = wc.iter_keys(cursors, result_ids.as_mut(), |key, id| {
+                    count += 1;
+                    if count % Self::CANCEL_CHECK_STEP == 0 {
+                        self.check_cancelled()?;
+                    }
+                    if count > max_count {
+                        return Ok(false);
+                    }
+                    if count > self.offset {
+                        match (key_offset, &mut values) {
+                            (Some(key_offset), values) => values.push_key(&key[key_offset..]),
+                            (None, PropertyValues::Long(ids)) => ids.push(id),
+                            _ => unreachable!(),
+                        }
+                    }
+                    Ok(true)
+                })?;
+                if !result {
+                    break;
+                }
+            }
+            Ok(())
+        })?;
+        Ok(Some(values))
+    }
+
+    pub fn find_property_values(
+        &self,
+        txn: &'txn IsarTxn,
+        property: Option<&Property>,
+    ) -> Result<PropertyValues<'txn>> {
+        if let Some(values) = self.find_covered_values(txn, property)? {
+            return Ok(values);
+        }
+        if let Some(property) = property {
+            let mut values = PropertyValues::new(property.data_type)?;
+            self.find_while(txn, |_, object| {
+                values.push(object, property.offset);
+                true
+            })?;
+            Ok(values)
+        } else {
+            let mut ids = vec![];
+            self.find_while(txn, |id, _| {
+                ids.push(id);
+                true
+            })?;
+            Ok(PropertyValues::Long(ids))
+        }
+    }
+
+    /// Reads the values of `properties` of all matching objects in one pass and passes them to
+    /// `callback` in batches of up to `batch_size` objects. The callback can return `false` to
+    /// stop the query.
+    pub fn find_column_batches<F>(
+        &self,
+        txn: &'txn IsarTxn,
+        properties: &[Property],
+        batch_size: usize,
+        mut callback: F,
+    ) -> Result<()>
+    where
+        F: FnMut(ColumnBatch<'txn>) -> bool,
+    {
+        if batch_size == 0 {
+            return illegal_arg("The batch size must be positive.");
+        }
+        let mut batch = ColumnBatch::new(properties)?;
+        let mut stopped = false;
+        self.find_while(txn, |id, object| {
+            batch.push(id, object, properties);
+            if batch.len() == batch_size {
+                stopped = !callback(batch.take());
+            }
+            !stopped
+        })?;
+        if !stopped && !batch.is_empty() {
+            callback(batch);
+        }
+        Ok(())
+    }
+
+    /// Returns the distinct values of a property for all matching objects. The offset and limit
+    /// of the query are applied to the values.
+    pub fn distinct_values(
+        &self,
+        txn: &'txn IsarTxn,
+        collection: &IsarCollection,
+        property: &Property,
+        case_sensitive: bool,
+    ) -> Result<PropertyValues<'txn>> {
+        let mut values = PropertyValues::new(property.data_type)?;
+        if self.limit == 0 {
+            return Ok(values);
+        }
+
+        let mut hashes = IntMap::new();
+        let mut skipped = 0;
+        let mut add_value = |object: IsarObject<'txn>| {
+            let hash = object.hash_property(property.offset, property.data_type, case_sensitive, 0);
+            if hashes.insert_checked(hash, ()) {
+                if skipped < self.offset {
+                    skipped += 1;
+                } else {
+                    values.push(object, property.offset);
+                }
+            }
+            values.len() < self.limit
+        };
+
+        let unrestricted =
+            self.filter.is_none() && self.where_clauses.iter().all(|wc| wc.is_unrestricted());
+        let index = collection
+            .get_distinct_index(property)
+            .filter(|_| unrestricted);
+        if let Some(index) = index {
+            // only the first object of every index key is needed
+            txn.read_shared(self.instance_id, |cursors| {
+                let mut data_cursor = cursors.get_cursor(collection.db)?;
+                index.iter_all(cursors, true, |id| {
+                    let (_, bytes) = data_cursor.move_to(&id)?.ok_or(IsarError::DbCorrupted {
+                        message: "Could not find object specified in index.".to_string(),
+                    })?;
+                    Ok(add_value(IsarObject::from_bytes(bytes)))
+                })?;
+                Ok(())
+            })?;
+        } else {
+            self.find_while(txn, |_, object| add_value(object))?;
+        }
+
+        Ok(values)
+    }
+
+    pub fn cursor(&self) -> Result<QueryCursor> {
+        QueryCursor::new(self.clone())
+    }
+
+    pub fn count(&self, txn: &IsarTxn) -> Result<u32> {
         let mut counter = 0;
         self.find_while(txn, |_, _| {
             counter += 1;
@@ -291,7 +859,7 @@ impl<'txn> Query {
 
     pub fn export_json(
         &self,
-        txn: &mut IsarTxn,
+        txn: &IsarTxn,
         collection: &IsarCollection,
         id_name: Option<&str>,
         primitive_null: bool,
@@ -313,3 +881,452 @@ impl<'txn> Query {
         Ok(json!(items))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::index_key::IndexValue;
+    use crate::instance::tests::{
+        collection, collections, find_ids, index, open_instance, put_object, put_values,
+    };
+    use crate::query::query_builder::QueryBuilder;
+    use serde_json::json;
+    use xxhash_rust::xxh3::xxh3_64;
+
+    #[test]
+    fn test_index_prefix_where_clause_with_range() {
+        let mut col = collection(
+            "col",
+            json!([{"name": "a", "type": "Long"}, {"name": "b", "type": "String"}]),
+        );
+        col["indexes"] = json!([index("ab", &[("a", false), ("b", true)], false)]);
+        let instance = open_instance(json!([col]));
+        let col = &instance.collections()[0];
+        let values = [
+            (1, "a"),
+            (1, "b"),
+            (1, "bz"),
+            (1, "d"),
+            (1, "dz"),
+            (1, "e"),
+            (2, "c"),
+        ];
+        for (id, (a, b)) in values.iter().enumerate() {
+            put_object(&instance, col, Some(id as i64 + 1), json!({"a": a, "b": b}));
+        }
+
+        let find = |prefix: &[IndexValue], lower, upper, sort| {
+            let mut qb = col.new_query_builder();
+            qb.add_index_prefix_where_clause(xxh3_64(b"ab"), prefix, lower, upper, sort, false)
+                .unwrap();
+            find_ids(&instance, &qb.build())
+        };
+        let prefix = [IndexValue::Long(1)];
+        let (b, d) = (IndexValue::String(Some("b")), IndexValue::String(Some("d")));
+        assert_eq!(
+            find(&prefix, Some((b, true)), Some((d, true)), Sort::Ascending),
+            vec![2, 3, 4]
+        );
+        assert_eq!(
+            find(&prefix, Some((b, true)), Some((d, true)), Sort::Descending),
+            vec![4, 3, 2]
+        );
+        assert_eq!(
+            find(&prefix, Some((b, false)), Some((d, false)), Sort::Ascending),
+            vec![3]
+        );
+        assert_eq!(
+            find(&prefix, None, Some((b, false)), Sort::Ascending),
+            vec![1]
+        );
+        assert_eq!(
+            find(&prefix, Some((d, false)), None, Sort::Ascending),
+            vec![5, 6]
+        );
+        let lower = Some((IndexValue::Long(1), false));
+        assert_eq!(find(&[], lower, None, Sort::Ascending), vec![7]);
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_case_insensitive_collation_groups_case_variants() {
+        let mut col = collection("col", json!([{"name": "name", "type": "String"}]));
+        let mut index = index("name", &[("name", true)], false);
+        index["properties"][0]["collation"] = json!("CaseInsensitive");
+        col["indexes"] = json!([index]);
+        let instance = open_instance(json!([col]));
+        let col = &instance.collections()[0];
+        for name in ["b", "B", "a", "A", "ab"] {
+            put_object(&instance, col, None, json!({ "name": name }));
+        }
+
+        let find = |value: Option<&str>, sort| {
+            let bound = value.map(|value| (IndexValue::String(Some(value)), true));
+            let mut qb = col.new_query_builder();
+            qb.add_index_prefix_where_clause(xxh3_64(b"name"), &[], bound, bound, sort, false)
+                .unwrap();
+            find_ids(&instance, &qb.build())
+        };
+        assert_eq!(find(None, Sort::Ascending), vec![4, 3, 5, 2, 1]);
+        assert_eq!(find(None, Sort::Descending), vec![1, 2, 5, 3, 4]);
+        // bounds include all case variants of the value
+        assert_eq!(find(Some("a"), Sort::Ascending), vec![4, 3]);
+        assert_eq!(find(Some("B"), Sort::Descending), vec![1, 2]);
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_find_property_values() {
+        let col = collection(
+            "col",
+            json!([
+                {"name": "name", "type": "String"},
+                {"name": "tags", "type": "LongList"},
+                {"name": "value", "type": "Long"},
+            ]),
+        );
+        let instance = open_instance(json!([col]));
+        let col = &instance.collections()[0];
+        put_object(&instance, col, Some(1), json!({"name": "a", "value": 5}));
+        put_object(&instance, col, Some(2), json!({"name": null, "value": 7}));
+
+        let query = col.new_query_builder().build();
+        let txn = instance.begin_txn(false, false).unwrap();
+        let values = query.find_property_values(&txn, Some(col.property("value").unwrap()));
+        assert!(matches!(values.unwrap(), PropertyValues::Long(v) if v == vec![5, 7]));
+        let values = query.find_property_values(&txn, Some(col.property("name").unwrap()));
+        assert!(matches!(values.unwrap(), PropertyValues::String(v) if v == vec![Some("a"), None]));
+        let ids = query.find_property_values(&txn, None).unwrap();
+        assert!(matches!(ids, PropertyValues::Long(v) if v == vec![1, 2]));
+        let tags = query.find_property_values(&txn, Some(col.property("tags").unwrap()));
+        assert!(tags.is_err());
+        drop(txn);
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_find_covered_values_without_loading_objects() {
+        let mut col = collection("col", json!([{"name": "value", "type": "Long"}]));
+        col["indexes"] = json!([index("value", &[("value", false)], false)]);
+        let instance = open_instance(json!([col]));
+        let col = &instance.collections()[0];
+        for (id, value) in [3, 1, 4, 2].iter().enumerate() {
+            put_object(
+                &instance,
+                col,
+                Some(id as i64 + 1),
+                json!({ "value": value }),
+            );
+        }
+        // values read from the index survive the objects being removed behind its back
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        txn.write(instance.instance_id, |cursors, _| {
+            let mut cursor = cursors.get_cursor(col.db)?;
+            for id in 1..=4i64 {
+                cursor.move_to(&id)?.unwrap();
+                cursor.delete_current()?;
+            }
+            Ok(())
+        })
+        .unwrap();
+        txn.commit().unwrap();
+
+        let property = col.property("value").unwrap();
+        let query = |filter: Option<Filter>| {
+            let lower = Some((IndexValue::Long(2), true));
+            let mut qb = col.new_query_builder();
+            qb.add_index_prefix_where_clause(
+                xxh3_64(b"value"),
+                &[],
+                lower,
+                None,
+                Sort::Ascending,
+                false,
+            )
+            .unwrap();
+            if let Some(filter) = filter {
+                qb.set_filter(filter);
+            }
+            qb.build()
+        };
+        let txn = instance.begin_txn(false, false).unwrap();
+        let values = query(None).find_property_values(&txn, Some(property));
+        assert!(matches!(values.unwrap(), PropertyValues::Long(v) if v == vec![2, 3, 4]));
+        let ids = query(None).find_property_values(&txn, None);
+        assert!(matches!(ids.unwrap(), PropertyValues::Long(v) if v == vec![4, 1, 3]));
+        // a filter needs the objects
+        let filter = Filter::long(property, 0, i64::MAX).unwrap();
+        let values = query(Some(filter)).find_property_values(&txn, Some(property));
+        assert!(matches!(values, Err(IsarError::DbCorrupted { .. })));
+        drop(txn);
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_find_column_batches() {
+        let col = collection(
+            "col",
+            json!([{"name": "name", "type": "String"}, {"name": "value", "type": "Long"}]),
+        );
+        let instance = open_instance(json!([col]));
+        let col = &instance.collections()[0];
+        for (id, name) in ["a", "b", "c", "d", "e"].iter().enumerate() {
+            let id = id as i64 + 1;
+            let object = json!({"name": name, "value": id * 10});
+            put_object(&instance, col, Some(id), object);
+        }
+
+        let properties = [
+            col.property("value").unwrap().clone(),
+            col.property("name").unwrap().clone(),
+        ];
+        let query = col.new_query_builder().build();
+        let txn = instance.begin_txn(false, false).unwrap();
+        let mut batches = vec![];
+        query
+            .find_column_batches(&txn, &properties, 2, |batch| {
+                batches.push(batch);
+                true
+            })
+            .unwrap();
+        let ids = batches.iter().map(|b| b.ids.clone()).collect::<Vec<_>>();
+        assert_eq!(ids, vec![vec![1, 2], vec![3, 4], vec![5]]);
+        assert!(matches!(&batches[1].columns[0], PropertyValues::Long(v) if v == &vec![30, 40]));
+        let names = &batches[2].columns[1];
+        assert!(matches!(names, PropertyValues::String(v) if v == &vec![Some("e")]));
+
+        // the callback can stop the query
+        let mut count = 0;
+        query
+            .find_column_batches(&txn, &properties, 2, |_| {
+                count += 1;
+                false
+            })
+            .unwrap();
+        assert_eq!(count, 1);
+        let result = query.find_column_batches(&txn, &properties, 0, |_| true);
+        assert!(result.is_err());
+        drop(batches);
+        drop(txn);
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_distinct_values() {
+        let mut col = collection("col", json!([{"name": "value", "type": "Long"}]));
+        col["indexes"] = json!([index("value", &[("value", false)], false)]);
+        let instance = open_instance(json!([col]));
+        let col = &instance.collections()[0];
+        for value in [3, 1, 3, 2] {
+            put_object(&instance, col, None, json!({ "value": value }));
+        }
+
+        let property = col.property("value").unwrap();
+        let distinct_values = |qb: QueryBuilder| {
+            let txn = instance.begin_txn(false, false).unwrap();
+            let values = qb.build().distinct_values(&txn, col, property, true);
+            match values.unwrap() {
+                PropertyValues::Long(values) => values,
+                _ => unreachable!(),
+            }
+        };
+        // the index returns the values in order
+        assert_eq!(distinct_values(col.new_query_builder()), vec![1, 2, 3]);
+        let mut qb = col.new_query_builder();
+        qb.set_offset(1);
+        qb.set_limit(1);
+        assert_eq!(distinct_values(qb), vec![2]);
+        let mut qb = col.new_query_builder();
+        qb.set_filter(Filter::long(property, 2, i64::MAX).unwrap());
+        assert_eq!(distinct_values(qb), vec![3, 2]);
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_link_sort_and_joined_results() {
+        let city = collection("city", json!([{"name": "name", "type": "String"}]));
+        let mut user = collection("user", json!([]));
+        user["links"] = json!([{"name": "city", "target": "city"}]);
+        let instance = open_instance(json!([user, city]));
+        // the collections are sorted by name
+        let (city, user) = (&instance.collections()[0], &instance.collections()[1]);
+        let paris = put_object(&instance, city, None, json!({"name": "Paris"}));
+        let berlin = put_object(&instance, city, None, json!({"name": "Berlin"}));
+        let users = (0..3)
+            .map(|_| put_object(&instance, user, None, json!({})))
+            .collect::<Vec<_>>();
+        let link_id = user.link_id("city").unwrap();
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        user.link(&mut txn, link_id, users[0], paris).unwrap();
+        user.link(&mut txn, link_id, users[1], berlin).unwrap();
+        txn.commit().unwrap();
+
+        let mut qb = user.new_query_builder();
+        let name = city.property("name").unwrap();
+        qb.add_link_sort(link_id, name, Sort::Ascending).unwrap();
+        let query = qb.build();
+        // objects without links come first
+        assert_eq!(
+            find_ids(&instance, &query),
+            vec![users[2], users[1], users[0]]
+        );
+
+        let txn = instance.begin_txn(false, false).unwrap();
+        let joined = query.find_all_joined(&txn, user, link_id).unwrap();
+        let joined = joined
+            .iter()
+            .map(|((id, _), (target_id, target))| {
+                (*id, *target_id, target.read_string(name.offset))
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            joined,
+            vec![
+                (users[1], berlin, Some("Berlin")),
+                (users[0], paris, Some("Paris"))
+            ]
+        );
+        drop(txn);
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_case_insensitive_sorted_distinct() {
+        let instance = open_instance(json!([collection(
+            "col",
+            json!([{"name": "name", "type": "String"}])
+        )]));
+        let col = &instance.collections()[0];
+        for (id, name) in ["b", "A", "B", "a", "c"].iter().enumerate() {
+            put_object(&instance, col, Some(id as i64 + 1), json!({ "name": name }));
+        }
+
+        let find = |case, sort| {
+            let mut qb = col.new_query_builder();
+            qb.add_distinct_by_name("name", case).unwrap();
+            qb.add_sort_by_name("name", sort).unwrap();
+            find_ids(&instance, &qb.build())
+        };
+        // the first case variant in sort order is kept
+        assert_eq!(find(Case::Insensitive, Sort::Ascending), vec![2, 3, 5]);
+        assert_eq!(find(Case::Insensitive, Sort::Descending), vec![5, 1, 4]);
+        assert_eq!(find(Case::Sensitive, Sort::Ascending), vec![2, 3, 4, 1, 5]);
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_query_memory_limit() {
+        let instance = open_instance(collections());
+        put_values(&instance, &(0..100).collect::<Vec<_>>());
+        let col = &instance.collections()[0];
+        let count = |qb: QueryBuilder| {
+            let txn = instance.begin_txn(false, false).unwrap();
+            let result = qb.build().find_all_vec(&txn);
+            result.map(|objects| objects.len())
+        };
+        let sorted = || {
+            let mut qb = col.new_query_builder();
+            qb.add_sort_by_name("value", Sort::Descending).unwrap();
+            qb
+        };
+        let distinct = || {
+            let mut qb = col.new_query_builder();
+            qb.add_distinct_by_name("value", Case::Sensitive).unwrap();
+            qb
+        };
+        assert_eq!(count(sorted()).unwrap(), 100);
+
+        instance.set_query_memory_limit(Some(100));
+        assert!(matches!(count(sorted()), Err(IsarError::QueryTooLarge {})));
+        assert!(matches!(
+            count(distinct()),
+            Err(IsarError::QueryTooLarge {})
+        ));
+        // unsorted queries stream their results
+        assert_eq!(count(col.new_query_builder()).unwrap(), 100);
+        // queries can override the limit of the instance
+        let mut qb = sorted();
+        qb.set_memory_limit(usize::MAX);
+        assert_eq!(count(qb).unwrap(), 100);
+
+        instance.set_query_memory_limit(None);
+        assert_eq!(count(distinct()).unwrap(), 100);
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_external_sort_orders_truncated_keys() {
+        let instance = open_instance(json!([collection(
+            "col",
+            json!([{"name": "name", "type": "String"}])
+        )]));
+        let col = &instance.collections()[0];
+        // the first four names only differ after the maximum sort key length
+        let long = "x".repeat(Query::MAX_SORT_KEY_LEN + 100);
+        let mut names = ["d", "b", "c", "a"]
+            .map(|suffix| format!("{long}{suffix}"))
+            .to_vec();
+        names.extend(["a".to_string(), "y".to_string()]);
+        for (id, name) in names.iter().enumerate() {
+            put_object(&instance, col, Some(id as i64 + 1), json!({ "name": name }));
+        }
+
+        let find = |sort: Sort, offset: usize, limit: usize| {
+            let mut qb = col.new_query_builder();
+            qb.add_sort_by_name("name", sort).unwrap();
+            qb.set_offset(offset);
+            qb.set_limit(limit);
+            qb.set_memory_limit(1);
+            let txn = instance.begin_txn(false, false).unwrap();
+            let result = qb.build().find_all_vec(&txn);
+            result.map(|r| r.iter().map(|(id, _)| *id).collect::<Vec<_>>())
+        };
+        assert!(matches!(
+            find(Sort::Ascending, 0, usize::MAX),
+            Err(IsarError::QueryTooLarge {})
+        ));
+
+        instance.set_query_spill(true, None);
+        assert_eq!(
+            find(Sort::Ascending, 0, usize::MAX).unwrap(),
+            vec![5, 4, 2, 3, 1, 6]
+        );
+        assert_eq!(
+            find(Sort::Descending, 0, usize::MAX).unwrap(),
+            vec![6, 1, 3, 2, 4, 5]
+        );
+        assert_eq!(find(Sort::Ascending, 2, 3).unwrap(), vec![2, 3, 1]);
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_cancel_running_query() {
+        let instance = open_instance(collections());
+        let values = (0..5000).collect::<Vec<_>>();
+        put_values(&instance, &values);
+        let col = &instance.collections()[0];
+        let cancel = Arc::new(AtomicBool::new(false));
+        let mut qb = col.new_query_builder();
+        qb.set_cancel_token(cancel.clone());
+        let query = qb.build();
+
+        let txn = instance.begin_txn(false, false).unwrap();
+        let mut found = 0;
+        let result = query.find_while(&txn, |_, _| {
+            found += 1;
+            cancel.store(true, atomic::Ordering::Relaxed);
+            true
+        });
+        assert!(matches!(result, Err(IsarError::Cancelled {})));
+        assert!(found <= Query::CANCEL_CHECK_STEP);
+
+        // the query stays cancelled
+        assert!(matches!(query.count(&txn), Err(IsarError::Cancelled {})));
+        cancel.store(false, atomic::Ordering::Relaxed);
+        assert_eq!(query.count(&txn).unwrap(), 5000);
+        query.cancel();
+        assert!(cancel.load(atomic::Ordering::Relaxed));
+        drop(txn);
+        assert!(instance.close_and_delete());
+    }
+}
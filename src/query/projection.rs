@@ -0,0 +1,138 @@
+use crate::error::{illegal_arg, Result};
+use crate::index::index_key::IndexKey;
+use crate::object::data_type::DataType;
+use crate::object::isar_object::IsarObject;
+use crate::object::property::Property;
+
+pub enum PropertyValues<'txn> {
+    Bool(Vec<Option<bool>>),
+    Byte(Vec<u8>),
+    Int(Vec<i32>),
+    Float(Vec<f32>),
+    Long(Vec<i64>),
+    Double(Vec<f64>),
+    String(Vec<Option<&'txn str>>),
+    Object(Vec<Option<IsarObject<'txn>>>),
+    ByteList(Vec<Option<&'txn [u8]>>),
+}
+
+impl<'txn> PropertyValues<'txn> {
+    pub(crate) fn new(data_type: DataType) -> Result<Self> {
+        let values = match data_type {
+            DataType::Bool => PropertyValues::Bool(vec![]),
+            DataType::Byte => PropertyValues::Byte(vec![]),
+            DataType::Int => PropertyValues::Int(vec![]),
+            DataType::Float => PropertyValues::Float(vec![]),
+            DataType::Long => PropertyValues::Long(vec![]),
+            DataType::Double => PropertyValues::Double(vec![]),
+            DataType::String => PropertyValues::String(vec![]),
+            DataType::Object => PropertyValues::Object(vec![]),
+            DataType::ByteList => PropertyValues::ByteList(vec![]),
+            _ => return illegal_arg("Property does not support projection."),
+        };
+        Ok(values)
+    }
+
+    pub(crate) fn push(&mut self, object: IsarObject<'txn>, offset: usize) {
+        match self {
+            PropertyValues::Bool(values) => values.push(object.read_bool(offset)),
+            PropertyValues::Byte(values) => values.push(object.read_byte(offset)),
+            PropertyValues::Int(values) => values.push(object.read_int(offset)),
+            PropertyValues::Float(values) => values.push(object.read_float(offset)),
+            PropertyValues::Long(values) => values.push(object.read_long(offset)),
+            PropertyValues::Double(values) => values.push(object.read_double(offset)),
+            PropertyValues::String(values) => values.push(object.read_string(offset)),
+            PropertyValues::Object(values) => values.push(object.read_object(offset)),
+            PropertyValues::ByteList(values) => values.push(object.read_byte_list(offset)),
+        }
+    }
+
+    /// Reads the value from the start of an index key component.
+    pub(crate) fn push_key(&mut self, key: &[u8]) {
+        match self {
+            PropertyValues::Bool(values) => values.push(IsarObject::byte_to_bool(key[0])),
+            PropertyValues::Byte(values) => values.push(key[0]),
+            PropertyValues::Int(values) => values.push(IndexKey::read_int(key)),
+            PropertyValues::Float(values) => values.push(IndexKey::read_float(key)),
+            PropertyValues::Long(values) => values.push(IndexKey::read_long(key)),
+            PropertyValues::Double(values) => values.push(IndexKey::read_double(key)),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the values and leaves an empty column of the same type.
+    fn take(&mut self) -> Self {
+        let empty = match self {
+            PropertyValues::Bool(_) => PropertyValues::Bool(vec![]),
+            PropertyValues::Byte(_) => PropertyValues::Byte(vec![]),
+            PropertyValues::Int(_) => PropertyValues::Int(vec![]),
+            PropertyValues::Float(_) => PropertyValues::Float(vec![]),
+            PropertyValues::Long(_) => PropertyValues::Long(vec![]),
+            PropertyValues::Double(_) => PropertyValues::Double(vec![]),
+            PropertyValues::String(_) => PropertyValues::String(vec![]),
+            PropertyValues::Object(_) => PropertyValues::Object(vec![]),
+            PropertyValues::ByteList(_) => PropertyValues::ByteList(vec![]),
+        };
+        std::mem::replace(self, empty)
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            PropertyValues::Bool(values) => values.len(),
+            PropertyValues::Byte(values) => values.len(),
+            PropertyValues::Int(values) => values.len(),
+            PropertyValues::Float(values) => values.len(),
+            PropertyValues::Long(values) => values.len(),
+            PropertyValues::Double(values) => values.len(),
+            PropertyValues::String(values) => values.len(),
+            PropertyValues::Object(values) => values.len(),
+            PropertyValues::ByteList(values) => values.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// The ids and property values of a batch of objects. Every column has one value per id.
+pub struct ColumnBatch<'txn> {
+    pub ids: Vec<i64>,
+    pub columns: Vec<PropertyValues<'txn>>,
+}
+
+impl<'txn> ColumnBatch<'txn> {
+    pub(crate) fn new(properties: &[Property]) -> Result<Self> {
+        let columns = properties
+            .iter()
+            .map(|p| PropertyValues::new(p.data_type))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(ColumnBatch {
+            ids: vec![],
+            columns,
+        })
+    }
+
+    pub(crate) fn push(&mut self, id: i64, object: IsarObject<'txn>, properties: &[Property]) {
+        self.ids.push(id);
+        for (column, property) in self.columns.iter_mut().zip(properties) {
+            column.push(object, property.offset);
+        }
+    }
+
+    /// Returns the batch and leaves an empty batch with the same columns.
+    pub(crate) fn take(&mut self) -> Self {
+        ColumnBatch {
+            ids: std::mem::take(&mut self.ids),
+            columns: self.columns.iter_mut().map(|c| c.take()).collect(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+}
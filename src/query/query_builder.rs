@@ -1,22 +1,39 @@
 use super::index_where_clause::IndexWhereClause;
 use crate::collection::IsarCollection;
-use crate::error::{illegal_arg, Result};
-use crate::index::index_key::IndexKey;
+use crate::error::{illegal_arg, IsarError, Result};
+use crate::index::geo::{geo_hash_ranges, GeoBox};
+use crate::index::index_key::{IndexKey, IndexValue};
+use crate::index::index_key_builder::IndexKeyBuilder;
+use crate::link::IsarLink;
+use crate::object::data_type::DataType;
 use crate::object::property::Property;
 use crate::query::filter::Filter;
 use crate::query::id_where_clause::IdWhereClause;
 use crate::query::link_where_clause::LinkWhereClause;
 use crate::query::where_clause::WhereClause;
-use crate::query::{Query, Sort};
+use crate::query::{Case, NanPolicy, Query, Sort};
+use crate::schema::index_schema::IndexType;
+use crate::txn::IsarTxn;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+enum IndexHint {
+    Use(u64),
+    NoIndex,
+}
 
 pub struct QueryBuilder<'a> {
     pub collection: &'a IsarCollection,
     where_clauses: Option<Vec<WhereClause>>,
     filter: Option<Filter>,
-    sort: Vec<(Property, Sort)>,
+    sort: Vec<(Property, Sort, Option<IsarLink>)>,
     distinct: Vec<(Property, bool)>,
     offset: usize,
     limit: usize,
+    index_hint: Option<IndexHint>,
+    cancel: Option<Arc<AtomicBool>>,
+    memory_limit: Option<usize>,
 }
 
 impl<'a> QueryBuilder<'a> {
@@ -29,6 +46,9 @@ impl<'a> QueryBuilder<'a> {
             distinct: vec![],
             offset: 0,
             limit: usize::MAX,
+            index_hint: None,
+            cancel: None,
+            memory_limit: None,
         }
     }
 
@@ -38,13 +58,103 @@ impl<'a> QueryBuilder<'a> {
         }
     }
 
+    /// Checks that a where clause using `index_id` does not contradict the index hint.
+    fn check_index_hint(&self, index_id: Option<u64>) -> Result<()> {
+        match (self.index_hint, index_id) {
+            (Some(IndexHint::Use(hint)), index_id) if index_id != Some(hint) => {
+                illegal_arg("The where clause does not use the hinted index.")
+            }
+            (Some(IndexHint::NoIndex), Some(_)) => illegal_arg("The query must not use an index."),
+            _ => Ok(()),
+        }
+    }
+
+    /// Forces the query to use the index. If no where clause is added, the whole index is
+    /// scanned.
+    pub fn use_index(&mut self, index_name: &str) -> Result<()> {
+        let index = self
+            .collection
+            .indexes
+            .iter()
+            .find(|i| i.name == index_name)
+            .ok_or(IsarError::UnknownIndex {})?;
+        if index.is_pending() {
+            return illegal_arg("The index has not been built yet.");
+        }
+        if self.index_hint.is_some() {
+            return illegal_arg("The query already has an index hint.");
+        }
+        let conflict = self
+            .where_clauses
+            .iter()
+            .flatten()
+            .any(|wc| wc.index_id() != Some(index.id));
+        if conflict {
+            return illegal_arg("The query has where clauses that do not use the index.");
+        }
+        self.index_hint = Some(IndexHint::Use(index.id));
+        Ok(())
+    }
+
+    /// Uses the candidate index with the fewest entries per key according to the index stats.
+    pub fn use_most_selective_index(
+        &mut self,
+        txn: &mut IsarTxn,
+        index_names: &[&str],
+    ) -> Result<()> {
+        let mut best: Option<(&str, f64)> = None;
+        for name in index_names {
+            let index = self
+                .collection
+                .indexes
+                .iter()
+                .find(|i| i.name == *name)
+                .ok_or(IsarError::UnknownIndex {})?;
+            if index.is_pending() {
+                continue;
+            }
+            let entries_per_key = self.collection.index_stats(txn, name)?.entries_per_key();
+            if best.is_none_or(|(_, best)| entries_per_key < best) {
+                best = Some((*name, entries_per_key));
+            }
+        }
+        if let Some((name, _)) = best {
+            self.use_index(name)
+        } else {
+            illegal_arg("None of the indexes can be used.")
+        }
+    }
+
+    /// Prevents the query from using an index.
+    pub fn no_index(&mut self) -> Result<()> {
+        if self.index_hint.is_some() {
+            return illegal_arg("The query already has an index hint.");
+        }
+        let conflict = self
+            .where_clauses
+            .iter()
+            .flatten()
+            .any(|wc| wc.index_id().is_some());
+        if conflict {
+            return illegal_arg("The query has index where clauses.");
+        }
+        self.index_hint = Some(IndexHint::NoIndex);
+        Ok(())
+    }
+
     pub fn add_id_where_clause(&mut self, start: i64, end: i64) -> Result<()> {
-        self.init_where_clauses();
-        let (lower, upper, sort) = if start > end {
-            (end, start, Sort::Descending)
+        if start > end {
+            self.add_sorted_id_where_clause(end, start, Sort::Descending)
         } else {
-            (start, end, Sort::Ascending)
-        };
+            self.add_sorted_id_where_clause(start, end, Sort::Ascending)
+        }
+    }
+
+    /// Descending id where clauses start at `upper` so queries with a limit only read the last
+    /// objects.
+    pub fn add_sorted_id_where_clause(&mut self, lower: i64, upper: i64, sort: Sort) -> Result<()> {
+        self.check_index_hint(None)?;
+        self.init_where_clauses();
         let wc = IdWhereClause::new(self.collection.db, lower, upper, sort);
         if !wc.is_empty() {
             self.where_clauses
@@ -63,6 +173,7 @@ impl<'a> QueryBuilder<'a> {
         sort: Sort,
         skip_duplicates: bool,
     ) -> Result<()> {
+        self.check_index_hint(Some(index_id))?;
         self.init_where_clauses();
         let index = self.collection.get_index_by_id(index_id)?;
         let wc = IndexWhereClause::new(
@@ -81,12 +192,204 @@ impl<'a> QueryBuilder<'a> {
         Ok(())
     }
 
+    /// Adds a where clause for a composite index. The first properties are fixed to `prefix` and
+    /// the next property is restricted to the range. The bool of each bound marks it inclusive.
+    pub fn add_index_prefix_where_clause(
+        &mut self,
+        index_id: u64,
+        prefix: &[IndexValue],
+        lower: Option<(IndexValue, bool)>,
+        upper: Option<(IndexValue, bool)>,
+        sort: Sort,
+        skip_duplicates: bool,
+    ) -> Result<()> {
+        let index = self.collection.get_index_by_id(index_id)?;
+        let key_builder = IndexKeyBuilder::new(&index.properties);
+        // lower > upper if the range is empty
+        let (lower, upper) = key_builder
+            .create_prefix_bounds(prefix, lower, upper)?
+            .unwrap_or_else(|| (IndexKey::from_bytes(vec![1]), IndexKey::from_bytes(vec![0])));
+        self.add_index_where_clause(index_id, lower, upper, sort, skip_duplicates)
+    }
+
+    /// Adds a where clause for a float or double index property. NaN is stored as the lowest key
+    /// so it is matched according to `nan` instead of the bounds.
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_float_where_clause(
+        &mut self,
+        index_id: u64,
+        prefix: &[IndexValue],
+        lower: Option<(f64, bool)>,
+        upper: Option<(f64, bool)>,
+        nan: NanPolicy,
+        sort: Sort,
+        skip_duplicates: bool,
+    ) -> Result<()> {
+        let index = self.collection.get_index_by_id(index_id)?;
+        let data_type = index
+            .properties
+            .get(prefix.len())
+            .filter(|p| p.index_type == IndexType::Value)
+            .map(|p| {
+                p.property
+                    .data_type
+                    .get_element_type()
+                    .unwrap_or(p.property.data_type)
+            });
+        let to_value: fn(f64) -> IndexValue<'static> = match data_type {
+            Some(DataType::Float) => |value| IndexValue::Float(value as f32),
+            Some(DataType::Double) => IndexValue::Double,
+            _ => illegal_arg("Float where clauses require a float or double index property.")?,
+        };
+        let is_nan = |bound: Option<(f64, bool)>| bound.is_some_and(|(value, _)| value.is_nan());
+        if is_nan(lower) || is_nan(upper) {
+            illegal_arg("Bounds must not be NaN. Use the NaN policy instead.")?;
+        }
+
+        let (lower, nan_clause) = match nan {
+            NanPolicy::Include => (lower, lower.is_some()),
+            NanPolicy::Exclude => (Some(lower.unwrap_or((f64::NEG_INFINITY, true))), false),
+        };
+        let mut nan_prefix = prefix.to_vec();
+        nan_prefix.push(to_value(f64::NAN));
+        if nan_clause && sort == Sort::Ascending {
+            self.add_index_prefix_where_clause(
+                index_id,
+                &nan_prefix,
+                None,
+                None,
+                sort,
+                skip_duplicates,
+            )?;
+        }
+        self.add_index_prefix_where_clause(
+            index_id,
+            prefix,
+            lower.map(|(value, include)| (to_value(value), include)),
+            upper.map(|(value, include)| (to_value(value), include)),
+            sort,
+            skip_duplicates,
+        )?;
+        if nan_clause && sort == Sort::Descending {
+            self.add_index_prefix_where_clause(
+                index_id,
+                &nan_prefix,
+                None,
+                None,
+                sort,
+                skip_duplicates,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Adds a where clause for strings starting with `value_prefix`. The first properties of a
+    /// composite index are fixed to `prefix`.
+    pub fn add_string_prefix_where_clause(
+        &mut self,
+        index_id: u64,
+        prefix: &[IndexValue],
+        value_prefix: &str,
+        sort: Sort,
+        skip_duplicates: bool,
+    ) -> Result<()> {
+        let index = self.collection.get_index_by_id(index_id)?;
+        let key_builder = IndexKeyBuilder::new(&index.properties);
+        let (lower, upper, exact) =
+            key_builder.create_string_prefix_bounds(prefix, value_prefix)?;
+        if exact {
+            return self.add_index_where_clause(index_id, lower, upper, sort, skip_duplicates);
+        }
+
+        let index_property = &index.properties[prefix.len()];
+        let filter = Filter::string_starts_with(
+            &index_property.property,
+            value_prefix,
+            index_property.case_sensitive,
+        )?;
+        self.check_index_hint(Some(index_id))?;
+        self.init_where_clauses();
+        let wc = IndexWhereClause::new(
+            self.collection.db,
+            index.clone(),
+            lower,
+            upper,
+            skip_duplicates,
+            sort,
+        )?
+        .with_filter(filter);
+        self.where_clauses
+            .as_mut()
+            .unwrap()
+            .push(WhereClause::Index(wc));
+        Ok(())
+    }
+
+    pub fn add_geo_box_where_clause(&mut self, index_id: u64, geo_box: GeoBox) -> Result<()> {
+        let (lat, lon) = self.get_geo_properties(index_id)?;
+        let filter = Filter::geo_box(lat, lon, geo_box)?;
+        self.add_geo_where_clauses(index_id, &geo_box, filter)
+    }
+
+    pub fn add_geo_radius_where_clause(
+        &mut self,
+        index_id: u64,
+        lat: f64,
+        lon: f64,
+        radius: f64,
+    ) -> Result<()> {
+        let (lat_property, lon_property) = self.get_geo_properties(index_id)?;
+        let filter = Filter::geo_radius(lat_property, lon_property, lat, lon, radius)?;
+        self.add_geo_where_clauses(index_id, &GeoBox::around(lat, lon, radius), filter)
+    }
+
+    fn get_geo_properties(&self, index_id: u64) -> Result<(&'a Property, &'a Property)> {
+        let index = self.collection.get_index_by_id(index_id)?;
+        let first = index.properties.first().unwrap();
+        if first.index_type != IndexType::Geo {
+            return illegal_arg("Index is not a geo index.");
+        }
+        Ok((&index.properties[0].property, &index.properties[1].property))
+    }
+
+    fn add_geo_where_clauses(
+        &mut self,
+        index_id: u64,
+        geo_box: &GeoBox,
+        filter: Filter,
+    ) -> Result<()> {
+        self.check_index_hint(Some(index_id))?;
+        self.init_where_clauses();
+        let index = self.collection.get_index_by_id(index_id)?;
+        for (lower, upper) in geo_hash_ranges(geo_box) {
+            let mut lower_key = IndexKey::new();
+            lower_key.add_hash(lower);
+            let mut upper_key = IndexKey::new();
+            upper_key.add_hash(upper);
+            let wc = IndexWhereClause::new(
+                self.collection.db,
+                index.clone(),
+                lower_key,
+                upper_key,
+                false,
+                Sort::Ascending,
+            )?
+            .with_filter(filter.clone());
+            self.where_clauses
+                .as_mut()
+                .unwrap()
+                .push(WhereClause::Index(wc));
+        }
+        Ok(())
+    }
+
     pub fn add_link_where_clause(
         &mut self,
         collection: &IsarCollection,
         link_id: u64,
         id: i64,
     ) -> Result<()> {
+        self.check_index_hint(None)?;
         let link = collection.get_link_backlink(link_id)?;
 
         self.init_where_clauses();
@@ -104,15 +407,40 @@ impl<'a> QueryBuilder<'a> {
 
     pub fn add_sort(&mut self, property: &Property, sort: Sort) -> Result<()> {
         if property.data_type.is_scalar() {
-            self.sort.push((property.clone(), sort));
+            self.sort.push((property.clone(), sort, None));
+            Ok(())
+        } else {
+            illegal_arg("Only scalar types may be used for sorting.")
+        }
+    }
+
+    /// Sorts by a property of the first linked object. Objects without links are sorted first.
+    pub fn add_link_sort(&mut self, link_id: u64, property: &Property, sort: Sort) -> Result<()> {
+        let link = self.collection.get_link_backlink(link_id)?;
+        if property.data_type.is_scalar() {
+            self.sort.push((property.clone(), sort, Some(link.clone())));
             Ok(())
         } else {
             illegal_arg("Only scalar types may be used for sorting.")
         }
     }
 
-    pub fn add_distinct(&mut self, property: &Property, case_sensitive: bool) {
-        self.distinct.push((property.clone(), case_sensitive));
+    /// Strings and string lists are compared ignoring case with `Case::Insensitive`. Sorting by
+    /// the same property then also ignores case.
+    pub fn add_distinct(&mut self, property: &Property, case: Case) {
+        self.distinct
+            .push((property.clone(), case == Case::Sensitive));
+    }
+
+    pub fn add_sort_by_name(&mut self, property_name: &str, sort: Sort) -> Result<()> {
+        let property = self.collection.property(property_name)?;
+        self.add_sort(property, sort)
+    }
+
+    pub fn add_distinct_by_name(&mut self, property_name: &str, case: Case) -> Result<()> {
+        let property = self.collection.property(property_name)?;
+        self.add_distinct(property, case);
+        Ok(())
     }
 
     pub fn set_offset(&mut self, offset: usize) {
@@ -123,9 +451,31 @@ impl<'a> QueryBuilder<'a> {
         self.limit = limit;
     }
 
+    /// Uses a token that is shared with other operations instead of a token per query.
+    pub fn set_cancel_token(&mut self, cancel: Arc<AtomicBool>) {
+        self.cancel = Some(cancel);
+    }
+
+    /// Overrides the memory limit of the instance for this query.
+    pub fn set_memory_limit(&mut self, max_bytes: usize) {
+        self.memory_limit = Some(max_bytes);
+    }
+
     pub fn build(mut self) -> Query {
         if self.where_clauses.is_none() {
-            self.add_id_where_clause(i64::MIN, i64::MAX).unwrap();
+            if let Some(IndexHint::Use(index_id)) = self.index_hint {
+                self.add_index_prefix_where_clause(
+                    index_id,
+                    &[],
+                    None,
+                    None,
+                    Sort::Ascending,
+                    false,
+                )
+                .unwrap();
+            } else {
+                self.add_id_where_clause(i64::MIN, i64::MAX).unwrap();
+            }
         }
         Query::new(
             self.collection.instance_id,
@@ -135,6 +485,147 @@ impl<'a> QueryBuilder<'a> {
             self.distinct,
             self.offset,
             self.limit,
+            self.cancel.unwrap_or_default(),
+            self.collection.property_transforms(),
+            self.memory_limit
+                .unwrap_or_else(|| self.collection.query_memory_limit()),
+            self.collection.query_spill_dir(),
+            self.collection.db,
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instance::tests::{
+        collection, collections, collections_with_index, find_ids, index, open_instance,
+        put_object, put_values,
+    };
+    use serde_json::json;
+
+    #[test]
+    fn test_sorted_id_where_clause() {
+        let instance = open_instance(collections());
+        let ids = put_values(&instance, &[1, 2, 3, 4, 5]);
+        let col = &instance.collections()[0];
+
+        let mut qb = col.new_query_builder();
+        qb.add_sorted_id_where_clause(i64::MIN, i64::MAX, Sort::Descending)
+            .unwrap();
+        qb.set_limit(2);
+        assert_eq!(find_ids(&instance, &qb.build()), vec![ids[4], ids[3]]);
+
+        let mut qb = col.new_query_builder();
+        qb.add_id_where_clause(ids[3], ids[1]).unwrap();
+        assert_eq!(
+            find_ids(&instance, &qb.build()),
+            vec![ids[3], ids[2], ids[1]]
+        );
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_float_where_clause_nan_policy() {
+        let mut col = collection("col", json!([{"name": "value", "type": "Double"}]));
+        col["indexes"] = json!([index("value", &[("value", false)], false)]);
+        let instance = open_instance(json!([col]));
+        let col = &instance.collections()[0];
+        let ids = [json!(1.0), json!(null), json!(-1.0), json!(2.0)]
+            .map(|value| put_object(&instance, col, None, json!({ "value": value })));
+        let (one, nan, minus_one, two) = (ids[0], ids[1], ids[2], ids[3]);
+
+        let index_id = col.indexes[0].id;
+        let find = |lower, upper, nan, sort| {
+            let mut qb = col.new_query_builder();
+            qb.add_float_where_clause(index_id, &[], lower, upper, nan, sort, false)?;
+            Ok::<_, IsarError>(find_ids(&instance, &qb.build()))
+        };
+        let all = find(None, None, NanPolicy::Include, Sort::Ascending).unwrap();
+        assert_eq!(all, vec![nan, minus_one, one, two]);
+        let all = find(None, None, NanPolicy::Exclude, Sort::Ascending).unwrap();
+        assert_eq!(all, vec![minus_one, one, two]);
+        // NaN is included even though it is stored below the lower bound
+        let lower = Some((0.0, false));
+        let found = find(lower, None, NanPolicy::Include, Sort::Ascending).unwrap();
+        assert_eq!(found, vec![nan, one, two]);
+        let found = find(lower, None, NanPolicy::Include, Sort::Descending).unwrap();
+        assert_eq!(found, vec![two, one, nan]);
+        let found = find(lower, None, NanPolicy::Exclude, Sort::Ascending).unwrap();
+        assert_eq!(found, vec![one, two]);
+
+        let nan_bound = Some((f64::NAN, true));
+        let result = find(nan_bound, None, NanPolicy::Include, Sort::Ascending);
+        assert!(matches!(result, Err(IsarError::IllegalArg { .. })));
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_index_hints() {
+        let instance = open_instance(collections_with_index(Some(false)));
+        let ids = put_values(&instance, &[3, 1, 2]);
+        let col = &instance.collections()[0];
+        let index_id = col.indexes[0].id;
+
+        // the whole index is scanned if there is no where clause
+        let mut qb = col.new_query_builder();
+        qb.use_index("value").unwrap();
+        assert!(qb.no_index().is_err());
+        assert!(qb.add_id_where_clause(0, 10).is_err());
+        let ids_by_value = [ids[1], ids[2], ids[0]];
+        assert_eq!(find_ids(&instance, &qb.build()), ids_by_value);
+
+        let mut qb = col.new_query_builder();
+        qb.no_index().unwrap();
+        let (lower, upper) = (IndexKey::new(), IndexKey::new());
+        let result = qb.add_index_where_clause(index_id, lower, upper, Sort::Ascending, false);
+        assert!(result.is_err());
+        assert_eq!(find_ids(&instance, &qb.build()), ids);
+
+        let mut qb = col.new_query_builder();
+        let result = qb.use_index("unknown");
+        assert!(matches!(result, Err(IsarError::UnknownIndex {})));
+        qb.add_id_where_clause(0, 10).unwrap();
+        assert!(qb.use_index("value").is_err());
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_use_most_selective_index() {
+        let props = json!([{"name": "a", "type": "Long"}, {"name": "b", "type": "Long"}]);
+        let mut col = collection("col", props);
+        col["indexes"] = json!([
+            index("a", &[("a", false)], false),
+            index("b", &[("b", false)], false)
+        ]);
+        let instance = open_instance(json!([col]));
+        let col = &instance.collections()[0];
+        let put = |i: i64| put_object(&instance, col, None, json!({"a": i % 2, "b": i}));
+        let ids = (0..100).map(put).collect::<Vec<_>>();
+
+        let mut txn = instance.begin_txn(false, false).unwrap();
+        let stats = col.index_stats(&mut txn, "a").unwrap();
+        assert_eq!((stats.entries, stats.distinct_keys), (100, 2));
+        assert!(stats.min_key < stats.max_key);
+        let mut qb = col.new_query_builder();
+        qb.use_most_selective_index(&mut txn, &["a", "b"]).unwrap();
+        txn.abort();
+        // the whole index b is scanned which keeps the ids in order
+        assert_eq!(find_ids(&instance, &qb.build()), ids);
+
+        // the stats are only recomputed after the entries changed by more than 10%
+        for i in 100..105 {
+            put(i);
+        }
+        let mut txn = instance.begin_txn(false, false).unwrap();
+        assert_eq!(col.index_stats(&mut txn, "a").unwrap().entries, 100);
+        txn.abort();
+        for i in 105..111 {
+            put(i);
+        }
+        let mut txn = instance.begin_txn(false, false).unwrap();
+        assert_eq!(col.index_stats(&mut txn, "a").unwrap().entries, 111);
+        txn.abort();
+        assert!(instance.close_and_delete());
+    }
+}
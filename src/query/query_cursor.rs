@@ -0,0 +1,232 @@
+use crate::error::{illegal_arg, Result};
+use crate::object::isar_object::IsarObject;
+use crate::query::filter::Filter;
+use crate::query::Query;
+use crate::txn::IsarTxn;
+use intmap::IntMap;
+
+pub struct QueryCursor {
+    query: Query,
+    where_clause_index: usize,
+    position: Option<(Vec<u8>, i64)>,
+    result_ids: Option<IntMap<()>>,
+    distinct_hashes: IntMap<()>,
+    skipped: usize,
+    returned: usize,
+    done: bool,
+}
+
+impl QueryCursor {
+    pub(crate) fn new(query: Query) -> Result<Self> {
+        if !query.sort.is_empty() {
+            return illegal_arg("Sorted queries do not support cursors.");
+        }
+        let result_ids = if query.where_clauses_dup {
+            Some(IntMap::new())
+        } else {
+            None
+        };
+        Ok(QueryCursor {
+            query,
+            where_clause_index: 0,
+            position: None,
+            result_ids,
+            distinct_hashes: IntMap::new(),
+            skipped: 0,
+            returned: 0,
+            done: false,
+        })
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    pub fn next_batch<'txn>(
+        &mut self,
+        txn: &'txn mut IsarTxn,
+        count: usize,
+    ) -> Result<Vec<(i64, IsarObject<'txn>)>> {
+        let mut results = vec![];
+        if self.done || count == 0 {
+            return Ok(results);
+        }
+
+        let QueryCursor {
+            query,
+            where_clause_index,
+            position,
+            result_ids,
+            distinct_hashes,
+            skipped,
+            returned,
+            done,
+        } = self;
+
+        let static_filter = Filter::stat(true);
+        let filter = query.filter.as_ref().unwrap_or(&static_filter);
+
+        txn.read(query.instance_id, |cursors| {
+            while let Some(where_clause) = query.where_clauses.get(*where_clause_index) {
+                let mut last = None;
+                let start = position.as_ref().map(|(key, id)| (key.as_slice(), *id));
+                let mut visit = |id, object| {
+                    if !filter.evaluate(id, object, Some(cursors))? {
+                        return Ok(true);
+                    }
+                    if !query.distinct.is_empty() {
+                        let hash = Query::hash_properties(object, &query.distinct);
+                        if !distinct_hashes.insert_checked(hash, ()) {
+                            return Ok(true);
+                        }
+                    }
+                    if *skipped < query.offset {
+                        *skipped += 1;
+                        return Ok(true);
+                    }
+
+                    results.push((id, object));
+                    *returned += 1;
+                    if *returned >= query.limit {
+                        *done = true;
+                        return Ok(false);
+                    }
+                    Ok(results.len() < count)
+                };
+                let completed = where_clause.iter_from(
+                    cursors,
+                    start,
+                    result_ids.as_mut(),
+                    |key, id, object| {
+                        let more = visit(id, object)?;
+                        // the position is only needed to resume after the last returned entry
+                        if !more {
+                            last = Some((key.to_vec(), id));
+                        }
+                        Ok(more)
+                    },
+                )?;
+
+                if let Some(last) = last {
+                    *position = Some(last);
+                }
+                if completed {
+                    *where_clause_index += 1;
+                    *position = None;
+                } else {
+                    break;
+                }
+            }
+            if *where_clause_index >= query.where_clauses.len() {
+                *done = true;
+            }
+            Ok(())
+        })?;
+
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::instance::tests::{collections_with_index, open_instance, put_values, reopen};
+    use crate::instance::IsarInstance;
+    use crate::query::Sort;
+    use xxhash_rust::xxh3::xxh3_64;
+
+    /// Reads the index with a cursor and returns the ids of each batch.
+    fn read_batches(
+        instance: &IsarInstance,
+        sort: Sort,
+        skip_duplicates: bool,
+        batch_size: usize,
+    ) -> Vec<Vec<i64>> {
+        let col = &instance.collections()[0];
+        let mut qb = col.new_query_builder();
+        qb.add_index_prefix_where_clause(xxh3_64(b"value"), &[], None, None, sort, skip_duplicates)
+            .unwrap();
+        let mut cursor = qb.build().cursor().unwrap();
+        let mut batches = vec![];
+        loop {
+            let mut txn = instance.begin_txn(false, false).unwrap();
+            let batch = cursor.next_batch(&mut txn, batch_size).unwrap();
+            if batch.is_empty() {
+                return batches;
+            }
+            batches.push(batch.iter().map(|(id, _)| *id).collect());
+        }
+    }
+
+    #[test]
+    fn test_cursor_resumes_after_last_position() {
+        let instance = open_instance(collections_with_index(Some(false)));
+        put_values(&instance, &[2, 1, 2, 1, 3]);
+        let batches = read_batches(&instance, Sort::Ascending, false, 2);
+        assert_eq!(batches, vec![vec![2, 4], vec![1, 3], vec![5]]);
+        let batches = read_batches(&instance, Sort::Descending, false, 2);
+        assert_eq!(batches, vec![vec![5, 3], vec![1, 4], vec![2]]);
+        let batches = read_batches(&instance, Sort::Ascending, true, 1);
+        assert_eq!(batches, vec![vec![2], vec![1], vec![5]]);
+
+        let col = &instance.collections()[0];
+        let mut qb = col.new_query_builder();
+        qb.add_index_prefix_where_clause(
+            xxh3_64(b"value"),
+            &[],
+            None,
+            None,
+            Sort::Ascending,
+            false,
+        )
+        .unwrap();
+        let mut cursor = qb.build().cursor().unwrap();
+        let mut txn = instance.begin_txn(false, false).unwrap();
+        let batch = cursor.next_batch(&mut txn, 2).unwrap();
+        assert_eq!(
+            batch.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            vec![2, 4]
+        );
+        drop(txn);
+
+        // objects before the position are skipped even if they are new
+        put_values(&instance, &[1, 0]);
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        col.delete(&mut txn, 1).unwrap();
+        txn.commit().unwrap();
+
+        let mut txn = instance.begin_txn(false, false).unwrap();
+        let batch = cursor.next_batch(&mut txn, 10).unwrap();
+        assert_eq!(
+            batch.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            vec![6, 3, 5]
+        );
+        assert!(cursor.next_batch(&mut txn, 10).unwrap().is_empty());
+        drop(txn);
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_pending_index_cursor_uses_index_order() {
+        let values = [2, 1, 2, 1, 3];
+        let instance = open_instance(collections_with_index(None));
+        put_values(&instance, &values);
+        let instance = reopen(instance, collections_with_index(Some(true)));
+        assert!(!instance.collections()[0].get_pending_index_ids().is_empty());
+
+        let read_all = |instance: &IsarInstance| {
+            vec![
+                read_batches(instance, Sort::Ascending, false, 2),
+                read_batches(instance, Sort::Descending, false, 2),
+                read_batches(instance, Sort::Ascending, true, 1),
+                read_batches(instance, Sort::Descending, true, 1),
+            ]
+        };
+        let pending = read_all(&instance);
+        assert_eq!(pending[0], vec![vec![2, 4], vec![1, 3], vec![5]]);
+
+        instance.build_pending_indexes(10).unwrap();
+        assert!(instance.collections()[0].get_pending_index_ids().is_empty());
+        assert_eq!(pending, read_all(&instance));
+        assert!(instance.close_and_delete());
+    }
+}
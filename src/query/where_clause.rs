@@ -14,6 +14,13 @@ pub(crate) enum WhereClause {
 }
 
 impl WhereClause {
+    pub fn index_id(&self) -> Option<u64> {
+        match self {
+            WhereClause::Index(wc) => Some(wc.index_id()),
+            _ => None,
+        }
+    }
+
     pub fn maybe_matches(&self, id: i64, object: IsarObject) -> bool {
         match self {
             WhereClause::Id(wc) => wc.id_matches(id),
@@ -38,6 +45,28 @@ impl WhereClause {
         }
     }
 
+    pub fn iter_from<'txn, 'env, F>(
+        &self,
+        cursors: &IsarCursors<'txn, 'env>,
+        start: Option<(&[u8], i64)>,
+        result_ids: Option<&mut IntMap<()>>,
+        mut callback: F,
+    ) -> Result<bool>
+    where
+        F: FnMut(&[u8], i64, IsarObject<'txn>) -> Result<bool>,
+    {
+        let start_id = start.map(|(_, id)| id);
+        match self {
+            WhereClause::Id(wc) => wc.iter_from(cursors, start_id, result_ids, |id, object| {
+                callback(&[], id, object)
+            }),
+            WhereClause::Index(wc) => wc.iter_from(cursors, start, result_ids, callback),
+            WhereClause::Link(wc) => wc.iter_from(cursors, start_id, result_ids, |id, object| {
+                callback(&[], id, object)
+            }),
+        }
+    }
+
     pub(crate) fn is_overlapping(&self, other: &Self) -> bool {
         match (self, other) {
             (WhereClause::Id(wc1), WhereClause::Id(wc2)) => wc1.is_overlapping(wc2),
@@ -46,6 +75,13 @@ impl WhereClause {
         }
     }
 
+    pub(crate) fn is_unrestricted(&self) -> bool {
+        match self {
+            WhereClause::Id(wc) => wc.is_unrestricted(),
+            _ => false,
+        }
+    }
+
     pub(crate) fn has_duplicates(&self) -> bool {
         match self {
             WhereClause::Id(_) => false,
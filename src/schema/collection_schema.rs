@@ -1,9 +1,11 @@
 use crate::error::{schema_error, IsarError, Result};
 use crate::object::data_type::DataType;
 use crate::object::property::Property;
-use crate::schema::index_schema::{IndexSchema, IndexType};
+use crate::schema::derived_schema::DerivedPropertySchema;
+use crate::schema::index_schema::{Collation, IndexSchema, IndexType};
 use crate::schema::link_schema::LinkSchema;
 use crate::schema::property_schema::PropertySchema;
+use crate::schema::unique_constraint_schema::UniqueConstraintSchema;
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 
@@ -19,7 +21,54 @@ pub struct CollectionSchema {
     #[serde(default)]
     pub(crate) links: Vec<LinkSchema>,
     #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) backlinks: Vec<LinkSchema>,
+    #[serde(default)]
     pub(crate) version: u8,
+    #[serde(default)]
+    #[serde(rename = "previousName")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) previous_name: Option<String>,
+    #[serde(default)]
+    #[serde(rename = "pendingIndexes")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) pending_indexes: Vec<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) derived: Vec<DerivedPropertySchema>,
+    #[serde(default)]
+    #[serde(rename = "neverReuseIds")]
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) never_reuse_ids: bool,
+    #[serde(default)]
+    #[serde(rename = "idStrategy")]
+    #[serde(skip_serializing_if = "IdStrategy::is_default")]
+    pub(crate) id_strategy: IdStrategy,
+    #[serde(default)]
+    #[serde(rename = "softDelete")]
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) soft_delete: bool,
+    #[serde(default)]
+    #[serde(rename = "uniqueConstraints")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) unique_constraints: Vec<UniqueConstraintSchema>,
+}
+
+/// How ids are generated for objects that are put without an id.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq, Default)]
+pub enum IdStrategy {
+    #[default]
+    AutoIncrement,
+    /// Random positive ids with 53 bits so they are safe to use in JavaScript.
+    Random,
+    /// Milliseconds since the epoch shifted by 10 bits followed by a sequence number.
+    TimeOrdered,
+}
+
+impl IdStrategy {
+    fn is_default(&self) -> bool {
+        *self == IdStrategy::AutoIncrement
+    }
 }
 
 impl PartialEq for CollectionSchema {
@@ -42,11 +91,63 @@ impl CollectionSchema {
             properties,
             indexes,
             links,
+            backlinks: vec![],
             version: SchemaManager::ISAR_VERSION,
+            previous_name: None,
+            pending_indexes: vec![],
+            derived: vec![],
+            never_reuse_ids: false,
+            id_strategy: IdStrategy::AutoIncrement,
+            soft_delete: false,
+            unique_constraints: vec![],
         }
     }
 
-    fn verify_name(name: &str) -> Result<()> {
+    pub fn with_previous_name(mut self, previous_name: &str) -> Self {
+        self.previous_name = Some(previous_name.to_string());
+        self
+    }
+
+    /// Declared backlinks replace the backlinks that are otherwise discovered from the links of
+    /// all other collections. Every link to this collection has to be declared.
+    pub fn with_backlinks(mut self, backlinks: Vec<LinkSchema>) -> Self {
+        self.backlinks = backlinks;
+        self
+    }
+
+    pub fn with_derived(mut self, derived: Vec<DerivedPropertySchema>) -> Self {
+        self.derived = derived;
+        self
+    }
+
+    /// Persists the highest auto-increment id so deleted or cleared ids are never reused.
+    pub fn with_never_reuse_ids(mut self, never_reuse_ids: bool) -> Self {
+        self.never_reuse_ids = never_reuse_ids;
+        self
+    }
+
+    pub fn with_id_strategy(mut self, id_strategy: IdStrategy) -> Self {
+        self.id_strategy = id_strategy;
+        self
+    }
+
+    /// Deletes hide objects behind a tombstone with the time of deletion until it is purged.
+    /// Hidden objects are removed from the collection and its indexes so queries do not return
+    /// them but they can still be read and restored.
+    pub fn with_soft_delete(mut self, soft_delete: bool) -> Self {
+        self.soft_delete = soft_delete;
+        self
+    }
+
+    pub fn with_unique_constraints(
+        mut self,
+        unique_constraints: Vec<UniqueConstraintSchema>,
+    ) -> Self {
+        self.unique_constraints = unique_constraints;
+        self
+    }
+
+    pub(crate) fn verify_name(name: &str) -> Result<()> {
         if name.is_empty() {
             schema_error("Empty names are not allowed.")
         } else if name.starts_with('_') {
@@ -59,9 +160,14 @@ impl CollectionSchema {
     pub(crate) fn verify(&self, collections: &[CollectionSchema]) -> Result<()> {
         Self::verify_name(&self.name)?;
 
-        if self.embedded && (!self.links.is_empty() || !self.indexes.is_empty()) {
+        if self.embedded
+            && (!self.links.is_empty() || !self.backlinks.is_empty() || !self.indexes.is_empty())
+        {
             schema_error("Embedded objects must not have Links or Indexes.")?;
         }
+        if self.embedded && !self.blobs().is_empty() {
+            schema_error("Embedded objects must not have Blobs.")?;
+        }
 
         let verify_target_col_exists = |col: &str, embedded: bool| -> Result<()> {
             if !collections
@@ -90,11 +196,56 @@ impl CollectionSchema {
                     schema_error("Target collection can only be set for object properties.")?;
                 }
             }
+
+            if let Some(enum_values) = &property.enum_values {
+                let supported = matches!(
+                    property.data_type,
+                    DataType::Byte
+                        | DataType::Int
+                        | DataType::Long
+                        | DataType::ByteList
+                        | DataType::IntList
+                        | DataType::LongList
+                );
+                if !supported {
+                    schema_error(
+                        "Enum values are only supported for Byte, Int and Long properties.",
+                    )?;
+                }
+                if enum_values.is_empty() {
+                    schema_error("Enum values must not be empty.")?;
+                }
+            }
         }
 
         for link in &self.links {
             Self::verify_name(&link.name)?;
             verify_target_col_exists(&link.target_col, false)?;
+            if link.source_link.is_some() {
+                schema_error("Backlinks have to be declared as backlinks.")?;
+            }
+        }
+
+        for backlink in &self.backlinks {
+            Self::verify_name(&backlink.name)?;
+            if backlink.ordered {
+                schema_error("Backlinks cannot be ordered.")?;
+            }
+            let source_link = collections
+                .iter()
+                .filter(|c| c.name == backlink.target_col && !c.embedded)
+                .flat_map(|c| &c.links)
+                .find(|l| Some(&l.name) == backlink.source_link.as_ref());
+            if source_link.is_none_or(|l| l.target_col != self.name) {
+                schema_error("Backlink source link does not exist.")?;
+            }
+        }
+        let backlink_sources = self
+            .backlinks
+            .iter()
+            .unique_by(|l| (&l.target_col, &l.source_link));
+        if backlink_sources.count() != self.backlinks.len() {
+            schema_error("Duplicate backlink")?;
         }
 
         let property_names = self
@@ -110,11 +261,68 @@ impl CollectionSchema {
             schema_error("Duplicate index name")?;
         }
 
-        let link_names = self.links.iter().unique_by(|l| l.name.as_str());
-        if link_names.count() != self.links.len() {
+        let link_names = self
+            .links
+            .iter()
+            .chain(&self.backlinks)
+            .unique_by(|l| l.name.as_str());
+        if link_names.count() != self.links.len() + self.backlinks.len() {
             schema_error("Duplicate link name")?;
         }
 
+        for derived in &self.derived {
+            let is_string_property = |name: &str| {
+                self.properties
+                    .iter()
+                    .any(|p| p.name.as_deref() == Some(name) && p.data_type == DataType::String)
+            };
+            if !is_string_property(&derived.name) || !is_string_property(&derived.source) {
+                schema_error("Derived properties and their source must be String properties.")?;
+            }
+            if self.derived.iter().any(|d| d.name == derived.source) {
+                schema_error(
+                    "Derived properties cannot be derived from other derived properties.",
+                )?;
+            }
+        }
+        let derived_names = self.derived.iter().unique_by(|d| d.name.as_str());
+        if derived_names.count() != self.derived.len() {
+            schema_error("Duplicate derived property")?;
+        }
+
+        for constraint in &self.unique_constraints {
+            Self::verify_name(&constraint.name)?;
+            if constraint.properties.is_empty() {
+                schema_error("Unique constraints need at least one property")?;
+            }
+            for name in &constraint.properties {
+                let property = self
+                    .properties
+                    .iter()
+                    .find(|p| p.name.as_ref() == Some(name));
+                if let Some(property) = property {
+                    let supported = property.data_type.is_scalar()
+                        && property.data_type != DataType::Object
+                        && property.data_type != DataType::Blob;
+                    if !supported {
+                        schema_error("Unique constraints only support scalar properties")?;
+                    }
+                } else {
+                    schema_error("Unique constraint property does not exist")?;
+                }
+            }
+            if constraint.properties.iter().unique().count() != constraint.properties.len() {
+                schema_error("Duplicate unique constraint property")?;
+            }
+        }
+        let constraint_names = self
+            .unique_constraints
+            .iter()
+            .unique_by(|c| c.name.as_str());
+        if constraint_names.count() != self.unique_constraints.len() {
+            schema_error("Duplicate unique constraint name")?;
+        }
+
         for index in &self.indexes {
             if index.properties.is_empty() {
                 schema_error("At least one property needs to be added to a valid index")?;
@@ -126,6 +334,15 @@ impl CollectionSchema {
                 schema_error("Only unique indexes can replace")?;
             }
 
+            if index
+                .properties
+                .iter()
+                .any(|p| p.index_type == IndexType::Geo)
+            {
+                self.verify_geo_index(index)?;
+                continue;
+            }
+
             for (i, index_property) in index.properties.iter().enumerate() {
                 let property = self
                     .properties
@@ -141,6 +358,9 @@ impl CollectionSchema {
                 {
                     schema_error("Object and ObjectList cannot be indexed.")?;
                 }
+                if property.data_type == DataType::Blob {
+                    schema_error("Blobs cannot be indexed.")?;
+                }
 
                 if property.data_type == DataType::Float
                     || property.data_type == DataType::Double
@@ -186,16 +406,67 @@ impl CollectionSchema {
                 {
                     schema_error("Only String and StringList indexes may be case sensitive.")?;
                 }
+                if index_property.collation != Collation::Binary
+                    && ((property.data_type != DataType::String
+                        && property.data_type != DataType::StringList)
+                        || index_property.index_type != IndexType::Value)
+                {
+                    schema_error("Only String and StringList value indexes support collation.")?;
+                }
             }
         }
 
         Ok(())
     }
 
+    fn verify_geo_index(&self, index: &IndexSchema) -> Result<()> {
+        if index.properties.len() != 2 {
+            schema_error("Geo indexes need a latitude and a longitude property.")?;
+        }
+        if index.unique {
+            schema_error("Geo indexes cannot be unique.")?;
+        }
+        for index_property in &index.properties {
+            let property = self
+                .properties
+                .iter()
+                .find(|p| p.name.as_ref() == Some(&index_property.name));
+            if property.map(|p| p.data_type) != Some(DataType::Double) {
+                schema_error("Geo indexes only support Double properties.")?;
+            }
+            if index_property.index_type != IndexType::Geo
+                || index_property.case_sensitive
+                || index_property.collation != Collation::Binary
+            {
+                schema_error("All properties of a geo index need to use the Geo type.")?;
+            }
+        }
+        Ok(())
+    }
+
     pub(crate) fn merge_properties(&mut self, existing: &Self) -> Result<Vec<String>> {
         let mut properties = existing.properties.clone();
         let mut removed_properties = vec![];
 
+        for property in &self.properties {
+            if let Some(previous_name) = &property.previous_name {
+                if properties.iter().any(|p| p.name == property.name) {
+                    continue;
+                }
+                let renamed = properties
+                    .iter_mut()
+                    .find(|p| p.name.as_ref() == Some(previous_name));
+                if let Some(renamed) = renamed {
+                    renamed.name = property.name.clone();
+                    if renamed != property {
+                        schema_error("Renamed properties must not change their type.")?;
+                    }
+                    // the index entries reference the old property name
+                    removed_properties.push(previous_name.clone());
+                }
+            }
+        }
+
         for property in &mut properties {
             if property.name.is_some() && !self.properties.contains(property) {
                 removed_properties.push(property.name.take().unwrap());
@@ -212,10 +483,64 @@ impl CollectionSchema {
         Ok(removed_properties)
     }
 
+    pub(crate) fn get_widened_properties(&self, existing: &Self) -> Vec<String> {
+        let mut widened = vec![];
+        for property in &self.properties {
+            let existing_property = existing
+                .properties
+                .iter()
+                .find(|p| p.name.is_some() && p.name == property.name);
+            if let Some(existing_property) = existing_property {
+                if existing_property.data_type.can_widen_to(property.data_type) {
+                    widened.push(property.name.clone().unwrap());
+                }
+            }
+        }
+        widened
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn is_embedded(&self) -> bool {
+        self.embedded
+    }
+
+    /// Properties in the order they are stored in the objects.
+    pub fn properties(&self) -> &[PropertySchema] {
+        &self.properties
+    }
+
+    pub fn indexes(&self) -> &[IndexSchema] {
+        &self.indexes
+    }
+
+    pub fn links(&self) -> &[LinkSchema] {
+        &self.links
+    }
+
+    pub fn backlinks(&self) -> &[LinkSchema] {
+        &self.backlinks
+    }
+
+    /// Names of the Blob properties.
+    pub fn blobs(&self) -> Vec<String> {
+        self.properties
+            .iter()
+            .filter(|p| p.data_type == DataType::Blob)
+            .filter_map(|p| p.name.clone())
+            .collect()
+    }
+
+    /// The properties that are stored in the objects.
     pub fn get_properties(&self) -> Vec<Property> {
         let mut properties = vec![];
         let mut offset = 2;
         for property_schema in self.properties.iter() {
+            if property_schema.data_type == DataType::Blob {
+                continue;
+            }
             let property = property_schema.as_property(offset);
             if let Some(property) = property {
                 properties.push(property);
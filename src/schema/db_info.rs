@@ -0,0 +1,29 @@
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DbInfo {
+    pub isar_version: u8,
+    pub core_version: String,
+    pub schema_hash: u64,
+    pub last_opened: u64,
+}
+
+impl DbInfo {
+    pub(crate) fn new(isar_version: u8, schema_hash: u64) -> Self {
+        let last_opened = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64);
+        DbInfo {
+            isar_version,
+            core_version: env!("CARGO_PKG_VERSION").to_string(),
+            schema_hash,
+            last_opened,
+        }
+    }
+
+    pub fn is_newer(&self, isar_version: u8) -> bool {
+        self.isar_version > isar_version
+    }
+}
@@ -0,0 +1,36 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Transform {
+    Lowercase,
+    Uppercase,
+    Trim,
+}
+
+impl Transform {
+    pub fn apply(&self, value: &str) -> String {
+        match self {
+            Transform::Lowercase => value.to_lowercase(),
+            Transform::Uppercase => value.to_uppercase(),
+            Transform::Trim => value.trim().to_string(),
+        }
+    }
+}
+
+/// A String property that is computed from another String property whenever an object is put.
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct DerivedPropertySchema {
+    pub(crate) name: String,
+    pub(crate) source: String,
+    pub(crate) transform: Transform,
+}
+
+impl DerivedPropertySchema {
+    pub fn new(name: &str, source: &str, transform: Transform) -> DerivedPropertySchema {
+        DerivedPropertySchema {
+            name: name.to_string(),
+            source: source.to_string(),
+            transform,
+        }
+    }
+}
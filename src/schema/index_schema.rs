@@ -1,6 +1,6 @@
 use crate::index::{IndexProperty, IsarIndex};
-use crate::mdbx::db::Db;
 use crate::object::property::Property;
+use crate::storage::LazyDb;
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 
@@ -9,6 +9,14 @@ pub enum IndexType {
     Value,
     Hash,
     HashElements,
+    Geo,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq, Default)]
+pub enum Collation {
+    #[default]
+    Binary,
+    CaseInsensitive,
 }
 
 #[derive(Serialize, Deserialize, Clone, Eq, PartialEq)]
@@ -18,6 +26,8 @@ pub struct IndexPropertySchema {
     pub(crate) index_type: IndexType,
     #[serde(rename = "caseSensitive")]
     pub(crate) case_sensitive: bool,
+    #[serde(default)]
+    pub(crate) collation: Collation,
 }
 
 impl IndexPropertySchema {
@@ -26,17 +36,50 @@ impl IndexPropertySchema {
             name: name.to_string(),
             index_type,
             case_sensitive,
+            collation: Collation::Binary,
         }
     }
+
+    pub fn with_collation(mut self, collation: Collation) -> Self {
+        self.collation = collation;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn index_type(&self) -> IndexType {
+        self.index_type
+    }
+
+    pub fn is_case_sensitive(&self) -> bool {
+        self.case_sensitive
+    }
+
+    pub fn collation(&self) -> &Collation {
+        &self.collation
+    }
 }
 
-#[derive(Serialize, Deserialize, Clone, Eq, PartialEq)]
+#[derive(Serialize, Deserialize, Clone, Eq)]
 pub struct IndexSchema {
     pub(crate) name: String,
     pub(crate) properties: Vec<IndexPropertySchema>,
     pub(crate) unique: bool,
     #[serde(default)]
     pub(crate) replace: bool,
+    #[serde(default)]
+    pub(crate) lazy: bool,
+}
+
+impl PartialEq for IndexSchema {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+            && self.properties == other.properties
+            && self.unique == other.unique
+            && self.replace == other.replace
+    }
 }
 
 impl IndexSchema {
@@ -51,18 +94,52 @@ impl IndexSchema {
             properties,
             unique,
             replace,
+            lazy: false,
         }
     }
 
-    pub(crate) fn as_index(&self, db: Db, properties: &[Property]) -> IsarIndex {
+    pub fn with_lazy(mut self, lazy: bool) -> Self {
+        self.lazy = lazy;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn properties(&self) -> &[IndexPropertySchema] {
+        &self.properties
+    }
+
+    pub fn is_unique(&self) -> bool {
+        self.unique
+    }
+
+    pub fn is_replace(&self) -> bool {
+        self.replace
+    }
+
+    pub(crate) fn as_index(&self, db: LazyDb, properties: &[Property], pending: bool) -> IsarIndex {
         let index_properties = self
             .properties
             .iter()
             .map(|ip| {
                 let property = properties.iter().find(|p| ip.name == *p.name).unwrap();
-                IndexProperty::new(property.clone(), ip.index_type, ip.case_sensitive)
+                IndexProperty::new(
+                    property.clone(),
+                    ip.index_type,
+                    ip.case_sensitive,
+                    ip.collation.clone(),
+                )
             })
             .collect_vec();
-        IsarIndex::new(&self.name, db, index_properties, self.unique, self.replace)
+        IsarIndex::new(
+            &self.name,
+            db,
+            index_properties,
+            self.unique,
+            self.replace,
+            pending,
+        )
     }
 }
@@ -1,10 +1,26 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Clone, Eq, PartialEq)]
+#[derive(Serialize, Deserialize, Clone, Eq)]
 pub struct LinkSchema {
     pub(crate) name: String,
     #[serde(rename = "target")]
     pub(crate) target_col: String,
+    #[serde(default)]
+    pub(crate) ordered: bool,
+    /// For backlinks the name of the link in the target collection.
+    #[serde(default)]
+    #[serde(rename = "link")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) source_link: Option<String>,
+}
+
+/// Links that only differ in their order are the same link. Their positions are migrated.
+impl PartialEq for LinkSchema {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+            && self.target_col == other.target_col
+            && self.source_link == other.source_link
+    }
 }
 
 impl LinkSchema {
@@ -12,6 +28,40 @@ impl LinkSchema {
         LinkSchema {
             name: name.to_string(),
             target_col: target_collection_name.to_string(),
+            ordered: false,
+            source_link: None,
         }
     }
+
+    /// A backlink of the link `source_link_name` in `source_collection_name`.
+    pub fn backlink(name: &str, source_collection_name: &str, source_link_name: &str) -> Self {
+        LinkSchema {
+            name: name.to_string(),
+            target_col: source_collection_name.to_string(),
+            ordered: false,
+            source_link: Some(source_link_name.to_string()),
+        }
+    }
+
+    pub fn with_ordered(mut self, ordered: bool) -> Self {
+        self.ordered = ordered;
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// For backlinks the collection that contains the link.
+    pub fn target_collection(&self) -> &str {
+        &self.target_col
+    }
+
+    pub fn is_ordered(&self) -> bool {
+        self.ordered
+    }
+
+    pub fn source_link(&self) -> Option<&str> {
+        self.source_link.as_deref()
+    }
 }
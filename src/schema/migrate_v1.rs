@@ -6,7 +6,11 @@ use crate::object::id::BytesToId;
 use crate::object::isar_object::IsarObject;
 use crate::object::object_builder::ObjectBuilder;
 use crate::schema::schema_manager::SchemaManager;
-use crate::{cursor::IsarCursors, error::Result, mdbx::txn::Txn};
+use crate::{
+    cursor::IsarCursors,
+    error::Result,
+    storage::{StorageCursor, StorageDb, Txn},
+};
 
 use super::collection_schema::CollectionSchema;
 
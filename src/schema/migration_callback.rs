@@ -0,0 +1,41 @@
+use crate::error::Result;
+use crate::object::isar_object::IsarObject;
+use crate::object::object_builder::ObjectBuilder;
+use intmap::IntMap;
+use once_cell::sync::Lazy;
+use std::sync::{Arc, RwLock};
+use xxhash_rust::xxh3::{xxh3_64, xxh3_64_with_seed};
+
+/// Transforms a stored object during migration. The builder already contains the object in
+/// the new layout and the callback may overwrite any property.
+pub type MigrationCallback =
+    Box<dyn Fn(IsarObject, &mut ObjectBuilder) -> Result<()> + Send + Sync>;
+
+static MIGRATION_CALLBACKS: Lazy<RwLock<IntMap<Arc<MigrationCallback>>>> =
+    Lazy::new(|| RwLock::new(IntMap::new()));
+
+fn callback_key(instance_id: u64, collection: &str) -> u64 {
+    xxh3_64_with_seed(collection.as_bytes(), instance_id)
+}
+
+pub fn set_migration_callback(
+    instance_name: &str,
+    collection: &str,
+    callback: Option<MigrationCallback>,
+) {
+    let key = callback_key(xxh3_64(instance_name.as_bytes()), collection);
+    let mut lock = MIGRATION_CALLBACKS.write().unwrap();
+    if let Some(callback) = callback {
+        lock.insert(key, Arc::new(callback));
+    } else {
+        lock.remove(key);
+    }
+}
+
+pub(crate) fn get_migration_callback(
+    instance_id: u64,
+    collection: &str,
+) -> Option<Arc<MigrationCallback>> {
+    let lock = MIGRATION_CALLBACKS.read().unwrap();
+    lock.get(callback_key(instance_id, collection)).cloned()
+}
@@ -0,0 +1,84 @@
+use crate::schema::collection_schema::CollectionSchema;
+use crate::schema::Schema;
+use serde::Serialize;
+
+#[derive(Serialize, Clone, Debug, Default, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CollectionMigrationPlan {
+    pub name: String,
+    pub added: bool,
+    pub renamed_from: Option<String>,
+    pub added_properties: Vec<String>,
+    pub removed_properties: Vec<String>,
+    pub renamed_properties: Vec<(String, String)>,
+    pub widened_properties: Vec<String>,
+    pub added_indexes: Vec<String>,
+    pub dropped_indexes: Vec<String>,
+    pub added_links: Vec<String>,
+    pub dropped_links: Vec<String>,
+    pub object_count: u64,
+    pub objects_to_rewrite: u64,
+    pub objects_to_index: u64,
+}
+
+impl CollectionMigrationPlan {
+    pub(crate) fn new_collection(schema: &CollectionSchema) -> Self {
+        CollectionMigrationPlan {
+            name: schema.name.clone(),
+            added: true,
+            added_properties: schema
+                .properties
+                .iter()
+                .filter_map(|p| p.name.clone())
+                .collect(),
+            added_indexes: schema.indexes.iter().map(|i| i.name.clone()).collect(),
+            added_links: schema.links.iter().map(|l| l.name.clone()).collect(),
+            ..Default::default()
+        }
+    }
+
+    pub fn has_changes(&self) -> bool {
+        self.added
+            || self.renamed_from.is_some()
+            || !self.added_properties.is_empty()
+            || !self.removed_properties.is_empty()
+            || !self.renamed_properties.is_empty()
+            || !self.widened_properties.is_empty()
+            || !self.added_indexes.is_empty()
+            || !self.dropped_indexes.is_empty()
+            || !self.added_links.is_empty()
+            || !self.dropped_links.is_empty()
+    }
+}
+
+#[derive(Serialize, Clone, Debug, Default, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct MigrationPlan {
+    pub collections: Vec<CollectionMigrationPlan>,
+    pub dropped_collections: Vec<String>,
+}
+
+impl MigrationPlan {
+    pub(crate) fn new_instance(schema: &Schema) -> Self {
+        MigrationPlan {
+            collections: schema
+                .collections
+                .iter()
+                .map(CollectionMigrationPlan::new_collection)
+                .collect(),
+            dropped_collections: vec![],
+        }
+    }
+
+    pub fn has_changes(&self) -> bool {
+        !self.dropped_collections.is_empty() || self.collections.iter().any(|c| c.has_changes())
+    }
+
+    /// Rough number of objects that have to be written or indexed by the migration.
+    pub fn estimated_work(&self) -> u64 {
+        self.collections
+            .iter()
+            .map(|c| c.objects_to_rewrite + c.objects_to_index)
+            .sum()
+    }
+}
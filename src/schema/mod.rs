@@ -1,14 +1,21 @@
 pub mod collection_schema;
+pub mod db_info;
+pub mod derived_schema;
 pub mod index_schema;
 pub mod link_schema;
 pub(crate) mod migrate_v1;
+pub mod migration_callback;
+pub mod migration_plan;
 pub mod property_schema;
 pub(crate) mod schema_manager;
+pub mod unique_constraint_schema;
 
-use crate::error::{schema_error, Result};
+use crate::error::{schema_error, IsarError, Result};
 use crate::schema::collection_schema::CollectionSchema;
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
 use xxhash_rust::xxh3::xxh3_64_with_seed;
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -16,6 +23,17 @@ pub struct Schema {
     pub(crate) collections: Vec<CollectionSchema>,
 }
 
+/// Versioned schema JSON. Unknown fields are ignored so newer clients can add optional fields.
+/// Schemas that older cores cannot read correctly have to raise `minVersion`.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SchemaEnvelope {
+    version: u32,
+    #[serde(default)]
+    min_version: u32,
+    collections: Vec<CollectionSchema>,
+}
+
 impl Schema {
     pub fn new(collections: Vec<CollectionSchema>) -> Result<Schema> {
         let collection_names = collections.iter().unique_by(|c| &c.name);
@@ -25,19 +43,78 @@ impl Schema {
         for col in &collections {
             col.verify(&collections)?;
         }
+        Self::verify_backlinks_declared(&collections)?;
 
         let schema = Schema { collections };
         Ok(schema)
     }
 
+    /// Collections that declare backlinks have to declare a backlink for every link to them.
+    fn verify_backlinks_declared(collections: &[CollectionSchema]) -> Result<()> {
+        let declaring: HashMap<&str, &CollectionSchema> = collections
+            .iter()
+            .filter(|c| !c.embedded && !c.backlinks.is_empty())
+            .map(|c| (c.name.as_str(), c))
+            .collect();
+        for col in collections {
+            for link in &col.links {
+                if let Some(target_col) = declaring.get(link.target_col.as_str()) {
+                    let declared = target_col.backlinks.iter().any(|b| {
+                        b.target_col == col.name && b.source_link.as_ref() == Some(&link.name)
+                    });
+                    if !declared {
+                        schema_error("Backlink is not declared.")?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The newest schema JSON version this core understands.
+    pub const VERSION: u32 = 1;
+
+    /// Accepts the versioned envelope and the legacy list of collections.
     pub fn from_json(json: &[u8]) -> Result<Schema> {
-        if let Ok(collections) = serde_json::from_slice::<Vec<CollectionSchema>>(json) {
+        let value = if let Ok(value) = serde_json::from_slice::<Value>(json) {
+            value
+        } else {
+            return schema_error("Could not deserialize schema JSON");
+        };
+
+        let collections = if value.is_array() {
+            serde_json::from_value::<Vec<CollectionSchema>>(value).ok()
+        } else {
+            let envelope = serde_json::from_value::<SchemaEnvelope>(value).ok();
+            if let Some(envelope) = &envelope {
+                if envelope.min_version > Self::VERSION {
+                    return schema_error("The schema requires a newer version of Isar.");
+                }
+            }
+            envelope.map(|e| e.collections)
+        };
+        if let Some(collections) = collections {
             Schema::new(collections)
         } else {
             schema_error("Could not deserialize schema JSON")
         }
     }
 
+    pub fn collections(&self) -> &[CollectionSchema] {
+        &self.collections
+    }
+
+    pub fn to_json(&self) -> Result<Vec<u8>> {
+        let envelope = SchemaEnvelope {
+            version: Self::VERSION,
+            min_version: 1,
+            collections: self.collections.clone(),
+        };
+        serde_json::to_vec(&envelope).map_err(|_| IsarError::SchemaError {
+            message: "Could not serialize schema.".to_string(),
+        })
+    }
+
     pub(crate) fn get_collection(&self, name: &str, embedded: bool) -> Option<&CollectionSchema> {
         self.collections
             .iter()
@@ -50,15 +127,33 @@ impl Schema {
             count += 1;
             count += col.indexes.len();
             count += col.links.len() * 2;
+            count += col.links.iter().filter(|l| l.ordered).count();
         }
         count
     }
 
+    /// Renames a collection and the links to it.
+    pub(crate) fn rename_collection(&mut self, old_name: &str, new_name: &str) {
+        for col in &mut self.collections {
+            if col.name == old_name && !col.embedded {
+                col.name = new_name.to_string();
+                col.previous_name = None;
+            }
+            for link in col.links.iter_mut().chain(&mut col.backlinks) {
+                if link.target_col == old_name {
+                    link.target_col = new_name.to_string();
+                }
+            }
+        }
+    }
+
+    /// Hash of the canonicalized schema. Collections are hashed in the order of their names.
     pub(crate) fn hash(&mut self) -> u64 {
         self.collections.sort_by(|a, b| a.name.cmp(&b.name));
-        self.collections
-            .iter()
-            .fold(0, |seed, col| xxh3_64_with_seed(col.name.as_bytes(), seed))
+        self.collections.iter().fold(0, |seed, col| {
+            let bytes = serde_json::to_vec(col).unwrap();
+            xxh3_64_with_seed(&bytes, seed)
+        })
     }
 }
 
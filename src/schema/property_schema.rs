@@ -10,6 +10,14 @@ pub struct PropertySchema {
     #[serde(default)]
     #[serde(rename = "target")]
     pub(crate) target_col: Option<String>,
+    #[serde(default)]
+    #[serde(rename = "previousName")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) previous_name: Option<String>,
+    #[serde(default)]
+    #[serde(rename = "enumValues")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) enum_values: Option<Vec<i64>>,
 }
 
 impl PropertySchema {
@@ -22,9 +30,36 @@ impl PropertySchema {
             name,
             data_type,
             target_col,
+            previous_name: None,
+            enum_values: None,
         }
     }
 
+    pub fn with_previous_name(mut self, previous_name: &str) -> Self {
+        self.previous_name = Some(previous_name.to_string());
+        self
+    }
+
+    /// Only the values (or null) may be stored. Supported for Byte, Int, Long and their lists.
+    pub fn with_enum_values(mut self, enum_values: Vec<i64>) -> Self {
+        self.enum_values = Some(enum_values);
+        self
+    }
+
+    /// `None` for properties that were removed but still occupy their space in the objects.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn data_type(&self) -> DataType {
+        self.data_type
+    }
+
+    /// The embedded collection of object properties.
+    pub fn target_collection(&self) -> Option<&str> {
+        self.target_col.as_deref()
+    }
+
     pub(crate) fn as_property(&self, offset: usize) -> Option<Property> {
         if let Some(name) = &self.name {
             let p = Property::new(name, self.data_type, offset, self.target_col.as_deref());
@@ -1,18 +1,32 @@
 use super::collection_schema::CollectionSchema;
+use super::db_info::DbInfo;
 use super::index_schema::IndexSchema;
 use super::link_schema::LinkSchema;
+use super::migration_callback::{get_migration_callback, MigrationCallback};
+use super::migration_plan::{CollectionMigrationPlan, MigrationPlan};
+use super::unique_constraint_schema::UniqueConstraintSchema;
 use super::Schema;
+use crate::blob::IsarBlobs;
 use crate::collection::IsarCollection;
 use crate::cursor::IsarCursors;
-use crate::error::{IsarError, Result};
+use crate::error::{illegal_arg, IsarError, Result};
 use crate::index::index_key::IndexKey;
+use crate::index::unique_constraint::UniqueConstraint;
 use crate::index::IsarIndex;
+use crate::instance::IndexFillOptions;
+use crate::isar_log;
 use crate::link::IsarLink;
-use crate::mdbx::cursor::{Cursor, UnboundCursor};
-use crate::mdbx::{db::Db, txn::Txn};
+use crate::logging::LogEvent;
+use crate::object::buffer_pool::{get_buffer_pool, BufferPool};
+use crate::object::data_type::DataType;
+use crate::object::id::BytesToId;
+use crate::object::isar_object::IsarObject;
+use crate::object::object_builder::ObjectBuilder;
 use crate::object::property::Property;
 use crate::schema::migrate_v1::migrate_v1;
+use crate::storage::{Cursor, Db, LazyDb, StorageCursor, StorageDb, Txn, UnboundCursor};
 use intmap::IntMap;
+use itertools::Itertools;
 use once_cell::sync::Lazy;
 use std::ops::Deref;
 use xxhash_rust::xxh3::xxh3_64;
@@ -23,6 +37,8 @@ static OLD_INFO_VERSION_KEY: Lazy<IndexKey> = Lazy::new(|| {
     key
 });
 
+static INFO_META_KEY: Lazy<IndexKey> = Lazy::new(|| IndexKey::from_bytes(b"_meta".to_vec()));
+
 static OLD_INFO_SCHEMA_KEY: Lazy<IndexKey> = Lazy::new(|| {
     let mut key = IndexKey::new();
     key.add_string(Some("schema"), true);
@@ -32,7 +48,14 @@ static OLD_INFO_SCHEMA_KEY: Lazy<IndexKey> = Lazy::new(|| {
 pub(crate) struct SchemaManager {
     instance_id: u64,
     info_db: Db,
+    versions_db: Option<Db>,
+    tombstones_db: Option<Db>,
+    blobs_db: Option<Db>,
     pub schemas: Vec<CollectionSchema>,
+    /// Schemas of the opened collections after migration.
+    pub opened: Vec<CollectionSchema>,
+    pub db_info: Option<DbInfo>,
+    skip_migration: bool,
 }
 
 impl SchemaManager {
@@ -42,17 +65,62 @@ impl SchemaManager {
         let info_db = Db::open(txn, Some("_info"), false, false, false)?;
         let mut info_cursor = UnboundCursor::new().bind(txn, info_db)?;
 
-        Self::migrate_old_info(&mut info_cursor)?;
+        if txn.write {
+            Self::migrate_old_info(&mut info_cursor)?;
+        }
+
+        let versions_db = Db::open(txn, Some("_versions"), false, false, false);
+        let versions_db = if txn.write {
+            Some(versions_db?)
+        } else {
+            versions_db.ok()
+        };
+
+        let tombstones_db = Db::open(txn, Some("_tombstones"), false, false, false);
+        let tombstones_db = if txn.write {
+            Some(tombstones_db?)
+        } else {
+            tombstones_db.ok()
+        };
+
+        let blobs_db = Db::open(txn, Some("_blobs"), false, false, false);
+        let blobs_db = if txn.write {
+            Some(blobs_db?)
+        } else {
+            blobs_db.ok()
+        };
+
+        let db_info = Self::get_db_info(&mut info_cursor)?;
+        if let Some(db_info) = &db_info {
+            if db_info.is_newer(Self::ISAR_VERSION) {
+                return Err(IsarError::VersionError {});
+            }
+        }
 
         let schemas = Self::get_schemas(&mut info_cursor)?;
         let manager = SchemaManager {
             instance_id,
             info_db,
+            versions_db,
+            tombstones_db,
+            blobs_db,
             schemas,
+            opened: vec![],
+            db_info,
+            skip_migration: false,
         };
         Ok(manager)
     }
 
+    /// Skips the migration of collections if the schema did not change since the database was
+    /// last opened.
+    pub fn set_schema_hash(&mut self, schema_hash: u64) {
+        self.skip_migration = self
+            .db_info
+            .as_ref()
+            .is_some_and(|info| info.schema_hash == schema_hash);
+    }
+
     fn migrate_old_info(info_cursor: &mut Cursor) -> Result<()> {
         let version = info_cursor.move_to(OLD_INFO_VERSION_KEY.deref())?;
         if let Some((_, version)) = version {
@@ -76,9 +144,32 @@ impl SchemaManager {
         Ok(())
     }
 
+    fn get_db_info(info_cursor: &mut Cursor) -> Result<Option<DbInfo>> {
+        if let Some((_, bytes)) = info_cursor.move_to(INFO_META_KEY.deref())? {
+            let db_info = serde_json::from_slice(bytes).map_err(|_| IsarError::DbCorrupted {
+                message: "Could not deserialize database info.".to_string(),
+            })?;
+            Ok(Some(db_info))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub fn save_db_info(&mut self, txn: &Txn, schema_hash: u64) -> Result<()> {
+        let db_info = DbInfo::new(Self::ISAR_VERSION, schema_hash);
+        let bytes = serde_json::to_vec(&db_info).unwrap();
+        let mut info_cursor = UnboundCursor::new().bind(txn, self.info_db)?;
+        info_cursor.put(INFO_META_KEY.deref(), &bytes)?;
+        Ok(())
+    }
+
     fn get_schemas(info_cursor: &mut Cursor) -> Result<Vec<CollectionSchema>> {
         let mut schemas = vec![];
-        info_cursor.iter_all(false, true, |_, _, bytes| {
+        info_cursor.iter_all(false, true, |_, key, bytes| {
+            // collection names never begin with an underscore
+            if key.starts_with(b"_") {
+                return Ok(true);
+            }
             let col = serde_json::from_slice::<CollectionSchema>(bytes).map_err(|_| {
                 IsarError::DbCorrupted {
                     message: "Could not deserialize existing schema.".to_string(),
@@ -97,6 +188,35 @@ impl SchemaManager {
         Ok(())
     }
 
+    pub(crate) fn auto_increment_key(col_name: &str) -> IndexKey {
+        IndexKey::from_bytes(format!("_ai_{}", col_name).into_bytes())
+    }
+
+    fn delete_auto_increment(info_cursor: &mut Cursor, col_name: &str) -> Result<()> {
+        if info_cursor
+            .move_to(&Self::auto_increment_key(col_name))?
+            .is_some()
+        {
+            info_cursor.delete_current()?;
+        }
+        Ok(())
+    }
+
+    fn rename_auto_increment(
+        info_cursor: &mut Cursor,
+        old_name: &str,
+        new_name: &str,
+    ) -> Result<()> {
+        let value = info_cursor
+            .move_to(&Self::auto_increment_key(old_name))?
+            .map(|(_, bytes)| bytes.to_vec());
+        if let Some(value) = value {
+            info_cursor.delete_current()?;
+            info_cursor.put(&Self::auto_increment_key(new_name), &value)?;
+        }
+        Ok(())
+    }
+
     fn delete_schema(info_cursor: &mut Cursor, schema: &CollectionSchema) -> Result<()> {
         let key = IndexKey::from_bytes(schema.name.as_bytes().to_vec());
         if info_cursor.move_to(&key)?.is_some() {
@@ -109,17 +229,50 @@ impl SchemaManager {
         Db::open(txn, Some(&col.name), true, false, false)
     }
 
-    pub fn open_index_db(txn: &Txn, col: &CollectionSchema, index: &IndexSchema) -> Result<Db> {
+    fn lazy_index_db(col: &CollectionSchema, index: &IndexSchema) -> LazyDb {
         let db_name = format!("_i_{}_{}", col.name, index.name);
-        Db::open(txn, Some(&db_name), false, !index.unique, false)
+        LazyDb::new(&db_name, false, !index.unique, false)
     }
 
-    pub fn open_link_dbs(txn: &Txn, col: &CollectionSchema, link: &LinkSchema) -> Result<(Db, Db)> {
+    pub fn open_index_db(txn: &Txn, col: &CollectionSchema, index: &IndexSchema) -> Result<Db> {
+        Self::lazy_index_db(col, index).open(txn)
+    }
+
+    fn lazy_link_dbs(col: &CollectionSchema, link: &LinkSchema) -> (LazyDb, LazyDb) {
         let link_db_name = format!("_l_{}_{}", col.name, link.name);
-        let db = Db::open(txn, Some(&link_db_name), true, true, true)?;
         let backlink_db_name = format!("_b_{}_{}", col.name, link.name);
-        let bl_db = Db::open(txn, Some(&backlink_db_name), true, true, true)?;
-        Ok((db, bl_db))
+        (
+            LazyDb::new(&link_db_name, true, true, true),
+            LazyDb::new(&backlink_db_name, true, true, true),
+        )
+    }
+
+    pub fn open_link_dbs(txn: &Txn, col: &CollectionSchema, link: &LinkSchema) -> Result<(Db, Db)> {
+        let (db, bl_db) = Self::lazy_link_dbs(col, link);
+        Ok((db.open(txn)?, bl_db.open(txn)?))
+    }
+
+    pub fn open_order_db(
+        txn: &Txn,
+        col: &CollectionSchema,
+        link: &LinkSchema,
+    ) -> Result<Option<Db>> {
+        if link.ordered {
+            let order_db_name = format!("_o_{}_{}", col.name, link.name);
+            let db = Db::open(txn, Some(&order_db_name), true, true, false)?;
+            Ok(Some(db))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub fn open_unique_constraint_db(
+        txn: &Txn,
+        col: &CollectionSchema,
+        constraint: &UniqueConstraintSchema,
+    ) -> Result<Db> {
+        let db_name = format!("_u_{}_{}", col.name, constraint.name);
+        Db::open(txn, Some(&db_name), true, true, true)
     }
 
     fn delete_collection(txn: &Txn, col: &CollectionSchema) -> Result<()> {
@@ -131,6 +284,149 @@ impl SchemaManager {
         for link in &col.links {
             Self::delete_link(txn, col, link)?;
         }
+        for constraint in &col.unique_constraints {
+            Self::delete_unique_constraint(txn, col, constraint)?;
+        }
+        Ok(())
+    }
+
+    fn move_db(txn: &Txn, from: Db, to: Db) -> Result<()> {
+        let mut from_cursor = UnboundCursor::new().bind(txn, from)?;
+        let mut to_cursor = UnboundCursor::new().bind(txn, to)?;
+        from_cursor.iter_all(false, true, |_, key, val| {
+            to_cursor.put(&IndexKey::from_bytes(key.to_vec()), val)?;
+            Ok(true)
+        })?;
+        drop(from_cursor);
+        from.drop(txn)
+    }
+
+    fn rename_collection(txn: &Txn, col: &CollectionSchema, new_name: &str) -> Result<()> {
+        if col.embedded {
+            return Ok(());
+        }
+        let mut renamed_col = col.clone();
+        renamed_col.name = new_name.to_string();
+
+        let db = Self::open_collection_db(txn, col)?;
+        let new_db = Self::open_collection_db(txn, &renamed_col)?;
+        Self::move_db(txn, db, new_db)?;
+        for index in &col.indexes {
+            let db = Self::open_index_db(txn, col, index)?;
+            let new_db = Self::open_index_db(txn, &renamed_col, index)?;
+            Self::move_db(txn, db, new_db)?;
+        }
+        for link in &col.links {
+            let (db, bl_db) = Self::open_link_dbs(txn, col, link)?;
+            let (new_db, new_bl_db) = Self::open_link_dbs(txn, &renamed_col, link)?;
+            Self::move_db(txn, db, new_db)?;
+            Self::move_db(txn, bl_db, new_bl_db)?;
+            if let Some(order_db) = Self::open_order_db(txn, col, link)? {
+                let new_order_db = Self::open_order_db(txn, &renamed_col, link)?.unwrap();
+                Self::move_db(txn, order_db, new_order_db)?;
+            }
+        }
+        for constraint in &col.unique_constraints {
+            let db = Self::open_unique_constraint_db(txn, col, constraint)?;
+            let new_db = Self::open_unique_constraint_db(txn, &renamed_col, constraint)?;
+            Self::move_db(txn, db, new_db)?;
+        }
+        Ok(())
+    }
+
+    /// Moves the entries of a renamed collection in the dbs that all collections share.
+    fn rename_shared_entries(&self, txn: &Txn, old_name: &str, new_name: &str) -> Result<()> {
+        let cursors = IsarCursors::new(txn, vec![]);
+        let col_id = xxh3_64(old_name.as_bytes());
+        let new_col_id = xxh3_64(new_name.as_bytes());
+        if let Some(versions_db) = self.versions_db {
+            IsarCollection::move_id_keys(&cursors, versions_db, col_id, new_col_id)?;
+        }
+        if let Some(tombstones_db) = self.tombstones_db {
+            IsarCollection::move_id_keys(&cursors, tombstones_db, col_id, new_col_id)?;
+        }
+        if let Some(blobs_db) = self.blobs_db {
+            IsarBlobs::new(blobs_db, col_id, &[]).move_to_collection(&cursors, new_col_id)?;
+        }
+        Ok(())
+    }
+
+    /// Renames the collections that were renamed via `previousName` before any collection is
+    /// migrated. Otherwise links to them would look like removed links.
+    pub fn rename_collections(&mut self, txn: &Txn, schema: &Schema) -> Result<()> {
+        for col in &schema.collections {
+            if let Some(previous_name) = &col.previous_name {
+                let stored =
+                    |name: &str| self.schemas.iter().any(|s| s.name == name && !s.embedded);
+                if !col.embedded && !stored(&col.name) && stored(previous_name) {
+                    isar_log!(LogEvent::Migration {
+                        collection: &col.name,
+                        step: "rename_collection",
+                    });
+                    self.rename_stored_collection(txn, previous_name, &col.name)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Opens the stored collections again without migrating them.
+    pub fn reopen_collections(
+        &mut self,
+        txn: &Txn,
+        schema: &Schema,
+    ) -> Result<Vec<IsarCollection>> {
+        self.skip_migration = true;
+        schema
+            .collections
+            .iter()
+            .map(|col| self.open_collection(txn, col.clone(), schema, None))
+            .collect()
+    }
+
+    /// Renames a stored collection and updates the links of all collections to it.
+    pub fn rename_stored_collection(
+        &mut self,
+        txn: &Txn,
+        old_name: &str,
+        new_name: &str,
+    ) -> Result<()> {
+        CollectionSchema::verify_name(new_name)?;
+        if self.schemas.iter().any(|s| s.name == new_name) {
+            return illegal_arg("A collection with the new name already exists.");
+        }
+        let position = self
+            .schemas
+            .iter()
+            .position(|s| s.name == old_name && !s.embedded);
+        let mut schema = if let Some(position) = position {
+            self.schemas.remove(position)
+        } else {
+            return illegal_arg("Collection does not exist.");
+        };
+
+        let cursors = IsarCursors::new(txn, vec![]);
+        let mut info_cursor = cursors.get_cursor(self.info_db)?;
+        Self::delete_schema(&mut info_cursor, &schema)?;
+        Self::rename_auto_increment(&mut info_cursor, old_name, new_name)?;
+        Self::rename_collection(txn, &schema, new_name)?;
+        self.rename_shared_entries(txn, old_name, new_name)?;
+        schema.name = new_name.to_string();
+        schema.previous_name = None;
+        self.schemas.push(schema);
+
+        for col in &mut self.schemas {
+            let mut changed = col.name == new_name;
+            for link in col.links.iter_mut().chain(&mut col.backlinks) {
+                if link.target_col == old_name {
+                    link.target_col = new_name.to_string();
+                    changed = true;
+                }
+            }
+            if changed {
+                Self::save_schema(&mut info_cursor, col)?;
+            }
+        }
         Ok(())
     }
 
@@ -142,15 +438,65 @@ impl SchemaManager {
     fn delete_link(txn: &Txn, col: &CollectionSchema, link: &LinkSchema) -> Result<()> {
         let (db, bl_db) = Self::open_link_dbs(txn, col, link)?;
         db.drop(txn)?;
+        if let Some(order_db) = Self::open_order_db(txn, col, link)? {
+            order_db.drop(txn)?;
+        }
         bl_db.drop(txn)
     }
 
+    /// Links that become ordered keep their targets in id order. Links that are no longer
+    /// ordered drop their positions.
+    fn migrate_link_order(
+        txn: &Txn,
+        col: &CollectionSchema,
+        existing_link: &LinkSchema,
+        link: &LinkSchema,
+    ) -> Result<()> {
+        if let Some(order_db) = Self::open_order_db(txn, col, existing_link)? {
+            order_db.drop(txn)?;
+        }
+        if let Some(order_db) = Self::open_order_db(txn, col, link)? {
+            let (db, _) = Self::open_link_dbs(txn, col, link)?;
+            let mut cursor = UnboundCursor::new().bind(txn, db)?;
+            let mut order_cursor = UnboundCursor::new().bind(txn, order_db)?;
+            let mut last_source_id = None;
+            let mut index = 0;
+            cursor.iter_all(false, true, |_, source_id, target_id| {
+                let source_id = source_id.to_id();
+                if last_source_id != Some(source_id) {
+                    last_source_id = Some(source_id);
+                    index = 0;
+                }
+                let position = IsarLink::initial_position(index);
+                let value = IsarLink::position_value(position, target_id.to_id());
+                order_cursor.put(&source_id, &value)?;
+                index += 1;
+                Ok(true)
+            })?;
+        }
+        Ok(())
+    }
+
+    fn delete_unique_constraint(
+        txn: &Txn,
+        col: &CollectionSchema,
+        constraint: &UniqueConstraintSchema,
+    ) -> Result<()> {
+        let db = Self::open_unique_constraint_db(txn, col, constraint)?;
+        db.drop(txn)
+    }
+
     fn perform_migration(
         txn: &Txn,
         schema: &mut CollectionSchema,
         existing_schema: &CollectionSchema,
+        tombstones_db: Option<Db>,
+        callback: Option<&MigrationCallback>,
+        buffer_pool: &BufferPool,
     ) -> Result<Vec<u64>> {
+        let widened_properties = schema.get_widened_properties(existing_schema);
         let removed_properties = schema.merge_properties(existing_schema)?;
+        let callback = callback.filter(|_| schema.properties != existing_schema.properties);
 
         let mut added_indexes = IntMap::new();
         for index in &schema.indexes {
@@ -168,10 +514,15 @@ impl SchemaManager {
                 .any(|p| removed_properties.contains(&p.name));
 
             if removed_index || changed_property {
+                isar_log!(LogEvent::Migration {
+                    collection: &schema.name,
+                    step: "delete_index",
+                });
                 Self::delete_index(txn, existing_schema, existing_index)?;
             }
 
-            if changed_property {
+            // indexes that are kept have to be filled again
+            if changed_property && !removed_index {
                 let index_id = xxh3_64(existing_index.name.as_bytes());
                 added_indexes.insert(index_id, ());
             }
@@ -179,18 +530,226 @@ impl SchemaManager {
 
         for link in &existing_schema.links {
             if !schema.links.contains(link) {
+                isar_log!(LogEvent::Migration {
+                    collection: &schema.name,
+                    step: "delete_link",
+                });
                 Self::delete_link(txn, existing_schema, link)?;
             }
         }
 
+        for link in &schema.links {
+            let existing_link = existing_schema.links.iter().find(|l| *l == link);
+            if let Some(existing_link) = existing_link {
+                if existing_link.ordered != link.ordered {
+                    isar_log!(LogEvent::Migration {
+                        collection: &schema.name,
+                        step: "migrate_link_order",
+                    });
+                    Self::migrate_link_order(txn, schema, existing_link, link)?;
+                }
+            }
+        }
+
+        for constraint in &existing_schema.unique_constraints {
+            if !schema.unique_constraints.contains(constraint) {
+                isar_log!(LogEvent::Migration {
+                    collection: &schema.name,
+                    step: "delete_unique_constraint",
+                });
+                Self::delete_unique_constraint(txn, existing_schema, constraint)?;
+            }
+        }
+
+        if let Some(callback) = callback {
+            isar_log!(LogEvent::Migration {
+                collection: &schema.name,
+                step: "migration_callback",
+            });
+            Self::rewrite_objects(
+                txn,
+                schema,
+                existing_schema,
+                tombstones_db,
+                &widened_properties,
+                Some(callback),
+                buffer_pool,
+            )?;
+
+            // the callback may have changed indexed values
+            for index in &schema.indexes {
+                Self::delete_index(txn, schema, index)?;
+                let index_id = xxh3_64(index.name.as_bytes());
+                added_indexes.insert(index_id, ());
+            }
+        } else if !widened_properties.is_empty() {
+            isar_log!(LogEvent::Migration {
+                collection: &schema.name,
+                step: "widen_properties",
+            });
+            Self::rewrite_objects(
+                txn,
+                schema,
+                existing_schema,
+                tombstones_db,
+                &widened_properties,
+                None,
+                buffer_pool,
+            )?;
+        }
+
         Ok(added_indexes.keys().copied().collect())
     }
 
+    fn rewrite_objects(
+        txn: &Txn,
+        schema: &CollectionSchema,
+        existing_schema: &CollectionSchema,
+        tombstones_db: Option<Db>,
+        widened_properties: &[String],
+        callback: Option<&MigrationCallback>,
+        buffer_pool: &BufferPool,
+    ) -> Result<()> {
+        let properties = schema.get_properties();
+        let existing_properties = existing_schema.get_properties();
+        let widened = widened_properties
+            .iter()
+            .map(|name| {
+                let old = existing_properties.iter().find(|p| &p.name == name);
+                let new = properties.iter().find(|p| &p.name == name);
+                (old.unwrap(), new.unwrap())
+            })
+            .collect_vec();
+
+        let mut buffer = Some(buffer_pool.get(0));
+        let mut compact_buffer = None;
+        let mut rewrite = |object: IsarObject, put: &mut dyn FnMut(&[u8]) -> Result<()>| {
+            let mut ob = ObjectBuilder::new(&properties, buffer.take());
+            for property in &properties {
+                if let Some((old, new)) = widened.iter().find(|(_, new)| *new == property) {
+                    Self::write_widened(&mut ob, object, old, new);
+                } else {
+                    ob.write_from(property, object);
+                }
+            }
+
+            if let Some(callback) = callback {
+                callback(object, &mut ob)?;
+
+                // overwritten dynamic properties leave unused bytes behind
+                let migrated = ob.finish();
+                let mut compacted = ObjectBuilder::new(&properties, compact_buffer.take());
+                for property in &properties {
+                    compacted.write_from(property, migrated);
+                }
+                put(compacted.finish().as_bytes())?;
+                compact_buffer = Some(compacted.recycle());
+            } else {
+                put(ob.finish().as_bytes())?;
+            }
+            buffer = Some(ob.recycle());
+            Ok(())
+        };
+
+        let db = Self::open_collection_db(txn, schema)?;
+        let mut cursor = UnboundCursor::new().bind(txn, db)?;
+        let mut write_cursor = UnboundCursor::new().bind(txn, db)?;
+        cursor.iter_all(false, true, |_, id, bytes| {
+            rewrite(IsarObject::from_bytes(bytes), &mut |bytes| {
+                write_cursor.put(&id.to_id(), bytes)
+            })?;
+            Ok(true)
+        })?;
+
+        // objects hidden by soft deletes
+        if let Some(tombstones_db) = tombstones_db {
+            let col_id = xxh3_64(schema.name.as_bytes());
+            let lower = IsarCollection::get_id_key(col_id, i64::MIN);
+            let upper = IsarCollection::get_id_key(col_id, i64::MAX);
+            let mut cursor = UnboundCursor::new().bind(txn, tombstones_db)?;
+            let mut write_cursor = UnboundCursor::new().bind(txn, tombstones_db)?;
+            cursor.iter_between(&lower, &upper, false, false, true, |_, key, tombstone| {
+                let object = IsarObject::from_bytes(&tombstone[8..]);
+                rewrite(object, &mut |bytes| {
+                    let mut rewritten = tombstone[..8].to_vec();
+                    rewritten.extend_from_slice(bytes);
+                    write_cursor.put(&IndexKey::from_bytes(key.to_vec()), &rewritten)
+                })?;
+                Ok(true)
+            })?;
+        }
+
+        for buffer in buffer.into_iter().chain(compact_buffer) {
+            buffer_pool.put(buffer);
+        }
+        Ok(())
+    }
+
+    fn write_widened(ob: &mut ObjectBuilder, object: IsarObject, old: &Property, new: &Property) {
+        match (old.data_type, new.data_type) {
+            (DataType::Byte, DataType::Int) => {
+                ob.write_int(new.offset, object.read_byte(old.offset) as i32)
+            }
+            (DataType::Byte, DataType::Long) => {
+                ob.write_long(new.offset, object.read_byte(old.offset) as i64)
+            }
+            (DataType::Int, DataType::Long) => {
+                let value = object.read_int(old.offset);
+                let value = if value == IsarObject::NULL_INT {
+                    IsarObject::NULL_LONG
+                } else {
+                    value as i64
+                };
+                ob.write_long(new.offset, value)
+            }
+            (DataType::Float, DataType::Double) => {
+                ob.write_double(new.offset, object.read_float(old.offset) as f64)
+            }
+            _ => ob.write_null(new.offset, new.data_type),
+        }
+    }
+
+    fn verify_read_only(
+        schema: &mut CollectionSchema,
+        existing_schema: &CollectionSchema,
+    ) -> Result<()> {
+        if existing_schema.version != Self::ISAR_VERSION {
+            return Err(IsarError::VersionError {});
+        }
+
+        let widened_properties = schema.get_widened_properties(existing_schema);
+        let removed_properties = schema.merge_properties(existing_schema)?;
+        let requires_migration = !widened_properties.is_empty()
+            || schema.indexes.iter().any(|index| {
+                !existing_schema.indexes.contains(index)
+                    || index
+                        .properties
+                        .iter()
+                        .any(|p| removed_properties.contains(&p.name))
+            })
+            || schema
+                .unique_constraints
+                .iter()
+                .any(|c| !existing_schema.unique_constraints.contains(c))
+            || schema.links.iter().any(|link| {
+                existing_schema
+                    .links
+                    .iter()
+                    .any(|l| l == link && l.ordered != link.ordered)
+            });
+        if requires_migration {
+            Err(IsarError::ReadOnly {})
+        } else {
+            Ok(())
+        }
+    }
+
     pub fn open_collection(
         &mut self,
         txn: &Txn,
         mut schema: CollectionSchema,
         schemas: &Schema,
+        index_fill: Option<&IndexFillOptions>,
     ) -> Result<IsarCollection> {
         let cursors = IsarCursors::new(txn, vec![]);
 
@@ -201,18 +760,46 @@ impl SchemaManager {
             .map(|index| self.schemas.remove(index));
 
         let added_indexes = if let Some(existing_schema) = &mut existing_schema {
-            if existing_schema.version == 1 {
-                migrate_v1(txn, existing_schema)?
-            } else if existing_schema.version != Self::ISAR_VERSION {
-                return Err(IsarError::VersionError {});
+            if !txn.write {
+                Self::verify_read_only(&mut schema, existing_schema)?;
+                vec![]
+            } else if self.skip_migration && existing_schema.version == Self::ISAR_VERSION {
+                // the existing schema already contains the result of the last migration
+                schema = existing_schema.clone();
+                vec![]
+            } else {
+                if existing_schema.version == 1 {
+                    isar_log!(LogEvent::Migration {
+                        collection: &schema.name,
+                        step: "migrate_v1",
+                    });
+                    migrate_v1(txn, existing_schema)?
+                } else if existing_schema.version != Self::ISAR_VERSION {
+                    return Err(IsarError::VersionError {});
+                }
+                let callback = get_migration_callback(self.instance_id, &schema.name);
+                let buffer_pool = get_buffer_pool(self.instance_id);
+                Self::perform_migration(
+                    txn,
+                    &mut schema,
+                    existing_schema,
+                    self.tombstones_db,
+                    callback.as_deref(),
+                    &buffer_pool,
+                )?
             }
-            Self::perform_migration(txn, &mut schema, existing_schema)?
+        } else if !txn.write {
+            return Err(IsarError::ReadOnly {});
         } else {
             vec![]
         };
-        let mut info_cursor = cursors.get_cursor(self.info_db)?;
+        let added_indexes =
+            Self::update_pending_indexes(&mut schema, existing_schema.as_ref(), added_indexes);
         schema.version = Self::ISAR_VERSION;
-        Self::save_schema(&mut info_cursor, &schema)?;
+        if txn.write {
+            let mut info_cursor = cursors.get_cursor(self.info_db)?;
+            Self::save_schema(&mut info_cursor, &schema)?;
+        }
         let schema = schema; // no longer mutable beyond this point
 
         let db = Self::open_collection_db(txn, &schema)?;
@@ -221,11 +808,67 @@ impl SchemaManager {
         let mut embedded_properties = IntMap::new();
         Self::get_embedded_properties(schemas, &properties, &mut embedded_properties);
 
-        let indexes = Self::open_indexes(txn, &schema, &properties)?;
-        let links = Self::open_links(txn, db, &schema, schemas)?;
+        let derived = schema
+            .derived
+            .iter()
+            .map(|d| {
+                let find = |name: &str| properties.iter().find(|p| p.name == name).unwrap().clone();
+                (find(&d.source), find(&d.name), d.transform)
+            })
+            .collect();
+        let enum_values = schema
+            .properties
+            .iter()
+            .filter_map(|p| {
+                let values = p.enum_values.clone()?;
+                let property = properties
+                    .iter()
+                    .find(|prop| Some(&prop.name) == p.name.as_ref())?;
+                Some((property.clone(), values))
+            })
+            .collect();
+        let indexes = Self::open_indexes(
+            txn,
+            &schema,
+            existing_schema.as_ref(),
+            &added_indexes,
+            &properties,
+        )?;
+        let unique_constraints = schema
+            .unique_constraints
+            .iter()
+            .map(|c| {
+                let db = Self::open_unique_constraint_db(txn, &schema, c)?;
+                let constraint_properties = c
+                    .properties
+                    .iter()
+                    .map(|name| properties.iter().find(|p| &p.name == name).unwrap().clone())
+                    .collect();
+                Ok(UniqueConstraint::new(
+                    &c.name,
+                    constraint_properties,
+                    c.case_sensitive,
+                    db,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        // objects may have changed if the properties changed
+        let filled_constraints = schema
+            .unique_constraints
+            .iter()
+            .filter(|c| {
+                existing_schema.as_ref().is_some_and(|s| {
+                    !s.unique_constraints.contains(c) || s.properties != schema.properties
+                })
+            })
+            .map(|c| c.name.clone())
+            .collect_vec();
+        let links = self.open_links(txn, db, &schema, existing_schema.as_ref(), schemas)?;
         let backlinks = Self::open_backlinks(txn, db, &schema, schemas)?;
         let col = IsarCollection::new(
             db,
+            self.versions_db,
+            self.tombstones_db.filter(|_| schema.soft_delete),
             self.instance_id,
             &schema.name,
             properties,
@@ -233,13 +876,28 @@ impl SchemaManager {
             indexes,
             links,
             backlinks,
+            derived,
+            enum_values,
+            unique_constraints,
+            if schema.never_reuse_ids {
+                Some(self.info_db)
+            } else {
+                None
+            },
+            schema.id_strategy,
+            self.blobs_db
+                .map(|db| IsarBlobs::new(db, xxh3_64(schema.name.as_bytes()), &schema.blobs())),
         );
 
         col.init_auto_increment(&cursors)?;
+        if !filled_constraints.is_empty() {
+            col.fill_unique_constraints(&filled_constraints, &cursors)?;
+        }
         if !added_indexes.is_empty() {
-            col.fill_indexes(&added_indexes, &cursors)?;
+            col.fill_indexes(&added_indexes, &cursors, index_fill)?;
         }
 
+        self.opened.push(schema);
         Ok(col)
     }
 
@@ -264,33 +922,92 @@ impl SchemaManager {
         }
     }
 
+    /// Marks added lazy indexes as pending and returns the indexes that have to be filled now.
+    fn update_pending_indexes(
+        schema: &mut CollectionSchema,
+        existing_schema: Option<&CollectionSchema>,
+        added_indexes: Vec<u64>,
+    ) -> Vec<u64> {
+        let (lazy_indexes, added_indexes): (Vec<u64>, Vec<u64>) =
+            added_indexes.into_iter().partition(|id| {
+                schema
+                    .indexes
+                    .iter()
+                    .any(|i| i.lazy && xxh3_64(i.name.as_bytes()) == *id)
+            });
+
+        let mut pending_indexes = existing_schema
+            .map(|s| s.pending_indexes.clone())
+            .unwrap_or_default();
+        pending_indexes.retain(|name| {
+            let id = xxh3_64(name.as_bytes());
+            schema.indexes.iter().any(|i| &i.name == name) && !added_indexes.contains(&id)
+        });
+        for index in &schema.indexes {
+            let id = xxh3_64(index.name.as_bytes());
+            if lazy_indexes.contains(&id) && !pending_indexes.contains(&index.name) {
+                pending_indexes.push(index.name.clone());
+            }
+        }
+        schema.pending_indexes = pending_indexes;
+
+        added_indexes
+    }
+
+    pub fn complete_pending_indexes(&mut self, txn: &Txn, col_name: &str) -> Result<()> {
+        if let Some(schema) = self.schemas.iter_mut().find(|s| s.name == col_name) {
+            schema.pending_indexes.clear();
+            let mut info_cursor = UnboundCursor::new().bind(txn, self.info_db)?;
+            Self::save_schema(&mut info_cursor, schema)?;
+        }
+        Ok(())
+    }
+
+    /// Dbs of existing indexes are opened on first use. New index dbs are created right away so
+    /// they exist once the transaction is committed.
     fn open_indexes(
         txn: &Txn,
         schema: &CollectionSchema,
+        existing_schema: Option<&CollectionSchema>,
+        added_indexes: &[u64],
         properties: &[Property],
     ) -> Result<Vec<IsarIndex>> {
         let mut indexes = vec![];
         for index_schema in &schema.indexes {
-            let db = Self::open_index_db(txn, schema, index_schema)?;
-            let index = index_schema.as_index(db, &properties);
+            let db = Self::lazy_index_db(schema, index_schema);
+            let pending = schema.pending_indexes.contains(&index_schema.name);
+            let added = added_indexes.contains(&xxh3_64(index_schema.name.as_bytes()));
+            let exists = existing_schema.is_some_and(|s| s.indexes.contains(index_schema));
+            if !exists || added || pending {
+                db.open(txn)?;
+            }
+            let index = index_schema.as_index(db, properties, pending);
             indexes.push(index);
         }
         Ok(indexes)
     }
 
     fn open_links(
+        &self,
         txn: &Txn,
         db: Db,
         schema: &CollectionSchema,
+        existing_schema: Option<&CollectionSchema>,
         schemas: &Schema,
     ) -> Result<Vec<IsarLink>> {
         let mut links = vec![];
         for link_schema in &schema.links {
-            let (link_db, backlink_db) = Self::open_link_dbs(txn, schema, link_schema)?;
+            let (link_db, backlink_db) = Self::lazy_link_dbs(schema, link_schema);
+            let exists = existing_schema.is_some_and(|s| s.links.contains(link_schema));
+            if !exists {
+                link_db.open(txn)?;
+                backlink_db.open(txn)?;
+            }
             let target_col_schema = schemas
                 .get_collection(&link_schema.target_col, false)
                 .unwrap();
             let target_db = Self::open_collection_db(txn, target_col_schema)?;
+            let order_db = Self::open_order_db(txn, schema, link_schema)?;
             let link = IsarLink::new(
                 &schema.name,
                 &link_schema.name,
@@ -299,12 +1016,16 @@ impl SchemaManager {
                 backlink_db,
                 db,
                 target_db,
-            );
+                order_db,
+            )
+            .with_tombstones(&schema.name, &target_col_schema.name, self.tombstones_db);
             links.push(link);
         }
         Ok(links)
     }
 
+    /// Declared backlinks are opened directly. Otherwise the links of all collections are
+    /// scanned for links to `schema`.
     fn open_backlinks(
         txn: &Txn,
         db: Db,
@@ -312,20 +1033,40 @@ impl SchemaManager {
         schemas: &Schema,
     ) -> Result<Vec<IsarLink>> {
         let mut backlinks = vec![];
+        if !schema.backlinks.is_empty() {
+            for backlink_schema in &schema.backlinks {
+                let source_col_schema = schemas
+                    .get_collection(&backlink_schema.target_col, false)
+                    .unwrap();
+                let link_schema = source_col_schema
+                    .links
+                    .iter()
+                    .find(|l| backlink_schema.source_link.as_ref() == Some(&l.name))
+                    .unwrap();
+                let backlink = Self::open_backlink(
+                    txn,
+                    db,
+                    &schema.name,
+                    &backlink_schema.name,
+                    source_col_schema,
+                    link_schema,
+                )?;
+                backlinks.push(backlink);
+            }
+            return Ok(backlinks);
+        }
+
         for other_col_schema in &schemas.collections {
             for link_schema in &other_col_schema.links {
                 if link_schema.target_col == schema.name {
-                    let other_col_db = Self::open_collection_db(txn, other_col_schema)?;
-                    let (link_db, bl_db) = Self::open_link_dbs(txn, other_col_schema, link_schema)?;
-                    let backlink = IsarLink::new(
+                    let backlink = Self::open_backlink(
+                        txn,
+                        db,
                         &other_col_schema.name,
                         &link_schema.name,
-                        true,
-                        bl_db,
-                        link_db,
-                        db,
-                        other_col_db,
-                    );
+                        other_col_schema,
+                        link_schema,
+                    )?;
                     backlinks.push(backlink);
                 }
             }
@@ -333,12 +1074,570 @@ impl SchemaManager {
         Ok(backlinks)
     }
 
+    /// The id of the backlink is derived from `collection` and `name`.
+    fn open_backlink(
+        txn: &Txn,
+        db: Db,
+        collection: &str,
+        name: &str,
+        other_col_schema: &CollectionSchema,
+        link_schema: &LinkSchema,
+    ) -> Result<IsarLink> {
+        let other_col_db = Self::open_collection_db(txn, other_col_schema)?;
+        // new link dbs are created by the link of the other collection
+        let (link_db, bl_db) = Self::lazy_link_dbs(other_col_schema, link_schema);
+        let order_db = Self::open_order_db(txn, other_col_schema, link_schema)?;
+        Ok(IsarLink::new(
+            collection,
+            name,
+            true,
+            bl_db,
+            link_db,
+            db,
+            other_col_db,
+            order_db,
+        ))
+    }
+
+    /// Computes what opening `schema` would change without writing anything.
+    pub fn plan_migration(&self, txn: &Txn, schema: &Schema) -> Result<MigrationPlan> {
+        let mut plan = MigrationPlan::default();
+        let mut matched = vec![];
+
+        for col in &schema.collections {
+            let existing = self
+                .schemas
+                .iter()
+                .find(|s| s.name == col.name && s.embedded == col.embedded)
+                .or_else(|| {
+                    let previous_name = col.previous_name.as_ref()?;
+                    self.schemas
+                        .iter()
+                        .find(|s| &s.name == previous_name && s.embedded == col.embedded)
+                });
+
+            let col_plan = if let Some(existing) = existing {
+                matched.push(existing.name.clone());
+                Self::plan_collection_migration(txn, col, existing)?
+            } else {
+                CollectionMigrationPlan::new_collection(col)
+            };
+            plan.collections.push(col_plan);
+        }
+
+        plan.dropped_collections = self
+            .schemas
+            .iter()
+            .filter(|s| !matched.contains(&s.name))
+            .map(|s| s.name.clone())
+            .collect();
+
+        Ok(plan)
+    }
+
+    fn plan_collection_migration(
+        txn: &Txn,
+        schema: &CollectionSchema,
+        existing_schema: &CollectionSchema,
+    ) -> Result<CollectionMigrationPlan> {
+        let mut plan = CollectionMigrationPlan {
+            name: schema.name.clone(),
+            ..Default::default()
+        };
+        if existing_schema.name != schema.name {
+            plan.renamed_from = Some(existing_schema.name.clone());
+        }
+
+        let has_property =
+            |name: &Option<String>| existing_schema.properties.iter().any(|p| p.name == *name);
+        plan.widened_properties = schema.get_widened_properties(existing_schema);
+        for property in &schema.properties {
+            let name = property.name.clone().unwrap();
+            if existing_schema.properties.contains(property)
+                || plan.widened_properties.contains(&name)
+            {
+                continue;
+            }
+            let renamed_from = property
+                .previous_name
+                .as_ref()
+                .filter(|_| !has_property(&property.name))
+                .filter(|previous_name| has_property(&Some(previous_name.to_string())));
+            if let Some(renamed_from) = renamed_from {
+                plan.renamed_properties.push((renamed_from.clone(), name));
+            } else {
+                plan.added_properties.push(name);
+            }
+        }
+        for property in &existing_schema.properties {
+            if let Some(name) = &property.name {
+                let kept = schema.properties.contains(property)
+                    || plan.widened_properties.contains(name)
+                    || plan.renamed_properties.iter().any(|(from, _)| from == name);
+                if !kept {
+                    plan.removed_properties.push(name.clone());
+                }
+            }
+        }
+
+        let mut merged_schema = schema.clone();
+        let removed_properties = merged_schema.merge_properties(existing_schema)?;
+        for existing_index in &existing_schema.indexes {
+            let changed_property = existing_index
+                .properties
+                .iter()
+                .any(|p| removed_properties.contains(&p.name));
+            if !schema.indexes.contains(existing_index) || changed_property {
+                plan.dropped_indexes.push(existing_index.name.clone());
+            }
+        }
+        for index in &schema.indexes {
+            let changed_property = index
+                .properties
+                .iter()
+                .any(|p| removed_properties.contains(&p.name));
+            if !existing_schema.indexes.contains(index) || changed_property {
+                plan.added_indexes.push(index.name.clone());
+            }
+        }
+
+        for link in &schema.links {
+            if !existing_schema.links.contains(link) {
+                plan.added_links.push(link.name.clone());
+            }
+        }
+        for link in &existing_schema.links {
+            if !schema.links.contains(link) {
+                plan.dropped_links.push(link.name.clone());
+            }
+        }
+
+        if !existing_schema.embedded {
+            let db = Self::open_collection_db(txn, existing_schema)?;
+            plan.object_count = db.stat(txn)?.entries;
+        }
+        let rewrite = existing_schema.version != Self::ISAR_VERSION
+            || plan.renamed_from.is_some()
+            || !plan.widened_properties.is_empty();
+        if rewrite {
+            plan.objects_to_rewrite = plan.object_count;
+        }
+        plan.objects_to_index = plan.object_count * plan.added_indexes.len() as u64;
+
+        Ok(plan)
+    }
+
     pub fn delete_unopened_collections(&self, txn: &Txn) -> Result<()> {
         let mut info_cursor = UnboundCursor::new().bind(txn, self.info_db)?;
         for col in &self.schemas {
+            isar_log!(LogEvent::Migration {
+                collection: &col.name,
+                step: "delete_collection",
+            });
             Self::delete_collection(txn, col)?;
+            if let Some(versions_db) = self.versions_db {
+                let cursors = IsarCursors::new(txn, vec![]);
+                IsarCollection::delete_id_keys(
+                    &cursors,
+                    versions_db,
+                    xxh3_64(col.name.as_bytes()),
+                )?;
+            }
+            if let Some(tombstones_db) = self.tombstones_db {
+                let cursors = IsarCursors::new(txn, vec![]);
+                IsarCollection::delete_id_keys(
+                    &cursors,
+                    tombstones_db,
+                    xxh3_64(col.name.as_bytes()),
+                )?;
+            }
+            if let Some(blobs_db) = self.blobs_db {
+                let cursors = IsarCursors::new(txn, vec![]);
+                IsarBlobs::new(blobs_db, xxh3_64(col.name.as_bytes()), &[]).clear(&cursors)?;
+            }
             Self::delete_schema(&mut info_cursor, col)?;
+            Self::delete_auto_increment(&mut info_cursor, &col.name)?;
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::blob::IsarBlobs;
+    use crate::index::index_key::IndexValue;
+    use crate::instance::tests::{collection, find_ids, index, open_instance, put_object, reopen};
+    use crate::instance::IsarInstance;
+    use crate::object::object_builder::ObjectBuilder;
+    use crate::query::Sort;
+    use crate::schema::migration_callback::set_migration_callback;
+    use crate::schema::migration_plan::CollectionMigrationPlan;
+    use crate::schema::Schema;
+    use serde_json::{json, Value};
+    use xxhash_rust::xxh3::xxh3_64;
+
+    /// A source collection with a link to the target collection.
+    fn linked_collections(target: &str, previous_name: Option<&str>) -> Value {
+        let mut source = collection("source", json!([]));
+        source["links"] = json!([{"name": "link", "target": target}]);
+        let mut target_col = collection(target, json!([]));
+        if let Some(previous_name) = previous_name {
+            target_col["previousName"] = json!(previous_name);
+        }
+        json!([source, target_col])
+    }
+
+    #[test]
+    fn test_rename_collection_keeps_versions() {
+        let instance = open_instance(linked_collections("a", None));
+        let col = &instance.collections()[1];
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        let ob = ObjectBuilder::new(&col.properties, None);
+        assert!(col.put_if_newer(&mut txn, 1, ob.finish(), 5).unwrap());
+        txn.commit().unwrap();
+
+        let instance = reopen(instance, linked_collections("b", Some("a")));
+        let col = &instance.collections()[1];
+        let mut txn = instance.begin_txn(false, false).unwrap();
+        assert_eq!(col.get_version(&mut txn, 1).unwrap(), Some(5));
+        drop(txn);
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_rename_collection_keeps_tombstones() {
+        let col = |name: &str| {
+            let mut col = collection(name, json!([]));
+            col["softDelete"] = json!(true);
+            col
+        };
+        let instance = open_instance(json!([col("a")]));
+        let col_a = &instance.collections()[0];
+        put_object(&instance, col_a, Some(1), json!({}));
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        assert!(col_a.delete(&mut txn, 1).unwrap());
+        txn.commit().unwrap();
+
+        let mut renamed = col("b");
+        renamed["previousName"] = json!("a");
+        let instance = reopen(instance, json!([renamed]));
+        let mut txn = instance.begin_txn(false, false).unwrap();
+        let tombstones = instance.collections()[0].tombstones(&mut txn).unwrap();
+        assert_eq!(
+            tombstones.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            vec![1]
+        );
+        drop(txn);
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_rename_property_keeps_values_and_indexes() {
+        let col = |name: &str, previous_name: Option<&str>| {
+            let mut property = json!({"name": name, "type": "String"});
+            if let Some(previous_name) = previous_name {
+                property["previousName"] = json!(previous_name);
+            }
+            let mut col = collection("col", json!([property]));
+            col["indexes"] = json!([index(name, &[(name, true)], false)]);
+            col
+        };
+        let instance = open_instance(json!([col("a", None)]));
+        let a = put_object(
+            &instance,
+            &instance.collections()[0],
+            None,
+            json!({"a": "x"}),
+        );
+        let b = put_object(
+            &instance,
+            &instance.collections()[0],
+            None,
+            json!({"a": "y"}),
+        );
+
+        let instance = reopen(instance, json!([col("b", Some("a"))]));
+        let col = &instance.collections()[0];
+        let property = col.property("b").unwrap();
+        let mut txn = instance.begin_txn(false, false).unwrap();
+        let object = col.get(&mut txn, a).unwrap().unwrap();
+        assert_eq!(object.read_string(property.offset), Some("x"));
+        drop(txn);
+
+        let mut qb = col.new_query_builder();
+        let prefix = [IndexValue::String(Some("y"))];
+        qb.add_index_prefix_where_clause(
+            xxh3_64(b"b"),
+            &prefix,
+            None,
+            None,
+            Sort::Ascending,
+            false,
+        )
+        .unwrap();
+        assert_eq!(find_ids(&instance, &qb.build()), vec![b]);
+        let mut txn = instance.begin_txn(false, false).unwrap();
+        instance.verify(&mut txn).unwrap();
+        drop(txn);
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_widen_property_types() {
+        let col = |types: [&str; 3]| {
+            let properties = ["a", "b", "c"]
+                .iter()
+                .zip(types)
+                .map(|(name, data_type)| json!({"name": name, "type": data_type}))
+                .collect::<Vec<_>>();
+            json!([collection("col", json!(properties))])
+        };
+        let instance = open_instance(col(["Byte", "Int", "Float"]));
+        let values = json!({"a": 7, "b": -5, "c": 1.5});
+        put_object(&instance, &instance.collections()[0], Some(1), values);
+
+        let instance = reopen(instance, col(["Int", "Long", "Double"]));
+        let col = &instance.collections()[0];
+        let offset = |name| col.property(name).unwrap().offset;
+        let mut txn = instance.begin_txn(false, false).unwrap();
+        let object = col.get(&mut txn, 1).unwrap().unwrap();
+        assert_eq!(object.read_int(offset("a")), 7);
+        assert_eq!(object.read_long(offset("b")), -5);
+        assert_eq!(object.read_double(offset("c")), 1.5);
+        drop(txn);
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_plan_migration_without_writes() {
+        let mut old_col = collection(
+            "col",
+            json!([{"name": "a", "type": "String"}, {"name": "b", "type": "Int"}]),
+        );
+        old_col["indexes"] = json!([index("a", &[("a", true)], false)]);
+        let instance = open_instance(json!([old_col]));
+        let col = &instance.collections()[0];
+        for a in ["x", "y"] {
+            put_object(&instance, col, None, json!({ "a": a }));
+        }
+
+        let mut new_col = collection(
+            "col",
+            json!([{"name": "b", "type": "Long"}, {"name": "c", "type": "Bool"}]),
+        );
+        new_col["indexes"] = json!([index("c", &[("c", false)], false)]);
+        let other = collection("other", json!([]));
+        let json = serde_json::to_vec(&json!([new_col, other])).unwrap();
+        let schema = Schema::from_json(&json).unwrap();
+        let plan = IsarInstance::plan_migration(&instance.name, &instance.dir, &schema).unwrap();
+        assert_eq!(
+            plan.collections[0],
+            CollectionMigrationPlan {
+                name: "col".to_string(),
+                added_properties: vec!["c".to_string()],
+                removed_properties: vec!["a".to_string()],
+                widened_properties: vec!["b".to_string()],
+                added_indexes: vec!["c".to_string()],
+                dropped_indexes: vec!["a".to_string()],
+                object_count: 2,
+                objects_to_rewrite: 2,
+                objects_to_index: 2,
+                ..Default::default()
+            }
+        );
+        assert!(plan.collections[1].added);
+        assert!(plan.dropped_collections.is_empty());
+        assert_eq!(plan.estimated_work(), 4);
+
+        let plan = IsarInstance::plan_migration(&instance.name, &instance.dir, &schema);
+        assert_eq!(plan.unwrap().collections[0].object_count, 2);
+        assert_eq!(instance.collections()[0].properties.len(), 2);
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_migration_callback_transforms_objects() {
+        let old_col = collection("col", json!([{"name": "a", "type": "Long"}]));
+        let instance = open_instance(json!([old_col]));
+        let col = &instance.collections()[0];
+        let old_offset = col.property("a").unwrap().offset;
+        for a in [2, 3] {
+            put_object(&instance, col, Some(a), json!({ "a": a }));
+        }
+
+        let mut new_col = collection(
+            "col",
+            json!([{"name": "a", "type": "Long"}, {"name": "b", "type": "Long"}]),
+        );
+        new_col["indexes"] = json!([index("b", &[("b", false)], false)]);
+        // the new property is stored after the existing one
+        let new_offset = old_offset + 8;
+        set_migration_callback(
+            &instance.name,
+            "col",
+            Some(Box::new(move |object, ob| {
+                ob.write_long(new_offset, object.read_long(old_offset) * 10);
+                Ok(())
+            })),
+        );
+        let name = instance.name.clone();
+        let instance = reopen(instance, json!([new_col]));
+        set_migration_callback(&name, "col", None);
+
+        let col = &instance.collections()[0];
+        let mut qb = col.new_query_builder();
+        let prefix = [IndexValue::Long(30)];
+        qb.add_index_prefix_where_clause(
+            xxh3_64(b"b"),
+            &prefix,
+            None,
+            None,
+            Sort::Ascending,
+            false,
+        )
+        .unwrap();
+        assert_eq!(find_ids(&instance, &qb.build()), vec![3]);
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_migration_rewrites_hidden_objects() {
+        let col = |data_type: &str| {
+            let mut col = collection("col", json!([{"name": "value", "type": data_type}]));
+            col["softDelete"] = json!(true);
+            json!([col])
+        };
+        let instance = open_instance(col("Int"));
+        let col_int = &instance.collections()[0];
+        put_object(&instance, col_int, Some(1), json!({"value": 5}));
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        assert!(col_int.delete(&mut txn, 1).unwrap());
+        txn.commit().unwrap();
+
+        let instance = reopen(instance, col("Long"));
+        let col_long = &instance.collections()[0];
+        let offset = col_long.property("value").unwrap().offset;
+        let mut txn = instance.begin_txn(false, false).unwrap();
+        let (object, _) = col_long.get_deleted(&mut txn, 1).unwrap().unwrap();
+        assert_eq!(object.read_long(offset), 5);
+        drop(txn);
+        assert!(instance.close_and_delete());
+    }
+
+    fn blob_col(name: &str) -> Value {
+        collection(name, json!([{"name": "data", "type": "Blob"}]))
+    }
+
+    fn put_blob(instance: &IsarInstance, bytes: &[u8]) {
+        let col = &instance.collections()[0];
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        let ob = ObjectBuilder::new(&col.properties, None);
+        col.put(&mut txn, Some(1), ob.finish()).unwrap();
+        col.append_blob(&mut txn, 1, "data", bytes).unwrap();
+        txn.commit().unwrap();
+    }
+
+    fn read_blob(instance: &IsarInstance) -> Option<Vec<u8>> {
+        let col = &instance.collections()[0];
+        let mut txn = instance.begin_txn(false, false).unwrap();
+        let mut blob = vec![];
+        let exists = col.read_blob(&mut txn, 1, "data", |chunk| {
+            blob.extend_from_slice(chunk);
+            Ok(true)
+        });
+        instance.verify(&mut txn).unwrap();
+        exists.unwrap().then_some(blob)
+    }
+
+    #[test]
+    fn test_rename_collection_keeps_blobs() {
+        let bytes = (0..IsarBlobs::CHUNK_SIZE * 2 + 10)
+            .map(|i| i as u8)
+            .collect::<Vec<_>>();
+        let instance = open_instance(json!([blob_col("a")]));
+        put_blob(&instance, &bytes);
+
+        let mut renamed = blob_col("b");
+        renamed["previousName"] = json!("a");
+        let instance = reopen(instance, json!([renamed]));
+        assert_eq!(read_blob(&instance), Some(bytes));
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_delete_collection_deletes_blobs() {
+        let instance = open_instance(json!([blob_col("a")]));
+        put_blob(&instance, b"blob");
+
+        let instance = reopen(instance, json!([blob_col("b")]));
+        let instance = reopen(instance, json!([blob_col("a")]));
+        put_object(&instance, &instance.collections()[0], Some(1), json!({}));
+        assert_eq!(read_blob(&instance), None);
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_link_order_change_keeps_links() {
+        let col = |ordered: bool| {
+            let mut col = collection("col", json!([]));
+            col["links"] = json!([{"name": "link", "target": "col", "ordered": ordered}]);
+            json!([col])
+        };
+        let instance = open_instance(col(false));
+        let col_schema = &instance.collections()[0];
+        let link_id = col_schema.link_id("link").unwrap();
+        for id in 1..=3 {
+            put_object(&instance, col_schema, Some(id), json!({}));
+        }
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        col_schema.link(&mut txn, link_id, 1, 3).unwrap();
+        col_schema.link(&mut txn, link_id, 1, 2).unwrap();
+        txn.commit().unwrap();
+
+        let instance = reopen(instance, col(true));
+        let ordered = &instance.collections()[0];
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        assert_eq!(
+            ordered.get_link_ids(&mut txn, link_id, 1).unwrap(),
+            vec![2, 3]
+        );
+        assert!(ordered.move_link(&mut txn, link_id, 1, 3, 0).unwrap());
+        assert_eq!(
+            ordered.get_link_ids(&mut txn, link_id, 1).unwrap(),
+            vec![3, 2]
+        );
+        txn.commit().unwrap();
+
+        let instance = reopen(instance, col(false));
+        let unordered = &instance.collections()[0];
+        let mut txn = instance.begin_txn(false, false).unwrap();
+        assert_eq!(
+            unordered.get_link_ids(&mut txn, link_id, 1).unwrap(),
+            vec![2, 3]
+        );
+        instance.verify(&mut txn).unwrap();
+        drop(txn);
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_rename_collection_keeps_links_to_it() {
+        let instance = open_instance(linked_collections("a", None));
+        for col in instance.collections() {
+            put_object(&instance, col, Some(1), json!({}));
+        }
+        let source = &instance.collections()[0];
+        let link_id = source.link_id("link").unwrap();
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        source.link(&mut txn, link_id, 1, 1).unwrap();
+        txn.commit().unwrap();
+
+        let instance = reopen(instance, linked_collections("b", Some("a")));
+        let source = &instance.collections()[0];
+        let link_id = source.link_id("link").unwrap();
+        let mut txn = instance.begin_txn(false, false).unwrap();
+        assert_eq!(source.link_count(&mut txn, link_id, 1).unwrap(), 1);
+        drop(txn);
+        assert!(instance.close_and_delete());
+    }
+}
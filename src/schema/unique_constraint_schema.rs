@@ -0,0 +1,21 @@
+use serde::{Deserialize, Serialize};
+
+/// Properties whose combined values have to be unique. Unlike a unique index, only a hash of the
+/// values is stored so the constraint is cheaper to maintain but cannot be used by queries.
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct UniqueConstraintSchema {
+    pub(crate) name: String,
+    pub(crate) properties: Vec<String>,
+    #[serde(rename = "caseSensitive")]
+    pub(crate) case_sensitive: bool,
+}
+
+impl UniqueConstraintSchema {
+    pub fn new(name: &str, properties: &[&str], case_sensitive: bool) -> UniqueConstraintSchema {
+        UniqueConstraintSchema {
+            name: name.to_string(),
+            properties: properties.iter().map(|p| p.to_string()).collect(),
+            case_sensitive,
+        }
+    }
+}
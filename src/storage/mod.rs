@@ -0,0 +1,427 @@
+use crate::error::Result;
+use once_cell::sync::OnceCell;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+#[cfg(feature = "mdbx")]
+pub(crate) use crate::mdbx::{
+    cursor::{Cursor, UnboundCursor},
+    db::Db,
+    env::Env,
+    txn::Txn,
+};
+#[cfg(not(feature = "mdbx"))]
+pub(crate) use crate::memory::{
+    cursor::{Cursor, UnboundCursor},
+    db::Db,
+    env::Env,
+    txn::Txn,
+};
+
+pub type KeyVal<'txn> = (&'txn [u8], &'txn [u8]);
+
+pub trait Key {
+    fn as_bytes(&self) -> Cow<'_, [u8]>;
+
+    fn cmp_bytes(&self, other: &[u8]) -> Ordering;
+}
+
+/// The operations a storage backend has to provide for a cursor. Keys are compared bytewise
+/// unless the db uses integer keys. Dbs with duplicates keep the values of a key sorted.
+pub trait StorageCursor<'txn>: Sized {
+    fn move_to<K: Key>(&mut self, key: &K) -> Result<Option<KeyVal<'txn>>>;
+
+    fn move_to_key_val<K: Key>(&mut self, key: &K, val: &[u8]) -> Result<Option<KeyVal<'txn>>>;
+
+    /// Moves to the first entry with a key greater than or equal to `key`.
+    fn move_to_gte<K: Key>(&mut self, key: &K) -> Result<Option<KeyVal<'txn>>>;
+
+    /// Moves to the next value of the current key.
+    fn move_to_next_dup(&mut self) -> Result<Option<KeyVal<'txn>>>;
+
+    /// Moves to the last value of the current key.
+    fn move_to_last_dup(&mut self) -> Result<Option<KeyVal<'txn>>>;
+
+    /// Moves to the last value of the previous key.
+    fn move_to_prev_no_dup(&mut self) -> Result<Option<KeyVal<'txn>>>;
+
+    fn move_to_first(&mut self) -> Result<Option<KeyVal<'txn>>>;
+
+    fn move_to_last(&mut self) -> Result<Option<KeyVal<'txn>>>;
+
+    /// Moves to the next or previous entry. If `skip_duplicates` is set, the remaining values of
+    /// the current key are skipped. An unpositioned cursor moves to the first or last entry.
+    fn step(&mut self, ascending: bool, skip_duplicates: bool) -> Result<Option<KeyVal<'txn>>>;
+
+    fn put<K: Key>(&mut self, key: &K, data: &[u8]) -> Result<()>;
+
+    /// Number of values stored for `key`.
+    fn count_dups<K: Key>(&mut self, key: &K) -> Result<usize>;
+
+    /// Requires the cursor to have a valid position
+    fn delete_current(&mut self) -> Result<()>;
+
+    fn move_to_next(&mut self) -> Result<Option<KeyVal<'txn>>> {
+        self.step(true, false)
+    }
+
+    fn iter(
+        &mut self,
+        skip_duplicates: bool,
+        ascending: bool,
+        mut callback: impl FnMut(&mut Self, &'txn [u8], &'txn [u8]) -> Result<bool>,
+    ) -> Result<bool> {
+        loop {
+            if let Some((key, val)) = self.step(ascending, skip_duplicates)? {
+                if !callback(self, key, val)? {
+                    return Ok(false);
+                }
+            } else {
+                return Ok(true);
+            }
+        }
+    }
+
+    fn iter_all(
+        &mut self,
+        skip_duplicates: bool,
+        ascending: bool,
+        mut callback: impl FnMut(&mut Self, &'txn [u8], &'txn [u8]) -> Result<bool>,
+    ) -> Result<bool> {
+        let first = if ascending {
+            self.move_to_first()?
+        } else {
+            self.move_to_last()?
+        };
+
+        if let Some((key, val)) = first {
+            if !callback(self, key, val)? {
+                return Ok(false);
+            }
+        } else {
+            return Ok(true);
+        }
+
+        self.iter(skip_duplicates, ascending, callback)
+    }
+
+    fn iter_between<K: Key>(
+        &mut self,
+        lower_key: &K,
+        upper_key: &K,
+        duplicates: bool,
+        skip_duplicates: bool,
+        ascending: bool,
+        mut callback: impl FnMut(&mut Self, &'txn [u8], &'txn [u8]) -> Result<bool>,
+    ) -> Result<bool> {
+        if upper_key.cmp_bytes(&lower_key.as_bytes()) == Ordering::Less {
+            return Ok(true);
+        }
+
+        if let Some((key, val)) =
+            iter_between_first(self, lower_key, upper_key, ascending, duplicates)?
+        {
+            if !callback(self, key, val)? {
+                return Ok(false);
+            }
+        } else {
+            return Ok(true);
+        }
+
+        self.iter(skip_duplicates, ascending, |cursor, key, val| {
+            let abort = if ascending {
+                upper_key.cmp_bytes(key) == Ordering::Less
+            } else {
+                lower_key.cmp_bytes(key) == Ordering::Greater
+            };
+            if abort {
+                Ok(true)
+            } else {
+                callback(cursor, key, val)
+            }
+        })
+    }
+
+    fn iter_dups<K: Key>(
+        &mut self,
+        key: &K,
+        mut callback: impl FnMut(&mut Self, &'txn [u8]) -> Result<bool>,
+    ) -> Result<bool> {
+        if let Some((_, val)) = self.move_to(key)? {
+            if !callback(self, val)? {
+                return Ok(false);
+            }
+        } else {
+            return Ok(true);
+        }
+        loop {
+            if let Some((_, val)) = self.move_to_next_dup()? {
+                if !callback(self, val)? {
+                    return Ok(false);
+                }
+            } else {
+                return Ok(true);
+            }
+        }
+    }
+}
+
+fn iter_between_first<'txn, K: Key>(
+    cursor: &mut impl StorageCursor<'txn>,
+    lower_key: &K,
+    upper_key: &K,
+    ascending: bool,
+    duplicates: bool,
+) -> Result<Option<KeyVal<'txn>>> {
+    let first_entry = if !ascending {
+        if let Some(first_entry) = cursor.move_to_gte(upper_key)? {
+            if duplicates {
+                cursor.move_to_last_dup()?.or(Some(first_entry))
+            } else {
+                Some(first_entry)
+            }
+        } else {
+            // If some key between upper_key and lower_key happens to be the last key in the db
+            cursor
+                .move_to_last()?
+                .filter(|last| lower_key.cmp_bytes(last.0) != Ordering::Greater)
+        }
+    } else {
+        cursor.move_to_gte(lower_key)?
+    };
+
+    if let Some(first_entry) = first_entry {
+        if upper_key.cmp_bytes(first_entry.0) == Ordering::Less {
+            if !ascending {
+                if let Some(prev) = cursor.move_to_prev_no_dup()? {
+                    if lower_key.cmp_bytes(prev.0) != Ordering::Greater {
+                        return Ok(Some(prev));
+                    }
+                }
+            }
+            Ok(None)
+        } else {
+            Ok(Some(first_entry))
+        }
+    } else {
+        Ok(None)
+    }
+}
+
+/// Size information of a single db.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct DbStat {
+    pub entries: u64,
+    pub depth: u32,
+    pub branch_pages: u64,
+    pub leaf_pages: u64,
+    pub overflow_pages: u64,
+    pub page_size: u32,
+}
+
+impl DbStat {
+    pub fn size(&self) -> u64 {
+        (self.branch_pages + self.leaf_pages + self.overflow_pages) * self.page_size as u64
+    }
+}
+
+/// A reader slot of the environment.
+pub struct ReaderSlot {
+    pub pid: i32,
+    pub thread: u64,
+    pub txn_id: u64,
+    pub lag: u64,
+    pub bytes_retained: u64,
+}
+
+/// A storage environment that contains named dbs. The backend is selected at compile time and
+/// the higher layers only use the types exported by this module.
+pub trait StorageEngine {
+    type Txn<'env>: StorageTxn
+    where
+        Self: 'env;
+
+    fn txn(&self, write: bool) -> Result<Self::Txn<'_>>;
+
+    fn copy(&self, path: &str) -> Result<()>;
+
+    /// Shrinks the file to the pages that are currently in use.
+    fn shrink(&self) -> Result<()>;
+
+    fn sync(&self) -> Result<()>;
+
+    fn readers(&self) -> Result<Vec<ReaderSlot>>;
+
+    /// Clears reader slots of processes that no longer exist. Returns the number of cleared slots.
+    fn check_readers(&self) -> Result<u32>;
+}
+
+pub trait StorageTxn: Sized {
+    fn id(&self) -> u64;
+
+    fn is_write(&self) -> bool;
+
+    /// Size of the pages changed by this write transaction.
+    fn dirty_bytes(&self) -> Result<u64>;
+
+    /// Begins a child write transaction. The parent must not be used until the child is
+    /// committed or aborted.
+    fn begin_nested(&self) -> Result<Self>;
+
+    fn commit(self) -> Result<()>;
+
+    fn abort(self);
+}
+
+pub trait StorageDb<T: StorageTxn>: Copy + Eq {
+    fn runtime_id(&self) -> u64;
+
+    fn open(txn: &T, name: Option<&str>, int_key: bool, dup: bool, int_dup: bool) -> Result<Self>;
+
+    fn stat(&self, txn: &T) -> Result<DbStat>;
+
+    fn clear(&self, txn: &T) -> Result<()>;
+
+    fn drop(self, txn: &T) -> Result<()>;
+}
+
+struct LazyDbInner {
+    name: String,
+    int_key: bool,
+    dup: bool,
+    int_dup: bool,
+    db: OnceCell<Db>,
+}
+
+/// A named db that is opened on first use.
+#[derive(Clone)]
+pub(crate) struct LazyDb(Arc<LazyDbInner>);
+
+impl LazyDb {
+    pub fn new(name: &str, int_key: bool, dup: bool, int_dup: bool) -> Self {
+        LazyDb(Arc::new(LazyDbInner {
+            name: name.to_string(),
+            int_key,
+            dup,
+            int_dup,
+            db: OnceCell::new(),
+        }))
+    }
+
+    fn open_db(&self, txn: &Txn) -> Result<Db> {
+        let inner = &self.0;
+        Db::open(
+            txn,
+            Some(&inner.name),
+            inner.int_key,
+            inner.dup,
+            inner.int_dup,
+        )
+    }
+
+    /// Opens the db in `txn` and creates it if it does not exist.
+    pub fn open(&self, txn: &Txn) -> Result<Db> {
+        let db = self.0.db.get_or_try_init(|| self.open_db(txn))?;
+        Ok(*db)
+    }
+
+    /// Returns the db which has to exist already. Handles opened by a write transaction are
+    /// closed if the transaction is aborted and a write transaction cannot begin a read
+    /// transaction on the same thread so write transactions do not cache the handle.
+    pub fn get(&self, txn: &Txn) -> Result<Db> {
+        if let Some(db) = self.0.db.get() {
+            Ok(*db)
+        } else if txn.is_write() {
+            self.open_db(txn)
+        } else {
+            let db = self.0.db.get_or_try_init(|| self.open_db(txn))?;
+            Ok(*db)
+        }
+    }
+}
+
+impl PartialEq for LazyDb {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for LazyDb {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instance::Durability;
+
+    fn create_env(path: &str) -> Env {
+        Env::create(
+            path,
+            10,
+            Durability::Safe,
+            false,
+            false,
+            Env::DEFAULT_MAX_SIZE,
+            Env::DEFAULT_GROWTH_STEP,
+            Env::DEFAULT_SHRINK_THRESHOLD,
+        )
+        .unwrap()
+    }
+
+    fn collect_between(cursor: &mut Cursor, lower: i64, upper: i64, ascending: bool) -> Vec<u8> {
+        let mut values = vec![];
+        cursor
+            .iter_between(&lower, &upper, true, false, ascending, |_, _, val| {
+                values.push(val[0]);
+                Ok(true)
+            })
+            .unwrap();
+        values
+    }
+
+    #[test]
+    fn test_storage_traits() {
+        let path = std::env::temp_dir().join(format!("storage_{}", rand::random::<u64>()));
+        let path = path.to_str().unwrap();
+        let env = create_env(path);
+
+        let txn = env.txn(true).unwrap();
+        let db = Db::open(&txn, Some("test"), true, true, false).unwrap();
+        let mut cursor = UnboundCursor::new().bind(&txn, db).unwrap();
+        for (key, val) in [(1i64, 1u8), (2, 2), (2, 3), (3, 4), (5, 5)] {
+            cursor.put(&key, &[val]).unwrap();
+        }
+        assert_eq!(cursor.count_dups(&2i64).unwrap(), 2);
+        assert_eq!(collect_between(&mut cursor, 2, 4, true), [2, 3, 4]);
+        assert_eq!(collect_between(&mut cursor, 2, 4, false), [4, 3, 2]);
+        cursor.move_to(&5i64).unwrap().unwrap();
+        cursor.delete_current().unwrap();
+        drop(cursor);
+
+        // changes of an aborted child transaction are discarded
+        let child = txn.begin_nested().unwrap();
+        let mut cursor = UnboundCursor::new().bind(&child, db).unwrap();
+        cursor.put(&6i64, &[6]).unwrap();
+        drop(cursor);
+        child.abort();
+        txn.commit().unwrap();
+
+        let txn = env.txn(false).unwrap();
+        assert!(!txn.is_write());
+        assert_eq!(db.stat(&txn).unwrap().entries, 4);
+        let mut cursor = UnboundCursor::new().bind(&txn, db).unwrap();
+        assert_eq!(collect_between(&mut cursor, 0, 10, true), [1, 2, 3, 4]);
+        let mut dups = vec![];
+        cursor
+            .iter_dups(&2i64, |_, val| {
+                dups.push(val[0]);
+                Ok(true)
+            })
+            .unwrap();
+        assert_eq!(dups, [2, 3]);
+        drop(cursor);
+        txn.abort();
+        drop(env);
+        Env::delete(path);
+    }
+}
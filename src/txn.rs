@@ -1,37 +1,227 @@
-use crate::cursor::IsarCursors;
-use crate::error::{IsarError, Result};
-use crate::mdbx::cursor::UnboundCursor;
-use crate::mdbx::db::Db;
-use crate::mdbx::txn::Txn;
+use crate::cursor::{CursorCounters, IsarCursors};
+use crate::error::{illegal_arg, IsarError, Result};
+use crate::instance::PerfCounters;
+use crate::isar_log;
+use crate::logging::LogEvent;
+use crate::storage::{Db, StorageCursor, StorageDb, StorageTxn, Txn, UnboundCursor};
 use crate::watch::change_set::ChangeSet;
-use std::cell::RefCell;
+use intmap::IntMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant};
+
+pub(crate) type OpenReaders = Arc<Mutex<IntMap<(u64, Instant)>>>;
+
+/// Ids of transactions committed by this process that have not been polled yet.
+pub(crate) type OwnCommits = Arc<Mutex<Vec<u64>>>;
+
+pub(crate) struct TxnCounters {
+    pub commits: AtomicU64,
+    pub syncs: AtomicU64,
+    pub bytes_written: AtomicU64,
+    pub cursors: Arc<CursorCounters>,
+    sync_on_commit: bool,
+}
+
+impl TxnCounters {
+    pub fn new(sync_on_commit: bool) -> Self {
+        TxnCounters {
+            commits: AtomicU64::new(0),
+            syncs: AtomicU64::new(0),
+            bytes_written: AtomicU64::new(0),
+            cursors: Arc::new(CursorCounters::default()),
+            sync_on_commit,
+        }
+    }
+
+    fn add_commit(&self, bytes_written: u64) {
+        self.commits.fetch_add(1, Ordering::Relaxed);
+        self.bytes_written
+            .fetch_add(bytes_written, Ordering::Relaxed);
+        if self.sync_on_commit {
+            self.syncs.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn snapshot(&self) -> PerfCounters {
+        PerfCounters {
+            commits: self.commits.load(Ordering::Relaxed),
+            syncs: self.syncs.load(Ordering::Relaxed),
+            bytes_written: self.bytes_written.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Registers a read transaction for `IsarInstance::reader_info` while it is alive.
+pub(crate) struct ReaderGuard {
+    readers: OpenReaders,
+    key: u64,
+}
+
+impl ReaderGuard {
+    pub fn new(readers: &OpenReaders, key: u64, txn_id: u64) -> Self {
+        readers
+            .lock()
+            .unwrap()
+            .insert(key, (txn_id, Instant::now()));
+        ReaderGuard {
+            readers: readers.clone(),
+            key,
+        }
+    }
+}
+
+impl Drop for ReaderGuard {
+    fn drop(&mut self) {
+        self.readers.lock().unwrap().remove(self.key);
+    }
+}
 
 pub struct IsarTxn<'env> {
     instance_id: u64,
+    // nested transactions have to be dropped before their parent
+    nested: Vec<(Txn<'env>, usize)>,
     txn: Txn<'env>,
     write: bool,
-    change_set: RefCell<Option<ChangeSet<'env>>>,
-    unbound_cursors: RefCell<Option<Vec<UnboundCursor>>>,
+    /// Write transactions can only be used by the thread that started them.
+    thread: ThreadId,
+    change_set: Option<ChangeSet<'env>>,
+    unbound_cursors: Mutex<Option<Vec<UnboundCursor>>>,
+    started: Instant,
+    max_age: Option<Duration>,
+    /// Snapshots never expire but log their age once it exceeds this duration.
+    snapshot_warn_after: Option<Duration>,
+    snapshot_warned: AtomicBool,
+    _reader: Option<ReaderGuard>,
+    own_commits: Option<OwnCommits>,
+    counters: Arc<TxnCounters>,
+    /// Cached objects returned by this transaction.
+    pinned: Mutex<Vec<Arc<[u8]>>>,
 }
 
 impl<'env> IsarTxn<'env> {
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         instance_id: u64,
         txn: Txn<'env>,
         write: bool,
         change_set: Option<ChangeSet<'env>>,
+        max_age: Option<Duration>,
+        reader: Option<ReaderGuard>,
+        own_commits: Option<OwnCommits>,
+        counters: Arc<TxnCounters>,
     ) -> Result<Self> {
+        isar_log!(LogEvent::TxnBegin { instance_id, write });
         Ok(IsarTxn {
             instance_id,
+            nested: vec![],
             txn,
             write,
-            change_set: RefCell::new(change_set),
-            unbound_cursors: RefCell::new(Some(vec![])),
+            thread: thread::current().id(),
+            change_set,
+            unbound_cursors: Mutex::new(Some(vec![])),
+            started: Instant::now(),
+            max_age,
+            snapshot_warn_after: None,
+            snapshot_warned: AtomicBool::new(false),
+            _reader: reader,
+            own_commits,
+            counters,
+            pinned: Mutex::new(vec![]),
         })
     }
 
+    pub fn is_write(&self) -> bool {
+        self.write
+    }
+
+    /// Turns a read transaction into a snapshot that is kept open intentionally. Snapshots are
+    /// not affected by the maximum read transaction age.
+    pub(crate) fn into_snapshot(mut self, warn_after: Duration) -> Self {
+        self.max_age = None;
+        self.snapshot_warn_after = Some(warn_after);
+        self
+    }
+
+    pub fn is_snapshot(&self) -> bool {
+        self.snapshot_warn_after.is_some()
+    }
+
+    pub fn age(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    fn check_snapshot_age(&self) {
+        if let Some(warn_after) = self.snapshot_warn_after {
+            let age = self.started.elapsed();
+            if age > warn_after && !self.snapshot_warned.swap(true, Ordering::Relaxed) {
+                isar_log!(LogEvent::SnapshotAge {
+                    instance_id: self.instance_id,
+                    age_ms: age.as_millis(),
+                });
+            }
+        }
+    }
+
+    /// Keeps the bytes alive until the transaction ends.
+    pub(crate) fn pin(&self, bytes: Arc<[u8]>) -> &[u8] {
+        let ptr: *const [u8] = &*bytes;
+        self.pinned.lock().unwrap().push(bytes);
+        // the bytes are never dropped or moved before the transaction
+        unsafe { &*ptr }
+    }
+
     pub fn is_active(&self) -> bool {
-        self.unbound_cursors.borrow().is_some()
+        self.unbound_cursors.lock().unwrap().is_some()
+    }
+
+    fn active_txn(&self) -> &Txn<'env> {
+        self.nested.last().map_or(&self.txn, |(txn, _)| txn)
+    }
+
+    /// Starts a savepoint that can be rolled back without aborting the whole transaction.
+    pub fn begin_nested(&mut self) -> Result<()> {
+        if !self.is_active() {
+            return Err(IsarError::TransactionClosed {});
+        } else if !self.write {
+            return Err(IsarError::WriteTxnRequired {});
+        }
+        self.close_cursors();
+        let nested = self.active_txn().begin_nested()?;
+        let journal_len = self.change_set.as_ref().map_or(0, |c| c.journal_len());
+        self.nested.push((nested, journal_len));
+        Ok(())
+    }
+
+    pub fn commit_nested(&mut self) -> Result<()> {
+        if let Some((nested, _)) = self.nested.pop() {
+            self.close_cursors();
+            nested.commit()
+        } else {
+            illegal_arg("There is no nested transaction.")
+        }
+    }
+
+    pub fn rollback_nested(&mut self) -> Result<()> {
+        if let Some((nested, journal_len)) = self.nested.pop() {
+            self.close_cursors();
+            nested.abort();
+            if let Some(change_set) = &mut self.change_set {
+                change_set.truncate_journal(journal_len);
+            }
+            Ok(())
+        } else {
+            illegal_arg("There is no nested transaction.")
+        }
+    }
+
+    /// Pooled cursors stay attached to the transaction they were last bound to and have to be
+    /// closed before a nested transaction begins or ends.
+    fn close_cursors(&mut self) {
+        if let Some(pool) = self.unbound_cursors.get_mut().unwrap() {
+            pool.clear();
+        }
     }
 
     fn verify_instance_id(&self, instance_id: u64) -> Result<()> {
@@ -43,14 +233,43 @@ impl<'env> IsarTxn<'env> {
     }
 
     pub(crate) fn read<'txn, T, F>(&'txn mut self, instance_id: u64, job: F) -> Result<T>
+    where
+        F: FnOnce(&IsarCursors<'txn, 'env>) -> Result<T>,
+    {
+        self.read_shared(instance_id, job)
+    }
+
+    /// Like `read` but only requires a shared reference. Reads may be nested, for example to run
+    /// a query while iterating the results of another one, and read transactions can be shared
+    /// by multiple threads to run queries in parallel. Every read uses its own cursors.
+    pub(crate) fn read_shared<'txn, T, F>(&'txn self, instance_id: u64, job: F) -> Result<T>
     where
         F: FnOnce(&IsarCursors<'txn, 'env>) -> Result<T>,
     {
         self.verify_instance_id(instance_id)?;
-        if let Some(unbound_cursors) = self.unbound_cursors.take() {
-            let cursors = IsarCursors::new(&self.txn, unbound_cursors);
+        if self.write && thread::current().id() != self.thread {
+            return illegal_arg("Write transactions cannot be used by other threads.");
+        }
+        if let Some(max_age) = self.max_age {
+            if !self.write && self.started.elapsed() > max_age {
+                return Err(IsarError::TransactionExpired {});
+            }
+        }
+        self.check_snapshot_age();
+        let unbound_cursors = self
+            .unbound_cursors
+            .lock()
+            .unwrap()
+            .as_mut()
+            .map(std::mem::take);
+        if let Some(unbound_cursors) = unbound_cursors {
+            let cursors = IsarCursors::new(self.active_txn(), unbound_cursors)
+                .track(&self.counters.cursors, self.instance_id);
             let result = job(&cursors);
-            self.unbound_cursors.borrow_mut().replace(cursors.close());
+            let unbound_cursors = cursors.close();
+            if let Some(pool) = self.unbound_cursors.lock().unwrap().as_mut() {
+                pool.extend(unbound_cursors);
+            }
             result
         } else {
             Err(IsarError::TransactionClosed {})
@@ -65,16 +284,20 @@ impl<'env> IsarTxn<'env> {
         if !self.write {
             return Err(IsarError::WriteTxnRequired {});
         }
-        if let Some(unbound_cursors) = self.unbound_cursors.take() {
+        if let Some(unbound_cursors) = self.unbound_cursors.get_mut().unwrap().take() {
             let mut change_set = self.change_set.take();
-            let cursors = IsarCursors::new(&self.txn, unbound_cursors);
+            // only borrow the transaction so the change set can be put back
+            let txn = self.nested.last().map_or(&self.txn, |(txn, _)| txn);
+            let cursors = IsarCursors::new(txn, unbound_cursors)
+                .track(&self.counters.cursors, self.instance_id);
             let result = job(&cursors, change_set.as_mut());
             let unbounded_cursors = cursors.close();
             if result.is_ok() {
-                self.unbound_cursors.borrow_mut().replace(unbounded_cursors);
-                if let Some(change_set) = change_set {
-                    self.change_set.borrow_mut().replace(change_set);
-                }
+                self.unbound_cursors
+                    .get_mut()
+                    .unwrap()
+                    .replace(unbounded_cursors);
+                self.change_set = change_set;
             }
             result
         } else {
@@ -82,28 +305,52 @@ impl<'env> IsarTxn<'env> {
         }
     }
 
-    pub fn commit(self) -> Result<()> {
+    pub fn commit(mut self) -> Result<()> {
         if !self.is_active() {
             return Err(IsarError::TransactionClosed {});
         }
 
         if self.write {
+            self.close_cursors();
+            while let Some((nested, _)) = self.nested.pop() {
+                nested.commit()?;
+            }
+            if let Some(change_set) = &self.change_set {
+                change_set.persist_journal(&self.txn)?;
+            }
+            let txn_id = self.txn.id();
+            let dirty_bytes = self.txn.dirty_bytes()?;
             self.txn.commit()?;
+            self.counters.add_commit(dirty_bytes);
+            if let Some(own_commits) = &self.own_commits {
+                own_commits.lock().unwrap().push(txn_id);
+            }
+            isar_log!(LogEvent::TxnCommit {
+                instance_id: self.instance_id
+            });
             if let Some(change_set) = self.change_set.take() {
-                change_set.notify_watchers();
+                change_set.notify_watchers(txn_id);
             }
         }
         Ok(())
     }
 
-    pub fn abort(self) {
+    pub fn abort(mut self) {
+        isar_log!(LogEvent::TxnAbort {
+            instance_id: self.instance_id
+        });
+        self.close_cursors();
+        while let Some((nested, _)) = self.nested.pop() {
+            nested.abort();
+        }
         self.txn.abort()
     }
 
     pub(crate) fn db_names(&mut self) -> Result<Vec<String>> {
-        let unnamed_db = Db::open(&self.txn, None, false, false, false)?;
+        let txn = self.active_txn();
+        let unnamed_db = Db::open(txn, None, false, false, false)?;
         let cursor = UnboundCursor::new();
-        let mut cursor = cursor.bind(&self.txn, unnamed_db)?;
+        let mut cursor = cursor.bind(txn, unnamed_db)?;
 
         let mut names = vec![];
         cursor.iter_all(false, true, |_, name, _| {
@@ -113,3 +360,88 @@ impl<'env> IsarTxn<'env> {
         Ok(names)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instance::tests::{collection, collections, index, open_instance, put_values};
+    use crate::object::object_builder::ObjectBuilder;
+    use serde_json::json;
+
+    #[test]
+    fn test_rollback_nested_keeps_outer_changes() {
+        let mut col = collection("col", json!([{"name": "value", "type": "Long"}]));
+        col["indexes"] = json!([index("value", &[("value", false)], true)]);
+        let instance = open_instance(json!([col]));
+        let col = &instance.collections()[0];
+        let put = |txn: &mut IsarTxn, id: i64| {
+            let mut ob = ObjectBuilder::new(&col.properties, None);
+            ob.write_long(col.properties[0].offset, id * 10);
+            col.put(txn, Some(id), ob.finish()).unwrap();
+        };
+
+        let mut txn = instance.begin_txn(true, false).unwrap();
+        put(&mut txn, 1);
+        txn.begin_nested().unwrap();
+        put(&mut txn, 2);
+        col.delete(&mut txn, 1).unwrap();
+        txn.rollback_nested().unwrap();
+
+        txn.begin_nested().unwrap();
+        put(&mut txn, 3);
+        txn.begin_nested().unwrap();
+        put(&mut txn, 4);
+        txn.rollback_nested().unwrap();
+        txn.commit_nested().unwrap();
+        assert!(txn.rollback_nested().is_err());
+        txn.commit().unwrap();
+
+        let mut txn = instance.begin_txn(false, false).unwrap();
+        for (id, exists) in [(1, true), (2, false), (3, true), (4, false)] {
+            assert_eq!(col.get(&mut txn, id).unwrap().is_some(), exists);
+        }
+        instance.verify(&mut txn).unwrap();
+        drop(txn);
+        assert!(instance.close_and_delete());
+    }
+
+    #[test]
+    fn test_shared_read_txn_runs_queries_in_parallel() {
+        let instance = open_instance(collections());
+        let col = &instance.collections()[0];
+        put_values(&instance, &(1..=100).collect::<Vec<_>>());
+
+        let query = col.new_query_builder().build();
+        let txn = instance.begin_txn(true, false).unwrap();
+        std::thread::scope(|s| {
+            let txn = &txn;
+            s.spawn(|| assert!(query.find_all_vec(txn).is_err()));
+        });
+        txn.commit().unwrap();
+
+        let txn = instance.begin_txn(false, false).unwrap();
+        std::thread::scope(|s| {
+            let handles: Vec<_> = (0..4)
+                .map(|_| {
+                    s.spawn(|| {
+                        let mut sum = 0;
+                        for _ in 0..10 {
+                            let results = query.find_all_vec(&txn).unwrap();
+                            assert_eq!(results.len(), 100);
+                            sum = results
+                                .iter()
+                                .map(|(_, object)| object.read_long(col.properties[0].offset))
+                                .sum::<i64>();
+                        }
+                        sum
+                    })
+                })
+                .collect();
+            for handle in handles {
+                assert_eq!(handle.join().unwrap(), 5050);
+            }
+        });
+        drop(txn);
+        assert!(instance.close_and_delete());
+    }
+}
@@ -1,19 +1,43 @@
+use crate::change_journal::{ChangeJournal, ChangeOp};
+use crate::error::Result;
+use crate::isar_log;
+use crate::logging::LogEvent;
+use crate::object::data_type::DataType;
 use crate::object::isar_object::IsarObject;
+use crate::object::property::Property;
+use crate::storage::Txn;
 use crate::watch::isar_watchers::IsarWatchers;
+use crate::watch::lazy_watcher::{LazyChanges, LazyQueryWatcher};
 use crate::watch::watcher::Watcher;
 use intmap::IntMap;
 use std::sync::{Arc, MutexGuard};
+use xxhash_rust::xxh3::{xxh3_64, xxh3_64_with_seed};
+
+struct PropertyChange {
+    old_hash: Option<u64>,
+    new_hash: Option<u64>,
+    watcher: Arc<Watcher>,
+}
 
 pub(crate) struct ChangeSet<'a> {
-    watchers: MutexGuard<'a, IsarWatchers>,
+    watchers: Option<MutexGuard<'a, IsarWatchers>>,
     changed_watchers: IntMap<Arc<Watcher>>,
+    property_changes: IntMap<PropertyChange>,
+    lazy_changes: IntMap<(Arc<LazyQueryWatcher>, LazyChanges)>,
+    journal: Option<ChangeJournal>,
 }
 
 impl<'a> ChangeSet<'a> {
-    pub fn new(watchers: MutexGuard<'a, IsarWatchers>) -> Self {
+    pub fn new(
+        watchers: Option<MutexGuard<'a, IsarWatchers>>,
+        journal: Option<ChangeJournal>,
+    ) -> Self {
         ChangeSet {
             watchers,
             changed_watchers: IntMap::new(),
+            property_changes: IntMap::new(),
+            lazy_changes: IntMap::new(),
+            journal,
         }
     }
 
@@ -28,8 +52,41 @@ impl<'a> ChangeSet<'a> {
         }
     }
 
-    pub fn register_change(&mut self, col_id: u64, id: i64, object: IsarObject) {
-        let cw = self.watchers.get_col_watchers(col_id);
+    fn get_lazy_changes<'b>(
+        lazy_changes: &'b mut IntMap<(Arc<LazyQueryWatcher>, LazyChanges)>,
+        watcher: &Arc<LazyQueryWatcher>,
+    ) -> &'b mut LazyChanges {
+        if !lazy_changes.contains_key(watcher.get_id()) {
+            lazy_changes.insert(watcher.get_id(), (watcher.clone(), LazyChanges::default()));
+        }
+        &mut lazy_changes.get_mut(watcher.get_id()).unwrap().1
+    }
+
+    fn hash_property(object: IsarObject, property: &Property) -> u64 {
+        match property.data_type {
+            DataType::Object => object
+                .read_object(property.offset)
+                .map_or(0, |object| xxh3_64(object.as_bytes())),
+            DataType::ObjectList => {
+                if let Some(objects) = object.read_object_list(property.offset) {
+                    objects.into_iter().fold(1, |hash, object| {
+                        let bytes = object.map_or(&[][..], |o| o.as_bytes());
+                        xxh3_64_with_seed(bytes, hash)
+                    })
+                } else {
+                    0
+                }
+            }
+            _ => object.hash_property(property.offset, property.data_type, true, 0),
+        }
+    }
+
+    fn register_object(&mut self, col_id: u64, id: i64, object: IsarObject, deleted: bool) {
+        let cw = if let Some(watchers) = &mut self.watchers {
+            watchers.get_col_watchers(col_id)
+        } else {
+            return;
+        };
         Self::register_watchers(&mut self.changed_watchers, &cw.watchers);
         if let Some(object_watchers) = cw.object_watchers.get(id as u64) {
             Self::register_watchers(&mut self.changed_watchers, object_watchers);
@@ -42,10 +99,42 @@ impl<'a> ChangeSet<'a> {
                 self.changed_watchers.insert(w.get_id(), w.clone());
             }
         }
+
+        for w in &cw.lazy_query_watchers {
+            Self::get_lazy_changes(&mut self.lazy_changes, w).add_object(id, object);
+        }
+
+        if let Some(property_watchers) = cw.property_watchers.get(id as u64) {
+            for (p, w) in property_watchers {
+                let hash = Some(Self::hash_property(object, p));
+                if let Some(change) = self.property_changes.get_mut(w.get_id()) {
+                    change.new_hash = if deleted { None } else { hash };
+                } else {
+                    let change = PropertyChange {
+                        old_hash: if deleted { hash } else { None },
+                        new_hash: if deleted { None } else { hash },
+                        watcher: w.clone(),
+                    };
+                    self.property_changes.insert(w.get_id(), change);
+                }
+            }
+        }
+    }
+
+    pub fn register_change(&mut self, col_id: u64, id: i64, object: IsarObject) {
+        self.register_object(col_id, id, object, false);
+    }
+
+    pub fn register_delete(&mut self, col_id: u64, id: i64, object: IsarObject) {
+        self.register_object(col_id, id, object, true);
     }
 
     pub fn register_all(&mut self, col_id: u64) {
-        let cw = self.watchers.get_col_watchers(col_id);
+        let cw = if let Some(watchers) = &mut self.watchers {
+            watchers.get_col_watchers(col_id)
+        } else {
+            return;
+        };
         Self::register_watchers(&mut self.changed_watchers, &cw.watchers);
         for watchers in cw.object_watchers.values() {
             Self::register_watchers(&mut self.changed_watchers, watchers)
@@ -53,11 +142,74 @@ impl<'a> ChangeSet<'a> {
         for (_, w) in &cw.query_watchers {
             self.changed_watchers.insert(w.get_id(), w.clone());
         }
+        for watchers in cw.property_watchers.values() {
+            for (_, w) in watchers {
+                self.changed_watchers.insert(w.get_id(), w.clone());
+            }
+        }
+        for w in &cw.lazy_query_watchers {
+            Self::get_lazy_changes(&mut self.lazy_changes, w).add_all();
+        }
     }
 
-    pub fn notify_watchers(self) {
+    /// Like `register_all` but also notifies watchers that only observe truncation.
+    pub fn register_clear(&mut self, col_id: u64) {
+        self.register_all(col_id);
+        if let Some(watchers) = &mut self.watchers {
+            let cw = watchers.get_col_watchers(col_id);
+            Self::register_watchers(&mut self.changed_watchers, &cw.clear_watchers);
+        }
+    }
+
+    pub fn record(&mut self, col_id: u64, id: Option<i64>, op: ChangeOp) {
+        if let Some(journal) = &mut self.journal {
+            journal.record(col_id, id, op);
+        }
+    }
+
+    pub fn journal_len(&self) -> usize {
+        self.journal.as_ref().map_or(0, |j| j.len())
+    }
+
+    /// Drops journal entries of rolled back changes. Watchers may still be notified.
+    pub fn truncate_journal(&mut self, len: usize) {
+        if let Some(journal) = &mut self.journal {
+            journal.truncate(len);
+        }
+    }
+
+    pub fn persist_journal(&self, txn: &Txn) -> Result<()> {
+        if let Some(journal) = &self.journal {
+            journal.persist(txn)?;
+        }
+        Ok(())
+    }
+
+    /// `sequence` is the id of the committed transaction.
+    pub fn notify_watchers(mut self, sequence: u64) {
+        if let Some(journal) = self.journal.take() {
+            journal.publish();
+        }
+        if self.watchers.is_none() {
+            return;
+        }
+
+        for (_, (watcher, changes)) in self.lazy_changes.drain() {
+            watcher.mark_dirty(changes);
+        }
+
+        for change in self.property_changes.values() {
+            if change.old_hash != change.new_hash {
+                let w = &change.watcher;
+                self.changed_watchers.insert(w.get_id(), w.clone());
+            }
+        }
+
+        isar_log!(LogEvent::WatcherNotify {
+            watchers: self.changed_watchers.len()
+        });
         for watcher in self.changed_watchers.values() {
-            watcher.notify();
+            watcher.notify(sequence);
         }
     }
 }
@@ -1,9 +1,12 @@
+use crate::object::property::Property;
 use crate::query::Query;
+use crate::watch::lazy_watcher::{LazyChanges, LazyQueryWatcher};
 use crate::watch::watcher::{Watcher, WatcherCallback};
 use crossbeam_channel::Receiver;
 use intmap::IntMap;
 use itertools::Itertools;
 use std::sync::Arc;
+use std::time::Duration;
 
 pub(crate) type WatcherModifier = Box<dyn FnOnce(&mut IsarWatchers) + Send + 'static>;
 
@@ -28,6 +31,28 @@ impl IsarWatchers {
         self.collection_watchers.get_mut(col_id).unwrap()
     }
 
+    /// Notifies every registered watcher. Used if it is unknown what changed.
+    pub(crate) fn notify_all(&self, sequence: u64) {
+        for cw in self.collection_watchers.values() {
+            let watchers = cw
+                .watchers
+                .iter()
+                .chain(cw.object_watchers.values().flatten())
+                .chain(cw.query_watchers.iter().map(|(_, w)| w))
+                .chain(cw.clear_watchers.iter())
+                .chain(cw.property_watchers.values().flatten().map(|(_, w)| w))
+                .unique_by(|w| w.get_id());
+            for watcher in watchers {
+                watcher.notify(sequence);
+            }
+            for watcher in &cw.lazy_query_watchers {
+                let mut changes = LazyChanges::default();
+                changes.add_all();
+                watcher.mark_dirty(changes);
+            }
+        }
+    }
+
     pub(crate) fn sync(&mut self) {
         let modifiers = self.modifiers.try_iter().collect_vec();
         for modifier in modifiers {
@@ -40,6 +65,9 @@ pub struct IsarCollectionWatchers {
     pub(super) watchers: Vec<Arc<Watcher>>,
     pub(super) object_watchers: IntMap<Vec<Arc<Watcher>>>,
     pub(super) query_watchers: Vec<(Query, Arc<Watcher>)>,
+    pub(super) lazy_query_watchers: Vec<Arc<LazyQueryWatcher>>,
+    pub(super) property_watchers: IntMap<Vec<(Property, Arc<Watcher>)>>,
+    pub(super) clear_watchers: Vec<Arc<Watcher>>,
 }
 
 impl IsarCollectionWatchers {
@@ -48,11 +76,19 @@ impl IsarCollectionWatchers {
             watchers: Vec::new(),
             object_watchers: IntMap::new(),
             query_watchers: Vec::new(),
+            lazy_query_watchers: Vec::new(),
+            property_watchers: IntMap::new(),
+            clear_watchers: Vec::new(),
         }
     }
 
-    pub fn add_watcher(&mut self, watcher_id: u64, callback: WatcherCallback) {
-        let watcher = Arc::new(Watcher::new(watcher_id, callback));
+    pub fn add_watcher(
+        &mut self,
+        watcher_id: u64,
+        callback: WatcherCallback,
+        debounce: Option<Duration>,
+    ) {
+        let watcher = Arc::new(Watcher::new(watcher_id, callback, debounce));
         self.watchers.push(watcher);
     }
 
@@ -65,8 +101,14 @@ impl IsarCollectionWatchers {
         self.watchers.remove(position);
     }
 
-    pub fn add_object_watcher(&mut self, watcher_id: u64, id: i64, callback: WatcherCallback) {
-        let watcher = Arc::new(Watcher::new(watcher_id, callback));
+    pub fn add_object_watcher(
+        &mut self,
+        watcher_id: u64,
+        id: i64,
+        callback: WatcherCallback,
+        debounce: Option<Duration>,
+    ) {
+        let watcher = Arc::new(Watcher::new(watcher_id, callback, debounce));
         if let Some(object_watchers) = self.object_watchers.get_mut(id as u64) {
             object_watchers.push(watcher);
         } else {
@@ -83,8 +125,14 @@ impl IsarCollectionWatchers {
         watchers.remove(position);
     }
 
-    pub fn add_query_watcher(&mut self, watcher_id: u64, query: Query, callback: WatcherCallback) {
-        let watcher = Arc::new(Watcher::new(watcher_id, callback));
+    pub fn add_query_watcher(
+        &mut self,
+        watcher_id: u64,
+        query: Query,
+        callback: WatcherCallback,
+        debounce: Option<Duration>,
+    ) {
+        let watcher = Arc::new(Watcher::new(watcher_id, callback, debounce));
         self.query_watchers.push((query, watcher));
     }
 
@@ -96,4 +144,62 @@ impl IsarCollectionWatchers {
             .unwrap();
         self.query_watchers.remove(position);
     }
+
+    pub fn add_lazy_query_watcher(&mut self, watcher: Arc<LazyQueryWatcher>) {
+        self.lazy_query_watchers.push(watcher);
+    }
+
+    pub fn remove_lazy_query_watcher(&mut self, watcher_id: u64) {
+        let position = self
+            .lazy_query_watchers
+            .iter()
+            .position(|w| w.get_id() == watcher_id)
+            .unwrap();
+        self.lazy_query_watchers.remove(position);
+    }
+
+    pub fn add_property_watcher(
+        &mut self,
+        watcher_id: u64,
+        id: i64,
+        property: Property,
+        callback: WatcherCallback,
+        debounce: Option<Duration>,
+    ) {
+        let watcher = Arc::new(Watcher::new(watcher_id, callback, debounce));
+        if let Some(property_watchers) = self.property_watchers.get_mut(id as u64) {
+            property_watchers.push((property, watcher));
+        } else {
+            self.property_watchers
+                .insert(id as u64, vec![(property, watcher)]);
+        }
+    }
+
+    pub fn remove_property_watcher(&mut self, id: i64, watcher_id: u64) {
+        let watchers = self.property_watchers.get_mut(id as u64).unwrap();
+        let position = watchers
+            .iter()
+            .position(|(_, w)| w.get_id() == watcher_id)
+            .unwrap();
+        watchers.remove(position);
+    }
+
+    pub fn add_clear_watcher(
+        &mut self,
+        watcher_id: u64,
+        callback: WatcherCallback,
+        debounce: Option<Duration>,
+    ) {
+        let watcher = Arc::new(Watcher::new(watcher_id, callback, debounce));
+        self.clear_watchers.push(watcher);
+    }
+
+    pub fn remove_clear_watcher(&mut self, watcher_id: u64) {
+        let position = self
+            .clear_watchers
+            .iter()
+            .position(|w| w.get_id() == watcher_id)
+            .unwrap();
+        self.clear_watchers.remove(position);
+    }
 }
@@ -0,0 +1,90 @@
+use crate::object::isar_object::IsarObject;
+use crate::query::Query;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Changes that were committed since the last poll.
+#[derive(Default)]
+pub(crate) struct LazyChanges {
+    all: bool,
+    objects: Vec<(i64, Vec<u8>)>,
+}
+
+impl LazyChanges {
+    pub fn add_object(&mut self, id: i64, object: IsarObject) {
+        if !self.all {
+            self.objects.push((id, object.as_bytes().to_vec()));
+        }
+    }
+
+    pub fn add_all(&mut self) {
+        self.all = true;
+        self.objects.clear();
+    }
+
+    fn merge(&mut self, other: LazyChanges) {
+        if other.all {
+            self.add_all();
+        } else if !self.all {
+            self.objects.extend(other.objects);
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        !self.all && self.objects.is_empty()
+    }
+}
+
+struct LazyState {
+    query: Query,
+    changes: LazyChanges,
+}
+
+/// A query watcher that only collects changes. The query filter is evaluated once the consumer
+/// calls `poll`.
+pub struct LazyQueryWatcher {
+    id: u64,
+    paused: Arc<AtomicBool>,
+    state: Mutex<LazyState>,
+}
+
+impl LazyQueryWatcher {
+    pub(crate) fn new(id: u64, query: Query, paused: Arc<AtomicBool>) -> Self {
+        LazyQueryWatcher {
+            id,
+            paused,
+            state: Mutex::new(LazyState {
+                query,
+                changes: LazyChanges::default(),
+            }),
+        }
+    }
+
+    pub(crate) fn get_id(&self) -> u64 {
+        self.id
+    }
+
+    pub(crate) fn mark_dirty(&self, changes: LazyChanges) {
+        if self.paused.load(Ordering::SeqCst) {
+            return;
+        }
+        self.state.lock().unwrap().changes.merge(changes);
+    }
+
+    /// Whether there are changes that have not been polled yet.
+    pub fn is_dirty(&self) -> bool {
+        !self.state.lock().unwrap().changes.is_empty()
+    }
+
+    /// Returns whether any change since the last poll might affect the query results.
+    pub fn poll(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let changes = std::mem::take(&mut state.changes);
+        changes.all
+            || changes.objects.iter().any(|(id, bytes)| {
+                state
+                    .query
+                    .maybe_matches_wc_filter(*id, IsarObject::from_bytes(bytes))
+            })
+    }
+}
@@ -1,18 +1,83 @@
+use crate::collection::IsarCollection;
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
 pub(crate) mod change_set;
 pub(crate) mod isar_watchers;
+pub mod lazy_watcher;
+pub mod query_diff;
+pub(crate) mod read_pool;
 pub(crate) mod watcher;
 
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize)]
+pub enum WatcherKind {
+    Collection,
+    CollectionClear,
+    Object,
+    Property,
+    Query,
+    LazyQuery,
+}
+
+/// Describes a watcher that has not been stopped yet.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WatcherInfo {
+    pub id: u64,
+    pub kind: WatcherKind,
+    pub collection: String,
+    pub object_id: Option<i64>,
+    pub query: Option<String>,
+    pub paused: bool,
+}
+
+impl WatcherInfo {
+    pub(crate) fn new(
+        id: u64,
+        kind: WatcherKind,
+        collection: &IsarCollection,
+        object_id: Option<i64>,
+        query: Option<String>,
+    ) -> Self {
+        WatcherInfo {
+            id,
+            kind,
+            collection: collection.name.clone(),
+            object_id,
+            query,
+            paused: false,
+        }
+    }
+}
+
 pub struct WatchHandle {
+    paused: Arc<AtomicBool>,
     stop_callback: Option<Box<dyn FnOnce()>>,
 }
 
 impl WatchHandle {
-    pub(crate) fn new(stop_callback: Box<dyn FnOnce()>) -> Self {
+    pub(crate) fn new(paused: Arc<AtomicBool>, stop_callback: Box<dyn FnOnce()>) -> Self {
         WatchHandle {
+            paused,
             stop_callback: Some(stop_callback),
         }
     }
 
+    /// Suspends notifications until `resume` is called. Changes while the watcher is paused are
+    /// not reported after resuming.
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+    }
+
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
     pub fn stop(self) {}
 }
 
@@ -0,0 +1,65 @@
+use crate::error::Result;
+use crate::query::Query;
+use crate::txn::IsarTxn;
+use intmap::IntMap;
+use xxhash_rust::xxh3::xxh3_64;
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct QueryDiff {
+    pub added: Vec<i64>,
+    pub removed: Vec<i64>,
+    pub changed: Vec<i64>,
+}
+
+impl QueryDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+pub type QueryDiffCallback = Box<dyn Fn(QueryDiff) + Send + Sync + 'static>;
+
+pub(crate) struct QueryDiffState {
+    query: Query,
+    hashes: IntMap<u64>,
+}
+
+impl QueryDiffState {
+    pub fn new(query: Query, txn: &mut IsarTxn) -> Result<Self> {
+        let hashes = Self::snapshot(&query, txn)?;
+        Ok(QueryDiffState { query, hashes })
+    }
+
+    fn snapshot(query: &Query, txn: &mut IsarTxn) -> Result<IntMap<u64>> {
+        let mut hashes = IntMap::new();
+        query.find_while(txn, |id, object| {
+            hashes.insert(id as u64, xxh3_64(object.as_bytes()));
+            true
+        })?;
+        Ok(hashes)
+    }
+
+    pub fn update(&mut self, txn: &mut IsarTxn) -> Result<QueryDiff> {
+        let hashes = Self::snapshot(&self.query, txn)?;
+
+        let mut diff = QueryDiff::default();
+        for (id, hash) in hashes.iter() {
+            match self.hashes.get(*id) {
+                Some(old_hash) if old_hash == hash => {}
+                Some(_) => diff.changed.push(*id as i64),
+                None => diff.added.push(*id as i64),
+            }
+        }
+        for id in self.hashes.keys() {
+            if !hashes.contains_key(*id) {
+                diff.removed.push(*id as i64);
+            }
+        }
+        diff.added.sort_unstable();
+        diff.removed.sort_unstable();
+        diff.changed.sort_unstable();
+
+        self.hashes = hashes;
+        Ok(diff)
+    }
+}
@@ -0,0 +1,35 @@
+use crossbeam_channel::{unbounded, Sender};
+use std::thread;
+
+pub(crate) type ReadJob = Box<dyn FnOnce() + Send + 'static>;
+
+/// Worker threads that re-evaluate watched queries in their own read transactions. The
+/// committing writer only enqueues jobs so expensive evaluations never delay the next write.
+pub(crate) struct WatcherReadPool {
+    sender: Sender<ReadJob>,
+}
+
+impl WatcherReadPool {
+    pub const DEFAULT_WORKERS: usize = 2;
+
+    /// The workers exit once the pool is dropped and all queued jobs are done.
+    pub fn new(workers: usize) -> Self {
+        let (sender, receiver) = unbounded::<ReadJob>();
+        for i in 0..workers.max(1) {
+            let receiver = receiver.clone();
+            thread::Builder::new()
+                .name(format!("isarwatcher{}", i))
+                .spawn(move || {
+                    for job in receiver {
+                        job();
+                    }
+                })
+                .unwrap();
+        }
+        WatcherReadPool { sender }
+    }
+
+    pub fn submit(&self, job: ReadJob) {
+        let _ = self.sender.send(job);
+    }
+}
@@ -1,20 +1,161 @@
-pub type WatcherCallback = Box<dyn Fn() + Send + Sync + 'static>;
+use crossbeam_channel::{unbounded, RecvTimeoutError, Sender};
+use once_cell::sync::Lazy;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Called with the commit sequence of the change. Debounced watchers receive the latest
+/// sequence.
+pub type WatcherCallback = Box<dyn Fn(u64) + Send + Sync + 'static>;
+
+/// Skips the callback while the watcher is paused.
+pub(crate) fn pausable(callback: WatcherCallback, paused: &Arc<AtomicBool>) -> WatcherCallback {
+    let paused = paused.clone();
+    Box::new(move |sequence| {
+        if !paused.load(Ordering::SeqCst) {
+            callback(sequence)
+        }
+    })
+}
+
+type TimerJob = Box<dyn FnOnce() + Send + 'static>;
+
+/// Runs the delayed notifications of all debounced watchers on a single thread.
+struct DebounceTimer {
+    sender: Sender<(Instant, TimerJob)>,
+}
+
+impl DebounceTimer {
+    fn new() -> Self {
+        let (sender, receiver) = unbounded::<(Instant, TimerJob)>();
+        thread::Builder::new()
+            .name("isardebounce".to_string())
+            .spawn(move || {
+                let mut jobs: Vec<(Instant, TimerJob)> = vec![];
+                loop {
+                    let received = if let Some(next) = jobs.iter().map(|(at, _)| *at).min() {
+                        receiver.recv_deadline(next)
+                    } else {
+                        receiver.recv().map_err(|_| RecvTimeoutError::Disconnected)
+                    };
+                    match received {
+                        Ok(job) => jobs.push(job),
+                        Err(RecvTimeoutError::Timeout) => {}
+                        Err(RecvTimeoutError::Disconnected) => return,
+                    }
+                    let now = Instant::now();
+                    let (due, pending): (Vec<_>, Vec<_>) =
+                        jobs.into_iter().partition(|(at, _)| *at <= now);
+                    jobs = pending;
+                    for (_, job) in due {
+                        job();
+                    }
+                }
+            })
+            .unwrap();
+        DebounceTimer { sender }
+    }
+
+    fn schedule(&self, at: Instant, job: TimerJob) {
+        let _ = self.sender.send((at, job));
+    }
+}
+
+static DEBOUNCE_TIMER: Lazy<DebounceTimer> = Lazy::new(DebounceTimer::new);
+
+#[derive(Default)]
+struct DebounceState {
+    last_notified: Option<Instant>,
+    pending: bool,
+    sequence: u64,
+}
 
 pub(super) struct Watcher {
     id: u64,
-    callback: WatcherCallback,
+    callback: Arc<WatcherCallback>,
+    debounce: Option<Duration>,
+    debounce_state: Arc<Mutex<DebounceState>>,
 }
 
 impl Watcher {
-    pub fn new(id: u64, callback: WatcherCallback) -> Self {
-        Watcher { id, callback }
+    pub fn new(id: u64, callback: WatcherCallback, debounce: Option<Duration>) -> Self {
+        Watcher {
+            id,
+            callback: Arc::new(callback),
+            debounce,
+            debounce_state: Arc::new(Mutex::new(DebounceState::default())),
+        }
     }
 
     pub fn get_id(&self) -> u64 {
         self.id
     }
 
-    pub fn notify(&self) {
-        (*self.callback)()
+    pub fn notify(&self, sequence: u64) {
+        let debounce = if let Some(debounce) = self.debounce {
+            debounce
+        } else {
+            (*self.callback)(sequence);
+            return;
+        };
+
+        let mut state = self.debounce_state.lock().unwrap();
+        state.sequence = state.sequence.max(sequence);
+        if state.pending {
+            return;
+        }
+
+        let now = Instant::now();
+        let elapsed = state.last_notified.map(|last| now.duration_since(last));
+        match elapsed {
+            Some(elapsed) if elapsed < debounce => {
+                state.pending = true;
+                let callback = Arc::downgrade(&self.callback);
+                let debounce_state = self.debounce_state.clone();
+                DEBOUNCE_TIMER.schedule(
+                    now + (debounce - elapsed),
+                    Box::new(move || Self::notify_delayed(callback, debounce_state)),
+                );
+            }
+            _ => {
+                state.last_notified = Some(now);
+                drop(state);
+                (*self.callback)(sequence);
+            }
+        }
+    }
+
+    fn notify_delayed(callback: Weak<WatcherCallback>, debounce_state: Arc<Mutex<DebounceState>>) {
+        let mut state = debounce_state.lock().unwrap();
+        state.pending = false;
+        state.last_notified = Some(Instant::now());
+        let sequence = state.sequence;
+        drop(state);
+
+        // the watcher might have been stopped in the meantime
+        if let Some(callback) = callback.upgrade() {
+            (*callback)(sequence);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_debounce_coalesces_notifications() {
+        let sequences = Arc::new(Mutex::new(vec![]));
+        let recorded = sequences.clone();
+        let callback = Box::new(move |sequence| recorded.lock().unwrap().push(sequence));
+        let watcher = Watcher::new(0, callback, Some(Duration::from_millis(50)));
+        for sequence in 1..=100 {
+            watcher.notify(sequence);
+        }
+        assert_eq!(*sequences.lock().unwrap(), vec![1]);
+
+        thread::sleep(Duration::from_millis(200));
+        assert_eq!(*sequences.lock().unwrap(), vec![1, 100]);
     }
 }
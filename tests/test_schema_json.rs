@@ -0,0 +1,153 @@
+use isar_core::schema::Schema;
+use serde_json::{json, Value};
+
+fn collections() -> Value {
+    json!([{
+        "name": "col",
+        "embedded": false,
+        "properties": [{"name": "value", "type": "Long"}]
+    }])
+}
+
+fn collections_of(schema: &Schema) -> Value {
+    let json = serde_json::from_slice::<Value>(&schema.to_json().unwrap()).unwrap();
+    json["collections"].clone()
+}
+
+#[test]
+fn test_legacy_schema() {
+    let json = serde_json::to_vec(&collections()).unwrap();
+    let schema = Schema::from_json(&json).unwrap();
+    assert_eq!(collections_of(&schema)[0]["name"], json!("col"));
+}
+
+#[test]
+fn test_round_trip() {
+    let json = serde_json::to_vec(&collections()).unwrap();
+    let schema = Schema::from_json(&json).unwrap();
+    let encoded = schema.to_json().unwrap();
+
+    let envelope = serde_json::from_slice::<Value>(&encoded).unwrap();
+    assert_eq!(envelope["version"], json!(Schema::VERSION));
+
+    let decoded = Schema::from_json(&encoded).unwrap();
+    assert_eq!(collections_of(&decoded), collections_of(&schema));
+}
+
+#[test]
+fn test_unknown_fields() {
+    let mut collections = collections();
+    collections[0]["futureField"] = json!(true);
+    collections[0]["properties"][0]["futureField"] = json!("value");
+    let envelope = json!({
+        "version": Schema::VERSION + 1,
+        "minVersion": Schema::VERSION,
+        "futureField": 1,
+        "collections": collections,
+    });
+
+    let json = serde_json::to_vec(&envelope).unwrap();
+    assert!(Schema::from_json(&json).is_ok());
+}
+
+#[test]
+fn test_newer_min_version() {
+    let envelope = json!({
+        "version": Schema::VERSION + 1,
+        "minVersion": Schema::VERSION + 1,
+        "collections": collections(),
+    });
+
+    let json = serde_json::to_vec(&envelope).unwrap();
+    assert!(Schema::from_json(&json).is_err());
+}
+
+#[test]
+fn test_enum_values() {
+    let mut collections = collections();
+    collections[0]["properties"][0]["enumValues"] = json!([0, 1, 2]);
+    let json = serde_json::to_vec(&collections).unwrap();
+    let schema = Schema::from_json(&json).unwrap();
+
+    let property = &collections_of(&schema)[0]["properties"][0];
+    assert_eq!(property["enumValues"], json!([0, 1, 2]));
+}
+
+#[test]
+fn test_unique_constraints() {
+    let mut collections = collections();
+    collections[0]["uniqueConstraints"] = json!([{
+        "name": "unique_value",
+        "properties": ["value"],
+        "caseSensitive": true,
+    }]);
+    let json = serde_json::to_vec(&collections).unwrap();
+    let schema = Schema::from_json(&json).unwrap();
+    let constraint = &collections_of(&schema)[0]["uniqueConstraints"][0];
+    assert_eq!(constraint["properties"], json!(["value"]));
+
+    collections[0]["uniqueConstraints"][0]["properties"] = json!(["missing"]);
+    let json = serde_json::to_vec(&collections).unwrap();
+    assert!(Schema::from_json(&json).is_err());
+}
+
+#[test]
+fn test_declared_backlinks() {
+    let mut collections = collections();
+    collections[0]["links"] = json!([{"name": "parent", "target": "col"}]);
+    let json = serde_json::to_vec(&collections).unwrap();
+    assert!(Schema::from_json(&json).is_ok());
+
+    collections[0]["backlinks"] = json!([{"name": "children", "target": "col", "link": "other"}]);
+    let json = serde_json::to_vec(&collections).unwrap();
+    assert!(Schema::from_json(&json).is_err());
+
+    collections[0]["backlinks"] = json!([{"name": "children", "target": "col", "link": "parent"}]);
+    let json = serde_json::to_vec(&collections).unwrap();
+    let schema = Schema::from_json(&json).unwrap();
+    let backlink = &collections_of(&schema)[0]["backlinks"][0];
+    assert_eq!(backlink["link"], json!("parent"));
+}
+
+#[test]
+fn test_schema_accessors() {
+    let mut collections = collections();
+    collections[0]["links"] = json!([{"name": "parent", "target": "col", "ordered": true}]);
+    let json = serde_json::to_vec(&collections).unwrap();
+    let schema = Schema::from_json(&json).unwrap();
+
+    let col = &schema.collections()[0];
+    assert_eq!(col.name(), "col");
+    assert!(!col.is_embedded());
+    assert_eq!(col.properties()[0].name(), Some("value"));
+    assert!(col.indexes().is_empty());
+    assert_eq!(col.links()[0].name(), "parent");
+    assert_eq!(col.links()[0].target_collection(), "col");
+    assert!(col.links()[0].is_ordered());
+}
+
+#[test]
+fn test_blobs() {
+    let mut collections = collections();
+    collections[0]["properties"] = json!([
+        {"name": "attachment", "type": "Blob"},
+        {"name": "value", "type": "Long"},
+    ]);
+    let json = serde_json::to_vec(&collections).unwrap();
+    let schema = Schema::from_json(&json).unwrap();
+    let col = &schema.collections()[0];
+    assert_eq!(col.blobs(), ["attachment"]);
+    // blobs take no space in the objects
+    let properties = col.get_properties();
+    assert_eq!(properties.len(), 1);
+    assert_eq!(properties[0].offset, 2);
+
+    collections[0]["indexes"] = json!([{
+        "name": "attachment",
+        "unique": false,
+        "replace": false,
+        "properties": [{"name": "attachment", "type": "Value", "caseSensitive": false}],
+    }]);
+    let json = serde_json::to_vec(&collections).unwrap();
+    assert!(Schema::from_json(&json).is_err());
+}